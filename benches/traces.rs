@@ -87,6 +87,70 @@ fn bench_downstream(
     });
 }
 
+fn bench_arity<const ARITY: usize>(
+    group: &mut BenchmarkGroup<WallTime>,
+    trace: &SequentialTrace,
+    trace_name: &str,
+) {
+    let trace = trace.chars_to_bytes();
+
+    group.throughput(Throughput::Elements(trace.num_edits() as u64));
+
+    let function_name = format!("{trace_name}/upstream");
+
+    // Criterion only measures time, so we report the memory used by the
+    // replica at the end of the trace ourselves.
+    let memory = replay_upstream::<ARITY>(&trace).memory_usage();
+
+    println!(
+        "arity/{function_name}/{ARITY}: {} bytes (inodes: {}, lnodes: {}, \
+         run indices: {})",
+        memory.total(),
+        memory.gtree_inodes(),
+        memory.gtree_lnodes(),
+        memory.run_indices(),
+    );
+
+    group.bench_function(BenchmarkId::new(function_name, ARITY), |b| {
+        b.iter(|| {
+            let replica = replay_upstream::<ARITY>(&trace);
+            assert_eq!(replica.len(), trace.end_content().len());
+        })
+    });
+}
+
+fn replay_upstream<const ARITY: usize>(
+    trace: &SequentialTrace,
+) -> Replica<ARITY> {
+    let mut replica =
+        Replica::<ARITY>::new_with_arity(1, trace.start_content().len());
+
+    for (start, end, text) in trace.edits() {
+        let _ = replica.deleted(start..end);
+        let _ = replica.inserted(start, text.len());
+    }
+
+    replica
+}
+
+fn arity(c: &mut Criterion) {
+    let mut group = c.benchmark_group("arity");
+
+    for (trace, trace_name) in [
+        (traces::automerge(), "automerge"),
+        (traces::rustcode(), "rustcode"),
+        (traces::seph_blog(), "seph_blog"),
+        (traces::sveltecomponent(), "sveltecomponent"),
+    ] {
+        bench_arity::<4>(&mut group, &trace, trace_name);
+        bench_arity::<8>(&mut group, &trace, trace_name);
+        bench_arity::<16>(&mut group, &trace, trace_name);
+        bench_arity::<32>(&mut group, &trace, trace_name);
+        bench_arity::<64>(&mut group, &trace, trace_name);
+        bench_arity::<128>(&mut group, &trace, trace_name);
+    }
+}
+
 fn upstream_automerge(c: &mut Criterion) {
     let mut group = c.benchmark_group("traces");
     bench_upstream(&mut group, &traces::automerge(), "automerge");
@@ -141,5 +205,6 @@ criterion_group!(
     downstream_rustcode,
     downstream_seph_blog,
     downstream_sveltecomponent,
+    arity,
 );
 criterion_main!(benches);
@@ -0,0 +1,208 @@
+//! Seeds the corpora of the fuzz targets from the sequential editing traces.
+//!
+//! ```sh
+//! cargo run --release --manifest-path fuzz/Cargo.toml --example seed_corpus
+//! ```
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use cola::{ChecksumAlgorithm, CrdtEdit, Replica};
+use cola_fuzz::{
+    encoded_replica_to_bytes,
+    history_to_bytes,
+    remote_edits_to_bytes,
+    SERDE_EDITS,
+};
+use cola_testing::{History, Step};
+use traces::SequentialTrace;
+
+/// The lengths of the trace prefixes turned into seeds.
+const PREFIXES: &[usize] = &[16, 128, 256];
+
+/// The number of peers in the histories of the `converge` target.
+const NUM_PEERS: usize = 3;
+
+/// The number of consecutive edits made by each peer of a history before
+/// they're delivered to the other peers.
+const EDITS_PER_TURN: usize = 8;
+
+fn main() {
+    let corpus = Path::new(env!("CARGO_MANIFEST_DIR")).join("corpus");
+
+    let traces = [
+        ("automerge", traces::automerge()),
+        ("rustcode", traces::rustcode()),
+        ("seph-blog", traces::seph_blog()),
+        ("sveltecomponent", traces::sveltecomponent()),
+    ];
+
+    for (name, trace) in traces {
+        let trace = trace.chars_to_bytes();
+
+        for &prefix in PREFIXES {
+            let seed = Seed::new(&corpus, format!("{name}-{prefix}"));
+            seed_replicas(&seed, &trace, prefix);
+            seed_edits(&seed, &trace, prefix);
+            seed_history(&seed, &trace, prefix);
+        }
+    }
+
+    println!("seeded the corpora in {}", corpus.display());
+}
+
+/// Seeds the `decode_replica` and `encode_roundtrip` targets with the
+/// replica that made the edits and with one that received them in reverse
+/// order, so that they're all still in its backlog.
+fn seed_replicas(seed: &Seed, trace: &SequentialTrace, prefix: usize) {
+    let (replica, edits) = replay(trace, prefix);
+
+    let mut reversed = Replica::new(1, trace.start_content().len()).fork(2);
+
+    for edit in edits.iter().rev() {
+        match edit {
+            CrdtEdit::Insertion(insertion) => {
+                let _ = reversed.integrate_insertion(insertion);
+            },
+            CrdtEdit::Deletion(deletion) => {
+                let _ = reversed.integrate_deletion(deletion);
+            },
+        }
+    }
+
+    for (suffix, replica) in [("", &replica), ("-backlogged", &reversed)] {
+        // Without a checksum the fuzzer can mutate the encoded replica
+        // without invalidating it.
+        let encoded = replica.encode_with_checksum(ChecksumAlgorithm::None);
+        let bytes = encoded_replica_to_bytes(&encoded);
+        seed.write("decode_replica", suffix, &bytes);
+        seed.write("encode_roundtrip", suffix, &bytes);
+    }
+}
+
+/// Seeds the `integrate_edits` target with the edits of the trace, both in
+/// the order they were made and in reverse.
+fn seed_edits(seed: &Seed, trace: &SequentialTrace, prefix: usize) {
+    let (_, mut edits) = replay(trace, prefix);
+
+    let len = trace.start_content().len() as u16;
+
+    let bytes = remote_edits_to_bytes(&(len, edits.clone()));
+    seed.write("integrate_edits", "", &bytes);
+
+    edits.reverse();
+
+    let bytes = remote_edits_to_bytes(&(len, edits));
+    seed.write("integrate_edits", "-reversed", &bytes);
+}
+
+/// Seeds the `converge` and `integrate_edits` targets with a history where
+/// the peers take turns making the edits of the trace, each turn ending with
+/// the other peers receiving all of them.
+fn seed_history(seed: &Seed, trace: &SequentialTrace, prefix: usize) {
+    let mut steps = Vec::new();
+
+    for (idx, (start, end, text)) in trace.edits().take(prefix).enumerate() {
+        let peer = (idx / EDITS_PER_TURN) % NUM_PEERS;
+
+        for chunk in chunks(end - start) {
+            steps.push(Step::Delete { peer, start, len: chunk });
+        }
+
+        let mut offset = start;
+
+        for chunk in chunks(text.len()) {
+            let text = "a".repeat(chunk);
+            steps.push(Step::Insert { peer, offset, text });
+            offset += chunk;
+        }
+
+        let is_end_of_turn = (idx + 1) % EDITS_PER_TURN == 0;
+
+        if is_end_of_turn {
+            deliver_all(&mut steps, peer);
+        }
+    }
+
+    let history = History {
+        initial_text: "a".repeat(trace.start_content().len().min(255)),
+        num_peers: NUM_PEERS,
+        steps,
+    };
+
+    let bytes = history_to_bytes(&history);
+
+    seed.write("converge", "", &bytes);
+
+    let shared_ids = [&[!SERDE_EDITS], bytes.as_slice()].concat();
+
+    seed.write("integrate_edits", "-shared-ids", &shared_ids);
+}
+
+/// Replays the first `prefix` edits of the trace, returning the replica that
+/// made them together with the edits.
+fn replay(trace: &SequentialTrace, prefix: usize) -> (Replica, Vec<CrdtEdit>) {
+    let mut replica = Replica::new(1, trace.start_content().len());
+
+    let mut edits = Vec::new();
+
+    for (start, end, text) in trace.edits().take(prefix) {
+        if end > start {
+            edits.push(replica.deleted(start..end).into());
+        }
+        if !text.is_empty() {
+            edits.push(replica.inserted(start, text.len()).into());
+        }
+    }
+
+    (replica, edits)
+}
+
+/// Delivers every message the given peer has sent since its last turn to
+/// all the other peers, in the order it sent them.
+fn deliver_all(steps: &mut Vec<Step>, sender: usize) {
+    let num_messages = steps
+        .iter()
+        .rev()
+        .take_while(|step| match step {
+            Step::Insert { peer, .. } | Step::Delete { peer, .. } => {
+                *peer == sender
+            },
+            _ => false,
+        })
+        .count();
+
+    for peer in (0..NUM_PEERS).filter(|&peer| peer != sender) {
+        for _ in 0..num_messages {
+            steps.push(Step::Deliver { peer, message: 0 });
+        }
+    }
+}
+
+/// Splits a length into chunks of at most 256, which is the maximum length
+/// of an insertion or deletion in the input of the `converge` target.
+fn chunks(mut len: usize) -> impl Iterator<Item = usize> {
+    core::iter::from_fn(move || {
+        let chunk = len.min(256);
+        len -= chunk;
+        (chunk > 0).then_some(chunk)
+    })
+}
+
+/// The name of a seed, shared by the corpora of all the targets.
+struct Seed<'a> {
+    corpus: &'a Path,
+    name: String,
+}
+
+impl<'a> Seed<'a> {
+    fn new(corpus: &'a Path, name: String) -> Self {
+        Self { corpus, name }
+    }
+
+    fn write(&self, target: &str, suffix: &str, bytes: &[u8]) {
+        let dir: PathBuf = self.corpus.join(target);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(format!("{}{suffix}", self.name)), bytes).unwrap();
+    }
+}
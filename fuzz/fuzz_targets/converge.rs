@@ -0,0 +1,9 @@
+#![no_main]
+
+use cola_fuzz::history_from_bytes;
+use cola_testing::BufferPeer;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    history_from_bytes(data).check::<BufferPeer>();
+});
@@ -0,0 +1,27 @@
+#![no_main]
+
+use cola::Replica;
+use cola_fuzz::{encoded_replica_from_bytes, DECODING_ID};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let Some(encoded) = encoded_replica_from_bytes(data) else { return };
+
+    if let Ok(resumed) = Replica::resume(&encoded) {
+        assert!(resumed.check_integrity().is_ok());
+    }
+
+    let Ok(mut replica) = Replica::decode(DECODING_ID, &encoded) else {
+        return;
+    };
+
+    // A decoded replica has passed its integrity checks, so it must be
+    // possible to keep editing it.
+    let len = replica.len();
+    let _ = replica.inserted(len / 2, 1);
+    let _ = replica.deleted(0..len / 2);
+    let _ = replica.fork(DECODING_ID + 1).inserted(0, 1);
+
+    assert_eq!(replica.len(), len + 1 - len / 2);
+    assert!(replica.check_integrity().is_ok());
+});
@@ -0,0 +1,25 @@
+#![no_main]
+
+use cola::Replica;
+use cola_fuzz::{encoded_replica_from_bytes, DECODING_ID};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let Some(encoded) = encoded_replica_from_bytes(data) else { return };
+
+    let Ok(replica) = Replica::decode(DECODING_ID, &encoded) else { return };
+
+    let decoded = Replica::decode(DECODING_ID, &replica.encode())
+        .expect("re-encoded replica can't be decoded");
+
+    assert!(replica.eq_decoded(&decoded));
+
+    let mut streamed = Vec::new();
+
+    replica.encode_to_writer(&mut streamed).unwrap();
+
+    let decoded = Replica::decode_from_reader(DECODING_ID, &*streamed)
+        .expect("streamed replica can't be decoded");
+
+    assert!(replica.eq_decoded(&decoded));
+});
@@ -0,0 +1,27 @@
+#![no_main]
+
+use cola::CrdtEdit;
+use cola_fuzz::remote_edits_from_bytes;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let Some((mut replica, edits)) = remote_edits_from_bytes(data) else {
+        return;
+    };
+
+    for edit in &edits {
+        match edit {
+            CrdtEdit::Insertion(insertion) => {
+                let _ = replica.try_integrate_insertion(insertion);
+            },
+            CrdtEdit::Deletion(deletion) => {
+                let _ = replica.integrate_deletion(deletion);
+            },
+        }
+    }
+
+    replica.backlogged_insertions().for_each(drop);
+    replica.backlogged_deletions().for_each(drop);
+
+    replica.check_integrity().unwrap();
+});
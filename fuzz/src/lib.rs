@@ -0,0 +1,316 @@
+//! Fuzz targets for the code paths of cola that process bytes received from
+//! other peers.
+//!
+//! - `decode_replica`: decodes arbitrary [`EncodedReplica`]s and makes some
+//!   edits on the ones that are valid;
+//! - `encode_roundtrip`: checks that every `EncodedReplica` that can be
+//!   decoded survives being encoded and decoded again;
+//! - `integrate_edits`: integrates arbitrary [`Insertion`]s and
+//!   [`Deletion`]s, either deserialized via `serde` or made by independent
+//!   peers sharing their `ReplicaId`s;
+//! - `converge`: replays arbitrary edit [`History`]s across several peers,
+//!   checking that they converge.
+//!
+//! This library contains the input formats shared by the targets and by the
+//! `seed_corpus` example, which seeds their corpora from the editing traces
+//! in the `traces` directory:
+//!
+//! ```sh
+//! cargo run --release --manifest-path fuzz/Cargo.toml --example seed_corpus
+//! cargo +nightly fuzz run converge
+//! ```
+//!
+//! [`Insertion`]: cola::Insertion
+//! [`Deletion`]: cola::Deletion
+
+use cola::{CrdtEdit, EncodedReplica, Replica, ReplicaId};
+use cola_testing::{History, Step};
+
+/// The first byte of the inputs of the `integrate_edits` target whose edits
+/// are deserialized via `serde`. Every other first byte means the rest of
+/// the input is a [`History`] replayed by peers sharing their ids.
+pub const SERDE_EDITS: u8 = 0;
+
+/// The id of the `Replica`s decoded from the inputs of the `decode_replica`
+/// and `encode_roundtrip` targets.
+pub const DECODING_ID: ReplicaId = 42;
+
+/// The maximum number of peers in a [`History`] parsed by
+/// [`history_from_bytes`].
+pub const MAX_PEERS: usize = 8;
+
+/// The maximum number of steps in a [`History`] parsed by
+/// [`history_from_bytes`].
+pub const MAX_STEPS: usize = 256;
+
+/// The number of distinct `ReplicaId`s given to the peers replaying a
+/// [`History`] in the `integrate_edits` target.
+pub const NUM_SHARED_IDS: usize = 3;
+
+/// The input of the `integrate_edits` target: the length of the document the
+/// peers start from, and the edits they send to the `Replica` under test.
+pub type RemoteEdits = (u16, Vec<CrdtEdit>);
+
+/// Deserializes an [`EncodedReplica`] from the input of the `decode_replica`
+/// and `encode_roundtrip` targets.
+#[inline]
+pub fn encoded_replica_from_bytes(bytes: &[u8]) -> Option<EncodedReplica> {
+    bincode::deserialize(bytes).ok()
+}
+
+/// The inverse of [`encoded_replica_from_bytes`].
+#[inline]
+pub fn encoded_replica_to_bytes(encoded: &EncodedReplica) -> Vec<u8> {
+    bincode::serialize(encoded).expect("serializing to a Vec can't fail")
+}
+
+/// Parses the input of the `integrate_edits` target, returning the
+/// `Replica` the edits are integrated into together with the edits.
+///
+/// If the first byte is [`SERDE_EDITS`] the rest of the input is a
+/// bincode-serialized [`RemoteEdits`]. The edits in the seed corpus are made
+/// by a `Replica` with id 1 starting from a document of the given length,
+/// while the returned `Replica` is a fork of it with id 2.
+///
+/// Otherwise the rest of the input is parsed into a [`History`] with
+/// [`history_from_bytes`], whose steps are replayed by peers forked from a
+/// `Replica` with id 1. The id of the `n`-th peer is `2 + n %`
+/// [`NUM_SHARED_IDS`], so most ids are shared by more than one peer, and
+/// the edits are those made by the peers, in the order they made them. The
+/// returned `Replica` is the one the peers were forked from.
+#[inline]
+pub fn remote_edits_from_bytes(
+    bytes: &[u8],
+) -> Option<(Replica, Vec<CrdtEdit>)> {
+    let (&tag, bytes) = bytes.split_first()?;
+
+    if tag == SERDE_EDITS {
+        let (len, edits) = bincode::deserialize::<RemoteEdits>(bytes).ok()?;
+        let replica = Replica::new(1, len as usize).fork(2);
+        return Some((replica, edits));
+    }
+
+    let history = history_from_bytes(bytes);
+
+    let replica = Replica::new(1, history.initial_text.len());
+
+    let mut peers = (0..history.num_peers)
+        .map(|idx| {
+            let id = 2 + (idx % NUM_SHARED_IDS) as ReplicaId;
+            (replica.fork(id), history.initial_text.len())
+        })
+        .collect::<Vec<_>>();
+
+    let mut edits = Vec::<CrdtEdit>::new();
+
+    for step in history.steps {
+        match step {
+            Step::Insert { peer, offset, text } => {
+                let (peer, len) = &mut peers[peer % history.num_peers];
+                let insertion = peer.inserted(offset % (*len + 1), text.len());
+                *len += text.len();
+                edits.push(insertion.into());
+            },
+            Step::Delete { peer, start, len: delete_len } => {
+                let (peer, len) = &mut peers[peer % history.num_peers];
+                if *len > 0 {
+                    let start = start % *len;
+                    let end = (start + delete_len).min(*len);
+                    edits.push(peer.deleted(start..end).into());
+                    *len -= end - start;
+                }
+            },
+            Step::Deliver { peer, message }
+            | Step::Duplicate { peer, message } => {
+                if !edits.is_empty() {
+                    let edit = &edits[message % edits.len()];
+                    let (peer, len) = &mut peers[peer % history.num_peers];
+                    integrate(peer, len, edit);
+                }
+            },
+        }
+    }
+
+    Some((replica, edits))
+}
+
+/// The inverse of [`remote_edits_from_bytes`] for inputs whose edits are
+/// deserialized via `serde`.
+#[inline]
+pub fn remote_edits_to_bytes(edits: &RemoteEdits) -> Vec<u8> {
+    let mut bytes = vec![SERDE_EDITS];
+    bincode::serialize_into(&mut bytes, edits)
+        .expect("serializing to a Vec can't fail");
+    bytes
+}
+
+/// Integrates an edit and the backlogged edits it unlocks into the
+/// `Replica` of a peer, keeping track of the length of its document.
+#[inline]
+fn integrate(replica: &mut Replica, len: &mut usize, edit: &CrdtEdit) {
+    match edit {
+        CrdtEdit::Insertion(insertion) => {
+            if let Ok(Some(_)) = replica.try_integrate_insertion(insertion) {
+                *len += insertion.text().temporal_range().len();
+            }
+        },
+        CrdtEdit::Deletion(deletion) => {
+            for range in replica.integrate_deletion(deletion) {
+                *len -= range.len();
+            }
+        },
+    }
+
+    for (text, _) in replica.backlogged_insertions() {
+        *len += text.temporal_range().len();
+    }
+
+    for ranges in replica.backlogged_deletions() {
+        for range in ranges {
+            *len -= range.len();
+        }
+    }
+}
+
+/// Parses the input of the `converge` target into a [`History`].
+///
+/// Every sequence of bytes is a valid input. The first byte picks the number
+/// of peers and the second one the length `n` of the initial text, which is
+/// made of the following `n` bytes. The rest of the input is a sequence of
+/// [`Step`]s, each one starting with a tag byte followed by the index of the
+/// peer:
+///
+/// - `0`: an [`Insert`](Step::Insert), followed by the offset as a
+///   little-endian `u16` and the length of the text minus one, then the text;
+/// - `1`: a [`Delete`](Step::Delete), followed by the start as a
+///   little-endian `u16` and the length minus one;
+/// - `2`: a [`Deliver`](Step::Deliver), followed by the index of the message;
+/// - `3`: a [`Duplicate`](Step::Duplicate), followed by the index of the
+///   message.
+///
+/// Tags are taken modulo 4, the number of peers modulo [`MAX_PEERS`], and
+/// text bytes are mapped to lowercase ASCII letters. Parsing stops at the
+/// first truncated step or after [`MAX_STEPS`] steps.
+#[inline]
+pub fn history_from_bytes(bytes: &[u8]) -> History {
+    let mut bytes = Bytes(bytes);
+
+    let num_peers = 2 + bytes.u8().unwrap_or(0) as usize % (MAX_PEERS - 1);
+
+    let initial_len = bytes.u8().unwrap_or(0) as usize;
+
+    let initial_text = bytes.text(initial_len).unwrap_or_default();
+
+    let mut steps = Vec::new();
+
+    while steps.len() < MAX_STEPS {
+        let Some(step) = bytes.step() else { break };
+        steps.push(step);
+    }
+
+    History { initial_text, num_peers, steps }
+}
+
+/// The inverse of [`history_from_bytes`], except that the peers, offsets
+/// and messages are truncated to fit in their fields.
+///
+/// # Panics
+///
+/// Panics if the history has more than [`MAX_PEERS`] peers, an initial text
+/// longer than 255 bytes, or an insertion or deletion whose length is not in
+/// `1..=256`.
+#[track_caller]
+#[inline]
+pub fn history_to_bytes(history: &History) -> Vec<u8> {
+    assert!((2..=MAX_PEERS).contains(&history.num_peers));
+
+    let mut bytes = vec![
+        (history.num_peers - 2) as u8,
+        u8::try_from(history.initial_text.len()).unwrap(),
+    ];
+
+    bytes.extend_from_slice(history.initial_text.as_bytes());
+
+    for step in &history.steps {
+        match step {
+            Step::Insert { peer, offset, text } => {
+                bytes.extend([0, *peer as u8]);
+                bytes.extend((*offset as u16).to_le_bytes());
+                bytes.push(length_byte(text.len()));
+                bytes.extend_from_slice(text.as_bytes());
+            },
+            Step::Delete { peer, start, len } => {
+                bytes.extend([1, *peer as u8]);
+                bytes.extend((*start as u16).to_le_bytes());
+                bytes.push(length_byte(*len));
+            },
+            Step::Deliver { peer, message } => {
+                bytes.extend([2, *peer as u8, *message as u8]);
+            },
+            Step::Duplicate { peer, message } => {
+                bytes.extend([3, *peer as u8, *message as u8]);
+            },
+        }
+    }
+
+    bytes
+}
+
+#[track_caller]
+#[inline]
+fn length_byte(len: usize) -> u8 {
+    assert!((1..=256).contains(&len), "invalid length: {len}");
+    (len - 1) as u8
+}
+
+/// A cursor over the input of the `converge` target.
+struct Bytes<'a>(&'a [u8]);
+
+impl Bytes<'_> {
+    #[inline]
+    fn step(&mut self) -> Option<Step> {
+        let tag = self.u8()?;
+
+        let peer = self.u8()? as usize;
+
+        let step = match tag % 4 {
+            0 => {
+                let offset = self.u16()? as usize;
+                let len = self.u8()? as usize + 1;
+                let text = self.text(len)?;
+                Step::Insert { peer, offset, text }
+            },
+            1 => {
+                let start = self.u16()? as usize;
+                let len = self.u8()? as usize + 1;
+                Step::Delete { peer, start, len }
+            },
+            2 => Step::Deliver { peer, message: self.u8()? as usize },
+            _ => Step::Duplicate { peer, message: self.u8()? as usize },
+        };
+
+        Some(step)
+    }
+
+    #[inline]
+    fn text(&mut self, len: usize) -> Option<String> {
+        if self.0.len() < len {
+            return None;
+        }
+        let (text, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Some(text.iter().map(|byte| char::from(b'a' + byte % 26)).collect())
+    }
+
+    #[inline]
+    fn u8(&mut self) -> Option<u8> {
+        let (&byte, rest) = self.0.split_first()?;
+        self.0 = rest;
+        Some(byte)
+    }
+
+    #[inline]
+    fn u16(&mut self) -> Option<u16> {
+        Some(u16::from_le_bytes([self.u8()?, self.u8()?]))
+    }
+}
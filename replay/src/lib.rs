@@ -0,0 +1,70 @@
+//! Records the operations applied to a cola [`Replica`](cola::Replica) and
+//! replays them to find where it diverged.
+//!
+//! Convergence bugs usually only show up after a long and unlucky sequence
+//! of edits, which is hard to reproduce outside of the session it happened
+//! in. A [`RecordingReplica`] wraps a `Replica` and logs every call that
+//! changes it, together with its result, to a trace. The `cola-replay`
+//! binary then reads the trace back as a [`Trace`], replays every operation
+//! on a fresh copy of the starting `Replica` and reports the first one that
+//! either returns a different result than the recorded one, panics, or
+//! leaves the `Replica` in an inconsistent state:
+//!
+//! ```sh
+//! cola-replay session.trace
+//! cola-replay --bisect session.trace
+//! ```
+//!
+//! # Trace format
+//!
+//! A trace is a JSON Lines file. The first line is a header modelled after
+//! the datasets of the concurrent editing traces, with a `kind` field set to
+//! `"recorded"` and the [`EncodedReplica`](cola::EncodedReplica) the
+//! recording started from:
+//!
+//! ```json
+//! {"kind":"recorded","start":{...}}
+//! ```
+//!
+//! Every following line is an [`Op`]. Local edits are recorded as
+//! `[position, deleted, inserted]` patches like the ones of the concurrent
+//! traces, except that the inserted text is replaced by its length since a
+//! `Replica` never sees the text itself, followed by the returned edit:
+//!
+//! ```json
+//! {"patch":[3,0,5,{"Insertion":{...}}]}
+//! {"integrateDeletion":{"deletion":{...},"ranges":[{"start":0,"end":2}]}}
+//! ```
+//!
+//! # Examples
+//!
+//! ```
+//! use cola::Replica;
+//! use cola_replay::{RecordingReplica, Trace};
+//!
+//! let mut peer1 = RecordingReplica::new(Replica::new(1, 3), Vec::new())?;
+//! let mut peer2 = Replica::new(1, 3).fork(2);
+//!
+//! let insertion = peer2.inserted(3, 2);
+//! assert_eq!(peer1.integrate_insertion(&insertion), Some(3));
+//! let _ = peer1.deleted(0..1);
+//!
+//! let (_, bytes) = peer1.finish()?;
+//!
+//! let trace = Trace::read(bytes.as_slice())?;
+//!
+//! assert_eq!(trace.ops().len(), 2);
+//!
+//! let replayed = trace.replay().unwrap();
+//!
+//! assert_eq!(replayed.len(), 4);
+//! # Ok::<_, std::io::Error>(())
+//! ```
+
+#![deny(missing_docs)]
+
+mod recorder;
+mod trace;
+
+pub use recorder::RecordingReplica;
+pub use trace::{Divergence, Op, Trace};
@@ -0,0 +1,56 @@
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use clap::Parser;
+use cola_replay::Trace;
+
+/// Replays a trace recorded by a `RecordingReplica`, reporting the first
+/// operation where the replayed replica diverged from the recorded one.
+#[derive(Parser)]
+#[command(version)]
+struct Args {
+    /// The trace to replay.
+    trace: PathBuf,
+
+    /// Only check the integrity of the replica at the end, bisecting the
+    /// trace to find the first operation that corrupted it.
+    #[arg(long)]
+    bisect: bool,
+
+    /// The number of operations before the divergent one to print.
+    #[arg(long, default_value = "0")]
+    context: usize,
+}
+
+fn main() -> io::Result<ExitCode> {
+    let args = Args::parse();
+
+    let trace = Trace::read(BufReader::new(File::open(&args.trace)?))?;
+
+    let result = if args.bisect { trace.bisect() } else { trace.replay() };
+
+    match result {
+        Ok(replica) => {
+            println!(
+                "replayed {} operations, the replica has length {}",
+                trace.ops().len(),
+                replica.len()
+            );
+            Ok(ExitCode::SUCCESS)
+        },
+
+        Err(divergence) => {
+            let index = divergence.index();
+
+            for idx in index.saturating_sub(args.context)..index {
+                println!("{idx}: {}", trace.ops()[idx]);
+            }
+
+            println!("{divergence}");
+
+            Ok(ExitCode::FAILURE)
+        },
+    }
+}
@@ -0,0 +1,175 @@
+use core::ops::{Bound, Deref, Range, RangeBounds};
+use std::io::{self, Write};
+
+use cola::{Deletion, Departure, Insertion, InsertionError, Replica, Text};
+
+use crate::trace::{Header, KIND};
+use crate::Op;
+
+/// A [`Replica`] that records every call that changes it to a trace.
+///
+/// This has the same methods as a `Replica` for editing it and integrating
+/// remote edits, each of which writes an [`Op`] to the writer before
+/// returning. The read-only methods are available via `Deref`.
+///
+/// Every operation is written with a single call to
+/// [`write_all`](Write::write_all), so you may want to wrap files in a
+/// [`BufWriter`](std::io::BufWriter). If a write fails the recording stops,
+/// and the error is returned by [`finish`](Self::finish).
+pub struct RecordingReplica<W: Write> {
+    replica: Replica,
+    writer: W,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> RecordingReplica<W> {
+    /// Same as [`Replica::backlogged_deletions`], except that the returned
+    /// ranges are collected.
+    #[inline]
+    pub fn backlogged_deletions(&mut self) -> Vec<Vec<Range<usize>>> {
+        let merged = self.replica.backlogged_deletions().collect::<Vec<_>>();
+        self.record(&Op::BackloggedDeletions { merged: merged.clone() });
+        merged
+    }
+
+    /// Same as [`Replica::backlogged_insertions`], except that the returned
+    /// insertions are collected.
+    #[inline]
+    pub fn backlogged_insertions(&mut self) -> Vec<(Text, usize)> {
+        let merged = self.replica.backlogged_insertions().collect::<Vec<_>>();
+        self.record(&Op::BackloggedInsertions { merged: merged.clone() });
+        merged
+    }
+
+    /// Records a call to [`Replica::deleted`].
+    #[track_caller]
+    #[inline]
+    pub fn deleted<R>(&mut self, range: R) -> Deletion
+    where
+        R: RangeBounds<usize>,
+    {
+        let start = match range.start_bound() {
+            Bound::Included(&start) => start,
+            Bound::Excluded(&start) => start + 1,
+            Bound::Unbounded => 0,
+        };
+
+        let end = match range.end_bound() {
+            Bound::Included(&end) => end + 1,
+            Bound::Excluded(&end) => end,
+            Bound::Unbounded => self.replica.len(),
+        };
+
+        let deletion = self.replica.deleted(start..end);
+        self.record(&Op::deleted(start..end, deletion.clone()));
+        deletion
+    }
+
+    /// Flushes the writer and returns the `Replica` and the writer, or the
+    /// first error encountered while recording.
+    #[inline]
+    pub fn finish(mut self) -> io::Result<(Replica, W)> {
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+        self.writer.flush()?;
+        Ok((self.replica, self.writer))
+    }
+
+    /// Records a call to [`Replica::inserted`].
+    #[track_caller]
+    #[inline]
+    pub fn inserted(&mut self, at_offset: usize, len: usize) -> Insertion {
+        let insertion = self.replica.inserted(at_offset, len);
+        self.record(&Op::inserted(at_offset, len, insertion.clone()));
+        insertion
+    }
+
+    /// Records a call to [`Replica::integrate_deletion`].
+    #[must_use]
+    #[inline]
+    pub fn integrate_deletion(
+        &mut self,
+        deletion: &Deletion,
+    ) -> Vec<Range<usize>> {
+        let ranges = self.replica.integrate_deletion(deletion);
+        self.record(&Op::IntegrateDeletion {
+            deletion: deletion.clone(),
+            ranges: ranges.clone(),
+        });
+        ranges
+    }
+
+    /// Records a call to [`Replica::integrate_departure`].
+    #[inline]
+    pub fn integrate_departure(&mut self, departure: &Departure) -> bool {
+        let integrated = self.replica.integrate_departure(departure);
+        self.record(&Op::IntegrateDeparture {
+            departure: departure.clone(),
+            integrated,
+        });
+        integrated
+    }
+
+    /// Records a call to [`Replica::integrate_insertion`].
+    #[must_use]
+    #[inline]
+    pub fn integrate_insertion(
+        &mut self,
+        insertion: &Insertion,
+    ) -> Option<usize> {
+        self.try_integrate_insertion(insertion).ok().flatten()
+    }
+
+    /// Starts recording the `Replica`, writing the header of the trace with
+    /// its current state.
+    #[inline]
+    pub fn new(replica: Replica, mut writer: W) -> io::Result<Self> {
+        let header = Header { kind: KIND.to_owned(), start: replica.encode() };
+        write_line(&mut writer, &header)?;
+        Ok(Self { replica, writer, error: None })
+    }
+
+    /// Records a call to [`Replica::try_integrate_insertion`].
+    #[inline]
+    pub fn try_integrate_insertion(
+        &mut self,
+        insertion: &Insertion,
+    ) -> Result<Option<usize>, InsertionError> {
+        let result = self.replica.try_integrate_insertion(insertion);
+        self.record(&Op::IntegrateInsertion {
+            insertion: insertion.clone(),
+            offset: result.clone().ok().flatten(),
+            collision: result.is_err(),
+        });
+        result
+    }
+
+    #[inline]
+    fn record(&mut self, op: &Op) {
+        if self.error.is_none() {
+            self.error = write_line(&mut self.writer, op).err();
+        }
+    }
+}
+
+impl<W: Write> Deref for RecordingReplica<W> {
+    type Target = Replica;
+
+    #[inline]
+    fn deref(&self) -> &Replica {
+        &self.replica
+    }
+}
+
+/// Writes the value as a line of JSON with a single call to `write_all`, so
+/// that it's never interleaved with other writes.
+#[inline]
+fn write_line<T: serde::Serialize, W: Write>(
+    writer: &mut W,
+    value: &T,
+) -> io::Result<()> {
+    let mut line = serde_json::to_vec(value)?;
+    line.push(b'\n');
+    writer.write_all(&line)
+}
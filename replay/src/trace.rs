@@ -0,0 +1,447 @@
+use core::fmt;
+use core::ops::Range;
+use std::io::{self, BufRead};
+use std::panic::{self, AssertUnwindSafe};
+
+use cola::{
+    CrdtEdit,
+    Deletion,
+    Departure,
+    EncodedReplica,
+    Insertion,
+    IntegrityError,
+    Replica,
+    Text,
+};
+use serde::{Deserialize, Serialize};
+
+/// The value of the `kind` field in the header of a trace.
+pub(crate) const KIND: &str = "recorded";
+
+/// The first line of a trace.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Header {
+    pub(crate) kind: String,
+    pub(crate) start: EncodedReplica,
+}
+
+/// A call to one of the methods of a [`Replica`] that change it, together
+/// with its result.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Op {
+    /// A call to [`Replica::inserted`] or [`Replica::deleted`], as a
+    /// `(position, deleted, inserted)` patch followed by the returned edit.
+    /// Either `deleted` or `inserted` is zero.
+    Patch(usize, usize, usize, CrdtEdit),
+
+    /// A call to [`Replica::integrate_insertion`] or
+    /// [`Replica::try_integrate_insertion`].
+    IntegrateInsertion {
+        /// The integrated insertion.
+        insertion: Insertion,
+
+        /// The returned offset.
+        offset: Option<usize>,
+
+        /// Whether the insertion was rejected with an
+        /// [`InsertionError`](cola::InsertionError), e.g. because it collided
+        /// with one made by another replica with the same id.
+        #[serde(default)]
+        collision: bool,
+    },
+
+    /// A call to [`Replica::integrate_deletion`].
+    IntegrateDeletion {
+        /// The integrated deletion.
+        deletion: Deletion,
+
+        /// The returned ranges.
+        ranges: Vec<Range<usize>>,
+    },
+
+    /// A call to [`Replica::integrate_departure`].
+    IntegrateDeparture {
+        /// The integrated departure.
+        departure: Departure,
+
+        /// Whether the departure was integrated.
+        integrated: bool,
+    },
+
+    /// A call to [`Replica::backlogged_insertions`], after which the
+    /// iterator was exhausted.
+    BackloggedInsertions {
+        /// The insertions yielded by the iterator.
+        merged: Vec<(Text, usize)>,
+    },
+
+    /// A call to [`Replica::backlogged_deletions`], after which the iterator
+    /// was exhausted.
+    BackloggedDeletions {
+        /// The ranges yielded by the iterator.
+        merged: Vec<Vec<Range<usize>>>,
+    },
+}
+
+/// A trace recorded by a [`RecordingReplica`](crate::RecordingReplica).
+#[derive(Clone)]
+pub struct Trace {
+    start: EncodedReplica,
+    ops: Vec<Op>,
+}
+
+/// The first operation of a [`Trace`] where the replayed [`Replica`]
+/// diverged from the recorded one.
+#[derive(Debug, Clone)]
+pub enum Divergence {
+    /// The operation returned a different result than the recorded one.
+    Output {
+        /// The index of the operation in the trace.
+        index: usize,
+
+        /// The recorded operation.
+        recorded: Box<Op>,
+
+        /// The replayed operation.
+        replayed: Box<Op>,
+    },
+
+    /// The operation panicked.
+    Panic {
+        /// The index of the operation in the trace.
+        index: usize,
+
+        /// The panic message.
+        message: String,
+    },
+
+    /// The `Replica` failed its [integrity
+    /// check](Replica::check_integrity) after the operation.
+    Integrity {
+        /// The index of the operation in the trace.
+        index: usize,
+
+        /// The error returned by the integrity check.
+        error: IntegrityError,
+    },
+}
+
+impl Op {
+    #[inline]
+    pub(crate) fn deleted(range: Range<usize>, deletion: Deletion) -> Self {
+        Self::Patch(range.start, range.len(), 0, CrdtEdit::Deletion(deletion))
+    }
+
+    #[inline]
+    pub(crate) fn inserted(
+        offset: usize,
+        len: usize,
+        insertion: Insertion,
+    ) -> Self {
+        Self::Patch(offset, 0, len, CrdtEdit::Insertion(insertion))
+    }
+
+    /// Calls the method this operation was recorded from on the `Replica`,
+    /// returning the operation it'd record.
+    #[inline]
+    fn replay(&self, replica: &mut Replica) -> Self {
+        match self {
+            &Self::Patch(position, deleted, inserted, _) => {
+                if deleted > 0 {
+                    let range = position..position + deleted;
+                    let deletion = replica.deleted(range.clone());
+                    Self::deleted(range, deletion)
+                } else {
+                    let insertion = replica.inserted(position, inserted);
+                    Self::inserted(position, inserted, insertion)
+                }
+            },
+
+            Self::IntegrateInsertion { insertion, .. } => {
+                let result = replica.try_integrate_insertion(insertion);
+                Self::IntegrateInsertion {
+                    insertion: insertion.clone(),
+                    offset: result.clone().ok().flatten(),
+                    collision: result.is_err(),
+                }
+            },
+
+            Self::IntegrateDeletion { deletion, .. } => {
+                Self::IntegrateDeletion {
+                    deletion: deletion.clone(),
+                    ranges: replica.integrate_deletion(deletion),
+                }
+            },
+
+            Self::IntegrateDeparture { departure, .. } => {
+                Self::IntegrateDeparture {
+                    departure: departure.clone(),
+                    integrated: replica.integrate_departure(departure),
+                }
+            },
+
+            Self::BackloggedInsertions { .. } => Self::BackloggedInsertions {
+                merged: replica.backlogged_insertions().collect(),
+            },
+
+            Self::BackloggedDeletions { .. } => Self::BackloggedDeletions {
+                merged: replica.backlogged_deletions().collect(),
+            },
+        }
+    }
+}
+
+impl fmt::Display for Op {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let json = serde_json::to_string(self).map_err(|_| fmt::Error)?;
+        f.write_str(&json)
+    }
+}
+
+impl Trace {
+    /// Replays the operations of the trace on a fresh copy of the starting
+    /// `Replica` like [`replay`](Self::replay), except that its integrity is
+    /// only checked once at the end.
+    ///
+    /// If the check fails, the first operation after which it fails is
+    /// found by bisecting the trace, assuming that once a `Replica` is
+    /// corrupted it stays corrupted. This is a lot faster than checking it
+    /// after every operation on long traces.
+    #[inline]
+    pub fn bisect(&self) -> Result<Replica, Divergence> {
+        let mut replica = self.resume();
+
+        let mut divergence = None;
+
+        // The number of operations that were replayed without diverging.
+        let mut num_replayed = self.ops.len();
+
+        for (index, op) in self.ops.iter().enumerate() {
+            if let Err(err) = step(&mut replica, index, op) {
+                divergence = Some(err);
+                num_replayed = index;
+                break;
+            }
+        }
+
+        if divergence.is_some() {
+            replica = self.replayed(num_replayed);
+        }
+
+        let Err(error) = replica.check_integrity() else {
+            return match divergence {
+                Some(divergence) => Err(divergence),
+                None => Ok(replica),
+            };
+        };
+
+        // The replica is consistent after `lo` operations and corrupted
+        // after `hi`.
+        let (mut lo, mut hi, mut error) = (0, num_replayed, error);
+
+        while hi - lo > 1 {
+            let mid = lo + (hi - lo) / 2;
+
+            match self.replayed(mid).check_integrity() {
+                Ok(()) => lo = mid,
+
+                Err(err) => {
+                    hi = mid;
+                    error = err;
+                },
+            }
+        }
+
+        Err(Divergence::Integrity { index: hi.saturating_sub(1), error })
+    }
+
+    /// Returns the operations of the trace.
+    #[inline]
+    pub fn ops(&self) -> &[Op] {
+        &self.ops
+    }
+
+    /// Reads a trace.
+    ///
+    /// Returns an error with kind [`InvalidData`](io::ErrorKind::InvalidData)
+    /// if the trace is malformed or if the `Replica` it starts from can't be
+    /// decoded.
+    #[inline]
+    pub fn read<R: BufRead>(reader: R) -> io::Result<Self> {
+        let mut lines = reader.lines().enumerate();
+
+        let header = match lines.next() {
+            Some((_, line)) => parse_line::<Header>(&line?, 1)?,
+            None => return Err(invalid_data("the trace is empty")),
+        };
+
+        if header.kind != KIND {
+            return Err(invalid_data(format!(
+                "expected a trace of kind \"{KIND}\", got \"{}\"",
+                header.kind
+            )));
+        }
+
+        Replica::resume(&header.start).map_err(|err| {
+            invalid_data(format!(
+                "couldn't decode the starting replica: {err}"
+            ))
+        })?;
+
+        let mut ops = Vec::new();
+
+        let mut lines = lines.peekable();
+
+        while let Some((idx, line)) = lines.next() {
+            let line = line?;
+
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            match parse_line(&line, idx + 1) {
+                Ok(op) => ops.push(op),
+
+                // The last line is truncated if the process recording the
+                // trace was killed while writing it.
+                Err(_) if lines.peek().is_none() => break,
+
+                Err(err) => return Err(err),
+            }
+        }
+
+        Ok(Self { start: header.start, ops })
+    }
+
+    /// Replays the operations of the trace on a fresh copy of the starting
+    /// `Replica`, checking its integrity after every operation.
+    ///
+    /// Returns the replayed `Replica` if none of the operations diverged
+    /// from the recorded ones.
+    #[inline]
+    pub fn replay(&self) -> Result<Replica, Divergence> {
+        let mut replica = self.resume();
+
+        for (index, op) in self.ops.iter().enumerate() {
+            step(&mut replica, index, op)?;
+
+            replica
+                .check_integrity()
+                .map_err(|error| Divergence::Integrity { index, error })?;
+        }
+
+        Ok(replica)
+    }
+
+    /// Replays the first `num_ops` operations, which are known not to
+    /// diverge.
+    #[inline]
+    fn replayed(&self, num_ops: usize) -> Replica {
+        let mut replica = self.resume();
+
+        for op in &self.ops[..num_ops] {
+            let _ = op.replay(&mut replica);
+        }
+
+        replica
+    }
+
+    #[inline]
+    fn resume(&self) -> Replica {
+        Replica::resume(&self.start)
+            .expect("the starting replica was decoded when reading the trace")
+    }
+}
+
+impl fmt::Debug for Trace {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Trace").field("ops", &self.ops).finish_non_exhaustive()
+    }
+}
+
+impl Divergence {
+    /// Returns the index of the operation in the trace.
+    #[inline]
+    pub fn index(&self) -> usize {
+        match self {
+            Self::Output { index, .. }
+            | Self::Panic { index, .. }
+            | Self::Integrity { index, .. } => *index,
+        }
+    }
+}
+
+impl fmt::Display for Divergence {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Output { index, recorded, replayed } => write!(
+                f,
+                "operation {index} diverged\n  recorded: {recorded}\n  \
+                 replayed: {replayed}"
+            ),
+            Self::Panic { index, message } => {
+                write!(f, "operation {index} panicked: {message}")
+            },
+            Self::Integrity { index, error } => {
+                write!(
+                    f,
+                    "the replica is corrupted after operation {index}: \
+                     {error:?}"
+                )
+            },
+        }
+    }
+}
+
+impl std::error::Error for Divergence {}
+
+#[inline]
+fn invalid_data(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+#[inline]
+fn parse_line<T: for<'de> Deserialize<'de>>(
+    line: &str,
+    line_number: usize,
+) -> io::Result<T> {
+    serde_json::from_str(line)
+        .map_err(|err| invalid_data(format!("line {line_number}: {err}")))
+}
+
+/// Replays an operation, checking that it returns the recorded result.
+#[inline]
+fn step(
+    replica: &mut Replica,
+    index: usize,
+    op: &Op,
+) -> Result<(), Divergence> {
+    let replayed =
+        panic::catch_unwind(AssertUnwindSafe(|| op.replay(replica))).map_err(
+            |payload| Divergence::Panic {
+                index,
+                message: payload
+                    .downcast_ref::<String>()
+                    .cloned()
+                    .or_else(|| {
+                        payload.downcast_ref::<&str>().map(|&s| s.to_owned())
+                    })
+                    .unwrap_or_default(),
+            },
+        )?;
+
+    if replayed == *op {
+        Ok(())
+    } else {
+        Err(Divergence::Output {
+            index,
+            recorded: Box::new(op.clone()),
+            replayed: Box::new(replayed),
+        })
+    }
+}
@@ -0,0 +1,195 @@
+use std::io::{self, Write};
+
+use cola::Replica;
+use cola_replay::{Divergence, Op, RecordingReplica, Trace};
+
+/// Records a session where a second peer edits the document concurrently,
+/// with some of its edits being delivered out of order.
+fn record() -> Vec<u8> {
+    let mut peer1 =
+        RecordingReplica::new(Replica::new(1, 8), Vec::new()).unwrap();
+
+    let mut peer2 = Replica::new(1, 8).fork(2);
+
+    let _ = peer1.inserted(2, 3);
+
+    let ins1 = peer2.inserted(4, 2);
+    let ins2 = peer2.inserted(5, 1);
+    let del = peer2.deleted(3..6);
+
+    assert!(peer1.integrate_deletion(&del).is_empty());
+    assert_eq!(peer1.integrate_insertion(&ins2), None);
+    assert_eq!(peer1.integrate_insertion(&ins1), Some(7));
+    assert_eq!(peer1.backlogged_insertions().len(), 1);
+    assert_eq!(peer1.backlogged_deletions().len(), 1);
+
+    let _ = peer1.deleted(..2);
+
+    let (replica, bytes) = peer1.finish().unwrap();
+
+    assert_eq!(replica.len(), 9);
+
+    bytes
+}
+
+/// Replaces the operation at the given index of the trace.
+fn tamper(bytes: &[u8], index: usize, op: &Op) -> Vec<u8> {
+    let mut lines =
+        std::str::from_utf8(bytes).unwrap().lines().collect::<Vec<_>>();
+    let line = op.to_string();
+    lines[index + 1] = &line;
+    lines.join("\n").into_bytes()
+}
+
+#[test]
+fn replay_recorded() {
+    let trace = Trace::read(record().as_slice()).unwrap();
+
+    assert_eq!(trace.ops().len(), 7);
+
+    assert!(matches!(trace.ops()[0], Op::Patch(2, 0, 3, _)));
+    assert!(matches!(trace.ops()[6], Op::Patch(0, 2, 0, _)));
+
+    assert_eq!(trace.replay().unwrap().len(), 9);
+    assert_eq!(trace.bisect().unwrap().len(), 9);
+}
+
+#[test]
+fn replay_patch_format() {
+    let bytes = record();
+
+    let text = std::str::from_utf8(&bytes).unwrap();
+
+    let mut lines = text.lines();
+
+    assert!(lines
+        .next()
+        .unwrap()
+        .starts_with(r#"{"kind":"recorded","start":"#));
+
+    assert!(lines
+        .next()
+        .unwrap()
+        .starts_with(r#"{"patch":[2,0,3,{"Insertion":"#));
+}
+
+#[test]
+fn replay_divergent_output() {
+    let bytes = record();
+
+    let trace = Trace::read(bytes.as_slice()).unwrap();
+
+    let Op::IntegrateInsertion { insertion, .. } = trace.ops()[3].clone()
+    else {
+        panic!("expected an insertion");
+    };
+
+    let forged = Op::IntegrateInsertion {
+        insertion,
+        offset: Some(0),
+        collision: false,
+    };
+
+    let trace = Trace::read(tamper(&bytes, 3, &forged).as_slice()).unwrap();
+
+    for result in [trace.replay(), trace.bisect()] {
+        let Err(Divergence::Output { index, recorded, replayed }) = result
+        else {
+            panic!("expected the outputs to diverge");
+        };
+
+        assert_eq!(index, 3);
+        assert_eq!(*recorded, forged);
+        assert!(matches!(
+            *replayed,
+            Op::IntegrateInsertion { offset: Some(7), .. }
+        ));
+    }
+}
+
+#[test]
+fn replay_panic() {
+    let bytes = record();
+
+    let trace = Trace::read(bytes.as_slice()).unwrap();
+
+    let Op::Patch(.., edit) = trace.ops()[6].clone() else {
+        panic!("expected a local edit");
+    };
+
+    // Deleting past the end of the document.
+    let forged = Op::Patch(0, 100, 0, edit);
+
+    let trace = Trace::read(tamper(&bytes, 6, &forged).as_slice()).unwrap();
+
+    let Err(Divergence::Panic { index, .. }) = trace.replay() else {
+        panic!("expected the replay to panic");
+    };
+
+    assert_eq!(index, 6);
+}
+
+#[test]
+fn replay_truncated_trace() {
+    let mut bytes = record();
+
+    // The last line was only partially written.
+    bytes.truncate(bytes.len() - 10);
+
+    let trace = Trace::read(bytes.as_slice()).unwrap();
+
+    assert_eq!(trace.ops().len(), 6);
+
+    trace.replay().unwrap();
+}
+
+#[test]
+fn replay_invalid_trace() {
+    let bytes = record();
+
+    let mut lines =
+        std::str::from_utf8(&bytes).unwrap().lines().collect::<Vec<_>>();
+
+    lines[3] = "{}";
+
+    let err = Trace::read(lines.join("\n").as_bytes()).unwrap_err();
+
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert!(err.to_string().starts_with("line 4:"), "{err}");
+
+    let err = Trace::read(&b"{\"kind\":\"concurrent\"}"[..]).unwrap_err();
+
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn replay_write_error() {
+    struct FailingWriter(usize);
+
+    impl Write for FailingWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.0 == 0 {
+                return Err(io::Error::other("disk full"));
+            }
+            self.0 -= 1;
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let mut replica =
+        RecordingReplica::new(Replica::new(1, 0), FailingWriter(1)).unwrap();
+
+    // The edits are still applied even if they can't be recorded.
+    let _ = replica.inserted(0, 2);
+    let _ = replica.inserted(2, 2);
+
+    assert_eq!(replica.len(), 4);
+
+    let err = replica.finish().err().unwrap();
+
+    assert_eq!(err.to_string(), "disk full");
+}
@@ -0,0 +1,415 @@
+//! The documents hosted by the server, and how they're persisted to disk.
+//!
+//! Every document lives in its own directory, which contains two files:
+//!
+//! - `snapshot`: the [`Snapshot`] of the document as it was when the log was
+//!   last compacted;
+//! - `log`: the [`Record`]s of everything that happened to the document
+//!   since then, each made of the length of its contents as a little-endian
+//!   `u32`, the contents themselves and their CRC32C checksum as a
+//!   little-endian `u32`.
+//!
+//! This is the same layout used by cola's `ReplicaStore`, whose log is
+//! shared with the server, except that the server also has to persist the
+//! text of the document and the ids it has handed out.
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader};
+use std::path::PathBuf;
+
+use cola::{
+    Deletion,
+    EncodedDocument,
+    Insertion,
+    InsertionError,
+    Replica,
+    ReplicaId,
+    Text,
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::{record_log, ServerMessage};
+
+/// The name of the file containing the latest snapshot.
+const SNAPSHOT: &str = "snapshot";
+
+/// The name of the file a new snapshot is written to before atomically
+/// replacing the previous one.
+const SNAPSHOT_TMP: &str = "snapshot.tmp";
+
+/// The name of the file containing the log of the records appended since
+/// the latest snapshot.
+const LOG: &str = "log";
+
+/// The id of the server's own `Replica`, which only ever integrates the
+/// edits made by the peers and never edits the document itself.
+const SERVER_ID: ReplicaId = 1;
+
+/// The sending half of the channel the messages for a peer are written to.
+pub(crate) type Outbox = mpsc::UnboundedSender<Message>;
+
+/// A document hosted by the server.
+pub(crate) struct Document {
+    /// The server's `Replica`, which has integrated all the edits relayed
+    /// so far.
+    replica: Replica,
+
+    /// The text of the document.
+    text: Vec<u8>,
+
+    /// The contents of the insertions that are in the `Replica`'s backlog.
+    pending: HashMap<Text, String>,
+
+    /// The id that'll be handed to the next peer to join the document.
+    next_replica_id: ReplicaId,
+
+    /// The peers currently connected to the document.
+    peers: HashMap<ReplicaId, Outbox>,
+
+    /// The directory the document is persisted to.
+    dir: PathBuf,
+
+    /// The log of the records appended since the latest snapshot.
+    log: File,
+
+    /// The number of records in the log.
+    logged_records: usize,
+
+    /// The number of records after which the log is compacted.
+    compaction_interval: usize,
+
+    /// Whether the document was [`close`](Self::close)d.
+    is_closed: bool,
+}
+
+/// Something that happened to a document, stored in a record of its log.
+#[derive(Serialize, Deserialize)]
+pub(crate) enum Record {
+    /// A new peer joined the document and was handed the given id.
+    Joined(ReplicaId),
+
+    /// A peer inserted some text.
+    Inserted { insertion: Insertion, text: String },
+
+    /// A peer deleted some text.
+    Deleted(Deletion),
+}
+
+/// The whole state of a document, minus its peers.
+#[derive(Serialize, Deserialize)]
+struct Snapshot {
+    document: EncodedDocument,
+    pending: Vec<(Text, String)>,
+    next_replica_id: ReplicaId,
+}
+
+impl Document {
+    /// Appends a record to the log.
+    #[inline]
+    fn append(&mut self, record: &Record) -> io::Result<()> {
+        let contents = bincode::serialize(record).map_err(invalid_data)?;
+        record_log::append(&mut self.log, &contents)
+    }
+
+    /// Applies a record to the in-memory state of the document.
+    ///
+    /// If the `Replica` rejects an insertion the document is left untouched
+    /// and the error is returned.
+    #[inline]
+    fn apply(&mut self, record: Record) -> Result<(), InsertionError> {
+        match record {
+            Record::Joined(replica_id) => {
+                self.next_replica_id =
+                    self.next_replica_id.max(replica_id + 1);
+            },
+
+            Record::Inserted { insertion, text } => {
+                if let Some(offset) =
+                    self.replica.try_integrate_insertion(&insertion)?
+                {
+                    self.text.splice(offset..offset, text.into_bytes());
+                } else if self.replica.has_backlogged_insertion(&insertion) {
+                    self.pending.insert(insertion.text().clone(), text);
+                }
+            },
+
+            Record::Deleted(deletion) => {
+                let ranges = self.replica.integrate_deletion(&deletion);
+                self.delete(ranges);
+            },
+        }
+
+        // Integrating the edit may have unblocked some backlogged edits,
+        // which may in turn unblock others.
+        loop {
+            let insertions =
+                self.replica.backlogged_insertions().collect::<Vec<_>>();
+
+            let deletions =
+                self.replica.backlogged_deletions().collect::<Vec<_>>();
+
+            if insertions.is_empty() && deletions.is_empty() {
+                break;
+            }
+
+            for (text, offset) in insertions {
+                let contents = self.pending.remove(&text).unwrap_or_default();
+                self.text.splice(offset..offset, contents.into_bytes());
+            }
+
+            for ranges in deletions {
+                self.delete(ranges);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes a new snapshot of the document and clears the log.
+    #[inline]
+    pub(crate) fn compact(&mut self) -> io::Result<()> {
+        let snapshot = Snapshot {
+            document: self.replica.encode_document(self.text()?),
+            pending: self.pending.clone().into_iter().collect(),
+            next_replica_id: self.next_replica_id,
+        };
+
+        record_log::replace(&self.dir, SNAPSHOT, SNAPSHOT_TMP, |writer| {
+            bincode::serialize_into(writer, &snapshot).map_err(invalid_data)
+        })?;
+
+        // If we crash before the log is cleared the edits it contains will be
+        // replayed on top of a snapshot that already contains them, which is
+        // fine since integrating the same edit twice is a no-op.
+        record_log::clear(&mut self.log)?;
+        self.logged_records = 0;
+
+        Ok(())
+    }
+
+    /// Disconnects all the peers, sending them the given reason, after
+    /// which the document can't be joined or edited anymore.
+    #[inline]
+    pub(crate) fn close(&mut self, reason: &str) {
+        let error =
+            Message::binary(ServerMessage::Error(reason.to_owned()).encode());
+
+        for (_, outbox) in self.peers.drain() {
+            let _ = outbox.send(error.clone());
+        }
+
+        self.is_closed = true;
+    }
+
+    #[inline]
+    fn delete(&mut self, ranges: Vec<core::ops::Range<usize>>) {
+        for range in ranges.into_iter().rev() {
+            self.text.drain(range);
+        }
+    }
+
+    /// Integrates an edit made by the given peer, appends it to the log and
+    /// relays it to all the other peers.
+    ///
+    /// The edit is integrated before it's logged, so that an edit which is
+    /// rejected or whose integration panics never makes it to the log. A
+    /// rejected edit leaves the document untouched and is returned as the
+    /// inner error, while if this returns an I/O error or panics the
+    /// in-memory state of the document can be ahead of its log, and the
+    /// document has to be closed.
+    #[inline]
+    pub(crate) fn edit(
+        &mut self,
+        from: ReplicaId,
+        record: Record,
+    ) -> io::Result<Result<(), InsertionError>> {
+        let contents = bincode::serialize(&record).map_err(invalid_data)?;
+
+        let message = match &record {
+            Record::Inserted { insertion, text } => ServerMessage::Inserted {
+                insertion: insertion.clone(),
+                text: text.clone(),
+            },
+            Record::Deleted(deletion) => {
+                ServerMessage::Deleted(deletion.clone())
+            },
+            Record::Joined(_) => unreachable!("joining is not an edit"),
+        };
+
+        if let Err(err) = self.apply(record) {
+            return Ok(Err(err));
+        }
+
+        record_log::append(&mut self.log, &contents)?;
+
+        self.logged()?;
+
+        let message = Message::binary(message.encode());
+
+        for (&replica_id, outbox) in &self.peers {
+            if replica_id != from {
+                // The peer's connection is closing, and it'll leave the
+                // document shortly.
+                let _ = outbox.send(message.clone());
+            }
+        }
+
+        Ok(Ok(()))
+    }
+
+    /// Returns `true` if the document was [`close`](Self::close)d.
+    #[inline]
+    pub(crate) fn is_closed(&self) -> bool {
+        self.is_closed
+    }
+
+    /// Returns `true` if no peers are connected to the document.
+    #[inline]
+    pub(crate) fn has_peers(&self) -> bool {
+        !self.peers.is_empty()
+    }
+
+    /// Hands a new id to a peer joining the document, sending it the current
+    /// state of the document via the outbox.
+    ///
+    /// From then on, the edits made by the other peers are also sent to the
+    /// outbox until the peer [`leave`](Self::leave)s.
+    #[inline]
+    pub(crate) fn join(&mut self, outbox: Outbox) -> io::Result<ReplicaId> {
+        let replica_id = self.next_replica_id;
+
+        // The id is logged before it's handed out, so it's never handed out
+        // again, even after a crash.
+        self.append(&Record::Joined(replica_id))?;
+        self.apply(Record::Joined(replica_id)).map_err(invalid_data)?;
+        self.logged()?;
+
+        let joined = ServerMessage::Joined {
+            replica_id,
+            document: self.replica.encode_document(self.text()?),
+        };
+
+        let _ = outbox.send(Message::binary(joined.encode()));
+
+        self.peers.insert(replica_id, outbox);
+
+        Ok(replica_id)
+    }
+
+    /// Removes the peer with the given id from the document.
+    #[inline]
+    pub(crate) fn leave(&mut self, replica_id: ReplicaId) {
+        self.peers.remove(&replica_id);
+    }
+
+    /// Called after a record has been both appended to the log and applied,
+    /// compacting the log if it has grown past the compaction interval.
+    #[inline]
+    fn logged(&mut self) -> io::Result<()> {
+        self.logged_records += 1;
+
+        if self.logged_records >= self.compaction_interval {
+            self.compact()?;
+        }
+
+        Ok(())
+    }
+
+    /// Opens the document persisted to the given directory, creating an
+    /// empty one if the directory doesn't contain a document yet.
+    #[inline]
+    pub(crate) fn open(
+        dir: PathBuf,
+        compaction_interval: usize,
+    ) -> io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+
+        // A leftover of a crash while writing a snapshot.
+        record_log::remove_leftover(&dir, SNAPSHOT_TMP)?;
+
+        let snapshot = match File::open(dir.join(SNAPSHOT)) {
+            Ok(file) => Some(
+                bincode::deserialize_from::<_, Snapshot>(BufReader::new(file))
+                    .map_err(invalid_data)?,
+            ),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+            Err(err) => return Err(err),
+        };
+
+        let log = record_log::open(&dir.join(LOG))?;
+
+        let mut document = Self {
+            replica: Replica::new(SERVER_ID, 0),
+            text: Vec::new(),
+            pending: HashMap::new(),
+            next_replica_id: SERVER_ID + 1,
+            peers: HashMap::new(),
+            dir,
+            log,
+            logged_records: 0,
+            compaction_interval,
+            is_closed: false,
+        };
+
+        match snapshot {
+            Some(snapshot) => {
+                // The server's replica never edits the document, so it's
+                // fine to decode it with the same id it was encoded with.
+                document.replica =
+                    Replica::decode_document(SERVER_ID, &snapshot.document)
+                        .map_err(invalid_data)?;
+                document.text = snapshot.document.text().as_bytes().to_vec();
+                document.pending = snapshot.pending.into_iter().collect();
+                document.next_replica_id = snapshot.next_replica_id;
+            },
+
+            None => document.compact()?,
+        }
+
+        document.logged_records = document.replay()?;
+
+        Ok(document)
+    }
+
+    /// Replays the records in the log, returning the number of records that
+    /// were replayed.
+    #[inline]
+    fn replay(&mut self) -> io::Result<usize> {
+        let mut records = Vec::new();
+
+        let replayed = record_log::replay(&mut self.log, |contents| {
+            // The record was written in full, so if it can't be decoded it
+            // was written by a newer version of the server and we can't go
+            // any further.
+            records.push(
+                bincode::deserialize::<Record>(contents)
+                    .map_err(invalid_data)?,
+            );
+            Ok(())
+        })?;
+
+        // Only the records that were applied successfully are logged, so a
+        // record that's rejected now means that the log is corrupted.
+        for record in records {
+            self.apply(record).map_err(invalid_data)?;
+        }
+
+        Ok(replayed)
+    }
+
+    #[inline]
+    fn text(&self) -> io::Result<&str> {
+        core::str::from_utf8(&self.text).map_err(invalid_data)
+    }
+}
+
+#[inline]
+fn invalid_data<E>(err: E) -> io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
@@ -0,0 +1,436 @@
+//! A WebSocket relay for collaborative editing sessions using cola.
+//!
+//! The server hosts any number of documents, each identified by a string id.
+//! A client opens a WebSocket connection and sends a
+//! [`ClientMessage::Join`], to which the server replies with a
+//! [`ServerMessage::Joined`] containing a [`ReplicaId`](cola::ReplicaId)
+//! that's unique within the document and the current state of the document
+//! as an [`EncodedDocument`](cola::EncodedDocument). From then on, every
+//! edit sent by the client is relayed to all the other peers of the
+//! document, and vice versa.
+//!
+//! The server keeps its own [`Replica`](cola::Replica) of every document,
+//! together with its text. Every edit is appended to a log on disk before
+//! it's relayed, and once the log grows past the
+//! [compaction interval](Server::set_compaction_interval) (or the last peer
+//! leaves the document) it's compacted into a new snapshot of the document.
+//!
+//! The server trusts its peers to send well-formed edits, and only checks
+//! that the insertions they send were made by them and match the length of
+//! their text. An insertion that the server's `Replica` rejects (e.g.
+//! because of a [`ReplicaIdCollision`](cola::ReplicaIdCollision)) is neither
+//! logged nor relayed, and the peer that sent it is disconnected. If
+//! integrating an edit fails in any other way, the document is closed and
+//! all its peers are disconnected. The next peer to join it reopens it from
+//! disk, without the edit.
+//!
+//! Reading and writing to disk happens on Tokio's blocking thread pool, so a
+//! slow disk never stalls the connections of the other documents.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # async fn run() -> std::io::Result<()> {
+//! let server = cola_server::Server::bind("127.0.0.1:8080", "data").await?;
+//! server.run().await
+//! # }
+//! ```
+
+#![deny(missing_docs)]
+
+mod document;
+mod protocol;
+/// The log of checksummed records used by cola's `ReplicaStore`.
+#[path = "../../src/record_log.rs"]
+mod record_log;
+
+use std::collections::HashMap;
+use std::io;
+use std::net::SocketAddr;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use futures_util::{SinkExt, StreamExt};
+use tokio::net::{TcpListener, TcpStream, ToSocketAddrs};
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::document::{Document, Record};
+pub use crate::protocol::{ClientMessage, ServerMessage, MAX_DOCUMENT_ID_LEN};
+
+/// The number of records appended to a document's log before it's
+/// compacted, unless changed via
+/// [`set_compaction_interval`](Server::set_compaction_interval).
+const DEFAULT_COMPACTION_INTERVAL: usize = 1024;
+
+/// A relay server listening for WebSocket connections.
+pub struct Server {
+    listener: TcpListener,
+    data_dir: PathBuf,
+    compaction_interval: usize,
+}
+
+impl core::fmt::Debug for Server {
+    #[inline]
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Server")
+            .field("local_addr", &self.listener.local_addr())
+            .field("data_dir", &self.data_dir)
+            .field("compaction_interval", &self.compaction_interval)
+            .finish()
+    }
+}
+
+impl Server {
+    /// Creates a new `Server` listening on the given address, which persists
+    /// its documents to subdirectories of the given directory.
+    #[inline]
+    pub async fn bind(
+        addr: impl ToSocketAddrs,
+        data_dir: impl Into<PathBuf>,
+    ) -> io::Result<Self> {
+        Ok(Self {
+            listener: TcpListener::bind(addr).await?,
+            data_dir: data_dir.into(),
+            compaction_interval: DEFAULT_COMPACTION_INTERVAL,
+        })
+    }
+
+    /// Returns the address the server is listening on.
+    #[inline]
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Accepts connections until an error occurs while listening for them.
+    ///
+    /// Every connection is handled by its own task, so this has to be
+    /// called within a Tokio runtime.
+    #[inline]
+    pub async fn run(self) -> io::Result<()> {
+        let state = Arc::new(State {
+            data_dir: self.data_dir,
+            compaction_interval: self.compaction_interval,
+            documents: Mutex::new(HashMap::new()),
+        });
+
+        loop {
+            let (stream, _) = self.listener.accept().await?;
+            tokio::spawn(handle_connection(Arc::clone(&state), stream));
+        }
+    }
+
+    /// Sets the number of records appended to a document's log after which
+    /// it's compacted into a new snapshot.
+    ///
+    /// Larger intervals mean fewer (expensive) snapshots, at the cost of
+    /// having to replay a longer log when the document is reopened.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the interval is zero.
+    #[track_caller]
+    #[inline]
+    pub fn set_compaction_interval(&mut self, interval: usize) {
+        assert!(interval > 0, "the compaction interval must be positive");
+        self.compaction_interval = interval;
+    }
+}
+
+/// The documents that are currently open, keyed by their id.
+type Documents = HashMap<String, Arc<Mutex<Document>>>;
+
+/// The state shared by all the connections.
+struct State {
+    data_dir: PathBuf,
+    compaction_interval: usize,
+    documents: Mutex<Documents>,
+}
+
+impl State {
+    /// Returns the documents that are currently open.
+    ///
+    /// The map is only ever changed by single insertions and removals, so
+    /// it's still valid if a thread panicked while holding its lock.
+    #[inline]
+    fn documents(&self) -> MutexGuard<'_, Documents> {
+        self.documents.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Applies an edit made by the given peer to the document, closing it if
+    /// that fails.
+    ///
+    /// An edit rejected by the document's `Replica` leaves the document
+    /// untouched, so it's returned as the inner error without closing it.
+    #[inline]
+    fn edit(
+        &self,
+        id: &str,
+        document: &Arc<Mutex<Document>>,
+        from: cola::ReplicaId,
+        record: Record,
+    ) -> io::Result<Result<(), cola::InsertionError>> {
+        let mut guard = lock(document);
+
+        if guard.is_closed() {
+            return Err(closed());
+        }
+
+        let edited = catch_panic(|| guard.edit(from, record));
+
+        if let Err(err) = &edited {
+            // The document may only contain part of the edit, or an edit
+            // that wasn't logged, so it has to be reopened from disk.
+            guard.close(&format!("the document was closed: {err}"));
+
+            drop(guard);
+
+            remove(&mut self.documents(), id, document);
+        }
+
+        edited
+    }
+
+    /// Joins the document with the given id, opening it if none of its peers
+    /// are currently connected.
+    #[inline]
+    fn join(
+        &self,
+        id: &str,
+        outbox: document::Outbox,
+    ) -> io::Result<(Arc<Mutex<Document>>, cola::ReplicaId)> {
+        let documents = &mut *self.documents();
+
+        let document = match documents.get(id) {
+            Some(document) if !lock(document).is_closed() => {
+                Arc::clone(document)
+            },
+            _ => {
+                let document = catch_panic(|| {
+                    Document::open(
+                        self.data_dir.join(id),
+                        self.compaction_interval,
+                    )
+                })?;
+                Arc::new(Mutex::new(document))
+            },
+        };
+
+        let replica_id = lock(&document).join(outbox)?;
+
+        documents.insert(id.to_owned(), Arc::clone(&document));
+
+        Ok((document, replica_id))
+    }
+
+    /// Leaves the document with the given id, compacting and closing it if
+    /// it was its last peer.
+    #[inline]
+    fn leave(
+        &self,
+        id: &str,
+        document: &Arc<Mutex<Document>>,
+        replica_id: cola::ReplicaId,
+    ) -> io::Result<()> {
+        let documents = &mut *self.documents();
+
+        let document_ref = &mut *lock(document);
+
+        if document_ref.is_closed() {
+            remove(documents, id, document);
+            return Ok(());
+        }
+
+        document_ref.leave(replica_id);
+
+        if document_ref.has_peers() {
+            return Ok(());
+        }
+
+        let compacted = document_ref.compact();
+
+        remove(documents, id, document);
+
+        compacted
+    }
+}
+
+/// Runs a closure that does blocking disk I/O on Tokio's blocking thread
+/// pool, turning a panic into an error.
+#[inline]
+async fn blocking<T, F>(f: F) -> io::Result<T>
+where
+    F: FnOnce() -> io::Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    tokio::task::spawn_blocking(f).await.unwrap_or_else(|_| Err(panicked()))
+}
+
+/// Calls the closure, turning a panic into an error.
+#[inline]
+fn catch_panic<T>(f: impl FnOnce() -> io::Result<T>) -> io::Result<T> {
+    panic::catch_unwind(AssertUnwindSafe(f))
+        .unwrap_or_else(|_| Err(panicked()))
+}
+
+#[inline]
+fn closed() -> io::Error {
+    io::Error::other("the document was closed")
+}
+
+/// Locks the document, closing it if a thread panicked while holding its
+/// lock since it could've been left in an inconsistent state.
+#[inline]
+fn lock(document: &Mutex<Document>) -> MutexGuard<'_, Document> {
+    document.lock().unwrap_or_else(|err| {
+        let mut document = err.into_inner();
+        if !document.is_closed() {
+            document.close(&panicked().to_string());
+        }
+        document
+    })
+}
+
+#[inline]
+fn panicked() -> io::Error {
+    io::Error::other("the server panicked")
+}
+
+/// Removes the document from the open ones, unless it's already been
+/// replaced by a new instance opened from disk.
+#[inline]
+fn remove(
+    documents: &mut Documents,
+    id: &str,
+    document: &Arc<Mutex<Document>>,
+) {
+    if documents.get(id).is_some_and(|open| Arc::ptr_eq(open, document)) {
+        documents.remove(id);
+    }
+}
+
+/// Handles a connection from its WebSocket handshake until it's closed.
+#[inline]
+async fn handle_connection(state: Arc<State>, stream: TcpStream) {
+    let Ok(ws) = tokio_tungstenite::accept_async(stream).await else {
+        return;
+    };
+
+    let (mut sink, mut stream) = ws.split();
+
+    let (outbox, mut inbox) = mpsc::unbounded_channel();
+
+    // The messages for the peer are written by their own task, so that a
+    // slow peer never blocks the others.
+    let writer = tokio::spawn(async move {
+        while let Some(message) = inbox.recv().await {
+            if sink.send(message).await.is_err() {
+                return;
+            }
+        }
+        let _ = sink.close().await;
+    });
+
+    let Some(ClientMessage::Join { document: id }) = next(&mut stream).await
+    else {
+        reject(&outbox, "expected a Join message");
+        return;
+    };
+
+    if !protocol::is_valid_document_id(&id) {
+        reject(&outbox, "invalid document id");
+        return;
+    }
+
+    let id: Arc<str> = id.into();
+
+    let joined = {
+        let (state, id, outbox) =
+            (Arc::clone(&state), Arc::clone(&id), outbox.clone());
+        blocking(move || state.join(&id, outbox)).await
+    };
+
+    let (document, replica_id) = match joined {
+        Ok(joined) => joined,
+        Err(err) => {
+            reject(&outbox, &format!("couldn't open the document: {err}"));
+            return;
+        },
+    };
+
+    while let Some(message) = next(&mut stream).await {
+        let record = match message {
+            ClientMessage::Inserted { insertion, text } => {
+                if insertion.text().inserted_by() != replica_id {
+                    reject(&outbox, "the insertion was made by another peer");
+                    break;
+                }
+
+                if insertion.text().temporal_range().len() != text.len() {
+                    reject(&outbox, "the insertion doesn't match its text");
+                    break;
+                }
+
+                Record::Inserted { insertion, text }
+            },
+
+            ClientMessage::Deleted(deletion) => Record::Deleted(deletion),
+
+            ClientMessage::Join { .. } => {
+                reject(&outbox, "already joined a document");
+                break;
+            },
+        };
+
+        let edited = {
+            let (state, id, document) =
+                (Arc::clone(&state), Arc::clone(&id), Arc::clone(&document));
+            blocking(move || state.edit(&id, &document, replica_id, record))
+                .await
+        };
+
+        match edited {
+            Ok(Ok(())) => {},
+
+            Ok(Err(err)) => {
+                reject(&outbox, &format!("the edit was rejected: {err}"));
+                break;
+            },
+
+            // The document was closed, and its peers were told why.
+            Err(_) => break,
+        }
+    }
+
+    // There's no one left to report an error to.
+    let _ = blocking(move || state.leave(&id, &document, replica_id)).await;
+
+    drop(outbox);
+
+    let _ = writer.await;
+}
+
+/// Returns the next message sent by the client, or `None` if the connection
+/// was closed or the client sent something that's not a `ClientMessage`.
+#[inline]
+async fn next<S>(stream: &mut S) -> Option<ClientMessage>
+where
+    S: StreamExt<Item = tokio_tungstenite::tungstenite::Result<Message>>
+        + Unpin,
+{
+    loop {
+        match stream.next().await?.ok()? {
+            Message::Binary(bytes) => return ClientMessage::decode(&bytes),
+            Message::Close(_) => return None,
+            _ => continue,
+        }
+    }
+}
+
+/// Sends an error to the client, after which the connection is closed.
+#[inline]
+fn reject(outbox: &document::Outbox, reason: &str) {
+    let error = ServerMessage::Error(reason.to_owned());
+    let _ = outbox.send(Message::binary(error.encode()));
+}
@@ -0,0 +1,37 @@
+use std::io;
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
+
+use clap::Parser;
+use cola_server::Server;
+
+/// A WebSocket relay for collaborative editing sessions using cola.
+#[derive(Parser)]
+#[command(version)]
+struct Args {
+    /// The address to listen on.
+    #[arg(long, default_value = "127.0.0.1:8080")]
+    addr: String,
+
+    /// The directory the documents are persisted to.
+    #[arg(long, default_value = "cola-data")]
+    data_dir: PathBuf,
+
+    /// The number of edits after which a document's log is compacted into a
+    /// new snapshot.
+    #[arg(long, default_value = "1024")]
+    compaction_interval: NonZeroUsize,
+}
+
+#[tokio::main]
+async fn main() -> io::Result<()> {
+    let args = Args::parse();
+
+    let mut server = Server::bind(&args.addr, args.data_dir).await?;
+
+    server.set_compaction_interval(args.compaction_interval.get());
+
+    eprintln!("listening on {}", server.local_addr()?);
+
+    server.run().await
+}
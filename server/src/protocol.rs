@@ -0,0 +1,138 @@
+//! The messages exchanged by the server and its clients.
+//!
+//! Every message is sent in its own binary WebSocket frame, encoded with
+//! [`bincode`].
+
+use cola::{Deletion, EncodedDocument, Insertion, ReplicaId};
+use serde::{Deserialize, Serialize};
+
+/// A message sent by a client to the server.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ClientMessage {
+    /// Joins the document with the given id, creating it if it doesn't
+    /// exist yet.
+    ///
+    /// This has to be the first message sent on a new connection, and the
+    /// server replies to it with a [`ServerMessage::Joined`].
+    Join {
+        /// The id of the document. It can only contain ASCII letters,
+        /// digits, `-` and `_`, and has to be at most
+        /// [`MAX_DOCUMENT_ID_LEN`] bytes long.
+        document: String,
+    },
+
+    /// An insertion made by the client, together with the text that was
+    /// inserted.
+    Inserted {
+        /// The insertion.
+        insertion: Insertion,
+
+        /// The inserted text.
+        text: String,
+    },
+
+    /// A deletion made by the client.
+    Deleted(Deletion),
+}
+
+/// A message sent by the server to a client.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub enum ServerMessage {
+    /// The reply to a [`ClientMessage::Join`].
+    ///
+    /// The client can create its `Replica` by passing the `replica_id` and
+    /// the `document` to
+    /// [`Replica::decode_document`](cola::Replica::decode_document). From
+    /// then on it'll receive all the edits made by the other peers of the
+    /// document.
+    Joined {
+        /// The id assigned to the client, which is never assigned to any
+        /// other peer of the same document.
+        replica_id: ReplicaId,
+
+        /// The current state of the document.
+        document: EncodedDocument,
+    },
+
+    /// An insertion made by another peer, together with the text that was
+    /// inserted.
+    Inserted {
+        /// The insertion.
+        insertion: Insertion,
+
+        /// The inserted text.
+        text: String,
+    },
+
+    /// A deletion made by another peer.
+    Deleted(Deletion),
+
+    /// The client sent an invalid message. The server closes the connection
+    /// right after sending this.
+    Error(String),
+}
+
+impl core::fmt::Debug for ServerMessage {
+    #[inline]
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Joined { replica_id, document } => f
+                .debug_struct("Joined")
+                .field("replica_id", replica_id)
+                .field("text", &document.text())
+                .finish_non_exhaustive(),
+            Self::Inserted { insertion, text } => f
+                .debug_struct("Inserted")
+                .field("insertion", insertion)
+                .field("text", text)
+                .finish(),
+            Self::Deleted(deletion) => {
+                f.debug_tuple("Deleted").field(deletion).finish()
+            },
+            Self::Error(reason) => {
+                f.debug_tuple("Error").field(reason).finish()
+            },
+        }
+    }
+}
+
+/// The maximum length of a document id.
+pub const MAX_DOCUMENT_ID_LEN: usize = 64;
+
+impl ClientMessage {
+    /// Decodes a `ClientMessage` from the payload of a binary frame.
+    #[inline]
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        bincode::deserialize(bytes).ok()
+    }
+
+    /// Encodes the `ClientMessage` into the payload of a binary frame.
+    #[inline]
+    pub fn encode(&self) -> Vec<u8> {
+        bincode::serialize(self).expect("serializing never fails")
+    }
+}
+
+impl ServerMessage {
+    /// Decodes a `ServerMessage` from the payload of a binary frame.
+    #[inline]
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        bincode::deserialize(bytes).ok()
+    }
+
+    /// Encodes the `ServerMessage` into the payload of a binary frame.
+    #[inline]
+    pub fn encode(&self) -> Vec<u8> {
+        bincode::serialize(self).expect("serializing never fails")
+    }
+}
+
+/// Returns `true` if the given string is a valid document id.
+#[inline]
+pub(crate) fn is_valid_document_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_DOCUMENT_ID_LEN
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+}
@@ -0,0 +1,432 @@
+use core::ops::Range;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::time::Duration;
+
+use cola::Replica;
+use cola_server::{ClientMessage, Server, ServerMessage};
+use futures_util::{SinkExt, StreamExt};
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+/// Starts a server listening on a random port of the loopback interface.
+async fn start(data_dir: &Path, compaction_interval: usize) -> SocketAddr {
+    let mut server = Server::bind("127.0.0.1:0", data_dir).await.unwrap();
+    server.set_compaction_interval(compaction_interval);
+    let addr = server.local_addr().unwrap();
+    tokio::spawn(server.run());
+    addr
+}
+
+fn log_len(data_dir: &Path, document: &str) -> u64 {
+    fs::metadata(data_dir.join(document).join("log")).unwrap().len()
+}
+
+struct Client {
+    ws: WebSocketStream<MaybeTlsStream<TcpStream>>,
+    replica: Replica,
+    buffer: String,
+}
+
+impl Client {
+    async fn connect(
+        addr: SocketAddr,
+    ) -> WebSocketStream<MaybeTlsStream<TcpStream>> {
+        let (ws, _) = tokio_tungstenite::connect_async(format!("ws://{addr}"))
+            .await
+            .unwrap();
+        ws
+    }
+
+    async fn join(addr: SocketAddr, document: &str) -> Self {
+        let mut ws = Self::connect(addr).await;
+
+        send(&mut ws, ClientMessage::Join { document: document.to_owned() })
+            .await;
+
+        let Some(ServerMessage::Joined { replica_id, document }) =
+            recv(&mut ws).await
+        else {
+            panic!("expected a Joined message");
+        };
+
+        let replica = Replica::decode_document(replica_id, &document).unwrap();
+
+        Self { ws, replica, buffer: document.text().to_owned() }
+    }
+
+    async fn delete(&mut self, range: Range<usize>) {
+        self.buffer.replace_range(range.clone(), "");
+        let deletion = self.replica.deleted(range);
+        send(&mut self.ws, ClientMessage::Deleted(deletion)).await;
+    }
+
+    async fn insert(&mut self, offset: usize, text: &str) {
+        self.buffer.insert_str(offset, text);
+        let insertion = self.replica.inserted(offset, text.len());
+        let text = text.to_owned();
+        send(&mut self.ws, ClientMessage::Inserted { insertion, text }).await;
+    }
+
+    /// Receives the next edit relayed by the server and applies it.
+    async fn receive(&mut self) {
+        match recv(&mut self.ws).await {
+            Some(ServerMessage::Inserted { insertion, text }) => {
+                let offset =
+                    self.replica.integrate_insertion(&insertion).unwrap();
+                self.buffer.insert_str(offset, &text);
+            },
+
+            Some(ServerMessage::Deleted(deletion)) => {
+                let ranges = self.replica.integrate_deletion(&deletion);
+                for range in ranges.into_iter().rev() {
+                    self.buffer.replace_range(range, "");
+                }
+            },
+
+            other => panic!("expected an edit, got {other:?}"),
+        }
+    }
+}
+
+async fn send(
+    ws: &mut WebSocketStream<MaybeTlsStream<TcpStream>>,
+    message: ClientMessage,
+) {
+    ws.send(Message::binary(message.encode())).await.unwrap();
+}
+
+/// Returns the next message sent by the server, or `None` if it closed the
+/// connection.
+async fn recv(
+    ws: &mut WebSocketStream<MaybeTlsStream<TcpStream>>,
+) -> Option<ServerMessage> {
+    loop {
+        match ws.next().await?.ok()? {
+            Message::Binary(bytes) => return ServerMessage::decode(&bytes),
+            Message::Close(_) => return None,
+            _ => continue,
+        }
+    }
+}
+
+#[tokio::test]
+async fn relay_concurrent_edits() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let addr = start(dir.path(), 1024).await;
+
+    let mut client1 = Client::join(addr, "doc").await;
+    let mut client2 = Client::join(addr, "doc").await;
+
+    client1.insert(0, "Hello").await;
+    client2.receive().await;
+
+    // Concurrent edits.
+    client1.insert(5, " world").await;
+    client2.delete(1..4).await;
+    client2.insert(1, "ipp").await;
+
+    client1.receive().await;
+    client1.receive().await;
+    client2.receive().await;
+
+    assert_eq!(client1.buffer, client2.buffer);
+    assert_eq!(client1.buffer, "Hippo world");
+}
+
+#[tokio::test]
+async fn relay_unique_replica_ids() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let addr = start(dir.path(), 1024).await;
+
+    let mut ids = Vec::new();
+
+    for _ in 0..5 {
+        let client = Client::join(addr, "doc").await;
+        ids.push(client.replica.id());
+    }
+
+    ids.sort_unstable();
+    ids.dedup();
+
+    assert_eq!(ids.len(), 5);
+
+    // Every document hands out its own ids.
+    let other = Client::join(addr, "other-doc").await;
+
+    assert_eq!(other.replica.id(), ids[0]);
+}
+
+#[tokio::test]
+async fn relay_late_joiner() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let addr = start(dir.path(), 1024).await;
+
+    let mut client1 = Client::join(addr, "doc").await;
+    let mut client2 = Client::join(addr, "doc").await;
+
+    client1.insert(0, "Hello world").await;
+    client2.receive().await;
+    client2.delete(5..11).await;
+    client1.receive().await;
+
+    let mut client3 = Client::join(addr, "doc").await;
+
+    assert_eq!(client3.buffer, "Hello");
+
+    client3.insert(5, "!").await;
+    client1.receive().await;
+    client2.receive().await;
+
+    assert_eq!(client1.buffer, "Hello!");
+    assert_eq!(client2.buffer, "Hello!");
+}
+
+/// Checks that the log is compacted into a snapshot after the given number
+/// of records.
+#[tokio::test]
+async fn relay_compaction_interval() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let addr = start(dir.path(), 3).await;
+
+    let mut client1 = Client::join(addr, "doc").await;
+    let mut client2 = Client::join(addr, "doc").await;
+
+    // The two joins and the insertion fill the log, which is then compacted.
+    client1.insert(0, "abc").await;
+    client2.receive().await;
+
+    assert_eq!(log_len(dir.path(), "doc"), 0);
+
+    client1.insert(3, "def").await;
+    client2.receive().await;
+
+    assert!(log_len(dir.path(), "doc") > 0);
+}
+
+/// Checks that the document is compacted when its last peer leaves, and
+/// that it's loaded back from its snapshot by a restarted server.
+#[tokio::test]
+async fn relay_restart() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let addr = start(dir.path(), 1024).await;
+
+    let mut client1 = Client::join(addr, "doc").await;
+    let mut client2 = Client::join(addr, "doc").await;
+
+    client1.insert(0, "Hello").await;
+    client2.receive().await;
+
+    let ids = [client1.replica.id(), client2.replica.id()];
+
+    drop(client1);
+    drop(client2);
+
+    while log_len(dir.path(), "doc") > 0 {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    let addr = start(dir.path(), 1024).await;
+
+    let client3 = Client::join(addr, "doc").await;
+
+    assert_eq!(client3.buffer, "Hello");
+
+    assert!(!ids.contains(&client3.replica.id()));
+}
+
+/// Simulates a crash of the server by copying its directory while the peers
+/// are still connected, so the edits are only stored in the log.
+#[tokio::test]
+async fn relay_recover_from_log() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let addr = start(dir.path(), 1024).await;
+
+    let mut client1 = Client::join(addr, "doc").await;
+    let mut client2 = Client::join(addr, "doc").await;
+
+    client1.insert(0, "Hello").await;
+    client2.receive().await;
+    client2.insert(5, " world").await;
+    client1.receive().await;
+    client1.delete(0..1).await;
+    client2.receive().await;
+
+    let crashed = tempfile::tempdir().unwrap();
+
+    fs::create_dir(crashed.path().join("doc")).unwrap();
+
+    for file in ["snapshot", "log"] {
+        fs::copy(
+            dir.path().join("doc").join(file),
+            crashed.path().join("doc").join(file),
+        )
+        .unwrap();
+    }
+
+    // Also tear the last record of the log.
+    let log = crashed.path().join("doc").join("log");
+    let len = fs::metadata(&log).unwrap().len();
+    fs::File::options()
+        .write(true)
+        .open(&log)
+        .unwrap()
+        .set_len(len - 1)
+        .unwrap();
+
+    let addr = start(crashed.path(), 1024).await;
+
+    let client3 = Client::join(addr, "doc").await;
+
+    // The torn deletion is lost.
+    assert_eq!(client3.buffer, "Hello world");
+
+    assert!(client3.replica.id() > client2.replica.id());
+}
+
+/// Checks that a document is closed if integrating an edit panics, and that
+/// it's reopened from disk without the edit by the next peer to join it.
+///
+/// The edit is an insertion whose Lamport timestamp overflows when it's
+/// integrated, which only panics with overflow checks enabled.
+#[cfg(debug_assertions)]
+#[tokio::test]
+async fn relay_closes_document_after_panic() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let addr = start(dir.path(), 1024).await;
+
+    let mut client1 = Client::join(addr, "doc").await;
+    let mut client2 = Client::join(addr, "doc").await;
+    let mut other = Client::join(addr, "other-doc").await;
+
+    client1.insert(0, "Hello").await;
+    client2.receive().await;
+
+    let insertion = client1.replica.inserted(5, 1);
+
+    // The Lamport timestamp is the last field of an `Insertion`.
+    let mut bytes = bincode::serialize(&insertion).unwrap();
+    let len = bytes.len();
+    bytes[len - 8..].copy_from_slice(&u64::MAX.to_le_bytes());
+    let insertion = bincode::deserialize(&bytes).unwrap();
+
+    let text = "!".to_owned();
+    send(&mut client1.ws, ClientMessage::Inserted { insertion, text }).await;
+
+    for client in [&mut client1, &mut client2] {
+        assert!(matches!(
+            recv(&mut client.ws).await,
+            Some(ServerMessage::Error(_))
+        ));
+    }
+
+    let mut client3 = Client::join(addr, "doc").await;
+
+    assert_eq!(client3.buffer, "Hello");
+
+    assert!(client3.replica.id() > client2.replica.id());
+
+    // The other documents are unaffected.
+    let mut client4 = Client::join(addr, "other-doc").await;
+    client4.insert(0, "Hi").await;
+    other.receive().await;
+
+    assert_eq!(other.buffer, "Hi");
+
+    client3.insert(5, "?").await;
+
+    assert_eq!(Client::join(addr, "doc").await.buffer, "Hello?");
+}
+
+/// Checks that an insertion rejected by the server's `Replica` is neither
+/// logged nor relayed, and that only the peer that sent it is disconnected.
+#[tokio::test]
+async fn relay_rejects_colliding_insertion() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let addr = start(dir.path(), 1024).await;
+
+    let mut client1 = Client::join(addr, "doc").await;
+    let mut client2 = Client::join(addr, "doc").await;
+
+    // A replica sharing its id with the first client.
+    let mut impostor = client1.replica.fork(client1.replica.id());
+
+    client1.insert(0, "ab").await;
+    client2.receive().await;
+
+    // This claims to be the first run inserted by the first client, but
+    // that run only has two characters.
+    let insertion = impostor.inserted(0, 3);
+    let text = "xyz".to_owned();
+    send(&mut client1.ws, ClientMessage::Inserted { insertion, text }).await;
+
+    assert!(matches!(
+        recv(&mut client1.ws).await,
+        Some(ServerMessage::Error(_))
+    ));
+    assert!(recv(&mut client1.ws).await.is_none());
+
+    let mut client3 = Client::join(addr, "doc").await;
+
+    assert_eq!(client3.buffer, "ab");
+
+    // The second client is still connected, and it never received the
+    // rejected insertion.
+    client3.insert(2, "c").await;
+    client2.receive().await;
+
+    assert_eq!(client2.buffer, "abc");
+
+    assert_eq!(Client::join(addr, "doc").await.buffer, "abc");
+}
+
+#[tokio::test]
+async fn relay_rejects_invalid_messages() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let addr = start(dir.path(), 1024).await;
+
+    // Not a valid document id.
+    let mut ws = Client::connect(addr).await;
+    send(&mut ws, ClientMessage::Join { document: "../doc".to_owned() }).await;
+    assert!(matches!(recv(&mut ws).await, Some(ServerMessage::Error(_))));
+    assert!(recv(&mut ws).await.is_none());
+
+    // An edit before joining.
+    let mut ws = Client::connect(addr).await;
+    let deletion = Replica::new(2, 1).deleted(0..1);
+    send(&mut ws, ClientMessage::Deleted(deletion)).await;
+    assert!(matches!(recv(&mut ws).await, Some(ServerMessage::Error(_))));
+
+    // An insertion made by another peer.
+    let mut client = Client::join(addr, "doc").await;
+    let mut impostor = client.replica.fork(client.replica.id() + 1);
+    let insertion = impostor.inserted(0, 1);
+    let text = "a".to_owned();
+    send(&mut client.ws, ClientMessage::Inserted { insertion, text }).await;
+    assert!(matches!(
+        recv(&mut client.ws).await,
+        Some(ServerMessage::Error(_))
+    ));
+    assert!(recv(&mut client.ws).await.is_none());
+
+    // An insertion whose text doesn't match its length.
+    let mut client = Client::join(addr, "doc").await;
+    let insertion = client.replica.inserted(0, 3);
+    let text = "a".to_owned();
+    send(&mut client.ws, ClientMessage::Inserted { insertion, text }).await;
+    assert!(matches!(
+        recv(&mut client.ws).await,
+        Some(ServerMessage::Error(_))
+    ));
+}
@@ -7,35 +7,86 @@ use crate::*;
 /// replicas but have not yet been merged.
 ///
 /// See [`Replica::backlogged`] for more information.
-#[derive(Debug, Clone, Default, PartialEq)]
-#[cfg_attr(feature = "encode", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Clone, Default)]
 pub(crate) struct Backlog {
     insertions: ReplicaIdMap<InsertionsBacklog>,
     deletions: ReplicaIdMap<DeletionsBacklog>,
 }
 
+impl PartialEq for Backlog {
+    /// Ignores the empty entries left behind by the replicas whose backlogged
+    /// edits have all been merged, since those are lost when encoding.
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        fn eq_non_empty<T: PartialEq>(
+            lhs: &ReplicaIdMap<T>,
+            rhs: &ReplicaIdMap<T>,
+            is_empty: impl Fn(&T) -> bool,
+        ) -> bool {
+            let is_contained =
+                |lhs: &ReplicaIdMap<T>, rhs: &ReplicaIdMap<T>| {
+                    lhs.iter()
+                        .filter(|(_, backlog)| !is_empty(backlog))
+                        .all(|(id, backlog)| rhs.get(id) == Some(backlog))
+                };
+            is_contained(lhs, rhs) && is_contained(rhs, lhs)
+        }
+
+        eq_non_empty(&self.insertions, &other.insertions, |b| b.is_empty())
+            && eq_non_empty(&self.deletions, &other.deletions, |b| {
+                b.is_empty()
+            })
+    }
+}
+
 impl Backlog {
-    pub fn assert_invariants(
+    pub fn check_invariants(
         &self,
         version_map: &VersionMap,
         deletion_map: &DeletionMap,
-    ) {
-        for (&id, insertions) in self.insertions.iter() {
-            insertions.assert_invariants(id, version_map);
+    ) -> Result<(), IntegrityError> {
+        for (&replica_id, insertions) in self.insertions.iter() {
+            if !insertions.is_valid(replica_id, version_map) {
+                return Err(IntegrityError::Backlog { replica_id });
+            }
         }
-        for (&id, deletions) in self.deletions.iter() {
-            deletions.assert_invariants(id, deletion_map);
+
+        for (&replica_id, deletions) in self.deletions.iter() {
+            if !deletions.is_valid(replica_id, deletion_map) {
+                return Err(IntegrityError::Backlog { replica_id });
+            }
         }
+
+        Ok(())
+    }
+
+    /// Returns an estimate of the number of bytes allocated on the heap by
+    /// the backlog.
+    #[inline]
+    pub fn heap_size(&self) -> usize {
+        let insertions = self
+            .insertions
+            .values()
+            .map(InsertionsBacklog::heap_size)
+            .sum::<usize>();
+
+        let deletions = self
+            .deletions
+            .values()
+            .map(DeletionsBacklog::heap_size)
+            .sum::<usize>();
+
+        hashmap_heap_size(&self.insertions)
+            + hashmap_heap_size(&self.deletions)
+            + insertions
+            + deletions
     }
 
-    /// Inserts a new [`Deletion`] into the backlog.
+    /// Inserts a new [`Deletion`] into the backlog, or does nothing if it's
+    /// already there.
     ///
     /// Runs in `O(n)` in the number of deletions already in the backlog, with
     /// a best-case of `O(log n)`.
-    ///
-    /// # Panics
-    ///
-    /// Panics if the deletion has already been backlogged.
     #[inline]
     pub fn insert_deletion(&mut self, deletion: Deletion) {
         self.deletions
@@ -60,16 +111,80 @@ impl Backlog {
             .insert(insertion);
     }
 
+    /// Returns a backlogged insertion made by the same replica as the given
+    /// one whose temporal range overlaps with it, if there is one.
+    #[inline]
+    pub fn overlapping_insertion(
+        &self,
+        insertion: &Insertion,
+    ) -> Option<&Insertion> {
+        self.insertions.get(&insertion.inserted_by())?.overlapping(insertion)
+    }
+
+    /// Returns `true` if the insertion of the given [`Text`] is in the
+    /// backlog.
+    #[inline]
+    pub fn contains_insertion(&self, text: &Text) -> bool {
+        self.insertions.get(&text.inserted_by()).is_some_and(|backlog| {
+            backlog.insertions.iter().any(|i| i.text() == text)
+        })
+    }
+
     /// Creates a new, empty `Backlog`.
     #[inline]
     pub fn new() -> Self {
         Self::default()
     }
+
+    /// Returns the number of backlogged deletions.
+    #[inline]
+    pub fn num_deletions(&self) -> usize {
+        self.deletions.values().map(|backlog| backlog.deletions.len()).sum()
+    }
+
+    /// Returns the number of backlogged insertions.
+    #[inline]
+    pub fn num_insertions(&self) -> usize {
+        self.insertions.values().map(|backlog| backlog.insertions.len()).sum()
+    }
+
+    /// Removes the backlogged insertions for which the predicate returns
+    /// `false`.
+    #[cfg(feature = "lsp")]
+    #[inline]
+    pub fn retain_insertions<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&Insertion) -> bool,
+    {
+        for backlog in self.insertions.values_mut() {
+            backlog.insertions.retain(&mut f);
+        }
+    }
+
+    /// Drops the backlogs of the given replica if they're empty, which is
+    /// always the case once all of its edits have been merged.
+    #[inline]
+    pub fn remove_replica(&mut self, replica_id: ReplicaId) {
+        if self
+            .insertions
+            .get(&replica_id)
+            .is_some_and(|backlog| backlog.insertions.is_empty())
+        {
+            self.insertions.remove(&replica_id);
+        }
+
+        if self
+            .deletions
+            .get(&replica_id)
+            .is_some_and(|backlog| backlog.deletions.is_empty())
+        {
+            self.deletions.remove(&replica_id);
+        }
+    }
 }
 
 /// Stores the backlogged [`Insertion`]s of a particular replica.
 #[derive(Clone, Default, PartialEq)]
-#[cfg_attr(feature = "encode", derive(serde::Serialize, serde::Deserialize))]
 struct InsertionsBacklog {
     insertions: VecDeque<Insertion>,
 }
@@ -83,20 +198,58 @@ impl core::fmt::Debug for InsertionsBacklog {
 }
 
 impl InsertionsBacklog {
-    fn assert_invariants(&self, id: ReplicaId, version_map: &VersionMap) {
+    #[inline]
+    fn is_empty(&self) -> bool {
+        self.insertions.is_empty()
+    }
+
+    fn is_valid(&self, id: ReplicaId, version_map: &VersionMap) -> bool {
         let Some(first) = self.insertions.front() else {
-            return;
+            return true;
         };
 
-        assert!(version_map.get(id) <= first.start());
+        // A replica's own insertions are always merged right away.
+        if id == version_map.this_id() {
+            return false;
+        }
+
+        if version_map.get(id) > first.start() {
+            return false;
+        }
 
         let mut prev_end = 0;
 
         for insertion in &self.insertions {
-            assert_eq!(insertion.inserted_by(), id);
-            assert!(insertion.start() >= prev_end);
+            if insertion.inserted_by() != id || insertion.start() < prev_end {
+                return false;
+            }
             prev_end = insertion.end();
         }
+
+        true
+    }
+
+    #[inline]
+    fn heap_size(&self) -> usize {
+        self.insertions.capacity() * core::mem::size_of::<Insertion>()
+    }
+
+    #[inline]
+    fn overlapping(&self, insertion: &Insertion) -> Option<&Insertion> {
+        let offset = match self
+            .insertions
+            .binary_search_by(|probe| probe.start().cmp(&insertion.start()))
+        {
+            Ok(idx) => return self.insertions.get(idx),
+            Err(offset) => offset,
+        };
+
+        let prev = offset.checked_sub(1).and_then(|i| self.insertions.get(i));
+
+        let next = self.insertions.get(offset);
+
+        prev.filter(|prev| prev.end() > insertion.start())
+            .or(next.filter(|next| next.start() < insertion.end()))
     }
 
     /// # Panics
@@ -115,7 +268,6 @@ impl InsertionsBacklog {
 
 /// Stores the backlogged [`Deletion`]s of a particular replica.
 #[derive(Clone, Default, PartialEq)]
-#[cfg_attr(feature = "encode", derive(serde::Serialize, serde::Deserialize))]
 struct DeletionsBacklog {
     deletions: VecDeque<Deletion>,
 }
@@ -129,35 +281,58 @@ impl core::fmt::Debug for DeletionsBacklog {
 }
 
 impl DeletionsBacklog {
-    fn assert_invariants(&self, id: ReplicaId, deletion_map: &DeletionMap) {
+    #[inline]
+    fn is_empty(&self) -> bool {
+        self.deletions.is_empty()
+    }
+
+    fn is_valid(&self, id: ReplicaId, deletion_map: &DeletionMap) -> bool {
         let Some(first) = self.deletions.front() else {
-            return;
+            return true;
         };
 
-        assert!(deletion_map.get(id) <= first.deletion_ts());
+        // A replica's own deletions are always merged right away.
+        if id == deletion_map.this_id() {
+            return false;
+        }
+
+        if deletion_map.get(id) > first.deletion_ts() {
+            return false;
+        }
 
         let mut prev_ts = 0;
 
         for deletion in &self.deletions {
-            assert_eq!(deletion.deleted_by(), id);
-            assert!(deletion.deletion_ts() > prev_ts);
+            if deletion.deleted_by() != id || deletion.deletion_ts() <= prev_ts
+            {
+                return false;
+            }
             prev_ts = deletion.deletion_ts();
         }
+
+        true
     }
 
-    /// # Panics
-    ///
-    /// Panics if the deletion has already inserted.
     #[inline]
-    fn insert(&mut self, deletion: Deletion) {
-        let offset = self
+    fn heap_size(&self) -> usize {
+        let version_maps = self
             .deletions
-            .binary_search_by(|probe| {
-                probe.deletion_ts().cmp(&deletion.deletion_ts())
-            })
-            .unwrap_err();
+            .iter()
+            .map(|deletion| deletion.version_map().heap_size())
+            .sum::<usize>();
+
+        self.deletions.capacity() * core::mem::size_of::<Deletion>()
+            + version_maps
+    }
 
-        self.deletions.insert(offset, deletion);
+    /// Does nothing if the deletion has already been inserted.
+    #[inline]
+    fn insert(&mut self, deletion: Deletion) {
+        if let Err(offset) = self.deletions.binary_search_by(|probe| {
+            probe.deletion_ts().cmp(&deletion.deletion_ts())
+        }) {
+            self.deletions.insert(offset, deletion);
+        }
     }
 }
 
@@ -167,15 +342,15 @@ impl DeletionsBacklog {
 /// This struct is created by the
 /// [`backlogged_deletions`](Replica::backlogged_deletions) method on
 /// [`Replica`]. See its documentation for more information.
-pub struct BackloggedDeletions<'a> {
-    replica: &'a mut Replica,
+pub struct BackloggedDeletions<'a, const ARITY: usize = DEFAULT_ARITY> {
+    replica: &'a mut Replica<ARITY>,
     current: Option<&'a mut DeletionsBacklog>,
     iter: ReplicaIdMapValuesMut<'a, DeletionsBacklog>,
 }
 
-impl<'a> BackloggedDeletions<'a> {
+impl<'a, const ARITY: usize> BackloggedDeletions<'a, ARITY> {
     #[inline]
-    pub(crate) fn from_replica(replica: &'a mut Replica) -> Self {
+    pub(crate) fn from_replica(replica: &'a mut Replica<ARITY>) -> Self {
         let backlog = replica.backlog_mut();
 
         // We transmute the exclusive reference to the backlog into the same
@@ -195,7 +370,7 @@ impl<'a> BackloggedDeletions<'a> {
     }
 }
 
-impl Iterator for BackloggedDeletions<'_> {
+impl<const ARITY: usize> Iterator for BackloggedDeletions<'_, ARITY> {
     type Item = Vec<Range<Length>>;
 
     #[inline]
@@ -207,13 +382,18 @@ impl Iterator for BackloggedDeletions<'_> {
             return self.next();
         };
 
+        // A deletion with the same timestamp was made by another replica
+        // sharing the same id, and it has already been merged.
+        if self.replica.has_merged_deletion(first) {
+            deletions.deletions.pop_front();
+            return self.next();
+        }
+
         if self.replica.can_merge_deletion(first) {
             let first = deletions.deletions.pop_front().unwrap();
-            let ranges = self.replica.merge_unchecked_deletion(&first);
-            if ranges.is_empty() {
-                self.next()
-            } else {
-                Some(ranges)
+            match self.replica.merge_unchecked_deletion(&first) {
+                Some(ranges) if !ranges.is_empty() => Some(ranges),
+                _ => self.next(),
             }
         } else {
             self.current = self.iter.next();
@@ -222,7 +402,10 @@ impl Iterator for BackloggedDeletions<'_> {
     }
 }
 
-impl core::iter::FusedIterator for BackloggedDeletions<'_> {}
+impl<const ARITY: usize> core::iter::FusedIterator
+    for BackloggedDeletions<'_, ARITY>
+{
+}
 
 /// An iterator over the backlogged insertions that are ready to be
 /// applied to a [`Replica`].
@@ -230,15 +413,27 @@ impl core::iter::FusedIterator for BackloggedDeletions<'_> {}
 /// This struct is created by the
 /// [`backlogged_insertion`](Replica::backlogged_insertions) method on
 /// [`Replica`]. See its documentation for more information.
-pub struct BackloggedInsertions<'a> {
-    replica: &'a mut Replica,
+pub struct BackloggedInsertions<'a, const ARITY: usize = DEFAULT_ARITY> {
+    replica: &'a mut Replica<ARITY>,
     current: Option<&'a mut InsertionsBacklog>,
     iter: ReplicaIdMapValuesMut<'a, InsertionsBacklog>,
+    has_merged: bool,
 }
 
-impl<'a> BackloggedInsertions<'a> {
+impl<'a, const ARITY: usize> BackloggedInsertions<'a, ARITY> {
     #[inline]
-    pub(crate) fn from_replica(replica: &'a mut Replica) -> Self {
+    pub(crate) fn from_replica(replica: &'a mut Replica<ARITY>) -> Self {
+        let mut iter = Self::backlogs(replica);
+
+        let current = iter.next();
+
+        Self { replica, current, iter, has_merged: false }
+    }
+
+    #[inline]
+    fn backlogs(
+        replica: &mut Replica<ARITY>,
+    ) -> ReplicaIdMapValuesMut<'a, InsertionsBacklog> {
         let backlog = replica.backlog_mut();
 
         // We transmute the exclusive reference to the backlog into the same
@@ -250,15 +445,24 @@ impl<'a> BackloggedInsertions<'a> {
         let backlog =
             unsafe { core::mem::transmute::<_, &mut Backlog>(backlog) };
 
-        let mut iter = backlog.insertions.values_mut();
+        backlog.insertions.values_mut()
+    }
 
-        let current = iter.next();
+    /// Moves on to the backlog of the next replica, starting over from the
+    /// first one if we've merged some insertions since the last time, as
+    /// those could be the anchors of insertions we've already skipped.
+    #[inline]
+    fn next_backlog(&mut self) {
+        self.current = self.iter.next();
 
-        Self { replica, current, iter }
+        if self.current.is_none() && core::mem::take(&mut self.has_merged) {
+            self.iter = Self::backlogs(self.replica);
+            self.current = self.iter.next();
+        }
     }
 }
 
-impl Iterator for BackloggedInsertions<'_> {
+impl<const ARITY: usize> Iterator for BackloggedInsertions<'_, ARITY> {
     type Item = (Text, Length);
 
     #[inline]
@@ -268,19 +472,118 @@ impl Iterator for BackloggedInsertions<'_> {
         };
 
         let Some(first) = insertions.insertions.front() else {
-            self.current = self.iter.next();
+            self.next_backlog();
             return self.next();
         };
 
+        // An insertion covering the same characters was made by another
+        // replica sharing the same id, and it has already been merged.
+        if self.replica.has_merged_insertion(first) {
+            insertions.insertions.pop_front();
+            return self.next();
+        }
+
         if self.replica.can_merge_insertion(first) {
             let first = insertions.insertions.pop_front().unwrap();
-            let edit = self.replica.merge_unchecked_insertion(&first);
+
+            // The insertion was made by a replica sharing its id with
+            // another one, so we drop it instead of corrupting the run tree.
+            if self.replica.has_replica_id_collision(&first) {
+                return self.next();
+            }
+
+            let Some(edit) = self.replica.merge_unchecked_insertion(&first)
+            else {
+                return self.next();
+            };
+
+            self.has_merged = true;
             Some((first.text().clone(), edit))
         } else {
-            self.current = self.iter.next();
+            self.next_backlog();
             self.next()
         }
     }
 }
 
-impl core::iter::FusedIterator for BackloggedInsertions<'_> {}
+impl<const ARITY: usize> core::iter::FusedIterator
+    for BackloggedInsertions<'_, ARITY>
+{
+}
+
+#[cfg(feature = "encode")]
+mod encode {
+    use super::*;
+    use crate::encode::{decode_len, Decode, Encode};
+
+    /// The `Backlog` is encoded as the number of backlogged insertions
+    /// followed by the insertions themselves, and then the same for the
+    /// deletions.
+    impl Encode for Backlog {
+        #[inline]
+        fn encode(&self, buf: &mut Vec<u8>) {
+            self.num_insertions().encode(buf);
+
+            for backlog in self.insertions.values() {
+                for insertion in &backlog.insertions {
+                    Encode::encode(insertion, buf);
+                }
+            }
+
+            self.num_deletions().encode(buf);
+
+            for backlog in self.deletions.values() {
+                for deletion in &backlog.deletions {
+                    Encode::encode(deletion, buf);
+                }
+            }
+        }
+    }
+
+    impl Decode for Backlog {
+        #[inline]
+        fn decode(buf: &mut &[u8]) -> Option<Self> {
+            let mut this = Self::new();
+
+            for _ in 0..decode_len(buf)? {
+                let insertion = <Insertion as Decode>::decode(buf)?;
+
+                let insertions = &mut this
+                    .insertions
+                    .entry(insertion.inserted_by())
+                    .or_default()
+                    .insertions;
+
+                // Unlike `InsertionsBacklog::insert()`, we can't assume that
+                // the insertion is not already in the backlog.
+                let offset = insertions
+                    .binary_search_by(|probe| {
+                        probe.start().cmp(&insertion.start())
+                    })
+                    .err()?;
+
+                insertions.insert(offset, insertion);
+            }
+
+            for _ in 0..decode_len(buf)? {
+                let deletion = <Deletion as Decode>::decode(buf)?;
+
+                let deletions = &mut this
+                    .deletions
+                    .entry(deletion.deleted_by())
+                    .or_default()
+                    .deletions;
+
+                let offset = deletions
+                    .binary_search_by(|probe| {
+                        probe.deletion_ts().cmp(&deletion.deletion_ts())
+                    })
+                    .err()?;
+
+                deletions.insert(offset, deletion);
+            }
+
+            Some(this)
+        }
+    }
+}
@@ -9,10 +9,7 @@ use crate::*;
 ///
 /// See the documentation of those methods for more information.
 #[derive(Debug, Clone, PartialEq)]
-#[cfg_attr(
-    any(feature = "encode", feature = "serde"),
-    derive(serde::Serialize, serde::Deserialize)
-)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Insertion {
     /// The anchor point of the insertion.
     anchor: Anchor,
@@ -42,6 +39,63 @@ impl Insertion {
         self.anchor_ts
     }
 
+    /// Decodes an `Insertion` from the bytes returned by
+    /// [`encode`](Self::encode), possibly on a different version of cola.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DecodeError::DifferentProtocol`] if the `Insertion` was
+    /// encoded with a protocol version this release of cola can't decode,
+    /// and [`DecodeError::InvalidData`] if the bytes are not a valid encoding
+    /// of an `Insertion`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use cola::{Insertion, Replica};
+    /// let mut replica1 = Replica::new(1, 3);
+    /// let mut replica2 = replica1.fork(2);
+    ///
+    /// let bytes = replica1.inserted(1, 2).encode();
+    ///
+    /// let insertion = Insertion::decode(&bytes).unwrap();
+    ///
+    /// assert_eq!(replica2.integrate_insertion(&insertion), Some(1));
+    /// ```
+    #[cfg(feature = "encode")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "encode")))]
+    #[inline]
+    pub fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        protocol::decode_edit(protocol::EditKind::Insertion, bytes)
+    }
+
+    /// Encodes the `Insertion` in a compact binary format that doesn't
+    /// depend on any serialization framework.
+    ///
+    /// The encoded bytes can be decoded back into an `Insertion` via the
+    /// [`decode`](Self::decode) method.
+    ///
+    /// # Format
+    ///
+    /// All the integers are encoded as [LEB128] varints. The encoding starts
+    /// with a header made of the [`ProtocolVersion`] of the release of cola
+    /// that encoded it followed by a `0` byte, which distinguishes
+    /// insertions from deletions. Then come:
+    ///
+    /// - the [`ReplicaId`] and offset of the anchor the text was inserted
+    ///   at, followed by the run timestamp of the edit run containing it;
+    /// - the [`ReplicaId`] of the replica that inserted the text, followed
+    ///   by the start and the length of its temporal range;
+    /// - the run timestamp and the Lamport timestamp of the insertion.
+    ///
+    /// [LEB128]: https://en.wikipedia.org/wiki/LEB128
+    #[cfg(feature = "encode")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "encode")))]
+    #[inline]
+    pub fn encode(&self) -> Vec<u8> {
+        protocol::encode_edit(protocol::EditKind::Insertion, self)
+    }
+
     #[inline(always)]
     pub(crate) fn end(&self) -> Length {
         self.text.range.end
@@ -100,6 +154,34 @@ impl Insertion {
     }
 }
 
+/// Either an [`Insertion`] or a [`Deletion`].
+///
+/// This is useful when edits of both kinds travel through the same channel,
+/// e.g. in the [`Message`]s exchanged by [`Session`]s.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CrdtEdit {
+    /// An insertion.
+    Insertion(Insertion),
+
+    /// A deletion.
+    Deletion(Deletion),
+}
+
+impl From<Insertion> for CrdtEdit {
+    #[inline]
+    fn from(insertion: Insertion) -> Self {
+        Self::Insertion(insertion)
+    }
+}
+
+impl From<Deletion> for CrdtEdit {
+    #[inline]
+    fn from(deletion: Deletion) -> Self {
+        Self::Deletion(deletion)
+    }
+}
+
 /// A deletion in CRDT coordinates.
 ///
 /// This struct is created by the [`deleted`](Replica::deleted) method on the
@@ -109,10 +191,7 @@ impl Insertion {
 ///
 /// See the documentation of those methods for more information.
 #[derive(Debug, Clone, PartialEq)]
-#[cfg_attr(
-    any(feature = "encode", feature = "serde"),
-    derive(serde::Serialize, serde::Deserialize)
-)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Deletion {
     /// The anchor point of the start of the deleted range.
     start: Anchor,
@@ -126,8 +205,9 @@ pub struct Deletion {
     /// The run timestamp of the [`EditRun`] containing the end `Anchor`.
     end_ts: RunTs,
 
-    /// The version map of the replica at the time of the deletion. This is
-    /// used by a `Replica` merging this deletion to determine:
+    /// The entries of the version map of the replica at the time of the
+    /// deletion for the replicas that inserted the runs within the deleted
+    /// range. This is used by a `Replica` merging this deletion to determine:
     ///
     /// a) if it has all the text that the `Replica` who created the
     ///   deletion had at the time of the deletion, and
@@ -142,6 +222,36 @@ pub struct Deletion {
 }
 
 impl Deletion {
+    /// Decodes a `Deletion` from the bytes returned by
+    /// [`encode`](Self::encode), possibly on a different version of cola.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DecodeError::DifferentProtocol`] if the `Deletion` was
+    /// encoded with a protocol version this release of cola can't decode,
+    /// and [`DecodeError::InvalidData`] if the bytes are not a valid encoding
+    /// of a `Deletion`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use cola::{Deletion, Replica};
+    /// let mut replica1 = Replica::new(1, 3);
+    /// let mut replica2 = replica1.fork(2);
+    ///
+    /// let bytes = replica1.deleted(1..2).encode();
+    ///
+    /// let deletion = Deletion::decode(&bytes).unwrap();
+    ///
+    /// assert_eq!(replica2.integrate_deletion(&deletion), vec![1..2]);
+    /// ```
+    #[cfg(feature = "encode")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "encode")))]
+    #[inline]
+    pub fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        protocol::decode_edit(protocol::EditKind::Deletion, bytes)
+    }
+
     #[inline(always)]
     pub(crate) fn deleted_by(&self) -> ReplicaId {
         self.version_map.this_id()
@@ -157,6 +267,42 @@ impl Deletion {
         self.end
     }
 
+    /// Encodes the `Deletion` in a compact binary format that doesn't depend
+    /// on any serialization framework.
+    ///
+    /// The encoded bytes can be decoded back into a `Deletion` via the
+    /// [`decode`](Self::decode) method.
+    ///
+    /// # Format
+    ///
+    /// All the integers are encoded as [LEB128] varints. The encoding starts
+    /// with a header made of the [`ProtocolVersion`] of the release of cola
+    /// that encoded it followed by a `1` byte, which distinguishes deletions
+    /// from insertions. Then come:
+    ///
+    /// - the [`ReplicaId`] and offset of the anchor at the start of the
+    ///   deleted range, followed by the run timestamp of the edit run
+    ///   containing it;
+    /// - the same for the anchor at the end of the deleted range;
+    /// - the entries of the version map of the replica that performed the
+    ///   deletion for the replicas whose text was in the deleted range;
+    /// - the deletion timestamp.
+    ///
+    /// The version map is encoded as the [`ReplicaId`] of the replica that
+    /// performed the deletion and the length of the text it had inserted (or
+    /// zero if none of it was in the deleted range),
+    /// followed by the number of remote replicas in it and, sorted by
+    /// `ReplicaId`, the difference between each `ReplicaId` and the previous
+    /// one together with the length of the text received from it.
+    ///
+    /// [LEB128]: https://en.wikipedia.org/wiki/LEB128
+    #[cfg(feature = "encode")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "encode")))]
+    #[inline]
+    pub fn encode(&self) -> Vec<u8> {
+        protocol::encode_edit(protocol::EditKind::Deletion, self)
+    }
+
     #[inline(always)]
     pub(crate) fn end_ts(&self) -> RunTs {
         self.end_ts
@@ -206,3 +352,64 @@ impl Deletion {
         &self.version_map
     }
 }
+
+#[cfg(feature = "encode")]
+mod encode {
+    use super::*;
+    use crate::encode::{Decode, Encode};
+
+    impl Encode for Insertion {
+        #[inline]
+        fn encode(&self, buf: &mut Vec<u8>) {
+            self.anchor.encode(buf);
+            self.anchor_ts.encode(buf);
+            self.text.encode(buf);
+            self.run_ts.encode(buf);
+            self.lamport_ts.encode(buf);
+        }
+    }
+
+    impl Decode for Insertion {
+        #[inline]
+        fn decode(buf: &mut &[u8]) -> Option<Self> {
+            let anchor = Anchor::decode(buf)?;
+            let anchor_ts = RunTs::decode(buf)?;
+            let text = Text::decode(buf)?;
+            let run_ts = RunTs::decode(buf)?;
+            let lamport_ts = LamportTs::decode(buf)?;
+            Some(Self::new(anchor, anchor_ts, text, lamport_ts, run_ts))
+        }
+    }
+
+    impl Encode for Deletion {
+        #[inline]
+        fn encode(&self, buf: &mut Vec<u8>) {
+            self.start.encode(buf);
+            self.start_ts.encode(buf);
+            self.end.encode(buf);
+            self.end_ts.encode(buf);
+            self.version_map.encode(buf);
+            self.deletion_ts.encode(buf);
+        }
+    }
+
+    impl Decode for Deletion {
+        #[inline]
+        fn decode(buf: &mut &[u8]) -> Option<Self> {
+            let start = Anchor::decode(buf)?;
+            let start_ts = RunTs::decode(buf)?;
+            let end = Anchor::decode(buf)?;
+            let end_ts = RunTs::decode(buf)?;
+            let version_map = VersionMap::decode(buf)?;
+            let deletion_ts = DeletionTs::decode(buf)?;
+            Some(Self::new(
+                start,
+                start_ts,
+                end,
+                end_ts,
+                version_map,
+                deletion_ts,
+            ))
+        }
+    }
+}
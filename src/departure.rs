@@ -0,0 +1,132 @@
+use crate::*;
+
+/// The announcement that a peer has left the collaborative session for good.
+///
+/// This struct is created by the [`depart`](Replica::depart) method on the
+/// [`Replica`] owned by the peer that's leaving, and can be integrated by
+/// another [`Replica`] via the
+/// [`integrate_departure`](Replica::integrate_departure) method.
+///
+/// It records how many characters the departed peer inserted and how many
+/// deletions it made, so that the other peers can tell when they've
+/// integrated all of its edits. From then on its [`ReplicaId`] can be handed
+/// to a new peer, which will continue from where the departed one left off.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Departure {
+    /// The `ReplicaId` of the departed peer.
+    replica_id: ReplicaId,
+
+    /// The number of characters inserted by the departed peer.
+    character_ts: Length,
+
+    /// The deletion timestamp of the last deletion made by the departed
+    /// peer.
+    deletion_ts: DeletionTs,
+}
+
+impl Departure {
+    #[inline(always)]
+    pub(crate) fn character_ts(&self) -> Length {
+        self.character_ts
+    }
+
+    /// Decodes a `Departure` from the bytes returned by
+    /// [`encode`](Self::encode), possibly on a different version of cola.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DecodeError::DifferentProtocol`] if the `Departure` was
+    /// encoded with a protocol version this release of cola can't decode,
+    /// and [`DecodeError::InvalidData`] if the bytes are not a valid encoding
+    /// of a `Departure`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use cola::{Departure, Replica};
+    /// let mut replica1 = Replica::new(1, 3);
+    /// let replica2 = replica1.fork(2);
+    ///
+    /// let bytes = replica2.depart().encode();
+    ///
+    /// let departure = Departure::decode(&bytes).unwrap();
+    ///
+    /// assert!(replica1.integrate_departure(&departure));
+    /// ```
+    #[cfg(feature = "encode")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "encode")))]
+    #[inline]
+    pub fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        protocol::decode_edit(protocol::EditKind::Departure, bytes)
+    }
+
+    #[inline(always)]
+    pub(crate) fn deletion_ts(&self) -> DeletionTs {
+        self.deletion_ts
+    }
+
+    /// Encodes the `Departure` in a compact binary format that doesn't
+    /// depend on any serialization framework.
+    ///
+    /// The encoded bytes can be decoded back into a `Departure` via the
+    /// [`decode`](Self::decode) method.
+    ///
+    /// # Format
+    ///
+    /// All the integers are encoded as [LEB128] varints. The encoding starts
+    /// with a header made of the [`ProtocolVersion`] of the release of cola
+    /// that encoded it followed by a `2` byte, which distinguishes
+    /// departures from insertions and deletions. Then come the
+    /// [`ReplicaId`] of the departed peer, the number of characters it
+    /// inserted and the timestamp of its last deletion.
+    ///
+    /// [LEB128]: https://en.wikipedia.org/wiki/LEB128
+    #[cfg(feature = "encode")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "encode")))]
+    #[inline]
+    pub fn encode(&self) -> Vec<u8> {
+        protocol::encode_edit(protocol::EditKind::Departure, self)
+    }
+
+    #[inline]
+    pub(crate) fn new(
+        replica_id: ReplicaId,
+        character_ts: Length,
+        deletion_ts: DeletionTs,
+    ) -> Self {
+        Self { replica_id, character_ts, deletion_ts }
+    }
+
+    /// Returns the [`ReplicaId`] of the departed peer.
+    #[inline]
+    pub fn replica_id(&self) -> ReplicaId {
+        self.replica_id
+    }
+}
+
+#[cfg(feature = "encode")]
+mod encode {
+    use super::*;
+    use crate::encode::{Decode, Encode};
+
+    impl Encode for Departure {
+        #[inline]
+        fn encode(&self, buf: &mut Vec<u8>) {
+            self.replica_id.encode(buf);
+            self.character_ts.encode(buf);
+            self.deletion_ts.encode(buf);
+        }
+    }
+
+    impl Decode for Departure {
+        #[inline]
+        fn decode(buf: &mut &[u8]) -> Option<Self> {
+            let replica_id = ReplicaId::decode(buf)?;
+            let character_ts = Length::decode(buf)?;
+            let deletion_ts = DeletionTs::decode(buf)?;
+            (replica_id != 0)
+                .then(|| Self::new(replica_id, character_ts, deletion_ts))
+        }
+    }
+}
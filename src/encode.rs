@@ -0,0 +1,184 @@
+//! The building blocks of cola's compact binary encoding.
+//!
+//! Unsigned integers are encoded as [LEB128] varints, i.e. 7 bits at a time
+//! starting from the least significant ones, with the most significant bit of
+//! each byte set if more bytes follow. Values that are expected to be close
+//! to some previous value are encoded as the difference between the two,
+//! mapped to an unsigned integer via [zigzag encoding] so that small negative
+//! differences also take a single byte.
+//!
+//! [LEB128]: https://en.wikipedia.org/wiki/LEB128
+//! [zigzag encoding]: https://protobuf.dev/programming-guides/encoding/#signed-ints
+
+/// A type that can be encoded into cola's binary format.
+pub(crate) trait Encode {
+    /// Appends the encoded representation of `self` to the buffer.
+    fn encode(&self, buf: &mut Vec<u8>);
+}
+
+/// A type that can be decoded from cola's binary format.
+pub(crate) trait Decode: Sized {
+    /// Decodes a value from the start of the buffer, advancing it past the
+    /// bytes that were read.
+    ///
+    /// Returns `None` if the buffer doesn't start with a valid encoding of
+    /// `Self`, in which case the buffer is left in an unspecified state.
+    fn decode(buf: &mut &[u8]) -> Option<Self>;
+}
+
+impl Encode for u64 {
+    #[inline]
+    fn encode(&self, buf: &mut Vec<u8>) {
+        let mut value = *self;
+
+        while value >= 0x80 {
+            buf.push((value as u8) | 0x80);
+            value >>= 7;
+        }
+
+        buf.push(value as u8);
+    }
+}
+
+impl Decode for u64 {
+    #[inline]
+    fn decode(buf: &mut &[u8]) -> Option<Self> {
+        let mut value = 0u64;
+
+        let mut shift = 0;
+
+        loop {
+            let (&byte, rest) = buf.split_first()?;
+
+            *buf = rest;
+
+            let bits = (byte & 0x7f) as u64;
+
+            // A `u64` takes at most 10 bytes, the last of which can only
+            // hold the most significant bit.
+            if shift == 63 && bits > 1 {
+                return None;
+            }
+
+            value |= bits << shift;
+
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+
+            shift += 7;
+
+            if shift > 63 {
+                return None;
+            }
+        }
+    }
+}
+
+impl Encode for usize {
+    #[inline]
+    fn encode(&self, buf: &mut Vec<u8>) {
+        (*self as u64).encode(buf)
+    }
+}
+
+impl Decode for usize {
+    #[inline]
+    fn decode(buf: &mut &[u8]) -> Option<Self> {
+        u64::decode(buf).and_then(|value| value.try_into().ok())
+    }
+}
+
+impl Encode for bool {
+    #[inline]
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.push(*self as u8);
+    }
+}
+
+impl Decode for bool {
+    #[inline]
+    fn decode(buf: &mut &[u8]) -> Option<Self> {
+        let (&byte, rest) = buf.split_first()?;
+        *buf = rest;
+        match byte {
+            0 => Some(false),
+            1 => Some(true),
+            _ => None,
+        }
+    }
+}
+
+/// Encodes `value` as its zigzag-encoded difference from `prev`.
+#[inline]
+pub(crate) fn encode_delta(buf: &mut Vec<u8>, prev: u64, value: u64) {
+    let delta = value.wrapping_sub(prev) as i64;
+    (((delta << 1) ^ (delta >> 63)) as u64).encode(buf);
+}
+
+/// Decodes a value encoded by [`encode_delta`] with the same `prev`.
+#[inline]
+pub(crate) fn decode_delta(buf: &mut &[u8], prev: u64) -> Option<u64> {
+    let zigzag = u64::decode(buf)?;
+    let delta = ((zigzag >> 1) as i64) ^ -((zigzag & 1) as i64);
+    Some(prev.wrapping_add(delta as u64))
+}
+
+/// Encodes a sequence of booleans as a bitmap, packing 8 of them in every
+/// byte starting from the least significant bit.
+///
+/// The number of booleans is not encoded, so it has to be known by the
+/// decoder.
+#[inline]
+pub(crate) fn encode_bitmap<I>(buf: &mut Vec<u8>, bits: I)
+where
+    I: IntoIterator<Item = bool>,
+{
+    let mut byte = 0u8;
+
+    let mut num_bits = 0;
+
+    for bit in bits {
+        byte |= (bit as u8) << (num_bits % 8);
+
+        num_bits += 1;
+
+        if num_bits % 8 == 0 {
+            buf.push(byte);
+            byte = 0;
+        }
+    }
+
+    if num_bits % 8 != 0 {
+        buf.push(byte);
+    }
+}
+
+/// Decodes `len` booleans encoded by [`encode_bitmap`].
+#[inline]
+pub(crate) fn decode_bitmap(buf: &mut &[u8], len: usize) -> Option<Vec<bool>> {
+    let num_bytes = len.div_ceil(8);
+
+    if buf.len() < num_bytes {
+        return None;
+    }
+
+    let (bytes, rest) = buf.split_at(num_bytes);
+
+    *buf = rest;
+
+    let bits = (0..len).map(|i| bytes[i / 8] & (1 << (i % 8)) != 0).collect();
+
+    Some(bits)
+}
+
+/// Decodes the number of elements of a sequence, making sure it's not
+/// larger than the number of bytes left in the buffer.
+///
+/// Every element takes at least one byte, so this prevents a corrupted
+/// length from causing a huge allocation.
+#[inline]
+pub(crate) fn decode_len(buf: &mut &[u8]) -> Option<usize> {
+    let len = usize::decode(buf)?;
+    (len <= buf.len()).then_some(len)
+}
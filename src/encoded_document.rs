@@ -0,0 +1,80 @@
+use crate::*;
+
+/// A [`Replica`] encoded together with the text of the document it tracks.
+///
+/// An [`EncodedReplica`] only contains the CRDT metadata of a `Replica`, so a
+/// peer joining a session by decoding one has to get the text of the document
+/// through some other channel and trust that it matches. An
+/// `EncodedDocument` bundles the two together, and checks that they're
+/// consistent when it's decoded.
+///
+/// This struct is created by either
+/// [`encode_document`](Replica::encode_document) or
+/// [`encode_document_with_deleted_text`](Replica::encode_document_with_deleted_text),
+/// and can be decoded back into a [`Replica`] by calling
+/// [`decode_document`](Replica::decode_document).
+///
+/// The text is stored as a UTF-8 string, so an `EncodedDocument` should only
+/// be used if the [`Length`]s given to the `Replica` are measured in bytes.
+#[cfg_attr(docsrs, doc(cfg(feature = "encode")))]
+#[derive(Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EncodedDocument {
+    replica: EncodedReplica,
+    text: String,
+    deleted_text: Option<Vec<(Text, String)>>,
+}
+
+impl EncodedDocument {
+    /// Returns the contents of the text that has been deleted from the
+    /// document, or `None` if the document was encoded without it.
+    ///
+    /// Every deleted [`Text`] is paired with its contents, in the order it
+    /// appeared in the document.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use cola::Replica;
+    /// let mut replica = Replica::new(1, 3);
+    ///
+    /// let _ = replica.deleted(1..2);
+    ///
+    /// let encoded = replica.encode_document("ac");
+    ///
+    /// assert!(encoded.deleted_text().is_none());
+    ///
+    /// let encoded =
+    ///     replica.encode_document_with_deleted_text("ac", |_| "b".to_owned());
+    ///
+    /// let deleted = encoded.deleted_text().unwrap();
+    ///
+    /// assert_eq!(deleted[0].0.temporal_range(), 1..2);
+    /// assert_eq!(deleted[0].1, "b");
+    /// ```
+    #[inline]
+    pub fn deleted_text(&self) -> Option<&[(Text, String)]> {
+        self.deleted_text.as_deref()
+    }
+
+    #[inline]
+    pub(crate) fn new(
+        replica: EncodedReplica,
+        text: String,
+        deleted_text: Option<Vec<(Text, String)>>,
+    ) -> Self {
+        Self { replica, text, deleted_text }
+    }
+
+    /// Returns the [`EncodedReplica`] contained in the document.
+    #[inline]
+    pub fn replica(&self) -> &EncodedReplica {
+        &self.replica
+    }
+
+    /// Returns the visible text of the document.
+    #[inline]
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+}
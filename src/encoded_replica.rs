@@ -1,9 +1,15 @@
+#[cfg(feature = "sha256")]
 use sha2::{Digest, Sha256};
 
 use crate::*;
 
 pub type Checksum = Vec<u8>;
 
+/// The first protocol version whose checksums start with the id of the
+/// [`ChecksumAlgorithm`] used to compute them. The checksums of older
+/// versions are always SHA-256 digests.
+const TAGGED_CHECKSUM_PROTOCOL_VERSION: ProtocolVersion = 2;
+
 /// A [`Replica`] encoded into a compact binary format suitable for
 /// transmission over the network.
 ///
@@ -46,10 +52,95 @@ impl EncodedReplica {
     }
 }
 
+/// The algorithm used to compute the checksum of an [`EncodedReplica`].
+///
+/// The checksum is verified when [`decode`](Replica::decode)ing the
+/// `EncodedReplica` to detect whether it was corrupted in transit. SHA-256 is
+/// used by default if the `sha256` feature is enabled (and no checksum at all
+/// otherwise), but a cheaper algorithm can be chosen via
+/// [`encode_with_checksum`](Replica::encode_with_checksum) if replicas are
+/// encoded frequently, or no checksum at all if the transport already
+/// guarantees the integrity of the data.
+///
+/// The algorithm is recorded in the `EncodedReplica`, so the peer decoding it
+/// doesn't have to know which one was used. However, both peers need to have
+/// the cargo feature enabling it, i.e. `crc32c` for `Crc32c`, `xxhash` for
+/// `XxHash` and `sha256` for `Sha256`.
+#[cfg_attr(docsrs, doc(cfg(feature = "encode")))]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ChecksumAlgorithm {
+    /// No checksum is computed, so corrupted `EncodedReplica`s can only be
+    /// detected if the corruption makes them undecodable.
+    #[cfg_attr(not(feature = "sha256"), default)]
+    None = 0,
+
+    /// The CRC-32C (Castagnoli) checksum, which most CPUs can compute in
+    /// hardware.
+    #[cfg(feature = "crc32c")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "crc32c")))]
+    Crc32c = 1,
+
+    /// The 64-bit XXH3 hash.
+    #[cfg(feature = "xxhash")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "xxhash")))]
+    XxHash = 2,
+
+    /// The SHA-256 digest. This is the slowest of the algorithms, but also
+    /// the only one that's used by all protocol versions.
+    #[cfg(feature = "sha256")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "sha256")))]
+    #[default]
+    Sha256 = 3,
+}
+
+impl ChecksumAlgorithm {
+    #[cfg_attr(
+        not(any(feature = "crc32c", feature = "xxhash", feature = "sha256")),
+        allow(unused_variables)
+    )]
+    #[inline]
+    fn digest(self, bytes: &[u8]) -> Vec<u8> {
+        match self {
+            Self::None => Vec::new(),
+
+            #[cfg(feature = "crc32c")]
+            Self::Crc32c => crc32c::crc32c(bytes).to_le_bytes().to_vec(),
+
+            #[cfg(feature = "xxhash")]
+            Self::XxHash => {
+                xxhash_rust::xxh3::xxh3_64(bytes).to_le_bytes().to_vec()
+            },
+
+            #[cfg(feature = "sha256")]
+            Self::Sha256 => Sha256::digest(bytes)[..].to_vec(),
+        }
+    }
+
+    #[inline]
+    fn from_id(id: u8) -> Option<Self> {
+        match id {
+            0 => Some(Self::None),
+
+            #[cfg(feature = "crc32c")]
+            1 => Some(Self::Crc32c),
+
+            #[cfg(feature = "xxhash")]
+            2 => Some(Self::XxHash),
+
+            #[cfg(feature = "sha256")]
+            3 => Some(Self::Sha256),
+
+            _ => None,
+        }
+    }
+}
+
 /// The type of error that can occur when [`decode`](Replica::decode)ing an
 /// [`EncodedReplica`].
 #[cfg_attr(docsrs, doc(cfg(feature = "encode")))]
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum DecodeError {
     /// This error occurs when the internal checksum of the [`EncodedReplica`]
     /// fails.
@@ -60,7 +151,10 @@ pub enum DecodeError {
 
     /// This error occurs when the machine that created the [`EncodedReplica`]
     /// and the one that is trying to [`decode`](Replica::decode) it are using
-    /// two incompatible versions of cola.
+    /// two incompatible versions of cola, i.e. when the `EncodedReplica` was
+    /// encoded with a protocol version that is either newer than
+    /// [`PROTOCOL_VERSION`] or older than
+    /// [`MIN_DECODABLE_PROTOCOL_VERSION`].
     DifferentProtocol {
         /// The `ProtocolVersion` of cola on the machine that created the
         /// `EncodedReplica`.
@@ -71,6 +165,24 @@ pub enum DecodeError {
         decoding_on: ProtocolVersion,
     },
 
+    /// This error occurs when the [`EncodedReplica`] was checksummed with a
+    /// [`ChecksumAlgorithm`] whose cargo feature is not enabled on the machine
+    /// that is trying to [`decode`](Replica::decode) it.
+    UnsupportedChecksum,
+
+    /// This error occurs when the length of the `Replica` contained in an
+    /// [`EncodedDocument`] is different from the length of its text.
+    ///
+    /// This means that the text of the document and the `Replica` tracking it
+    /// have gotten out of sync.
+    TextLengthMismatch {
+        /// The length of the decoded `Replica`.
+        replica_len: Length,
+
+        /// The length of the text of the `EncodedDocument`.
+        text_len: Length,
+    },
+
     /// This error is an umbrella variant that encompasses all other errors
     /// that can occur when the binary data wrapped by the [`EncodedReplica`]
     /// cannot be decoded into a `Replica`.
@@ -86,7 +198,87 @@ pub enum DecodeError {
     InvalidData,
 }
 
+impl core::fmt::Display for DecodeError {
+    #[inline]
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::ChecksumFailed => {
+                f.write_str("the checksum of the encoded replica failed")
+            },
+
+            Self::DifferentProtocol { encoded_on, decoding_on } => write!(
+                f,
+                "the replica was encoded with protocol version {encoded_on}, \
+                 which can't be decoded with protocol version {decoding_on}"
+            ),
+
+            Self::UnsupportedChecksum => f.write_str(
+                "the encoded replica was checksummed with an unsupported \
+                 algorithm",
+            ),
+
+            Self::TextLengthMismatch { replica_len, text_len } => write!(
+                f,
+                "the length of the replica is {replica_len} but the length \
+                 of the text is {text_len}"
+            ),
+
+            Self::InvalidData => f.write_str("the encoded replica is invalid"),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// Computes the checksum of the bytes of an `EncodedReplica` with the given
+/// protocol version.
+///
+/// Protocol versions predating [`TAGGED_CHECKSUM_PROTOCOL_VERSION`] can only
+/// use SHA-256.
+#[inline]
+pub fn checksum(
+    version: ProtocolVersion,
+    algorithm: ChecksumAlgorithm,
+    bytes: &[u8],
+) -> Checksum {
+    if version < TAGGED_CHECKSUM_PROTOCOL_VERSION {
+        #[cfg(feature = "sha256")]
+        debug_assert_eq!(algorithm, ChecksumAlgorithm::Sha256);
+        return algorithm.digest(bytes);
+    }
+
+    let mut checksum = vec![algorithm as u8];
+    checksum.extend(algorithm.digest(bytes));
+    checksum
+}
+
+/// Checks that the checksum of an `EncodedReplica` with the given protocol
+/// version matches its bytes.
 #[inline]
-pub fn checksum(bytes: &[u8]) -> Checksum {
-    Sha256::digest(bytes)[..].to_vec()
+pub fn verify_checksum(
+    version: ProtocolVersion,
+    checksum: &[u8],
+    bytes: &[u8],
+) -> Result<(), DecodeError> {
+    let (algorithm, digest) = if version < TAGGED_CHECKSUM_PROTOCOL_VERSION {
+        // Untagged checksums are always SHA-256, whose id is 3.
+        let algorithm = ChecksumAlgorithm::from_id(3)
+            .ok_or(DecodeError::UnsupportedChecksum)?;
+
+        (algorithm, checksum)
+    } else {
+        let (&id, digest) =
+            checksum.split_first().ok_or(DecodeError::ChecksumFailed)?;
+
+        let algorithm = ChecksumAlgorithm::from_id(id)
+            .ok_or(DecodeError::UnsupportedChecksum)?;
+
+        (algorithm, digest)
+    };
+
+    if algorithm.digest(bytes) == digest {
+        Ok(())
+    } else {
+        Err(DecodeError::ChecksumFailed)
+    }
 }
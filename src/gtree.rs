@@ -3,7 +3,7 @@ use core::marker::PhantomData;
 use core::mem;
 use core::ops::{Add, AddAssign, Sub, SubAssign};
 
-use crate::{Range, RangeExt};
+use crate::{IntegrityError, Range, RangeExt};
 
 /// A trait used to express the length of a node.
 pub trait Length:
@@ -96,7 +96,6 @@ const _NODE_IDX_LAYOUT_CHECK: usize = {
 ///
 /// TODO: finish describing the data structure.
 #[derive(Clone, PartialEq)]
-#[cfg_attr(feature = "encode", derive(serde::Serialize, serde::Deserialize))]
 pub(crate) struct Gtree<const ARITY: usize, L: Leaf> {
     /// The internal nodes of the Gtree.
     ///
@@ -118,14 +117,6 @@ pub(crate) struct Gtree<const ARITY: usize, L: Leaf> {
     ///
     /// Saving this allows to make repeated edits at the same cursor position
     /// fast af.
-    #[cfg_attr(
-        feature = "encode",
-        serde(bound(serialize = "L::Length: serde::Serialize"))
-    )]
-    #[cfg_attr(
-        feature = "encode",
-        serde(bound(deserialize = "L::Length: serde::Deserialize<'de>"))
-    )]
     cursor: Option<Cursor<L>>,
 }
 
@@ -134,7 +125,6 @@ pub(crate) struct Gtree<const ARITY: usize, L: Leaf> {
 /// It can be passed to [`Gtree::inode()`] and [`Gtree::inode_mut()`] to
 /// get access to the inode.
 #[derive(Clone, Copy, PartialEq, Eq)]
-#[cfg_attr(feature = "encode", derive(serde::Serialize, serde::Deserialize))]
 struct InodeIdx(usize);
 
 impl InodeIdx {
@@ -163,7 +153,6 @@ impl InodeIdx {
 
 /// A stable identifier for a particular leaf in the Gtree.
 #[derive(Eq)]
-#[cfg_attr(feature = "encode", derive(serde::Serialize, serde::Deserialize))]
 pub struct LeafIdx<L> {
     idx: usize,
     _pd: PhantomData<L>,
@@ -193,6 +182,13 @@ impl<L> LeafIdx<L> {
         Self::new(usize::MAX)
     }
 
+    /// Returns the position of the leaf in the Gtree's storage, which stays
+    /// the same for the whole lifetime of the leaf.
+    #[inline]
+    pub fn as_usize(self) -> usize {
+        self.idx
+    }
+
     #[inline]
     const fn new(idx: usize) -> Self {
         Self { idx, _pd: PhantomData }
@@ -205,7 +201,6 @@ impl<L> LeafIdx<L> {
 /// two leaf nodes in the tree, much like a line cursor identifies a position
 /// between two characters in a text editor.
 #[derive(PartialEq, Eq)]
-#[cfg_attr(feature = "encode", derive(serde::Serialize, serde::Deserialize))]
 struct Cursor<L: Leaf> {
     /// The index of the leaf node that comes *after* the cursor. There always
     /// is one because the cursor is never parked after the last leafof the
@@ -284,38 +279,6 @@ impl<const ARITY: usize, L: Leaf> Gtree<ARITY, L> {
         leaf_offset
     }
 
-    /// Asserts the invariants of the Gtree.
-    ///
-    /// If this method returns without panicking, then the Gtree is in a
-    /// consistent state.
-    ///
-    /// Only used for debugging.
-    pub fn assert_invariants(&self) {
-        fn recursively_assert_inode_invariants<const N: usize, L: Leaf>(
-            gtree: &Gtree<N, L>,
-            idx: InodeIdx,
-        ) {
-            gtree.assert_inode_invariants(idx);
-
-            if let Either::Internal(inode_idxs) = gtree.inode(idx).children() {
-                for &idx in inode_idxs {
-                    recursively_assert_inode_invariants(gtree, idx);
-                }
-            }
-        }
-
-        recursively_assert_inode_invariants(self, self.root_idx);
-
-        if let Some(cursor) = self.cursor {
-            assert_eq!(self.offset_of_leaf(cursor.leaf_idx), cursor.offset);
-
-            assert_eq!(
-                self.idx_of_leaf_in_parent(cursor.leaf_idx),
-                cursor.child_idx
-            );
-        }
-    }
-
     /// Returns the average number of children per internal node.
     ///
     /// Just like a Btree, every internal node in the Gtree contains from a
@@ -326,18 +289,86 @@ impl<const ARITY: usize, L: Leaf> Gtree<ARITY, L> {
         (total as f32) / (self.inodes.len() as f32)
     }
 
-    /// Returns an `(empty_leaves, total_leaves)` tuple.
+    /// Checks the invariants of the Gtree, returning an error if any of them
+    /// is violated.
     ///
-    /// Only used for debugging.
-    pub fn count_empty_leaves(&self) -> (usize, usize) {
-        let empty_leaves = self
-            .lnodes
-            .iter()
-            .map(Lnode::value)
-            .filter(|leaf| leaf.is_empty())
-            .count();
+    /// Unlike the rest of the API, this doesn't assume the Gtree to be in a
+    /// consistent state, so it's safe to call it on a Gtree deserialized from
+    /// untrusted data.
+    pub fn check_invariants(&self) -> Result<(), IntegrityError> {
+        if self.root_idx.0 >= self.inodes.len() {
+            return Err(IntegrityError::BrokenLink);
+        }
+
+        let mut cursor_is_valid = self.cursor.is_none();
+
+        let mut leaves_depth = None;
+
+        // The inodes left to check, together with their offset in the Gtree
+        // and their depth. We use a stack instead of recursing because the
+        // depth of a corrupted Gtree is unbounded.
+        let mut stack = vec![(self.root_idx, L::Length::zero(), 0)];
+
+        while let Some((inode_idx, mut offset, depth)) = stack.pop() {
+            let inode = self.inode(inode_idx);
 
-        (empty_leaves, self.lnodes.len())
+            let mut child_lengths = L::Length::zero();
+
+            match inode.children() {
+                Either::Internal(inode_idxs) => {
+                    for &child_idx in inode_idxs {
+                        let Some(child) = self.inodes.get(child_idx.0) else {
+                            return Err(IntegrityError::BrokenLink);
+                        };
+
+                        if child.parent() != inode_idx {
+                            return Err(IntegrityError::BrokenLink);
+                        }
+
+                        stack.push((child_idx, offset, depth + 1));
+                        offset += child.len();
+                        child_lengths += child.len();
+                    }
+                },
+
+                Either::Leaf(leaf_idxs) => {
+                    if *leaves_depth.get_or_insert(depth) != depth {
+                        return Err(IntegrityError::UnbalancedTree);
+                    }
+
+                    for (child_idx, &leaf_idx) in leaf_idxs.iter().enumerate()
+                    {
+                        let Some(child) = self.lnodes.get(leaf_idx.idx) else {
+                            return Err(IntegrityError::BrokenLink);
+                        };
+
+                        if child.parent() != inode_idx {
+                            return Err(IntegrityError::BrokenLink);
+                        }
+
+                        if let Some(cursor) = self.cursor {
+                            if cursor.leaf_idx == leaf_idx {
+                                cursor_is_valid = cursor.offset == offset
+                                    && cursor.child_idx == child_idx;
+                            }
+                        }
+
+                        offset += child.value().len();
+                        child_lengths += child.value().len();
+                    }
+                },
+            }
+
+            if !inode.len().is_zero() && child_lengths != inode.len() {
+                return Err(IntegrityError::LengthMismatch);
+            }
+        }
+
+        if cursor_is_valid {
+            Ok(())
+        } else {
+            Err(IntegrityError::StaleCursor)
+        }
     }
 
     /// Returns a struct whose `Debug` implementation makes it easy to see the
@@ -479,40 +510,90 @@ impl<const ARITY: usize, L: Leaf> Gtree<ARITY, L> {
         )
     }
 
-    /// Creates a new Gtree with from an iterator over some leaves and the
-    /// total length of the all the leaves the iterator will yield.
+    /// Creates a new Gtree from an iterator over its leaves, in order.
+    ///
+    /// The tree is built bottom-up and is as shallow as possible, with the
+    /// leaves spread evenly across the inodes of each level so that every
+    /// inode other than the root has between `ARITY / 2` and `ARITY`
+    /// children.
     ///
-    /// Panics if the iterator yields more than `ARITY` leaves.
+    /// Panics if the iterator doesn't yield any leaves.
     #[inline]
-    pub fn from_leaves<I>(leaves: I, tot_len: L::Length) -> Self
+    pub fn from_leaves<I>(leaves: I) -> Self
     where
-        I: ExactSizeIterator<Item = L>,
+        I: IntoIterator<Item = L>,
     {
-        let len = leaves.len();
+        let mut this = Self::uninit();
 
-        let root_idx = InodeIdx(0);
+        // The indices and lengths of the nodes in the level we're currently
+        // grouping under new inodes, starting from the leaves.
+        let mut level = leaves
+            .into_iter()
+            .map(|leaf| {
+                let len = leaf.len();
+                let idx = this.push_leaf(leaf, InodeIdx::dangling());
+                (NodeIdx::from_leaf(idx), len)
+            })
+            .collect::<Vec<_>>();
 
-        let mut inode_children = [NodeIdx::dangling(); ARITY];
+        assert!(!level.is_empty());
 
-        let mut lnodes = Vec::with_capacity(leaves.len());
+        let mut has_leaves = true;
 
-        for (i, child) in leaves.enumerate() {
-            let leaf_idx = LeafIdx::new(i);
-            inode_children[i] = NodeIdx::from_leaf(leaf_idx);
-            lnodes.push(Lnode::new(child, root_idx));
-        }
+        loop {
+            let num_inodes = level.len().div_ceil(ARITY);
 
-        let inode = Inode {
-            tot_len,
-            parent: InodeIdx::dangling(),
-            num_children: len,
-            children: inode_children,
-            has_leaves: true,
-        };
+            let mut next_level = Vec::with_capacity(num_inodes);
 
-        let inodes = vec![inode];
+            let mut children = level.into_iter();
 
-        Self { inodes, lnodes, root_idx: InodeIdx(0), cursor: None }
+            let mut num_left = children.len();
+
+            for inodes_left in (1..=num_inodes).rev() {
+                let num_children = num_left.div_ceil(inodes_left);
+
+                num_left -= num_children;
+
+                let mut inode = Inode {
+                    tot_len: L::Length::zero(),
+                    parent: InodeIdx::dangling(),
+                    num_children,
+                    children: [NodeIdx::dangling(); ARITY],
+                    has_leaves,
+                };
+
+                for (child, (node_idx, len)) in inode
+                    .children
+                    .iter_mut()
+                    .zip(children.by_ref().take(num_children))
+                {
+                    *child = node_idx;
+                    inode.tot_len += len;
+                }
+
+                let len = inode.tot_len;
+
+                let idx = this.push_inode(inode, InodeIdx::dangling());
+
+                next_level.push((NodeIdx::from_internal(idx), len));
+            }
+
+            if num_inodes == 1 {
+                this.root_idx = InodeIdx(this.inodes.len() - 1);
+                return this;
+            }
+
+            level = next_level;
+
+            has_leaves = false;
+        }
+    }
+
+    /// Returns a shared reference to the leaf node at the given index, or
+    /// `None` if the index is out of bounds.
+    #[inline]
+    pub fn get_leaf(&self, leaf_idx: LeafIdx<L>) -> Option<&L> {
+        self.lnodes.get(leaf_idx.idx).map(Lnode::value)
     }
 
     /// Returns a shared reference to the leaf node at the given index.
@@ -521,6 +602,19 @@ impl<const ARITY: usize, L: Leaf> Gtree<ARITY, L> {
         self.lnode(leaf_idx).value()
     }
 
+    /// Returns the number of levels of internal nodes in the Gtree.
+    #[inline]
+    pub fn height(&self) -> usize {
+        self.inode_height(self.root_idx)
+    }
+
+    /// Returns the number of bytes allocated on the heap to store the
+    /// internal nodes of the Gtree.
+    #[inline]
+    pub fn inodes_heap_size(&self) -> usize {
+        self.inodes.capacity() * mem::size_of::<Inode<ARITY, L>>()
+    }
+
     /// Inserts a leaf at the given offset. The offset must be strictly
     /// positive, if it's zero consider using `prepend()` instead.
     ///
@@ -662,6 +756,20 @@ impl<const ARITY: usize, L: Leaf> Gtree<ARITY, L> {
         self.root().len()
     }
 
+    /// Returns an iterator over all the nodes of the Gtree in depth-first
+    /// pre-order, starting from the root.
+    #[inline]
+    pub fn nodes(&self) -> Nodes<'_, ARITY, L> {
+        Nodes::new(self)
+    }
+
+    /// Returns the number of bytes allocated on the heap to store the leaf
+    /// nodes of the Gtree.
+    #[inline]
+    pub fn lnodes_heap_size(&self) -> usize {
+        self.lnodes.capacity() * mem::size_of::<Lnode<L>>()
+    }
+
     /// Creates a new Gtree with the given leaf as its first leaf.
     #[inline]
     pub fn new(first_leaf: L) -> (Self, LeafIdx<L>) {
@@ -994,41 +1102,6 @@ impl<const ARITY: usize, L: Leaf> Gtree<ARITY, L> {
         }
     }
 
-    /// Asserts the invariants of the given inode. Does not recurse into its
-    /// children.
-    fn assert_inode_invariants(&self, inode_idx: InodeIdx) {
-        let inode = self.inode(inode_idx);
-
-        let inode_height = self.inode_height(inode_idx);
-
-        let mut child_lengths = L::Length::zero();
-
-        match inode.children() {
-            Either::Internal(inode_idxs) => {
-                for &child_idx in inode_idxs {
-                    let child = self.inode(child_idx);
-                    assert_eq!(child.parent(), inode_idx);
-                    assert_eq!(self.inode_height(child_idx) + 1, inode_height);
-                    child_lengths += child.len();
-                }
-            },
-
-            Either::Leaf(leaf_idxs) => {
-                assert_eq!(inode_height, 1);
-
-                for &child_idx in leaf_idxs {
-                    let child = self.lnode(child_idx);
-                    assert_eq!(child.parent(), inode_idx);
-                    child_lengths += child.value().len();
-                }
-            },
-        }
-
-        if !inode.len().is_zero() {
-            assert_eq!(child_lengths, inode.len());
-        }
-    }
-
     /// Inserts `maybe_split` after `inode_idx` in the parent of `inode_idx`.
     ///
     /// It that causes another split, it recurses up the tree.
@@ -1532,12 +1605,6 @@ impl<const ARITY: usize, L: Leaf> Gtree<ARITY, L> {
         }
     }
 
-    #[allow(dead_code)]
-    #[inline(always)]
-    fn height(&self) -> usize {
-        self.inode_height(self.root_idx)
-    }
-
     #[inline(always)]
     fn idx_of_inode_in_parent(&self, inode_idx: InodeIdx) -> ChildIdx {
         let parent = self.inode(self.inode(inode_idx).parent());
@@ -2725,7 +2792,6 @@ impl<const ARITY: usize, L: Leaf> Inode<ARITY, L> {
 
 /// A leaf node of the Gtree.
 #[derive(Debug, Clone, PartialEq)]
-#[cfg_attr(feature = "encode", derive(serde::Serialize, serde::Deserialize))]
 struct Lnode<Leaf> {
     /// The value of this leaf node.
     value: Leaf,
@@ -3356,7 +3422,7 @@ mod debug {
     }
 }
 
-pub use iter::{Leaves, Siblings};
+pub use iter::{Leaves, Node, Nodes, Siblings};
 
 mod iter {
     use super::*;
@@ -3442,234 +3508,143 @@ mod iter {
         }
     }
 
-    #[derive(Debug)]
-    pub struct Siblings<'a, const N: usize, L: Leaf> {
-        gtree: &'a Gtree<N, L>,
-        leaf_idxs: &'a [LeafIdx<L>],
-    }
-
-    impl<'a, const N: usize, L: Leaf> Siblings<'a, N, L> {
-        #[inline]
-        pub(super) fn new<const INCLUDE_START: bool>(
-            gtree: &'a Gtree<N, L>,
-            start_idx: LeafIdx<L>,
-        ) -> Self {
-            let parent_idx = gtree.lnode(start_idx).parent();
-            let parent = gtree.inode(parent_idx);
-            let child_idx = parent.idx_of_leaf_child(start_idx);
-            let siblings = parent.children().unwrap_leaf();
-            let idx = if INCLUDE_START { child_idx } else { child_idx + 1 };
-            Self { gtree, leaf_idxs: &siblings[idx..] }
-        }
-    }
+    /// A node of the Gtree, as yielded by the [`Nodes`] iterator.
+    pub enum Node<'a, L: Leaf> {
+        Internal {
+            /// The position of the inode in the Gtree's storage.
+            idx: usize,
 
-    impl<'a, const N: usize, L: Leaf> Iterator for Siblings<'a, N, L> {
-        type Item = (LeafIdx<L>, &'a L);
+            /// The position of the inode's parent, or `None` for the root.
+            parent: Option<usize>,
 
-        #[inline]
-        fn next(&mut self) -> Option<Self::Item> {
-            let (&first, rest) = self.leaf_idxs.split_first()?;
-            let leaf = self.gtree.leaf(first);
-            self.leaf_idxs = rest;
-            Some((first, leaf))
-        }
-    }
-}
+            /// The depth of the inode, with the root being at depth 0.
+            depth: usize,
 
-#[cfg(feature = "encode")]
-mod inode_serde {
-    use serde::ser::SerializeMap;
-    use serde::{de, ser};
+            /// The combined length of all the leaves under this inode.
+            len: L::Length,
+        },
 
-    use super::*;
+        Leaf {
+            idx: LeafIdx<L>,
 
-    impl<const N: usize, L: Leaf> ser::Serialize for Inode<N, L>
-    where
-        L::Length: ser::Serialize,
-    {
-        fn serialize<S: ser::Serializer>(
-            &self,
-            serializer: S,
-        ) -> Result<S::Ok, S::Error> {
-            let mut map = serializer.serialize_map(Some(5))?;
+            /// The position of the inode this leaf is a child of.
+            parent: usize,
 
-            map.serialize_entry("tot_len", &self.tot_len)?;
-            map.serialize_entry("parent", &self.parent)?;
-            map.serialize_entry("num_children", &self.num_children)?;
-            map.serialize_entry("has_leaves", &self.has_leaves)?;
+            /// The depth of the leaf, which is one more than the depth of its
+            /// parent.
+            depth: usize,
 
-            match self.children() {
-                Either::Internal(inode_idxs) => {
-                    map.serialize_entry("children", inode_idxs)?;
-                },
+            leaf: &'a L,
+        },
+    }
 
-                Either::Leaf(leaf_idxs) => {
-                    map.serialize_entry("children", leaf_idxs)?;
+    impl<L: Leaf> Node<'_, L> {
+        #[inline]
+        pub fn depth(&self) -> usize {
+            match self {
+                Self::Internal { depth, .. } | Self::Leaf { depth, .. } => {
+                    *depth
                 },
             }
-
-            map.end()
         }
     }
 
-    impl<'de, const N: usize, L: Leaf> de::Deserialize<'de> for Inode<N, L>
-    where
-        L::Length: de::Deserialize<'de>,
-    {
-        fn deserialize<D: de::Deserializer<'de>>(
-            deserializer: D,
-        ) -> Result<Self, D::Error> {
-            struct InodeVisitor<const N: usize, L: Leaf>(PhantomData<L>);
-
-            impl<'de, const N: usize, L> de::Visitor<'de> for InodeVisitor<N, L>
-            where
-                L: Leaf,
-                L::Length: de::Deserialize<'de>,
-            {
-                type Value = Inode<N, L>;
-
-                #[inline]
-                fn expecting(
-                    &self,
-                    formatter: &mut core::fmt::Formatter,
-                ) -> core::fmt::Result {
-                    formatter.write_str("a map representing an Inode")
-                }
+    /// An iterator over all the nodes of a Gtree in depth-first pre-order.
+    pub struct Nodes<'a, const N: usize, L: Leaf> {
+        gtree: &'a Gtree<N, L>,
+        stack: Vec<(Either<InodeIdx, LeafIdx<L>>, usize)>,
+    }
 
-                #[inline]
-                fn visit_map<V: de::MapAccess<'de>>(
-                    self,
-                    mut map: V,
-                ) -> Result<Self::Value, V::Error> {
-                    let mut tot_len = None;
-                    let mut parent = None;
-                    let mut num_children = None;
-                    let mut has_leaves = None;
-                    let mut children_vec = None;
-
-                    while let Some(key) = map.next_key()? {
-                        match key {
-                            "tot_len" => {
-                                if tot_len.is_some() {
-                                    return Err(de::Error::duplicate_field(
-                                        "tot_len",
-                                    ));
-                                }
-                                tot_len = Some(map.next_value()?);
-                            },
+    impl<'a, const N: usize, L: Leaf> Nodes<'a, N, L> {
+        #[inline]
+        pub(super) fn new(gtree: &'a Gtree<N, L>) -> Self {
+            Self { gtree, stack: vec![(Either::Internal(gtree.root_idx), 0)] }
+        }
+    }
 
-                            "parent" => {
-                                if parent.is_some() {
-                                    return Err(de::Error::duplicate_field(
-                                        "parent",
-                                    ));
-                                }
-                                parent = Some(map.next_value()?);
-                            },
+    impl<'a, const N: usize, L: Leaf> Iterator for Nodes<'a, N, L> {
+        type Item = Node<'a, L>;
 
-                            "num_children" => {
-                                if num_children.is_some() {
-                                    return Err(de::Error::duplicate_field(
-                                        "num_children",
-                                    ));
-                                }
-                                num_children = Some(map.next_value()?);
-                            },
+        #[inline]
+        fn next(&mut self) -> Option<Self::Item> {
+            let (node_idx, depth) = self.stack.pop()?;
 
-                            "has_leaves" => {
-                                if has_leaves.is_some() {
-                                    return Err(de::Error::duplicate_field(
-                                        "has_leaves",
-                                    ));
-                                }
-                                has_leaves = Some(map.next_value()?);
-                            },
+            let node = match node_idx {
+                Either::Internal(inode_idx) => {
+                    let inode = self.gtree.inode(inode_idx);
 
-                            "children" => {
-                                if children_vec.is_some() {
-                                    return Err(de::Error::duplicate_field(
-                                        "children",
-                                    ));
-                                }
-                                children_vec =
-                                    Some(map.next_value::<Vec<usize>>()?);
-                            },
+                    match inode.children() {
+                        Either::Internal(inode_idxs) => {
+                            self.stack.extend(inode_idxs.iter().rev().map(
+                                |&idx| (Either::Internal(idx), depth + 1),
+                            ));
+                        },
 
-                            _ => {
-                                return Err(de::Error::unknown_field(
-                                    key,
-                                    &[
-                                        "tot_len",
-                                        "parent",
-                                        "num_children",
-                                        "has_leaves",
-                                        "children",
-                                    ],
-                                ));
-                            },
-                        }
+                        Either::Leaf(leaf_idxs) => {
+                            self.stack.extend(
+                                leaf_idxs.iter().rev().map(|&idx| {
+                                    (Either::Leaf(idx), depth + 1)
+                                }),
+                            );
+                        },
                     }
 
-                    let tot_len = tot_len
-                        .ok_or_else(|| de::Error::missing_field("tot_len"))?;
-
-                    let parent = parent
-                        .ok_or_else(|| de::Error::missing_field("parent"))?;
+                    let parent = inode.parent();
 
-                    let num_children = num_children.ok_or_else(|| {
-                        de::Error::missing_field("num_children")
-                    })?;
-
-                    let has_leaves = has_leaves.ok_or_else(|| {
-                        de::Error::missing_field("has_leaves")
-                    })?;
+                    Node::Internal {
+                        idx: inode_idx.0,
+                        parent: (!parent.is_dangling()).then_some(parent.0),
+                        depth,
+                        len: inode.len(),
+                    }
+                },
 
-                    let children_slice = children_vec
-                        .ok_or_else(|| de::Error::missing_field("children"))?;
+                Either::Leaf(leaf_idx) => {
+                    let lnode = self.gtree.lnode(leaf_idx);
 
-                    if children_slice.len() != num_children {
-                        return Err(de::Error::invalid_length(
-                            children_slice.len(),
-                            &num_children.to_string().as_str(),
-                        ));
+                    Node::Leaf {
+                        idx: leaf_idx,
+                        parent: lnode.parent().0,
+                        depth,
+                        leaf: lnode.value(),
                     }
+                },
+            };
 
-                    if children_slice.len() > N {
-                        return Err(de::Error::invalid_length(
-                            children_slice.len(),
-                            &format!("no more than {N}").as_str(),
-                        ));
-                    }
+            Some(node)
+        }
+    }
 
-                    let mut children = [NodeIdx::dangling(); N];
+    #[derive(Debug)]
+    pub struct Siblings<'a, const N: usize, L: Leaf> {
+        gtree: &'a Gtree<N, L>,
+        leaf_idxs: &'a [LeafIdx<L>],
+    }
 
-                    if has_leaves {
-                        for (i, child_idx) in
-                            children_slice.into_iter().enumerate()
-                        {
-                            children[i] =
-                                NodeIdx::from_leaf(LeafIdx::new(child_idx));
-                        }
-                    } else {
-                        for (i, child_idx) in
-                            children_slice.into_iter().enumerate()
-                        {
-                            children[i] =
-                                NodeIdx::from_internal(InodeIdx(child_idx));
-                        }
-                    }
+    impl<'a, const N: usize, L: Leaf> Siblings<'a, N, L> {
+        #[inline]
+        pub(super) fn new<const INCLUDE_START: bool>(
+            gtree: &'a Gtree<N, L>,
+            start_idx: LeafIdx<L>,
+        ) -> Self {
+            let parent_idx = gtree.lnode(start_idx).parent();
+            let parent = gtree.inode(parent_idx);
+            let child_idx = parent.idx_of_leaf_child(start_idx);
+            let siblings = parent.children().unwrap_leaf();
+            let idx = if INCLUDE_START { child_idx } else { child_idx + 1 };
+            Self { gtree, leaf_idxs: &siblings[idx..] }
+        }
+    }
 
-                    Ok(Inode {
-                        tot_len,
-                        parent,
-                        num_children,
-                        has_leaves,
-                        children,
-                    })
-                }
-            }
+    impl<'a, const N: usize, L: Leaf> Iterator for Siblings<'a, N, L> {
+        type Item = (LeafIdx<L>, &'a L);
 
-            deserializer.deserialize_map(InodeVisitor(PhantomData))
+        #[inline]
+        fn next(&mut self) -> Option<Self::Item> {
+            let (&first, rest) = self.leaf_idxs.split_first()?;
+            let leaf = self.gtree.leaf(first);
+            self.leaf_idxs = rest;
+            Some((first, leaf))
         }
     }
 }
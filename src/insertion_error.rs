@@ -0,0 +1,48 @@
+use crate::{ReplicaIdCollision, Text};
+
+/// The type of error returned by
+/// [`try_integrate_insertion`](crate::Replica::try_integrate_insertion) when
+/// an [`Insertion`](crate::Insertion) can't be integrated.
+///
+/// The [`Replica`](crate::Replica) is left untouched when one of these errors
+/// is returned, and the insertion shouldn't be applied to the buffer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InsertionError {
+    /// The insertion conflicts with the ones already seen from the replica
+    /// that made it, so its [`ReplicaId`](crate::ReplicaId) is shared with
+    /// another replica.
+    ReplicaIdCollision(ReplicaIdCollision),
+
+    /// The insertion is anchored to a character that the `Replica` should
+    /// already have according to its version map, but which isn't where the
+    /// insertion claims it is. This means that the insertion was corrupted,
+    /// or that the character was inserted by a replica sharing its id with
+    /// another one.
+    ///
+    /// The [`Text`] of the insertion is given.
+    UnknownAnchor(Text),
+}
+
+impl core::fmt::Display for InsertionError {
+    #[inline]
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::ReplicaIdCollision(collision) => collision.fmt(f),
+            Self::UnknownAnchor(text) => write!(
+                f,
+                "the insertion of the characters {:?} by replica {} is \
+                 anchored to an unknown character",
+                text.range, text.inserted_by,
+            ),
+        }
+    }
+}
+
+impl std::error::Error for InsertionError {}
+
+impl From<ReplicaIdCollision> for InsertionError {
+    #[inline]
+    fn from(collision: ReplicaIdCollision) -> Self {
+        Self::ReplicaIdCollision(collision)
+    }
+}
@@ -0,0 +1,49 @@
+use crate::ReplicaId;
+
+/// The type of error returned by
+/// [`check_integrity`](crate::Replica::check_integrity) when a
+/// [`Replica`](crate::Replica)'s internal state is inconsistent.
+///
+/// A `Replica` that's only ever modified through its public API should never
+/// fail its integrity check, so getting one of these errors means that either
+/// the `Replica` was decoded from corrupted data or that there's a bug in
+/// cola. Either way, the `Replica` shouldn't be used anymore.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IntegrityError {
+    /// A node of the run tree points to a node that doesn't exist, or to one
+    /// that doesn't point back to it as its parent.
+    BrokenLink,
+
+    /// The leaves of the run tree are not all at the same depth.
+    UnbalancedTree,
+
+    /// The length stored in an internal node of the run tree doesn't match
+    /// the combined length of its children.
+    LengthMismatch,
+
+    /// The cached position of the last edit in the run tree points to the
+    /// wrong place.
+    StaleCursor,
+
+    /// The index used to find the runs inserted by the given replica doesn't
+    /// match the runs in the run tree.
+    RunIndices {
+        /// The `ReplicaId` of the replica whose runs are mis-indexed.
+        replica_id: ReplicaId,
+    },
+
+    /// The version map doesn't record the same number of characters inserted
+    /// by the given replica as the runs it inserted in the run tree.
+    VersionMap {
+        /// The `ReplicaId` of the replica whose clock is out of sync.
+        replica_id: ReplicaId,
+    },
+
+    /// The backlogged edits of the given replica are out of order, they
+    /// include edits that have already been merged, or they were made by the
+    /// local replica itself.
+    Backlog {
+        /// The `ReplicaId` of the replica whose backlog is inconsistent.
+        replica_id: ReplicaId,
+    },
+}
@@ -108,11 +108,42 @@
 //! - `encode`: enables the [`encode`](Replica::encode) and
 //! [`decode`](Replica::decode) methods on [`Replica`] (disabled by default);
 //!
+//! - `crc32c`: enables `ChecksumAlgorithm::Crc32c` (disabled by default);
+//!
+//! - `xxhash`: enables `ChecksumAlgorithm::XxHash` (disabled by default);
+//!
+//! - `sha256`: enables `ChecksumAlgorithm::Sha256`, which becomes the default
+//! checksum algorithm, and the [`encode_to_writer`](Replica::encode_to_writer)
+//! and [`decode_from_reader`](Replica::decode_from_reader) methods on
+//! [`Replica`] (disabled by default);
+//!
 //! - `serde`: enables the [`Serialize`] and [`Deserialize`] impls for
-//! [`Insertion`], [`Deletion`] and [`EncodedReplica`] (disabled by default).
+//! [`Insertion`], [`Deletion`] and [`EncodedReplica`], and the `sha256`
+//! feature (disabled by default);
+//!
+//! - `persist`: enables the [`ReplicaStore`] type, which persists a
+//! [`Replica`] to disk by appending its edits to a log (disabled by default);
+//!
+//! - `async`: enables the [`ReplicaHandle`] type, which shares a [`Replica`]
+//! between async tasks by handing it to a [`ReplicaActor`] future that can
+//! be spawned on any runtime (disabled by default);
+//!
+//! - `futures`: enables the [`SinkStreamTransport`] type, which lets a
+//! [`Session`] exchange its messages over any `Sink` and `Stream` pair
+//! (disabled by default);
+//!
+//! - `lsp`: enables the [`LspDocument`] type, which converts between cola's
+//! edits and the Language Server Protocol's text synchronization events
+//! (disabled by default);
+//!
+//! - `yjs`: enables the [`encode_yjs_update`](Replica::encode_yjs_update)
+//! and [`decode_yjs_update`](Replica::decode_yjs_update) methods on
+//! [`Replica`], which convert its history to and from the update format of
+//! [Yjs] (disabled by default).
 //!
 //! [CRDT]: https://en.wikipedia.org/wiki/Conflict-free_replicated_data_type
 //! [cola]: https://nomad.foo/blog/cola
+//! [Yjs]: https://yjs.dev
 //! [`Serialize`]: https://docs.rs/serde/latest/serde/trait.Serialize.html
 //! [`Deserialize`]: https://docs.rs/serde/latest/serde/trait.Deserialize.html
 
@@ -128,35 +159,102 @@ extern crate alloc;
 
 mod backlog;
 mod crdt_edit;
+mod departure;
 mod gtree;
+mod insertion_error;
+mod integrity_error;
+mod memory_report;
 mod replica;
 mod replica_id;
+mod replica_stats;
 mod run_indices;
 mod run_tree;
+mod run_tree_dump;
+mod session;
 mod text_edit;
 mod utils;
 mod version_map;
 
 use backlog::Backlog;
 pub use backlog::{BackloggedDeletions, BackloggedInsertions};
-pub use crdt_edit::{Deletion, Insertion};
+pub use crdt_edit::{CrdtEdit, Deletion, Insertion};
+pub use departure::Departure;
 use gtree::{Gtree, LeafIdx};
+pub use insertion_error::InsertionError;
+pub use integrity_error::IntegrityError;
+pub use memory_report::MemoryReport;
 pub use replica::Replica;
 use replica::*;
-pub use replica_id::ReplicaId;
+pub use replica_id::{ReplicaId, ReplicaIdAllocator, ReplicaIdCollision};
 use replica_id::{ReplicaIdMap, ReplicaIdMapValuesMut};
+pub use replica_stats::ReplicaStats;
 use run_indices::{AnchorBias, RunIndices};
 use run_tree::*;
+pub use run_tree_dump::RunTreeDump;
+pub use session::{
+    ChannelTransport,
+    Message,
+    SeqNum,
+    Session,
+    TextChange,
+    Transport,
+};
 pub use text_edit::Text;
 use utils::*;
 use version_map::{DeletionMap, VersionMap};
 
+#[cfg(feature = "encode")]
+mod encode;
+#[cfg(feature = "encode")]
+mod encoded_document;
+#[cfg(feature = "encode")]
+pub use encoded_document::EncodedDocument;
 #[cfg(feature = "encode")]
 mod encoded_replica;
 #[cfg(feature = "encode")]
-use encoded_replica::checksum;
+use encoded_replica::{checksum, verify_checksum};
+#[cfg(feature = "encode")]
+pub use encoded_replica::{ChecksumAlgorithm, DecodeError, EncodedReplica};
+#[cfg(feature = "encode")]
+mod protocol;
 #[cfg(feature = "encode")]
-pub use encoded_replica::{DecodeError, EncodedReplica};
+pub use protocol::{
+    negotiate_protocol,
+    MIN_DECODABLE_PROTOCOL_VERSION,
+    SUPPORTED_PROTOCOL_VERSIONS,
+};
+#[cfg(feature = "sha256")]
+mod stream;
+
+#[cfg(feature = "lsp")]
+mod lsp;
+#[cfg(feature = "lsp")]
+pub use lsp::LspDocument;
+
+#[cfg(feature = "persist")]
+mod persist;
+#[cfg(feature = "persist")]
+pub use persist::ReplicaStore;
+#[cfg(feature = "persist")]
+mod record_log;
+#[cfg(feature = "futures")]
+pub use session::SinkStreamTransport;
+
+#[cfg(feature = "async")]
+mod replica_handle;
+#[cfg(feature = "async")]
+pub use replica_handle::{
+    ActorStopped,
+    EditError,
+    ReplicaActor,
+    ReplicaHandle,
+    TextChanges,
+};
+
+#[cfg(feature = "yjs")]
+mod yjs;
+#[cfg(feature = "yjs")]
+pub use yjs::{YjsError, MAX_YJS_CLIENT_ID};
 
 /// The version of the protocol cola uses to represent `EncodedReplica`s and
 /// `CrdtEdit`s.
@@ -166,8 +264,11 @@ pub use encoded_replica::{DecodeError, EncodedReplica};
 /// between peers.
 ///
 /// If different peers are using versions of cola with the same protocol number
-/// they're compatible. If not, decoding `EncodedReplica`s and deserializing
-/// `CrdtEdit`s will fail.
+/// they're compatible. A newer version of cola can also decode the
+/// `EncodedReplica`s of older protocol versions down to
+/// `MIN_DECODABLE_PROTOCOL_VERSION` by migrating them to its own protocol,
+/// and peers running different versions can agree on a common protocol via
+/// `negotiate_protocol`.
 ///
 /// # Protocol stability
 ///
@@ -263,8 +364,16 @@ pub type ProtocolVersion = u64;
 /// ```
 pub type Length = usize;
 
+/// The default maximum number of children of the internal nodes of the tree
+/// used by a [`Replica`] to store its edit runs.
+///
+/// See the [`Replica`] documentation for more infos on how to choose a
+/// different one.
+pub const DEFAULT_ARITY: usize = 32;
+
 /// The protocol version of the current cola release.
 ///
 /// See [`ProtocolVersion`] for more infos.
 #[cfg(feature = "encode")]
-const PROTOCOL_VERSION: ProtocolVersion = 0;
+#[cfg_attr(docsrs, doc(cfg(feature = "encode")))]
+pub const PROTOCOL_VERSION: ProtocolVersion = 2;
@@ -0,0 +1,467 @@
+use core::ops::Range;
+use std::collections::HashMap;
+
+use lsp_types::{Position, Range as LspRange, TextDocumentContentChangeEvent};
+
+use crate::*;
+
+/// A [`Replica`] together with the text buffer it tracks, which translates
+/// between cola's edits and the `TextDocumentContentChangeEvent`s used by the
+/// Language Server Protocol's incremental text synchronization.
+///
+/// Incoming `textDocument/didChange` events are applied to the buffer as local
+/// edits via [`apply_change`](Self::apply_change), which returns the
+/// [`Deletion`] and [`Insertion`] to be sent to the other peers.
+///
+/// Remote edits are integrated via
+/// [`integrate_insertion`](Self::integrate_insertion) and
+/// [`integrate_deletion`](Self::integrate_deletion), which return the change
+/// events to forward to the LSP client (or server) on the other side.
+///
+/// The `Replica` wrapped by an `LspDocument` measures lengths in UTF-8 bytes,
+/// so all the peers in the session must do the same. Only the events use
+/// UTF-16 code units, as mandated by the LSP specification.
+///
+/// # Examples
+///
+/// ```
+/// # use cola::LspDocument;
+/// # use lsp_types::{Position, Range, TextDocumentContentChangeEvent};
+/// let mut peer1 = LspDocument::new(1, "fn main() {}\n");
+/// let mut peer2 = peer1.fork(2);
+///
+/// // The editor at peer 1 inserts a line after the first one.
+/// let change = TextDocumentContentChangeEvent {
+///     range: Some(Range::new(Position::new(1, 0), Position::new(1, 0))),
+///     range_length: None,
+///     text: "// 🥤\n".to_owned(),
+/// };
+///
+/// let (deletion, insertion) = peer1.apply_change(&change);
+///
+/// assert!(deletion.is_none());
+///
+/// // Peer 2 integrates the insertion and gets back the event to send to its
+/// // own editor.
+/// let event = peer2.integrate_insertion(&insertion.unwrap(), &change.text);
+///
+/// assert_eq!(event.unwrap().range, change.range);
+/// assert_eq!(peer1.text(), peer2.text());
+/// ```
+#[cfg_attr(docsrs, doc(cfg(feature = "lsp")))]
+pub struct LspDocument {
+    /// The contents of the document.
+    buffer: String,
+
+    /// The CRDT tracking the edits made to the buffer.
+    replica: Replica,
+
+    /// The contents of the remote insertions that are sitting in the
+    /// `Replica`'s backlog.
+    backlog: HashMap<Text, String>,
+}
+
+impl core::fmt::Debug for LspDocument {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.debug_struct("LspDocument")
+            .field("buffer", &self.buffer)
+            .field("replica", &self.replica)
+            .finish()
+    }
+}
+
+impl LspDocument {
+    /// Applies a `textDocument/didChange` content change to the buffer,
+    /// returning the [`Deletion`] and the [`Insertion`] to be sent to the
+    /// other peers.
+    ///
+    /// Changes without a range replace the whole document. If the change also
+    /// carries a new text the `Deletion` must be sent before the `Insertion`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use cola::LspDocument;
+    /// # use lsp_types::{Position, Range, TextDocumentContentChangeEvent};
+    /// let mut document = LspDocument::new(1, "Hello world");
+    ///
+    /// let change = TextDocumentContentChangeEvent {
+    ///     range: Some(Range::new(Position::new(0, 5), Position::new(0, 6))),
+    ///     range_length: None,
+    ///     text: ", ".to_owned(),
+    /// };
+    ///
+    /// let (deletion, insertion) = document.apply_change(&change);
+    ///
+    /// assert!(deletion.is_some());
+    /// assert!(insertion.is_some());
+    /// assert_eq!(document.text(), "Hello, world");
+    /// ```
+    #[must_use]
+    #[inline]
+    pub fn apply_change(
+        &mut self,
+        change: &TextDocumentContentChangeEvent,
+    ) -> (Option<Deletion>, Option<Insertion>) {
+        let (start, end) = match change.range {
+            Some(range) => {
+                let start = offset_of_position(&self.buffer, range.start);
+                let end = offset_of_position(&self.buffer, range.end);
+                (start.min(end), start.max(end))
+            },
+
+            None => (0, self.buffer.len()),
+        };
+
+        let deletion = (start < end).then(|| {
+            self.buffer.replace_range(start..end, "");
+            self.replica.deleted(start..end)
+        });
+
+        let insertion = (!change.text.is_empty()).then(|| {
+            self.buffer.insert_str(start, &change.text);
+            self.replica.inserted(start, change.text.len())
+        });
+
+        (deletion, insertion)
+    }
+
+    /// Returns the [`Text`]s of the insertions sitting in the `Replica`'s
+    /// backlog together with their contents, which should be sent along
+    /// with the `Replica` when passing it to
+    /// [`from_replica`](Self::from_replica).
+    #[inline]
+    pub fn backlogged_texts(&self) -> impl Iterator<Item = (&Text, &str)> {
+        self.backlog.iter().map(|(text, contents)| (text, contents.as_str()))
+    }
+
+    /// Creates a new `LspDocument` from a [`Replica`], the contents of the
+    /// buffer it tracks and the contents of the insertions in its backlog
+    /// (see [`backlogged_texts`](Self::backlogged_texts)), e.g. after
+    /// [`decode`](Replica::decode)ing a `Replica` received from another peer.
+    ///
+    /// The backlogged insertions whose contents are missing are dropped from
+    /// the `Replica`, so they'll be backlogged again if they're integrated
+    /// before they can be applied.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the length of the text in bytes doesn't match the length of
+    /// the `Replica`.
+    #[track_caller]
+    #[inline]
+    pub fn from_replica<T, I>(
+        mut replica: Replica,
+        text: T,
+        backlogged: I,
+    ) -> Self
+    where
+        T: Into<String>,
+        I: IntoIterator<Item = (Text, String)>,
+    {
+        let buffer = text.into();
+
+        assert_eq!(
+            buffer.len(),
+            replica.len(),
+            "the length of the text doesn't match the length of the Replica"
+        );
+
+        let mut backlog = backlogged.into_iter().collect::<HashMap<_, _>>();
+
+        replica.backlog_mut().retain_insertions(|insertion| {
+            backlog.contains_key(insertion.text())
+        });
+
+        backlog
+            .retain(|text, _| replica.backlog_mut().contains_insertion(text));
+
+        Self { buffer, replica, backlog }
+    }
+
+    /// Creates a new `LspDocument` with the same contents as this one but
+    /// with a [`fork`](Replica::fork)ed `Replica`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the [`ReplicaId`] is zero.
+    #[track_caller]
+    #[inline]
+    pub fn fork(&self, new_id: ReplicaId) -> Self {
+        Self {
+            buffer: self.buffer.clone(),
+            replica: self.replica.fork(new_id),
+            backlog: self.backlog.clone(),
+        }
+    }
+
+    /// Integrates the edits in the `Replica`'s backlog which are now ready to
+    /// be applied, returning the change events to forward to the editor.
+    ///
+    /// See [`Replica::backlogged_insertions`] and
+    /// [`Replica::backlogged_deletions`] for more information.
+    #[must_use]
+    #[inline]
+    pub fn integrate_backlogged(
+        &mut self,
+    ) -> Vec<TextDocumentContentChangeEvent> {
+        let mut events = Vec::new();
+
+        // Insertions whose contents we don't have can't be applied to the
+        // buffer, so like in `from_replica` we drop them from the `Replica`'s
+        // backlog before integrating it.
+        let backlog = &self.backlog;
+
+        self.replica.backlog_mut().retain_insertions(|insertion| {
+            backlog.contains_key(insertion.text())
+        });
+
+        for (text, offset) in self.replica.backlogged_insertions() {
+            let Some(text) = self.backlog.remove(&text) else { continue };
+            events.push(change_event(&self.buffer, offset..offset, &text));
+            self.buffer.insert_str(offset, &text);
+        }
+
+        for ranges in self.replica.backlogged_deletions() {
+            delete_ranges(&mut self.buffer, ranges, &mut events);
+        }
+
+        events
+    }
+
+    /// Integrates a remote [`Deletion`], returning the change events to
+    /// forward to the editor.
+    ///
+    /// If the deleted range has been split by concurrent insertions the
+    /// `Deletion` results in multiple events. These are returned from the
+    /// last range to the first one, so that the positions of each event are
+    /// still valid after the previous events have been applied. The events
+    /// must therefore be applied in the order they're returned in.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use cola::LspDocument;
+    /// # use lsp_types::{Position, Range, TextDocumentContentChangeEvent};
+    /// let mut peer1 = LspDocument::new(1, "abcd");
+    /// let mut peer2 = peer1.fork(2);
+    ///
+    /// let delete_bc = TextDocumentContentChangeEvent {
+    ///     range: Some(Range::new(Position::new(0, 1), Position::new(0, 3))),
+    ///     range_length: None,
+    ///     text: String::new(),
+    /// };
+    ///
+    /// let insert_x = TextDocumentContentChangeEvent {
+    ///     range: Some(Range::new(Position::new(0, 2), Position::new(0, 2))),
+    ///     range_length: None,
+    ///     text: "x".to_owned(),
+    /// };
+    ///
+    /// let (deletion, _) = peer1.apply_change(&delete_bc);
+    /// let _ = peer2.apply_change(&insert_x);
+    ///
+    /// // The "x" was inserted between the "b" and the "c", so the deletion
+    /// // is split in two.
+    /// let events = peer2.integrate_deletion(&deletion.unwrap());
+    ///
+    /// assert_eq!(events.len(), 2);
+    /// assert_eq!(events[0].range.unwrap().start, Position::new(0, 3));
+    /// assert_eq!(events[1].range.unwrap().start, Position::new(0, 1));
+    /// assert_eq!(peer2.text(), "axd");
+    /// ```
+    #[must_use]
+    #[inline]
+    pub fn integrate_deletion(
+        &mut self,
+        deletion: &Deletion,
+    ) -> Vec<TextDocumentContentChangeEvent> {
+        let ranges = self.replica.integrate_deletion(deletion);
+        let mut events = Vec::with_capacity(ranges.len());
+        delete_ranges(&mut self.buffer, ranges, &mut events);
+        events
+    }
+
+    /// Integrates a remote [`Insertion`] of the given text, optionally
+    /// returning the change event to forward to the editor.
+    ///
+    /// A `None` value is returned if the `Insertion` has already been
+    /// integrated or if it was backlogged, in which case the event will be
+    /// returned by [`integrate_backlogged`](Self::integrate_backlogged) once
+    /// the `Insertion` can be applied.
+    ///
+    /// `None` is also returned, leaving the document untouched, if the
+    /// length of the text in bytes doesn't match the length of the
+    /// `Insertion`, or if the `Replica` rejects the `Insertion` (see
+    /// [`Replica::try_integrate_insertion`]).
+    #[must_use]
+    #[inline]
+    pub fn integrate_insertion(
+        &mut self,
+        insertion: &Insertion,
+        text: &str,
+    ) -> Option<TextDocumentContentChangeEvent> {
+        if insertion.text().temporal_range().len() != text.len() {
+            return None;
+        }
+
+        match self.replica.try_integrate_insertion(insertion) {
+            Ok(Some(offset)) => {
+                let event = change_event(&self.buffer, offset..offset, text);
+                self.buffer.insert_str(offset, text);
+                Some(event)
+            },
+
+            Ok(None) => {
+                // The insertion was backlogged, so we hold on to its text
+                // until it can be integrated.
+                if !insertion.is_no_op()
+                    && !self.replica.has_merged_insertion(insertion)
+                {
+                    self.backlog
+                        .entry(insertion.text().clone())
+                        .or_insert_with(|| text.to_owned());
+                }
+                None
+            },
+
+            Err(_) => None,
+        }
+    }
+
+    /// Creates a new `LspDocument` with the given [`ReplicaId`] and initial
+    /// text.
+    ///
+    /// Like with [`Replica::new`], this should only be used by the first peer
+    /// in the session. The other peers should get their `LspDocument` by
+    /// either [`fork`](Self::fork)ing an existing one or by calling
+    /// [`from_replica`](Self::from_replica).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the [`ReplicaId`] is zero.
+    #[track_caller]
+    #[inline]
+    pub fn new<T: Into<String>>(replica_id: ReplicaId, text: T) -> Self {
+        let buffer = text.into();
+        let replica = Replica::new(replica_id, buffer.len());
+        Self { buffer, replica, backlog: HashMap::new() }
+    }
+
+    /// Returns the [`Replica`] tracking this document.
+    #[inline]
+    pub fn replica(&self) -> &Replica {
+        &self.replica
+    }
+
+    /// Returns the current contents of the document.
+    #[inline]
+    pub fn text(&self) -> &str {
+        self.buffer.as_str()
+    }
+}
+
+/// Creates the event replacing the given byte range of the buffer with the
+/// given text.
+#[inline]
+fn change_event(
+    buffer: &str,
+    byte_range: Range<usize>,
+    text: &str,
+) -> TextDocumentContentChangeEvent {
+    let start = position_of_offset(buffer, byte_range.start);
+    let end = position_of_offset(buffer, byte_range.end);
+
+    TextDocumentContentChangeEvent {
+        range: Some(LspRange::new(start, end)),
+        range_length: None,
+        text: text.to_owned(),
+    }
+}
+
+/// Deletes the given byte ranges from the buffer, from the last one to the
+/// first, pushing the corresponding events onto `events`.
+#[inline]
+fn delete_ranges(
+    buffer: &mut String,
+    ranges: Vec<Range<Length>>,
+    events: &mut Vec<TextDocumentContentChangeEvent>,
+) {
+    for range in ranges.into_iter().rev() {
+        events.push(change_event(buffer, range.clone(), ""));
+        buffer.replace_range(range, "");
+    }
+}
+
+/// Converts an LSP [`Position`] into a byte offset into the text.
+///
+/// Like the specification requires, a character offset greater than the length
+/// of the line defaults back to the end of the line, and a line greater than
+/// the number of lines defaults to the end of the text.
+#[inline]
+fn offset_of_position(text: &str, position: Position) -> usize {
+    let bytes = text.as_bytes();
+
+    let mut line_start = 0;
+
+    let mut line = 0;
+
+    while line < position.line {
+        match bytes.get(line_start) {
+            Some(b'\n') => line += 1,
+
+            Some(b'\r') => {
+                if bytes.get(line_start + 1) != Some(&b'\n') {
+                    line += 1;
+                }
+            },
+
+            Some(_) => {},
+
+            None => return text.len(),
+        }
+
+        line_start += 1;
+    }
+
+    let mut utf16_offset = 0;
+
+    for (offset, ch) in text[line_start..].char_indices() {
+        if ch == '\n' || ch == '\r' || utf16_offset >= position.character {
+            return line_start + offset;
+        }
+        utf16_offset += ch.len_utf16() as u32;
+    }
+
+    text.len()
+}
+
+/// Converts a byte offset into the text into an LSP [`Position`].
+#[inline]
+fn position_of_offset(text: &str, offset: usize) -> Position {
+    let mut position = Position::new(0, 0);
+
+    let mut chars = text[..offset].chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\n' => {
+                position.line += 1;
+                position.character = 0;
+            },
+
+            // A "\r\n" only counts as a single line break, which we'll handle
+            // when we get to the '\n'.
+            '\r' if chars.peek() == Some(&'\n') => {},
+
+            '\r' => {
+                position.line += 1;
+                position.character = 0;
+            },
+
+            _ => position.character += ch.len_utf16() as u32,
+        }
+    }
+
+    position
+}
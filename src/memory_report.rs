@@ -0,0 +1,93 @@
+/// A breakdown of the memory used by a [`Replica`](crate::Replica).
+///
+/// This struct is created by the
+/// [`memory_usage`](crate::Replica::memory_usage) method on
+/// [`Replica`](crate::Replica). See its documentation for more information.
+///
+/// All the sizes are in bytes and only account for the memory allocated on
+/// the heap, i.e. they don't include the `size_of::<Replica>()` bytes taken
+/// by the `Replica` struct itself. The sizes of the hash maps are estimates
+/// since the standard library doesn't expose their exact memory layout.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryReport {
+    pub(crate) gtree_inodes: usize,
+    pub(crate) gtree_lnodes: usize,
+    pub(crate) run_indices: usize,
+    pub(crate) backlog: usize,
+    pub(crate) version_map: usize,
+    pub(crate) deletion_map: usize,
+    pub(crate) visible_runs: usize,
+    pub(crate) tombstoned_runs: usize,
+}
+
+impl MemoryReport {
+    /// Returns the number of bytes held by the backlog of remote edits that
+    /// are waiting to be merged.
+    #[inline]
+    pub fn backlog(&self) -> usize {
+        self.backlog
+    }
+
+    /// Returns the number of bytes held by the map used to keep track of the
+    /// deletions merged from every replica.
+    #[inline]
+    pub fn deletion_map(&self) -> usize {
+        self.deletion_map
+    }
+
+    /// Returns the number of bytes held by the internal nodes of the tree of
+    /// edit runs.
+    #[inline]
+    pub fn gtree_inodes(&self) -> usize {
+        self.gtree_inodes
+    }
+
+    /// Returns the number of bytes held by the leaf nodes of the tree of edit
+    /// runs.
+    #[inline]
+    pub fn gtree_lnodes(&self) -> usize {
+        self.gtree_lnodes
+    }
+
+    /// Returns the number of bytes held by the secondary index used to map
+    /// CRDT anchors to the edit runs containing them.
+    #[inline]
+    pub fn run_indices(&self) -> usize {
+        self.run_indices
+    }
+
+    /// Returns the number of edit runs whose text has been deleted.
+    ///
+    /// Tombstoned runs are never removed from the tree, so a document with
+    /// many more tombstoned runs than [`visible`](Self::visible_runs) ones is
+    /// mostly spending its memory on deleted text.
+    #[inline]
+    pub fn tombstoned_runs(&self) -> usize {
+        self.tombstoned_runs
+    }
+
+    /// Returns the total number of bytes held by the `Replica`, i.e. the sum
+    /// of all the other sizes in this report.
+    #[inline]
+    pub fn total(&self) -> usize {
+        self.gtree_inodes
+            + self.gtree_lnodes
+            + self.run_indices
+            + self.backlog
+            + self.version_map
+            + self.deletion_map
+    }
+
+    /// Returns the number of bytes held by the map used to keep track of the
+    /// insertions merged from every replica.
+    #[inline]
+    pub fn version_map(&self) -> usize {
+        self.version_map
+    }
+
+    /// Returns the number of edit runs whose text is still in the document.
+    #[inline]
+    pub fn visible_runs(&self) -> usize {
+        self.visible_runs
+    }
+}
@@ -0,0 +1,352 @@
+use core::ops::{Range, RangeBounds};
+use std::fs::{self, File};
+use std::io::{self, BufReader};
+use std::path::{Path, PathBuf};
+
+use crate::*;
+
+/// The name of the file containing the latest checkpoint.
+const CHECKPOINT: &str = "checkpoint";
+
+/// The name of the file a new checkpoint is written to before atomically
+/// replacing the previous one.
+const CHECKPOINT_TMP: &str = "checkpoint.tmp";
+
+/// The name of the file containing the log of the edits applied since the
+/// latest checkpoint.
+const LOG: &str = "log";
+
+/// The number of edits appended to the log before a new checkpoint is
+/// written, unless changed via
+/// [`set_checkpoint_interval`](ReplicaStore::set_checkpoint_interval).
+const DEFAULT_CHECKPOINT_INTERVAL: usize = 1024;
+
+/// The kind of edit stored in a log record, identified by its first byte.
+#[derive(Clone, Copy)]
+enum RecordKind {
+    Insertion = 0,
+    Deletion = 1,
+}
+
+/// A [`Replica`] persisted to disk in an append-only fashion.
+///
+/// Calling [`encode`](Replica::encode) on every save rewrites the whole state
+/// of the `Replica`, which gets expensive as the document grows. A
+/// `ReplicaStore` instead appends every `Insertion` and `Deletion` it sees to
+/// a log, and only periodically writes a checkpoint of the whole `Replica`,
+/// after which the log is cleared.
+///
+/// The store lives in a directory containing two files:
+///
+/// - `checkpoint`: the `Replica` as it was when the latest checkpoint was
+///   written, encoded via [`encode_to_writer`](Replica::encode_to_writer);
+/// - `log`: the edits applied to the `Replica` since then, each stored in a
+///   record made of the length of its contents as a little-endian `u32`, the
+///   contents themselves (i.e. a byte identifying the kind of edit followed
+///   by its [encoding](Insertion::encode)) and their CRC32C checksum as a
+///   little-endian `u32`.
+///
+/// When the store is [`open`](ReplicaStore::open)ed the `Replica` is
+/// recovered by loading the checkpoint and replaying the log on top of it.
+/// If the process crashed while appending to the log, the torn record at its
+/// end is truncated away, together with everything after it. New
+/// checkpoints are written to a temporary file which atomically replaces the
+/// previous one, so a crash while writing a checkpoint leaves the previous
+/// one untouched.
+///
+/// Note that the edits yielded by
+/// [`backlogged_insertions`](Replica::backlogged_insertions) and
+/// [`backlogged_deletions`](Replica::backlogged_deletions) are not logged, so
+/// they're only persisted by the next checkpoint.
+///
+/// # Examples
+///
+/// ```
+/// # use cola::{Replica, ReplicaStore};
+/// # let dir = tempfile::tempdir().unwrap();
+/// let mut store = ReplicaStore::create(dir.path(), Replica::new(1, 0))?;
+///
+/// let _ = store.inserted(0, 5)?;
+///
+/// drop(store);
+///
+/// // Reopening the store recovers the replica as it was before, which can
+/// // keep editing the document as the same peer.
+/// let mut store = ReplicaStore::open(dir.path())?;
+///
+/// assert_eq!(store.replica().id(), 1);
+/// assert_eq!(store.replica().len(), 5);
+///
+/// let _ = store.inserted(5, 3)?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[cfg_attr(docsrs, doc(cfg(feature = "persist")))]
+pub struct ReplicaStore {
+    /// The `Replica` being persisted.
+    replica: Replica,
+
+    /// The directory containing the checkpoint and the log.
+    dir: PathBuf,
+
+    /// The log of the edits applied since the latest checkpoint.
+    log: File,
+
+    /// The number of edits in the log.
+    logged_edits: usize,
+
+    /// The number of edits after which a new checkpoint is written.
+    checkpoint_interval: usize,
+}
+
+impl core::fmt::Debug for ReplicaStore {
+    #[inline]
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("ReplicaStore")
+            .field("dir", &self.dir)
+            .field("logged_edits", &self.logged_edits)
+            .field("checkpoint_interval", &self.checkpoint_interval)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ReplicaStore {
+    /// Appends an edit to the log.
+    #[inline]
+    fn append(&mut self, kind: RecordKind, edit: &[u8]) -> io::Result<()> {
+        let mut contents = Vec::with_capacity(edit.len() + 1);
+        contents.push(kind as u8);
+        contents.extend_from_slice(edit);
+        record_log::append(&mut self.log, &contents)
+    }
+
+    /// Writes a new checkpoint of the `Replica` and clears the log.
+    ///
+    /// This is called automatically every
+    /// [`set_checkpoint_interval`](ReplicaStore::set_checkpoint_interval)
+    /// edits, but can also be called manually, for example before shutting
+    /// down.
+    #[inline]
+    pub fn checkpoint(&mut self) -> io::Result<()> {
+        record_log::replace(
+            &self.dir,
+            CHECKPOINT,
+            CHECKPOINT_TMP,
+            |writer| self.replica.encode_to_writer(writer),
+        )?;
+
+        // If we crash before the log is cleared the edits it contains will be
+        // replayed on top of a checkpoint that already contains them, which
+        // is fine since integrating the same edit twice is a no-op.
+        record_log::clear(&mut self.log)?;
+        self.logged_edits = 0;
+
+        Ok(())
+    }
+
+    /// Creates a new store in the given directory, writing the `Replica` as
+    /// its first checkpoint.
+    ///
+    /// The directory is created if it doesn't exist. Returns an error of
+    /// kind [`AlreadyExists`](io::ErrorKind::AlreadyExists) if it already
+    /// contains a store.
+    #[inline]
+    pub fn create(
+        dir: impl AsRef<Path>,
+        replica: Replica,
+    ) -> io::Result<Self> {
+        let dir = dir.as_ref();
+
+        fs::create_dir_all(dir)?;
+
+        if dir.join(CHECKPOINT).exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "the directory already contains a replica store",
+            ));
+        }
+
+        let log = record_log::open(&dir.join(LOG))?;
+
+        let mut store = Self {
+            replica,
+            dir: dir.to_owned(),
+            log,
+            logged_edits: 0,
+            checkpoint_interval: DEFAULT_CHECKPOINT_INTERVAL,
+        };
+
+        store.checkpoint()?;
+
+        Ok(store)
+    }
+
+    /// Informs the `Replica` that you have deleted the characters in the
+    /// given offset range, appending the resulting [`Deletion`] to the log.
+    ///
+    /// See [`Replica::deleted`] for more information.
+    #[track_caller]
+    #[inline]
+    pub fn deleted<R>(&mut self, range: R) -> io::Result<Deletion>
+    where
+        R: RangeBounds<Length>,
+    {
+        let deletion = self.replica.deleted(range);
+        self.append(RecordKind::Deletion, &deletion.encode())?;
+        self.logged()?;
+        Ok(deletion)
+    }
+
+    /// Informs the `Replica` that you have inserted `len` characters at the
+    /// given offset, appending the resulting [`Insertion`] to the log.
+    ///
+    /// See [`Replica::inserted`] for more information.
+    #[track_caller]
+    #[inline]
+    pub fn inserted(
+        &mut self,
+        at_offset: Length,
+        len: Length,
+    ) -> io::Result<Insertion> {
+        let insertion = self.replica.inserted(at_offset, len);
+        self.append(RecordKind::Insertion, &insertion.encode())?;
+        self.logged()?;
+        Ok(insertion)
+    }
+
+    /// Appends the remote [`Deletion`] to the log before integrating it into
+    /// the `Replica`.
+    ///
+    /// See [`Replica::integrate_deletion`] for more information.
+    #[inline]
+    pub fn integrate_deletion(
+        &mut self,
+        deletion: &Deletion,
+    ) -> io::Result<Vec<Range<Length>>> {
+        self.append(RecordKind::Deletion, &deletion.encode())?;
+        let ranges = self.replica.integrate_deletion(deletion);
+        self.logged()?;
+        Ok(ranges)
+    }
+
+    /// Appends the remote [`Insertion`] to the log before integrating it into
+    /// the `Replica`.
+    ///
+    /// See [`Replica::integrate_insertion`] for more information.
+    #[inline]
+    pub fn integrate_insertion(
+        &mut self,
+        insertion: &Insertion,
+    ) -> io::Result<Option<Length>> {
+        self.append(RecordKind::Insertion, &insertion.encode())?;
+        let offset = self.replica.integrate_insertion(insertion);
+        self.logged()?;
+        Ok(offset)
+    }
+
+    /// Consumes the store, returning the `Replica` it contains.
+    ///
+    /// Nothing is written to disk, so the store can be reopened later.
+    #[inline]
+    pub fn into_replica(self) -> Replica {
+        self.replica
+    }
+
+    /// Called after an edit has been both appended to the log and applied to
+    /// the `Replica`, writing a new checkpoint if the log has grown past the
+    /// checkpoint interval.
+    #[inline]
+    fn logged(&mut self) -> io::Result<()> {
+        self.logged_edits += 1;
+
+        if self.logged_edits >= self.checkpoint_interval {
+            self.checkpoint()?;
+        }
+
+        Ok(())
+    }
+
+    /// Opens the store in the given directory, recovering the `Replica` by
+    /// loading the latest checkpoint and replaying the log on top of it.
+    ///
+    /// If the log ends with a record that was only partially written, for
+    /// example because the process crashed in the middle of appending it,
+    /// that record and everything after it are truncated away.
+    ///
+    /// The recovered `Replica` keeps the [`ReplicaId`] it was created with,
+    /// and can keep editing the document as the same peer.
+    #[inline]
+    pub fn open(dir: impl AsRef<Path>) -> io::Result<Self> {
+        let dir = dir.as_ref();
+
+        // A leftover of a crash while writing a checkpoint.
+        record_log::remove_leftover(dir, CHECKPOINT_TMP)?;
+
+        let checkpoint = BufReader::new(File::open(dir.join(CHECKPOINT))?);
+
+        let fields = stream::decode(checkpoint)?;
+
+        let mut replica = Replica::resumed_from_encoded_fields(fields)
+            .map_err(stream::decode_error)?;
+
+        let mut log = record_log::open(&dir.join(LOG))?;
+
+        let logged_edits = record_log::replay(&mut log, |contents| {
+            replay(&mut replica, contents)
+        })?;
+
+        replica.restore_run_clock();
+
+        Ok(Self {
+            replica,
+            dir: dir.to_owned(),
+            log,
+            logged_edits,
+            checkpoint_interval: DEFAULT_CHECKPOINT_INTERVAL,
+        })
+    }
+
+    /// Returns the `Replica` being persisted.
+    #[inline]
+    pub fn replica(&self) -> &Replica {
+        &self.replica
+    }
+
+    /// Sets the number of edits after which a new checkpoint is written
+    /// automatically.
+    ///
+    /// Larger intervals mean fewer (expensive) checkpoints, at the cost of
+    /// having to replay a longer log when the store is opened.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the interval is zero.
+    #[track_caller]
+    #[inline]
+    pub fn set_checkpoint_interval(&mut self, interval: usize) {
+        assert!(interval > 0, "the checkpoint interval must be positive");
+        self.checkpoint_interval = interval;
+    }
+}
+
+/// Replays an edit read from a log record on top of the `Replica`.
+#[inline]
+fn replay(replica: &mut Replica, contents: &[u8]) -> io::Result<()> {
+    // The record was written in full, so if it can't be decoded it was
+    // written by a newer version of cola and we can't go any further.
+    let Some((&kind, edit)) = contents.split_first() else {
+        return Err(stream::decode_error(DecodeError::InvalidData));
+    };
+
+    if kind == RecordKind::Insertion as u8 {
+        let insertion =
+            Insertion::decode(edit).map_err(stream::decode_error)?;
+        replica.replay_insertion(&insertion);
+    } else if kind == RecordKind::Deletion as u8 {
+        let deletion = Deletion::decode(edit).map_err(stream::decode_error)?;
+        let _ = replica.integrate_deletion(&deletion);
+    } else {
+        return Err(stream::decode_error(DecodeError::InvalidData));
+    }
+
+    Ok(())
+}
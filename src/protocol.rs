@@ -0,0 +1,513 @@
+//! The registry of the protocol versions cola knows about, and the
+//! migrations between them.
+//!
+//! Every time the binary format of an [`EncodedReplica`](crate::EncodedReplica)
+//! changes the
+//! [`PROTOCOL_VERSION`] is incremented, and a migration from the previous
+//! version is added to [`MIGRATIONS`]. A migration takes the bytes of an
+//! `EncodedReplica` in one protocol version and returns the bytes of the
+//! same `EncodedReplica` in the next one, so decoding a replica encoded with
+//! an older version of cola is just a matter of running the chain of
+//! migrations up to the current version before decoding it as usual.
+
+use alloc::borrow::Cow;
+use core::ops::RangeInclusive;
+
+use crate::encode::{Decode, Encode};
+use crate::{DecodeError, ProtocolVersion, PROTOCOL_VERSION};
+
+/// The oldest protocol version whose [`EncodedReplica`](crate::EncodedReplica)s
+/// can still be decoded by this release of cola.
+///
+/// Replicas encoded with a protocol version between this and
+/// [`PROTOCOL_VERSION`] are migrated to the current version when they're
+/// [`decode`](crate::Replica::decode)d.
+pub const MIN_DECODABLE_PROTOCOL_VERSION: ProtocolVersion = 0;
+
+/// The range of protocol versions that this release of cola can both encode
+/// and decode.
+///
+/// This is what a peer should advertise to the other peers it's about to
+/// exchange [`EncodedReplica`](crate::EncodedReplica)s with, so that they can
+/// agree on a common protocol via [`negotiate_protocol`].
+///
+/// Protocol version 0 is not included since the releases of cola using it
+/// predate protocol negotiation. Replicas encoded with it can still be
+/// decoded, but not produced.
+pub const SUPPORTED_PROTOCOL_VERSIONS: RangeInclusive<ProtocolVersion> =
+    1..=PROTOCOL_VERSION;
+
+/// Returns the highest protocol version supported by both this release of
+/// cola and a remote peer supporting the given range of versions, or `None`
+/// if the two ranges don't overlap.
+///
+/// The returned version can be passed to
+/// [`Replica::encode_with_protocol`](crate::Replica::encode_with_protocol) to
+/// produce an [`EncodedReplica`](crate::EncodedReplica) that the remote peer
+/// can decode.
+///
+/// # Examples
+///
+/// ```
+/// # use cola::{negotiate_protocol, PROTOCOL_VERSION};
+/// // A peer running the same release supports the same versions.
+/// assert_eq!(
+///     negotiate_protocol(cola::SUPPORTED_PROTOCOL_VERSIONS),
+///     Some(PROTOCOL_VERSION)
+/// );
+///
+/// // A peer running a newer release which still supports our version.
+/// assert_eq!(
+///     negotiate_protocol(PROTOCOL_VERSION..=PROTOCOL_VERSION + 2),
+///     Some(PROTOCOL_VERSION)
+/// );
+///
+/// // A peer that has dropped support for our version.
+/// assert_eq!(
+///     negotiate_protocol(PROTOCOL_VERSION + 1..=PROTOCOL_VERSION + 2),
+///     None
+/// );
+/// ```
+#[inline]
+pub fn negotiate_protocol(
+    remote: RangeInclusive<ProtocolVersion>,
+) -> Option<ProtocolVersion> {
+    let start = *SUPPORTED_PROTOCOL_VERSIONS.start().max(remote.start());
+    let end = *SUPPORTED_PROTOCOL_VERSIONS.end().min(remote.end());
+    (start <= end).then_some(end)
+}
+
+/// A migration of the bytes of an `EncodedReplica` from the protocol version
+/// `from` to `from + 1`.
+struct Migration {
+    from: ProtocolVersion,
+    replica: fn(&[u8]) -> Option<Vec<u8>>,
+}
+
+/// The migrations between consecutive protocol versions, sorted by the
+/// version they migrate from.
+///
+/// Protocol version 2 only changed the checksum of `EncodedReplica`s to
+/// record the [`ChecksumAlgorithm`](crate::ChecksumAlgorithm) used to compute
+/// it, so there's no migration from version 1.
+const MIGRATIONS: &[Migration] =
+    &[Migration { from: 0, replica: v0::migrate_replica }];
+
+/// Returns an error if this release of cola can't decode data encoded with
+/// the given protocol version.
+#[inline]
+pub(crate) fn check_decodable(
+    version: ProtocolVersion,
+) -> Result<(), DecodeError> {
+    check_version(MIN_DECODABLE_PROTOCOL_VERSION, version)
+}
+
+/// Returns an error if this release of cola can't decode a stream written by
+/// [`Replica::encode_to_writer`](crate::Replica::encode_to_writer) with the
+/// given protocol version.
+#[cfg(feature = "sha256")]
+#[inline]
+pub(crate) fn check_decodable_stream(
+    version: ProtocolVersion,
+) -> Result<(), DecodeError> {
+    check_version(MIN_DECODABLE_STREAM_PROTOCOL_VERSION, version)
+}
+
+#[inline]
+fn check_version(
+    min_decodable: ProtocolVersion,
+    version: ProtocolVersion,
+) -> Result<(), DecodeError> {
+    if (min_decodable..=PROTOCOL_VERSION).contains(&version) {
+        Ok(())
+    } else {
+        Err(DecodeError::DifferentProtocol {
+            encoded_on: version,
+            decoding_on: PROTOCOL_VERSION,
+        })
+    }
+}
+
+/// Migrates the bytes of an `EncodedReplica` from the given protocol version
+/// to the current one.
+///
+/// The version must have already been checked with [`check_decodable`].
+#[inline]
+pub(crate) fn migrate_replica(
+    version: ProtocolVersion,
+    bytes: &[u8],
+) -> Result<Cow<'_, [u8]>, DecodeError> {
+    let mut bytes = Cow::Borrowed(bytes);
+
+    for migration in MIGRATIONS.iter().filter(|m| m.from >= version) {
+        bytes = (migration.replica)(&bytes)
+            .map(Cow::Owned)
+            .ok_or(DecodeError::InvalidData)?;
+    }
+
+    Ok(bytes)
+}
+
+/// The oldest protocol version whose encoded [`Insertion`](crate::Insertion)s
+/// and [`Deletion`](crate::Deletion)s can be decoded.
+///
+/// Edits only got their own binary encoding in protocol version 1, so there
+/// are no edit migrations yet. [`Migration`] only migrates `EncodedReplica`s,
+/// so when the format of an edit changes it'll need a separate function
+/// migrating the bytes of edits, applied by [`decode_edit`] the same way
+/// [`migrate_replica`] applies the replica ones.
+const MIN_DECODABLE_EDIT_PROTOCOL_VERSION: ProtocolVersion = 1;
+
+/// The oldest protocol version whose replica streams can be decoded.
+///
+/// Streams were introduced in protocol version 1, so just like for edits
+/// there are no stream migrations yet.
+#[cfg(feature = "sha256")]
+const MIN_DECODABLE_STREAM_PROTOCOL_VERSION: ProtocolVersion = 1;
+
+/// The kinds of edits that can be encoded on their own, identified by the
+/// byte following the protocol version in their header.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum EditKind {
+    Insertion = 0,
+    Deletion = 1,
+    Departure = 2,
+}
+
+/// Encodes an edit prefixed by its header.
+#[inline]
+pub(crate) fn encode_edit<E: Encode>(kind: EditKind, edit: &E) -> Vec<u8> {
+    let mut buf = Vec::new();
+    PROTOCOL_VERSION.encode(&mut buf);
+    buf.push(kind as u8);
+    edit.encode(&mut buf);
+    buf
+}
+
+/// Decodes an edit encoded by [`encode_edit`], possibly by an older version
+/// of cola.
+#[inline]
+pub(crate) fn decode_edit<E: Decode>(
+    kind: EditKind,
+    mut bytes: &[u8],
+) -> Result<E, DecodeError> {
+    let buf = &mut bytes;
+
+    let version =
+        ProtocolVersion::decode(buf).ok_or(DecodeError::InvalidData)?;
+
+    check_version(MIN_DECODABLE_EDIT_PROTOCOL_VERSION, version)?;
+
+    match buf.split_first() {
+        Some((&byte, rest)) if byte == kind as u8 => *buf = rest,
+        _ => return Err(DecodeError::InvalidData),
+    }
+
+    match E::decode(buf) {
+        Some(edit) if buf.is_empty() => Ok(edit),
+        _ => Err(DecodeError::InvalidData),
+    }
+}
+
+/// Protocol version 0 was used by cola up to and including v0.1.1.
+///
+/// The bytes of an `EncodedReplica` were the run tree, the Lamport clock, the
+/// version map, the deletion map and the backlog, each serialized with
+/// `bincode`'s default configuration (i.e. little-endian fixed-width integers
+/// and `u64` lengths) and prefixed by the length of its serialization as a
+/// little-endian `u64`.
+///
+/// Since this format will never change again it's parsed by hand instead of
+/// depending on `bincode` and `serde` just for this.
+mod v0 {
+    use crate::encode::Encode;
+    use crate::*;
+
+    #[inline]
+    pub(super) fn migrate_replica(bytes: &[u8]) -> Option<Vec<u8>> {
+        let mut reader = Reader(bytes);
+
+        let runs = runs(reader.field()?)?;
+        let lamport_clock = Reader::read_all(reader.field()?, Reader::u64)?;
+        let version_map =
+            Reader::read_all(reader.field()?, Reader::version_map)?;
+        let deletion_map =
+            Reader::read_all(reader.field()?, Reader::deletion_map)?;
+        let (insertions, deletions) =
+            Reader::read_all(reader.field()?, Reader::backlog)?;
+
+        if !reader.0.is_empty() {
+            return None;
+        }
+
+        let mut migrated = Vec::new();
+
+        run_tree::encode_runs(&runs, &mut migrated);
+        lamport_clock.encode(&mut migrated);
+        version_map.encode(&mut migrated);
+        deletion_map.encode(&mut migrated);
+        insertions.len().encode(&mut migrated);
+        for insertion in &insertions {
+            Encode::encode(insertion, &mut migrated);
+        }
+        deletions.len().encode(&mut migrated);
+        for deletion in &deletions {
+            Encode::encode(deletion, &mut migrated);
+        }
+
+        Some(migrated)
+    }
+
+    /// Returns the runs of a v0 `RunTree` in document order.
+    ///
+    /// The `RunTree` was serialized as its `Gtree` followed by its
+    /// `RunIndices`. Only the former is read since the latter can be rebuilt
+    /// from the runs.
+    #[inline]
+    fn runs(bytes: &[u8]) -> Option<Vec<EditRun>> {
+        let mut reader = Reader(bytes);
+
+        let inodes = reader.seq(Reader::inode)?;
+        let lnodes = reader.seq(Reader::lnode)?;
+        let root_idx = reader.usize()?;
+
+        // The cursor is only a cache, so it can be skipped.
+        if reader.bool()? {
+            reader.u64()?;
+            reader.u64()?;
+            reader.u64()?;
+        }
+
+        let mut runs = Vec::with_capacity(lnodes.len());
+
+        let mut visited = vec![false; inodes.len()];
+
+        let mut stack = vec![root_idx];
+
+        while let Some(inode_idx) = stack.pop() {
+            // Every inode can only be visited once, which also rules out
+            // cycles.
+            if core::mem::replace(visited.get_mut(inode_idx)?, true) {
+                return None;
+            }
+
+            let inode = &inodes[inode_idx];
+
+            if inode.has_leaves {
+                for &leaf_idx in &inode.children {
+                    runs.push(lnodes.get(leaf_idx)?.clone());
+                }
+            } else {
+                stack.extend(inode.children.iter().rev());
+            }
+        }
+
+        Some(runs)
+    }
+
+    struct Inode {
+        has_leaves: bool,
+        children: Vec<usize>,
+    }
+
+    /// A reader of values serialized with `bincode`'s default configuration.
+    struct Reader<'a>(&'a [u8]);
+
+    impl<'a> Reader<'a> {
+        #[inline]
+        fn anchor(&mut self) -> Option<Anchor> {
+            Some(Anchor::new(self.u64()?, self.usize()?))
+        }
+
+        /// Reads the backlogged insertions and deletions.
+        #[inline]
+        fn backlog(&mut self) -> Option<(Vec<Insertion>, Vec<Deletion>)> {
+            let insertions = self.seq(|r| {
+                r.u64()?;
+                r.seq(Self::insertion)
+            })?;
+
+            let deletions = self.seq(|r| {
+                r.u64()?;
+                r.seq(Self::deletion)
+            })?;
+
+            Some((
+                insertions.into_iter().flatten().collect(),
+                deletions.into_iter().flatten().collect(),
+            ))
+        }
+
+        #[inline]
+        fn bool(&mut self) -> Option<bool> {
+            match self.bytes::<1>()? {
+                [0] => Some(false),
+                [1] => Some(true),
+                _ => None,
+            }
+        }
+
+        #[inline]
+        fn bytes<const N: usize>(&mut self) -> Option<[u8; N]> {
+            let bytes = self.slice(N)?;
+            bytes.try_into().ok()
+        }
+
+        #[inline]
+        fn deletion(&mut self) -> Option<Deletion> {
+            Some(Deletion::new(
+                self.anchor()?,
+                self.u64()?,
+                self.anchor()?,
+                self.u64()?,
+                self.version_map()?,
+                self.u64()?,
+            ))
+        }
+
+        #[inline]
+        fn deletion_map(&mut self) -> Option<DeletionMap> {
+            let mut map = DeletionMap::new(self.u64()?, self.u64()?);
+            for (id, ts) in self.seq(|r| Some((r.u64()?, r.u64()?)))? {
+                map.insert(id, ts);
+            }
+            Some(map)
+        }
+
+        #[inline]
+        fn edit_run(&mut self) -> Option<EditRun> {
+            let text = self.text()?;
+            let run_ts = self.u64()?;
+            let lamport_ts = self.u64()?;
+            let is_deleted = self.bool()?;
+            Some(EditRun::decoded(text, run_ts, lamport_ts, is_deleted))
+        }
+
+        /// Reads a field of the `EncodedReplica`, i.e. a slice prefixed by
+        /// its length.
+        #[inline]
+        fn field(&mut self) -> Option<&'a [u8]> {
+            let len = self.usize()?;
+            self.slice(len)
+        }
+
+        #[inline]
+        fn inode(&mut self) -> Option<Inode> {
+            // Inodes were serialized as maps with 5 entries.
+            if self.usize()? != 5 {
+                return None;
+            }
+
+            let mut has_leaves = None;
+            let mut num_children = None;
+            let mut children = None;
+
+            for _ in 0..5 {
+                match self.str()? {
+                    b"tot_len" | b"parent" => {
+                        self.u64()?;
+                    },
+                    b"num_children" => num_children = Some(self.usize()?),
+                    b"has_leaves" => has_leaves = Some(self.bool()?),
+                    b"children" => children = Some(self.seq(Self::usize)?),
+                    _ => return None,
+                }
+            }
+
+            let children = children?;
+
+            (children.len() == num_children?)
+                .then_some(Inode { has_leaves: has_leaves?, children })
+        }
+
+        #[inline]
+        fn insertion(&mut self) -> Option<Insertion> {
+            let anchor = self.anchor()?;
+            let anchor_ts = self.u64()?;
+            let text = self.text()?;
+            let run_ts = self.u64()?;
+            let lamport_ts = self.u64()?;
+            Some(Insertion::new(anchor, anchor_ts, text, lamport_ts, run_ts))
+        }
+
+        #[inline]
+        fn lnode(&mut self) -> Option<EditRun> {
+            let run = self.edit_run()?;
+            // The index of the leaf's parent.
+            self.u64()?;
+            Some(run)
+        }
+
+        /// Reads a value from the given bytes, making sure that all of them
+        /// are consumed.
+        #[inline]
+        fn read_all<T>(
+            bytes: &'a [u8],
+            read: impl FnOnce(&mut Self) -> Option<T>,
+        ) -> Option<T> {
+            let mut reader = Self(bytes);
+            let value = read(&mut reader)?;
+            reader.0.is_empty().then_some(value)
+        }
+
+        /// Reads a sequence of values prefixed by its length.
+        #[inline]
+        fn seq<T>(
+            &mut self,
+            mut read: impl FnMut(&mut Self) -> Option<T>,
+        ) -> Option<Vec<T>> {
+            let len = self.usize()?;
+
+            // Every element takes at least one byte, so this prevents a
+            // corrupted length from causing a huge allocation.
+            if len > self.0.len() {
+                return None;
+            }
+
+            (0..len).map(|_| read(self)).collect()
+        }
+
+        #[inline]
+        fn slice(&mut self, len: usize) -> Option<&'a [u8]> {
+            if self.0.len() < len {
+                return None;
+            }
+            let (slice, rest) = self.0.split_at(len);
+            self.0 = rest;
+            Some(slice)
+        }
+
+        #[inline]
+        fn str(&mut self) -> Option<&'a [u8]> {
+            let len = self.usize()?;
+            self.slice(len)
+        }
+
+        #[inline]
+        fn text(&mut self) -> Option<Text> {
+            let inserted_by = self.u64()?;
+            let start = self.usize()?;
+            let end = self.usize()?;
+            (start <= end).then(|| Text::new(inserted_by, start..end))
+        }
+
+        #[inline]
+        fn u64(&mut self) -> Option<u64> {
+            self.bytes().map(u64::from_le_bytes)
+        }
+
+        #[inline]
+        fn usize(&mut self) -> Option<usize> {
+            self.u64()?.try_into().ok()
+        }
+
+        #[inline]
+        fn version_map(&mut self) -> Option<VersionMap> {
+            let mut map = VersionMap::new(self.u64()?, self.usize()?);
+            for (id, len) in self.seq(|r| Some((r.u64()?, r.usize()?)))? {
+                map.insert(id, len);
+            }
+            Some(map)
+        }
+    }
+}
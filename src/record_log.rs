@@ -0,0 +1,162 @@
+//! An append-only log of checksummed records, and the atomic replacement of
+//! the snapshot its records are compacted into.
+//!
+//! Every record is made of the length of its contents as a little-endian
+//! `u32`, the contents themselves and their CRC32C checksum as a
+//! little-endian `u32`. If the process crashes while appending a record, the
+//! torn record is truncated away the next time the log is replayed.
+//!
+//! This is used by the `ReplicaStore`, and is also included as a module by
+//! `cola-server`, so it can't depend on anything else in this crate.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+/// Appends a record with the given contents to the log, and syncs it to
+/// disk.
+#[inline]
+pub(crate) fn append(log: &mut File, contents: &[u8]) -> io::Result<()> {
+    let Ok(len) = u32::try_from(contents.len()) else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "the record is too large to be logged",
+        ));
+    };
+
+    let mut record = Vec::with_capacity(contents.len() + 8);
+    record.extend_from_slice(&len.to_le_bytes());
+    record.extend_from_slice(contents);
+    record.extend_from_slice(&crc32c::crc32c(contents).to_le_bytes());
+
+    log.write_all(&record)?;
+    log.sync_data()
+}
+
+/// Removes all the records from the log.
+#[inline]
+pub(crate) fn clear(log: &mut File) -> io::Result<()> {
+    log.set_len(0)?;
+    log.sync_data()
+}
+
+/// Opens the log at the given path, creating it if it doesn't exist.
+#[inline]
+pub(crate) fn open(path: &Path) -> io::Result<File> {
+    OpenOptions::new()
+        .read(true)
+        .append(true)
+        .create(true)
+        .truncate(false)
+        .open(path)
+}
+
+/// Removes the temporary file left behind by a crash in the middle of
+/// [`replace`], if any.
+#[inline]
+pub(crate) fn remove_leftover(dir: &Path, tmp_name: &str) -> io::Result<()> {
+    match fs::remove_file(dir.join(tmp_name)) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    }
+}
+
+/// Atomically replaces the file with the given name in the directory with
+/// the bytes written by the closure.
+///
+/// The bytes are first written to a temporary file which is then renamed,
+/// so a crash in the middle of this leaves the previous file untouched.
+#[inline]
+pub(crate) fn replace(
+    dir: &Path,
+    name: &str,
+    tmp_name: &str,
+    write: impl FnOnce(&mut BufWriter<File>) -> io::Result<()>,
+) -> io::Result<()> {
+    let tmp_path = dir.join(tmp_name);
+
+    let mut writer = BufWriter::new(File::create(&tmp_path)?);
+
+    write(&mut writer)?;
+
+    writer.into_inner().map_err(io::IntoInnerError::into_error)?.sync_all()?;
+
+    fs::rename(&tmp_path, dir.join(name))?;
+
+    // Make sure the rename itself is durable.
+    #[cfg(unix)]
+    File::open(dir)?.sync_all()?;
+
+    Ok(())
+}
+
+/// Calls the closure with the contents of every record in the log,
+/// returning the number of records that were replayed.
+///
+/// The log is truncated right before the first record that's incomplete or
+/// whose checksum doesn't match its contents.
+#[inline]
+pub(crate) fn replay(
+    log: &mut File,
+    mut replay_record: impl FnMut(&[u8]) -> io::Result<()>,
+) -> io::Result<usize> {
+    let mut reader = BufReader::new(&*log);
+
+    let mut valid_len = 0u64;
+
+    let mut replayed = 0;
+
+    let mut contents = Vec::new();
+
+    loop {
+        let mut len = [0; 4];
+
+        if !read_record_part(&mut reader, &mut len)? {
+            break;
+        }
+
+        let len = u32::from_le_bytes(len) as u64;
+
+        contents.clear();
+
+        if reader.by_ref().take(len).read_to_end(&mut contents)? as u64 != len
+        {
+            break;
+        }
+
+        let mut checksum = [0; 4];
+
+        if !read_record_part(&mut reader, &mut checksum)?
+            || u32::from_le_bytes(checksum) != crc32c::crc32c(&contents)
+        {
+            break;
+        }
+
+        replay_record(&contents)?;
+
+        valid_len += 8 + len;
+
+        replayed += 1;
+    }
+
+    if log.metadata()?.len() > valid_len {
+        log.set_len(valid_len)?;
+        log.sync_data()?;
+    }
+
+    Ok(replayed)
+}
+
+/// Fills the buffer with the next bytes of a log record, returning `false`
+/// if the log ends before the buffer is filled.
+#[inline]
+fn read_record_part(
+    reader: &mut impl Read,
+    buf: &mut [u8],
+) -> io::Result<bool> {
+    match reader.read_exact(buf) {
+        Ok(()) => Ok(true),
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(err) => Err(err),
+    }
+}
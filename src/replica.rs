@@ -46,13 +46,32 @@ use crate::*;
 ///
 /// Basically, you tell your `Replica` how your buffer changes, and it tells
 /// you how your buffer *should* change when receiving remote edits.
-pub struct Replica {
+///
+/// # Choosing the arity of the run tree.
+///
+/// Internally, a `Replica` stores its edit runs in a B-tree-like structure
+/// whose internal nodes hold up to `ARITY` children. The default of
+/// [`DEFAULT_ARITY`](crate::DEFAULT_ARITY) is a good fit for most documents,
+/// but smaller arities waste less memory on half-empty nodes when the number
+/// of runs is small, while larger ones make the tree shallower when it's
+/// huge. A `Replica` with a custom arity can be created via
+/// [`new_with_arity`](Self::new_with_arity) or
+/// [`decode_with_arity`](Self::decode_with_arity).
+///
+/// The arity must be an even number greater than or equal to 4. This is
+/// checked at compile time:
+///
+/// ```compile_fail
+/// # use cola::Replica;
+/// let replica = Replica::<5>::new_with_arity(1, 0);
+/// ```
+pub struct Replica<const ARITY: usize = DEFAULT_ARITY> {
     /// The unique identifier of this replica.
     id: ReplicaId,
 
     /// Contains all the [`EditRun`]s that have been applied to this replica so
     /// far. This is the main data structure.
-    run_tree: RunTree,
+    run_tree: RunTree<ARITY>,
 
     /// The value of the Lamport clock at this replica.
     lamport_clock: LamportClock,
@@ -75,17 +94,349 @@ pub struct Replica {
 }
 
 impl Replica {
-    #[doc(hidden)]
-    pub fn assert_invariants(&self) {
-        self.run_tree.assert_invariants();
-        self.backlog.assert_invariants(&self.version_map, &self.deletion_map);
+    /// Creates a new `Replica` with the given [`ReplicaId`] by decoding the
+    /// contents of the [`EncodedReplica`].
+    ///
+    /// To keep editing as the `Replica` that was encoded, e.g. after a
+    /// restart, use [`resume`](Replica::resume) instead.
+    ///
+    /// `EncodedReplica`s created by older versions of cola are migrated to
+    /// the current [`PROTOCOL_VERSION`] before being decoded, as long as
+    /// their protocol version is not older than
+    /// [`MIN_DECODABLE_PROTOCOL_VERSION`](crate::MIN_DECODABLE_PROTOCOL_VERSION).
+    ///
+    /// Just like with [`fork`](Replica::fork), if the encoded `Replica` has
+    /// already integrated edits made with the given [`ReplicaId`] the new
+    /// `Replica` continues from where those left off.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the [`ReplicaId`] is zero.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use cola::{Replica, EncodedReplica};
+    /// let replica1 = Replica::new(1, 42);
+    ///
+    /// let encoded: EncodedReplica = replica1.encode();
+    ///
+    /// let replica2 = Replica::decode(2, &encoded).unwrap();
+    ///
+    /// assert_eq!(replica2.id(), 2);
+    /// ```
+    #[cfg(feature = "encode")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "encode")))]
+    #[track_caller]
+    #[inline]
+    pub fn decode(
+        id: ReplicaId,
+        encoded: &EncodedReplica,
+    ) -> Result<Self, DecodeError> {
+        Self::decode_with_arity(id, encoded)
     }
 
-    #[doc(hidden)]
-    pub fn average_gtree_inode_occupancy(&self) -> f32 {
-        self.run_tree.average_inode_occupancy()
+    /// Creates a new `Replica` with the given [`ReplicaId`] by decoding the
+    /// replica contained in the [`EncodedDocument`], checking that it's
+    /// consistent with the document's text.
+    ///
+    /// This fails with [`DecodeError::TextLengthMismatch`] if the length of
+    /// the decoded `Replica` is different from the length of the text, and
+    /// with [`DecodeError::InvalidData`] if the document contains deleted
+    /// text that doesn't match the deleted text tracked by the `Replica`.
+    ///
+    /// The text of the document can be accessed via
+    /// [`EncodedDocument::text`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the [`ReplicaId`] is zero.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use cola::Replica;
+    /// let replica1 = Replica::new(1, 5);
+    ///
+    /// let encoded = replica1.encode_document("Hello");
+    ///
+    /// let replica2 = Replica::decode_document(2, &encoded).unwrap();
+    ///
+    /// let buffer = encoded.text().to_owned();
+    ///
+    /// assert_eq!(replica2.len(), buffer.len());
+    /// ```
+    #[cfg(feature = "encode")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "encode")))]
+    #[track_caller]
+    #[inline]
+    pub fn decode_document(
+        id: ReplicaId,
+        encoded: &EncodedDocument,
+    ) -> Result<Self, DecodeError> {
+        let replica = Self::decode(id, encoded.replica())?;
+
+        if replica.len() != encoded.text().len() {
+            return Err(DecodeError::TextLengthMismatch {
+                replica_len: replica.len(),
+                text_len: encoded.text().len(),
+            });
+        }
+
+        if let Some(deleted) = encoded.deleted_text() {
+            let mut runs =
+                replica.run_tree.runs().filter(|run| run.is_deleted());
+
+            let is_consistent = deleted.iter().all(|(text, contents)| {
+                runs.next().is_some_and(|run| {
+                    run.text() == text && run.len() == contents.len()
+                })
+            }) && runs.next().is_none();
+
+            if !is_consistent {
+                return Err(DecodeError::InvalidData);
+            }
+        }
+
+        Ok(replica)
+    }
+
+    /// Creates a new `Replica` with the given [`ReplicaId`] by decoding a
+    /// stream written by [`encode_to_writer`](Replica::encode_to_writer).
+    ///
+    /// The stream is read a chunk at a time and its checksum is computed
+    /// incrementally, so the whole encoding is never held in memory. Nothing
+    /// past the end of the stream is read, so it can be followed by other
+    /// data. Note that the reader is read in small pieces, so you should
+    /// wrap it in a [`BufReader`](std::io::BufReader) if each read is costly
+    /// (e.g. when reading from a file or a socket).
+    ///
+    /// Any [`DecodeError`] is returned as an [`io::Error`](std::io::Error) of
+    /// kind [`InvalidData`](std::io::ErrorKind::InvalidData) wrapping it.
+    /// Since the checksum can only be verified once the whole stream has been
+    /// read, a corrupted stream can be reported as
+    /// [`InvalidData`](DecodeError::InvalidData) instead of
+    /// [`ChecksumFailed`](DecodeError::ChecksumFailed) if the corruption
+    /// makes it undecodable before that.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the [`ReplicaId`] is zero.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::io::Cursor;
+    /// # use cola::Replica;
+    /// let replica1 = Replica::new(1, 42);
+    ///
+    /// let mut stream = Cursor::new(Vec::new());
+    ///
+    /// replica1.encode_to_writer(&mut stream).unwrap();
+    ///
+    /// stream.set_position(0);
+    ///
+    /// let replica2 = Replica::decode_from_reader(2, &mut stream).unwrap();
+    ///
+    /// assert_eq!(replica2.id(), 2);
+    /// ```
+    #[cfg(feature = "sha256")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "sha256")))]
+    #[track_caller]
+    #[inline]
+    pub fn decode_from_reader(
+        id: ReplicaId,
+        reader: impl std::io::Read,
+    ) -> std::io::Result<Self> {
+        Self::decode_from_reader_with_arity(id, reader)
+    }
+
+    /// Creates a new `Replica` with the given [`ReplicaId`] from a Yjs (v1)
+    /// update of the root text type with the given name, returning it
+    /// together with the text of the type.
+    ///
+    /// The items of the update are integrated in the same order Yjs would,
+    /// and become runs of the `Replica` inserted by the [`ReplicaId`] equal
+    /// to their client id. Since Yjs measures lengths in UTF-16 code units,
+    /// so does the returned `Replica`.
+    ///
+    /// Formatting attributes are ignored, but any other visible content that
+    /// isn't a string (like embeds or nested types) makes this fail with
+    /// [`UnsupportedContent`](YjsError::UnsupportedContent).
+    ///
+    /// Note that the returned `Replica` is a new document with the same
+    /// history, and it can't exchange edits with the `Replica`s whose state
+    /// was [encoded](Replica::encode_yjs_update) in the update.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the [`ReplicaId`] is zero.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use cola::Replica;
+    /// let mut replica1 = Replica::new(1, 0);
+    /// let _ = replica1.inserted(0, 5);
+    ///
+    /// let update = replica1.encode_yjs_update("Hello", "text").unwrap();
+    ///
+    /// let (replica2, text) =
+    ///     Replica::decode_yjs_update(2, &update, "text").unwrap();
+    ///
+    /// assert_eq!(text, "Hello");
+    /// assert_eq!(replica2.len(), 5);
+    /// ```
+    #[cfg(feature = "yjs")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "yjs")))]
+    #[track_caller]
+    #[inline]
+    pub fn decode_yjs_update(
+        id: ReplicaId,
+        update: &[u8],
+        type_name: &str,
+    ) -> Result<(Self, String), YjsError> {
+        if id == 0 {
+            panic::replica_id_is_zero();
+        }
+
+        let (runs, text) = yjs::decode_update(update, type_name)?;
+
+        let Some(max_lamport_ts) =
+            runs.iter().map(|run| run.lamport_ts()).max()
+        else {
+            return Ok((Self::new(id, 0), text));
+        };
+
+        let mut version_map = VersionMap::new(id, 0);
+
+        for run in &runs {
+            let version = version_map.get_mut(run.replica_id());
+            *version = (*version).max(run.end());
+        }
+
+        let run_tree =
+            RunTree::from_runs(runs).ok_or(YjsError::InvalidUpdate)?;
+
+        let fields = (
+            run_tree,
+            LamportClock(max_lamport_ts + 1),
+            version_map,
+            DeletionMap::new(id, 0),
+            Backlog::new(),
+        );
+
+        let replica = Self::from_encoded_fields(id, fields)
+            .map_err(|_| YjsError::InvalidUpdate)?;
+
+        Ok((replica, text))
     }
 
+    /// Creates a new `Replica` with the given [`ReplicaId`] from the initial
+    /// [`Length`] of your buffer.
+    ///
+    /// Note that if you have multiple peers working on the same document you
+    /// should only use this constructor on the first peer, usually the one
+    /// that starts the collaboration session.
+    ///
+    /// The other peers should get their `Replica` from another `Replica`
+    /// already in the session by either:
+    ///
+    /// a) [`fork`](Replica::fork)ing it if the collaboration happens all in
+    /// the same process (e.g. a text editor with plugins running on separate
+    /// threads),
+    ///
+    /// b) [`encode`](Replica::encode)ing it and sending the result over the
+    /// network if the collaboration is between different processes or
+    /// machines.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the [`ReplicaId`] is zero.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::thread;
+    /// # use cola::Replica;
+    /// // A text editor initializes a new Replica on the main thread where the
+    /// // buffer is "foo".
+    /// let replica_main = Replica::new(1, 3);
+    ///
+    /// // It then starts a plugin on a separate thread and wants to give it a
+    /// // Replica to keep its buffer synchronized with the one on the main
+    /// // thread. It does *not* call `new()` again, but instead forks the
+    /// // existing Replica and sends it to the new thread.
+    /// let replica_plugin = replica_main.fork(2);
+    ///
+    /// thread::spawn(move || {
+    ///     // The plugin can now use its Replica to exchange edits with the
+    ///     // main thread.
+    ///     println!("{replica_plugin:?}");
+    /// });
+    /// ```
+    #[track_caller]
+    #[inline]
+    pub fn new(id: ReplicaId, len: Length) -> Self {
+        Self::new_with_arity(id, len)
+    }
+
+    /// Creates the `Replica` that encoded the [`EncodedReplica`], keeping
+    /// its [`ReplicaId`] and clocks so that it can keep editing as the same
+    /// peer, e.g. after the process owning it restarted.
+    ///
+    /// Unlike [`decode`](Replica::decode), the new `Replica` takes the id
+    /// of the encoded one, and the edits it makes from now on continue
+    /// exactly from where the encoded `Replica` left off.
+    ///
+    /// Note that any edit made by the encoded `Replica` after it was encoded
+    /// is lost, and the resumed `Replica` will make different edits with the
+    /// same timestamps, which the other peers can't tell apart. Only resume a
+    /// `Replica` from its latest encoding, or enable the `persist` feature
+    /// and use a `ReplicaStore` to persist every edit.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`decode`](Replica::decode).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use cola::Replica;
+    /// let mut replica1 = Replica::new(1, 3);
+    /// let mut replica2 = replica1.fork(2);
+    ///
+    /// let _ = replica2.integrate_insertion(&replica1.inserted(3, 2));
+    ///
+    /// let encoded = replica1.encode();
+    ///
+    /// // The process owning replica 1 restarts.
+    /// drop(replica1);
+    ///
+    /// let mut replica1 = Replica::resume(&encoded).unwrap();
+    ///
+    /// assert_eq!(replica1.id(), 1);
+    ///
+    /// let insertion = replica1.inserted(5, 1);
+    ///
+    /// assert_eq!(replica2.try_integrate_insertion(&insertion), Ok(Some(5)));
+    /// ```
+    #[cfg(feature = "encode")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "encode")))]
+    #[inline]
+    pub fn resume(encoded: &EncodedReplica) -> Result<Self, DecodeError> {
+        Self::resume_with_arity(encoded)
+    }
+}
+
+impl<const ARITY: usize> Replica<ARITY> {
+    /// Used by the constructors to reject arities the run tree can't work
+    /// with at compile time.
+    const ASSERT_VALID_ARITY: () = assert!(
+        ARITY >= 4 && ARITY.is_multiple_of(2),
+        "the arity of a Replica must be an even number >= 4"
+    );
+
     /// The [`integrate_deletion`](Replica::integrate_deletion) method is not
     /// able to immediately produce the offset range(s) to be deleted if the
     /// `Deletion` is itself dependent on some context that the `Replica`
@@ -136,7 +487,7 @@ impl Replica {
     /// assert_eq!(deletions.next(), None);
     /// ```
     #[inline]
-    pub fn backlogged_deletions(&mut self) -> BackloggedDeletions<'_> {
+    pub fn backlogged_deletions(&mut self) -> BackloggedDeletions<'_, ARITY> {
         BackloggedDeletions::from_replica(self)
     }
 
@@ -195,7 +546,9 @@ impl Replica {
     /// assert!(matches!(backlogged.next(), Some((_, 4))));
     /// ```
     #[inline]
-    pub fn backlogged_insertions(&mut self) -> BackloggedInsertions<'_> {
+    pub fn backlogged_insertions(
+        &mut self,
+    ) -> BackloggedInsertions<'_, ARITY> {
         BackloggedInsertions::from_replica(self)
     }
 
@@ -246,18 +599,51 @@ impl Replica {
         )
     }
 
+    /// Checks that the internal state of this `Replica` is consistent,
+    /// returning an [`IntegrityError`] describing the first inconsistency
+    /// found if it's not.
+    ///
+    /// A `Replica` that's only modified through its public API always passes
+    /// this check, so you don't need to call it during normal operation. It
+    /// can however be useful to validate a `Replica` after
+    /// [`decode`](Replica::decode)ing it from data coming from an untrusted
+    /// source.
+    ///
+    /// This runs in `O(n)` in the number of edit runs in the `Replica`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use cola::Replica;
+    /// let mut replica = Replica::new(1, 3);
+    ///
+    /// let _ = replica.inserted(1, 2);
+    /// let _ = replica.deleted(0..2);
+    ///
+    /// assert!(replica.check_integrity().is_ok());
+    /// ```
+    #[inline]
+    pub fn check_integrity(&self) -> Result<(), IntegrityError> {
+        self.run_tree.check_invariants()?;
+        self.run_tree.run_indices().check_version_map(&self.version_map)?;
+        self.backlog.check_invariants(&self.version_map, &self.deletion_map)
+    }
+
     #[doc(hidden)]
-    pub fn debug(&self) -> debug::DebugAsSelf<'_> {
+    pub fn debug(&self) -> debug::DebugAsSelf<'_, ARITY> {
         self.into()
     }
 
     #[doc(hidden)]
-    pub fn debug_as_btree(&self) -> debug::DebugAsBtree<'_> {
+    pub fn debug_as_btree(&self) -> debug::DebugAsBtree<'_, ARITY> {
         self.into()
     }
 
-    /// Creates a new `Replica` with the given [`ReplicaId`] by decoding the
-    /// contents of the [`EncodedReplica`].
+    /// Same as [`decode`](Replica::decode), but for a `Replica` with a custom
+    /// [arity](Replica#choosing-the-arity-of-the-run-tree).
+    ///
+    /// The arity doesn't have to match the one of the `Replica` that was
+    /// encoded, since the run tree is rebuilt from scratch when decoding.
     ///
     /// # Panics
     ///
@@ -266,12 +652,12 @@ impl Replica {
     /// # Examples
     ///
     /// ```
-    /// # use cola::{Replica, EncodedReplica};
-    /// let replica1 = Replica::new(1, 42);
+    /// # use cola::Replica;
+    /// let replica1 = Replica::<8>::new_with_arity(1, 42);
     ///
-    /// let encoded: EncodedReplica = replica1.encode();
+    /// let encoded = replica1.encode();
     ///
-    /// let replica2 = Replica::decode(2, &encoded).unwrap();
+    /// let replica2 = Replica::<16>::decode_with_arity(2, &encoded).unwrap();
     ///
     /// assert_eq!(replica2.id(), 2);
     /// ```
@@ -279,41 +665,171 @@ impl Replica {
     #[cfg_attr(docsrs, doc(cfg(feature = "encode")))]
     #[track_caller]
     #[inline]
-    pub fn decode(
+    pub fn decode_with_arity(
         id: ReplicaId,
         encoded: &EncodedReplica,
     ) -> Result<Self, DecodeError> {
+        let () = Self::ASSERT_VALID_ARITY;
+
         if id == 0 {
             panic::replica_id_is_zero();
         }
 
-        if encoded.protocol_version() != PROTOCOL_VERSION {
-            return Err(DecodeError::DifferentProtocol {
-                encoded_on: encoded.protocol_version(),
-                decoding_on: PROTOCOL_VERSION,
-            });
-        }
+        Self::from_encoded_fields(id, Self::decode_fields(encoded)?)
+    }
 
-        if encoded.checksum() != &checksum(encoded.bytes()) {
-            return Err(DecodeError::ChecksumFailed);
+    /// Decodes the fields of the [`EncodedReplica`], after checking its
+    /// protocol version and checksum and migrating it to the current
+    /// protocol version.
+    #[cfg(feature = "encode")]
+    #[inline]
+    fn decode_fields(
+        encoded: &EncodedReplica,
+    ) -> Result<EncodedFields<ARITY>, DecodeError> {
+        protocol::check_decodable(encoded.protocol_version())?;
+
+        verify_checksum(
+            encoded.protocol_version(),
+            encoded.checksum(),
+            encoded.bytes(),
+        )?;
+
+        let bytes = protocol::migrate_replica(
+            encoded.protocol_version(),
+            encoded.bytes(),
+        )?;
+
+        encode::decode(&bytes).ok_or(DecodeError::InvalidData)
+    }
+
+    /// Creates a new `Replica` with the given [`ReplicaId`] by decoding a
+    /// stream written by [`encode_to_writer`](Replica::encode_to_writer)
+    /// using a custom arity for the run tree.
+    ///
+    /// See [`decode_from_reader`](Replica::decode_from_reader) for more
+    /// information.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the [`ReplicaId`] is zero.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use cola::Replica;
+    /// let replica1 = Replica::<8>::new_with_arity(1, 42);
+    ///
+    /// let mut stream = Vec::new();
+    ///
+    /// replica1.encode_to_writer(&mut stream).unwrap();
+    ///
+    /// let replica2 =
+    ///     Replica::<16>::decode_from_reader_with_arity(2, stream.as_slice())
+    ///         .unwrap();
+    ///
+    /// assert_eq!(replica2.id(), 2);
+    /// ```
+    #[cfg(feature = "sha256")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "sha256")))]
+    #[track_caller]
+    #[inline]
+    pub fn decode_from_reader_with_arity(
+        id: ReplicaId,
+        reader: impl std::io::Read,
+    ) -> std::io::Result<Self> {
+        let () = Self::ASSERT_VALID_ARITY;
+
+        if id == 0 {
+            panic::replica_id_is_zero();
         }
 
-        let Some((
+        let fields = stream::decode(reader)?;
+
+        Self::from_encoded_fields(id, fields).map_err(stream::decode_error)
+    }
+
+    #[cfg(feature = "encode")]
+    #[inline]
+    fn from_encoded_fields(
+        id: ReplicaId,
+        fields: EncodedFields<ARITY>,
+    ) -> Result<Self, DecodeError> {
+        let (
             run_tree,
             lamport_clock,
             mut version_map,
             mut deletion_map,
             backlog,
-        )) = encode::decode(encoded.bytes())
-        else {
-            return Err(DecodeError::InvalidData);
+        ) = fields;
+
+        version_map.fork_in_place(id, version_map.get(id));
+
+        deletion_map.fork_in_place(id, deletion_map.get(id));
+
+        let mut replica = Self {
+            id,
+            run_tree,
+            run_clock: RunClock::new(),
+            lamport_clock,
+            version_map,
+            deletion_map,
+            backlog,
         };
 
-        version_map.fork_in_place(id, 0);
+        replica.restore_run_clock();
 
-        deletion_map.fork_in_place(id, 0);
+        // The checksum only guarantees that the bytes haven't been corrupted
+        // in transit, not that they describe a valid `Replica`.
+        if replica.check_integrity().is_err() {
+            return Err(DecodeError::InvalidData);
+        }
 
-        let replica = Self {
+        Ok(replica)
+    }
+
+    /// Same as [`resume`](Replica::resume), but for a `Replica` with a custom
+    /// [arity](Replica#choosing-the-arity-of-the-run-tree).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use cola::Replica;
+    /// let replica = Replica::<8>::new_with_arity(1, 42);
+    ///
+    /// let encoded = replica.encode();
+    ///
+    /// let resumed = Replica::<16>::resume_with_arity(&encoded).unwrap();
+    ///
+    /// assert_eq!(resumed.id(), 1);
+    /// ```
+    #[cfg(feature = "encode")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "encode")))]
+    #[inline]
+    pub fn resume_with_arity(
+        encoded: &EncodedReplica,
+    ) -> Result<Self, DecodeError> {
+        let () = Self::ASSERT_VALID_ARITY;
+
+        Self::resumed_from_encoded_fields(Self::decode_fields(encoded)?)
+    }
+
+    /// Creates the `Replica` that encoded the given fields, keeping its
+    /// `ReplicaId` and clocks so that it can keep editing as the same peer.
+    #[cfg(feature = "encode")]
+    #[inline]
+    pub(crate) fn resumed_from_encoded_fields(
+        fields: EncodedFields<ARITY>,
+    ) -> Result<Self, DecodeError> {
+        let (run_tree, lamport_clock, version_map, deletion_map, backlog) =
+            fields;
+
+        let id = version_map.this_id();
+
+        if id == 0 || deletion_map.this_id() != id {
+            return Err(DecodeError::InvalidData);
+        }
+
+        let mut replica = Self {
             id,
             run_tree,
             run_clock: RunClock::new(),
@@ -323,9 +839,70 @@ impl Replica {
             backlog,
         };
 
+        replica.restore_run_clock();
+
+        if replica.check_integrity().is_err() {
+            return Err(DecodeError::InvalidData);
+        }
+
         Ok(replica)
     }
 
+    /// Integrates an `Insertion` read back from a log, which may have been
+    /// made by this `Replica` itself before it was encoded, so unlike
+    /// [`try_integrate_insertion`](Self::try_integrate_insertion) this
+    /// doesn't check for `ReplicaId` collisions.
+    #[cfg(feature = "persist")]
+    #[inline]
+    pub(crate) fn replay_insertion(&mut self, insertion: &Insertion) {
+        if insertion.is_no_op() || self.has_merged_insertion(insertion) {
+            return;
+        }
+
+        if self.can_merge_insertion(insertion) {
+            let _ = self.merge_unchecked_insertion(insertion);
+        } else if self.backlog.overlapping_insertion(insertion).is_none() {
+            self.backlog.insert_insertion(insertion.clone());
+        }
+    }
+
+    /// Sets the `RunClock` right after the `RunTs` of the last run inserted
+    /// with this `Replica`'s id, which is the only way to recover it since
+    /// it's not encoded.
+    #[inline]
+    pub(crate) fn restore_run_clock(&mut self) {
+        let num_runs = self.run_tree.run_indices().num_runs(self.id);
+        self.run_clock = RunClock(num_runs as RunTs);
+    }
+
+    /// Announces that the peer owning this `Replica` is leaving the
+    /// collaborative session for good.
+    ///
+    /// This consumes the `Replica` and produces a [`Departure`] which should
+    /// be sent to the other peers after all the edits made by this `Replica`.
+    /// See [`integrate_departure`](Replica::integrate_departure) for more
+    /// information.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use cola::Replica;
+    /// let replica1 = Replica::new(1, 3);
+    ///
+    /// let departure = replica1.depart();
+    ///
+    /// assert_eq!(departure.replica_id(), 1);
+    /// ```
+    #[must_use]
+    #[inline]
+    pub fn depart(self) -> Departure {
+        Departure::new(
+            self.id,
+            self.version_map.this(),
+            self.deletion_map.this(),
+        )
+    }
+
     /// Informs the `Replica` that you have deleted the characters in the given
     /// offset range.
     ///
@@ -370,8 +947,8 @@ impl Replica {
 
         let deleted_range = (start..end).into();
 
-        let (start, start_ts, end, end_ts) =
-            self.run_tree.delete(deleted_range);
+        let (start, start_ts, end, end_ts, version_map) =
+            self.run_tree.delete(deleted_range, &self.version_map);
 
         *self.deletion_map.this_mut() += 1;
 
@@ -380,42 +957,368 @@ impl Replica {
             start_ts,
             end,
             end_ts,
-            self.version_map.clone(),
+            version_map,
             self.deletion_map.this(),
         )
     }
 
-    #[doc(hidden)]
-    pub fn empty_leaves(&self) -> (usize, usize) {
-        self.run_tree.count_empty_leaves()
+    /// Returns a [`RunTreeDump`] of the internal tree of edit runs of this
+    /// `Replica`, which can be rendered as a Graphviz DOT graph or as JSON.
+    ///
+    /// This is meant to help debug convergence issues between replicas, for
+    /// example by diffing the dumps of two replicas that should contain the
+    /// same document but don't.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use cola::Replica;
+    /// let mut replica = Replica::new(1, 10);
+    ///
+    /// let _ = replica.deleted(2..4);
+    ///
+    /// let dot = replica.dump().to_dot();
+    ///
+    /// // The deleted run is filled in gray.
+    /// assert!(dot.contains("style=filled, fillcolor=lightgray"));
+    /// ```
+    #[inline]
+    pub fn dump(&self) -> RunTreeDump<'_, ARITY> {
+        RunTreeDump::new(&self.run_tree)
+    }
+
+    /// Returns `true` if the given `Replica` shares the same document state as
+    /// this one.
+    ///
+    /// This is used in tests to make sure that an encode-decode roundtrip was
+    /// successful.
+    #[doc(hidden)]
+    pub fn eq_decoded(&self, other: &Self) -> bool {
+        self.run_tree.has_same_runs(&other.run_tree)
+            && self.backlog == other.backlog
+    }
+
+    /// Encodes the `Replica` in a custom binary format.
+    ///
+    /// This can be used to send a `Replica` to another peer over the network.
+    /// Once they have received the [`EncodedReplica`] they can decode it via
+    /// the [`decode`](Replica::decode) method.
+    ///
+    /// The `Replica` is encoded with the current [`PROTOCOL_VERSION`]. Use
+    /// [`encode_with_protocol`](Replica::encode_with_protocol) to target a
+    /// peer running a different version of cola.
+    ///
+    /// The checksum of the `EncodedReplica` is computed with SHA-256. Use
+    /// [`encode_with_checksum`](Replica::encode_with_checksum) to pick a
+    /// different [`ChecksumAlgorithm`].
+    ///
+    /// Note that if you want to collaborate within a single process you can
+    /// just [`fork`](Replica::fork) the `Replica` without having to encode it
+    /// and decode it again.
+    #[cfg(feature = "encode")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "encode")))]
+    #[inline]
+    pub fn encode(&self) -> EncodedReplica {
+        self.encode_with_checksum(ChecksumAlgorithm::default())
+    }
+
+    /// Encodes the `Replica` together with the text of the document it
+    /// tracks.
+    ///
+    /// The text is stored as a UTF-8 string, so this should only be used if
+    /// the [`Length`]s given to the `Replica` are measured in bytes. See
+    /// [`EncodedDocument`] for more information.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the length of the text is different from the
+    /// [`len`](Replica::len) of the `Replica`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use cola::Replica;
+    /// let mut buffer = String::from("Hello world");
+    ///
+    /// let mut replica1 = Replica::new(1, buffer.len());
+    ///
+    /// buffer.insert(5, ',');
+    /// let _ = replica1.inserted(5, 1);
+    ///
+    /// let encoded = replica1.encode_document(&buffer);
+    ///
+    /// let replica2 = Replica::decode_document(2, &encoded).unwrap();
+    ///
+    /// assert_eq!(encoded.text(), "Hello, world");
+    /// assert_eq!(replica2.len(), encoded.text().len());
+    /// ```
+    #[cfg(feature = "encode")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "encode")))]
+    #[track_caller]
+    #[inline]
+    pub fn encode_document(&self, text: &str) -> EncodedDocument {
+        if text.len() != self.len() {
+            panic::text_length_mismatch(text.len(), self.len());
+        }
+
+        EncodedDocument::new(self.encode(), text.to_owned(), None)
+    }
+
+    /// Encodes the `Replica` together with the text of the document it
+    /// tracks, also including the text that has been deleted from it.
+    ///
+    /// The `deleted_text` closure is called once for every range of deleted
+    /// [`Text`] still tracked by the `Replica`, in the order it appeared in
+    /// the document, and must return its contents. This is only possible if
+    /// you keep the contents of every insertion around, for example to
+    /// implement history features.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the length of the text is different from the
+    /// [`len`](Replica::len) of the `Replica`, or if the length of a string
+    /// returned by `deleted_text` is different from the length of the
+    /// temporal range of its `Text`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use cola::Replica;
+    /// let mut replica1 = Replica::new(1, 0);
+    ///
+    /// let insertion = replica1.inserted(0, 11);
+    ///
+    /// // The contents of every insertion, keyed by their `Text`.
+    /// let inserted = [(insertion.text().clone(), "Hello world")];
+    ///
+    /// let _ = replica1.deleted(0..6);
+    ///
+    /// let encoded =
+    ///     replica1.encode_document_with_deleted_text("world", |text| {
+    ///         let (inserted_text, contents) = &inserted[0];
+    ///         let start = text.temporal_range().start
+    ///             - inserted_text.temporal_range().start;
+    ///         contents[start..start + text.temporal_range().len()].to_owned()
+    ///     });
+    ///
+    /// let deleted = encoded.deleted_text().unwrap();
+    ///
+    /// assert_eq!(deleted.len(), 1);
+    /// assert_eq!(deleted[0].1, "Hello ");
+    ///
+    /// assert!(Replica::decode_document(2, &encoded).is_ok());
+    /// ```
+    #[cfg(feature = "encode")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "encode")))]
+    #[track_caller]
+    #[inline]
+    pub fn encode_document_with_deleted_text<F>(
+        &self,
+        text: &str,
+        mut deleted_text: F,
+    ) -> EncodedDocument
+    where
+        F: FnMut(&Text) -> String,
+    {
+        if text.len() != self.len() {
+            panic::text_length_mismatch(text.len(), self.len());
+        }
+
+        let deleted = self
+            .run_tree
+            .runs()
+            .filter(|run| run.is_deleted())
+            .map(|run| {
+                let contents = deleted_text(run.text());
+
+                if contents.len() != run.len() {
+                    panic::text_length_mismatch(contents.len(), run.len());
+                }
+
+                (run.text().clone(), contents)
+            })
+            .collect();
+
+        EncodedDocument::new(self.encode(), text.to_owned(), Some(deleted))
+    }
+
+    /// Encodes the history of the `Replica` as a Yjs (v1) update of the root
+    /// text type with the given name, given the current text of the
+    /// document.
+    ///
+    /// Every run of the `Replica` becomes an item whose client id is the
+    /// [`ReplicaId`] that inserted it and whose clocks are its temporal
+    /// range, with deleted runs encoded as deleted items. Applying the update
+    /// to an empty Yjs document produces the same text, but since Yjs
+    /// measures lengths in UTF-16 code units, the `Replica` has to track the
+    /// document in UTF-16 code units as well.
+    ///
+    /// Yjs client ids can't be larger than [`MAX_YJS_CLIENT_ID`], so this
+    /// fails with [`ReplicaIdTooLarge`](YjsError::ReplicaIdTooLarge) if any
+    /// of the text was inserted by a `ReplicaId` larger than that.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the length of the text in UTF-16 code units is different
+    /// from the [`len`](Replica::len) of the `Replica`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use cola::Replica;
+    /// let mut replica = Replica::new(1, 0);
+    /// let _ = replica.inserted(0, 2);
+    ///
+    /// let update = replica.encode_yjs_update("hi", "text").unwrap();
+    ///
+    /// assert_eq!(
+    ///     update,
+    ///     [1, 1, 1, 0, 4, 1, 4, b't', b'e', b'x', b't', 2, b'h', b'i', 0]
+    /// );
+    /// ```
+    #[cfg(feature = "yjs")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "yjs")))]
+    #[track_caller]
+    #[inline]
+    pub fn encode_yjs_update(
+        &self,
+        text: &str,
+        type_name: &str,
+    ) -> Result<Vec<u8>, YjsError> {
+        let text_len = text.encode_utf16().count();
+
+        if text_len != self.len() {
+            panic::utf16_length_mismatch(text_len, self.len());
+        }
+
+        yjs::encode_update(self.run_tree.runs(), text, type_name)
+    }
+
+    /// Encodes the `Replica` like [`encode`](Replica::encode), but computes
+    /// the checksum of the [`EncodedReplica`] with the given
+    /// [`ChecksumAlgorithm`] instead of SHA-256.
+    ///
+    /// The algorithm is recorded in the `EncodedReplica`, so
+    /// [`decode`](Replica::decode) verifies the checksum with the right one.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use cola::{ChecksumAlgorithm, Replica};
+    /// let replica1 = Replica::new(1, 42);
+    ///
+    /// // The transport already guarantees the integrity of the data, so
+    /// // there's no point in computing a checksum.
+    /// let encoded = replica1.encode_with_checksum(ChecksumAlgorithm::None);
+    ///
+    /// let replica2 = Replica::decode(2, &encoded).unwrap();
+    ///
+    /// assert_eq!(replica2.id(), 2);
+    /// ```
+    #[cfg(feature = "encode")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "encode")))]
+    #[inline]
+    pub fn encode_with_checksum(
+        &self,
+        algorithm: ChecksumAlgorithm,
+    ) -> EncodedReplica {
+        let bytes = encode::encode(self);
+        let checksum = checksum(PROTOCOL_VERSION, algorithm, &bytes);
+        EncodedReplica::new(PROTOCOL_VERSION, checksum, bytes)
     }
 
-    /// Returns `true` if the given `Replica` shares the same document state as
-    /// this one.
+    /// Encodes the `Replica` in the same binary format as
+    /// [`encode`](Replica::encode), writing it to the writer a chunk at a
+    /// time.
     ///
-    /// This is used in tests to make sure that an encode-decode roundtrip was
-    /// successful.
-    #[doc(hidden)]
-    pub fn eq_decoded(&self, other: &Self) -> bool {
-        self.run_tree == other.run_tree && self.backlog == other.backlog
+    /// Unlike [`encode`](Replica::encode), this never holds the whole
+    /// encoding in memory, which makes it a better fit for replicas of very
+    /// large documents. The runs of the `Replica` are written in chunks of
+    /// bounded size, and the checksum is computed incrementally as the bytes
+    /// are written.
+    ///
+    /// The writer is flushed once the whole `Replica` has been written. The
+    /// stream can be decoded via [`decode_from_reader`](Replica::decode_from_reader).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use cola::Replica;
+    /// let replica1 = Replica::new(1, 42);
+    ///
+    /// let mut stream = Vec::new();
+    ///
+    /// replica1.encode_to_writer(&mut stream).unwrap();
+    ///
+    /// let replica2 = Replica::decode_from_reader(2, stream.as_slice()).unwrap();
+    ///
+    /// assert_eq!(replica2.id(), 2);
+    /// ```
+    #[cfg(feature = "sha256")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "sha256")))]
+    #[inline]
+    pub fn encode_to_writer(
+        &self,
+        writer: impl std::io::Write,
+    ) -> std::io::Result<()> {
+        stream::encode(
+            writer,
+            &self.run_tree,
+            &self.lamport_clock,
+            &self.version_map,
+            &self.deletion_map,
+            &self.backlog,
+        )
     }
 
-    /// Encodes the `Replica` in a custom binary format.
+    /// Encodes the `Replica` with the given protocol version, or returns
+    /// `None` if this release of cola can't encode it.
     ///
-    /// This can be used to send a `Replica` to another peer over the network.
-    /// Once they have received the [`EncodedReplica`] they can decode it via
-    /// the [`decode`](Replica::decode) method.
+    /// The protocol version is usually obtained by calling
+    /// [`negotiate_protocol`](crate::negotiate_protocol) with the range of
+    /// versions supported by the peer the `EncodedReplica` is meant for.
     ///
-    /// Note that if you want to collaborate within a single process you can
-    /// just [`fork`](Replica::fork) the `Replica` without having to encode it
-    /// and decode it again.
+    /// The checksum is computed with the default [`ChecksumAlgorithm`], except
+    /// for protocol version 1 which only supports SHA-256 and can therefore
+    /// only be encoded if the `sha256` feature is enabled.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use cola::{negotiate_protocol, Replica, PROTOCOL_VERSION};
+    /// let replica = Replica::new(1, 42);
+    ///
+    /// let version = negotiate_protocol(0..=PROTOCOL_VERSION).unwrap();
+    ///
+    /// let encoded = replica.encode_with_protocol(version).unwrap();
+    ///
+    /// assert!(Replica::decode(2, &encoded).is_ok());
+    ///
+    /// // Protocol version 0 can be decoded but not encoded.
+    /// assert!(replica.encode_with_protocol(0).is_none());
+    /// ```
     #[cfg(feature = "encode")]
     #[cfg_attr(docsrs, doc(cfg(feature = "encode")))]
     #[inline]
-    pub fn encode(&self) -> EncodedReplica {
-        let bytes = encode::encode(self);
-        let checksum = checksum(&bytes);
-        EncodedReplica::new(PROTOCOL_VERSION, checksum, bytes)
+    pub fn encode_with_protocol(
+        &self,
+        version: ProtocolVersion,
+    ) -> Option<EncodedReplica> {
+        match version {
+            // Protocol version 1 only differs from the current one in its
+            // checksum, which is always SHA-256.
+            #[cfg(feature = "sha256")]
+            1 => {
+                let bytes = encode::encode(self);
+                let checksum =
+                    checksum(version, ChecksumAlgorithm::Sha256, &bytes);
+                Some(EncodedReplica::new(version, checksum, bytes))
+            },
+
+            PROTOCOL_VERSION => Some(self.encode()),
+
+            _ => None,
+        }
     }
 
     /// Creates a new `Replica` with the given [`ReplicaId`] but with the same
@@ -427,6 +1330,11 @@ impl Replica {
     /// machines you should [`encode`](Replica::encode) the `Replica` and send
     /// the result to the other peers.
     ///
+    /// If this `Replica` has already integrated edits made with the given
+    /// [`ReplicaId`] the new `Replica` continues from where those left off,
+    /// which is what allows the ids of departed peers to be reused (see
+    /// [`integrate_departure`](Replica::integrate_departure)).
+    ///
     /// # Panics
     ///
     /// Panics if the [`ReplicaId`] is zero.
@@ -446,31 +1354,83 @@ impl Replica {
             panic::replica_id_is_zero();
         }
 
-        Self {
+        let mut forked = Self {
             id: new_id,
             run_tree: self.run_tree.clone(),
             run_clock: RunClock::new(),
             lamport_clock: self.lamport_clock,
-            version_map: self.version_map.fork(new_id, 0),
-            deletion_map: self.deletion_map.fork(new_id, 0),
+            version_map: self
+                .version_map
+                .fork(new_id, self.version_map.get(new_id)),
+            deletion_map: self
+                .deletion_map
+                .fork(new_id, self.deletion_map.get(new_id)),
             backlog: self.backlog.clone(),
-        }
+        };
+
+        forked.restore_run_clock();
+
+        forked
+    }
+
+    /// Returns `true` if the given `Insertion` is in this `Replica`'s
+    /// backlog, waiting to be returned by
+    /// [`backlogged_insertions`](Replica::backlogged_insertions).
+    ///
+    /// This can be used to tell the insertions that were backlogged by
+    /// [`integrate_insertion`](Replica::integrate_insertion) apart from the
+    /// ones that were already integrated, since both return `None`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use cola::Replica;
+    /// let mut replica1 = Replica::new(1, 0);
+    /// let mut replica2 = replica1.fork(2);
+    ///
+    /// let insert_a = replica1.inserted(0, 1);
+    /// let insert_b = replica1.inserted(1, 1);
+    ///
+    /// assert!(replica2.integrate_insertion(&insert_b).is_none());
+    /// assert!(replica2.has_backlogged_insertion(&insert_b));
+    ///
+    /// assert!(replica2.integrate_insertion(&insert_a).is_some());
+    /// assert!(!replica2.has_backlogged_insertion(&insert_a));
+    /// ```
+    #[inline]
+    pub fn has_backlogged_insertion(&self, insertion: &Insertion) -> bool {
+        self.backlog.contains_insertion(insertion.text())
     }
 
     /// Returns `true` if this `Replica` has already merged the given
     /// `Deletion`.
     #[inline]
-    fn has_merged_deletion(&self, deletion: &Deletion) -> bool {
+    pub(crate) fn has_merged_deletion(&self, deletion: &Deletion) -> bool {
         self.deletion_map.get(deletion.deleted_by()) >= deletion.deletion_ts()
     }
 
     /// Returns `true` if this `Replica` has already merged the given
     /// `Insertion`.
     #[inline]
-    fn has_merged_insertion(&self, insertion: &Insertion) -> bool {
+    pub(crate) fn has_merged_insertion(&self, insertion: &Insertion) -> bool {
         self.version_map.get(insertion.inserted_by()) > insertion.start()
     }
 
+    /// Returns `true` if the given `Insertion` conflicts with the ones this
+    /// `Replica` has already seen from the replica that made it, which means
+    /// that its `ReplicaId` is shared with another replica.
+    #[inline]
+    pub(crate) fn has_replica_id_collision(
+        &self,
+        insertion: &Insertion,
+    ) -> bool {
+        let merged = self.version_map.get(insertion.inserted_by());
+
+        // We're the only ones who can make new insertions with our id.
+        (insertion.inserted_by() == self.id && insertion.start() >= merged)
+            || self.run_tree.run_indices().conflicts_with(insertion, merged)
+    }
+
     /// Returns the id of this `Replica`.
     #[inline]
     pub fn id(&self) -> ReplicaId {
@@ -534,12 +1494,126 @@ impl Replica {
         )
     }
 
-    #[allow(clippy::len_without_is_empty)]
-    #[doc(hidden)]
+    /// Returns `true` if the document tracked by this `Replica` is empty.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use cola::Replica;
+    /// let mut replica = Replica::new(1, 0);
+    ///
+    /// assert!(replica.is_empty());
+    ///
+    /// let _ = replica.inserted(0, 3);
+    ///
+    /// assert!(!replica.is_empty());
+    /// ```
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the length of the document tracked by this `Replica`, i.e. the
+    /// length your buffer should have after applying all the edits that have
+    /// been integrated so far.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use cola::Replica;
+    /// let mut replica = Replica::new(1, 10);
+    ///
+    /// let _ = replica.inserted(3, 5);
+    /// let _ = replica.deleted(0..2);
+    ///
+    /// assert_eq!(replica.len(), 13);
+    /// ```
+    #[inline]
     pub fn len(&self) -> Length {
         self.run_tree.len()
     }
 
+    /// Integrates a [`Departure`] created by the
+    /// [`depart`](Replica::depart) method on a peer's `Replica`, returning
+    /// `true` once this `Replica` has integrated all of that peer's edits.
+    ///
+    /// At that point, if this is the `Replica` new peers are forked or
+    /// decoded from, the departed peer's [`ReplicaId`] can be handed out
+    /// again, e.g. by passing it to
+    /// [`ReplicaIdAllocator::retire`](crate::ReplicaIdAllocator::retire).
+    /// The new peer continues from where the departed one left off, so the
+    /// text it inserted stays resolvable and the new insertions don't
+    /// collide with the old ones, even on peers that haven't received all
+    /// of the departed peer's edits yet.
+    ///
+    /// The metadata this `Replica` keeps for the departed peer is then
+    /// compacted: the empty backlogs left behind by its edits are dropped,
+    /// its entries are moved out of the version and deletion maps' hash
+    /// tables, and the indices of the text it inserted switch to a
+    /// representation that only takes as much memory as the fragments that
+    /// text has been split into. Anchors into that text keep resolving, and
+    /// a peer reusing the id builds on the compacted metadata. Compaction
+    /// only affects this `Replica`'s in-memory representation, so a
+    /// `Replica` decoded from it has to integrate the `Departure` again to
+    /// compact it.
+    ///
+    /// If this returns `false` some of the departed peer's edits are still
+    /// missing or backlogged, and the `Departure` should be integrated again
+    /// once they've been merged. Its `ReplicaId` must not be reused until
+    /// then.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use cola::{Replica, ReplicaIdAllocator};
+    /// let mut ids = ReplicaIdAllocator::new();
+    ///
+    /// let mut replica1 = Replica::new(ids.allocate(), 3);
+    /// let mut replica2 = replica1.fork(ids.allocate());
+    ///
+    /// let insertion = replica2.inserted(3, 2);
+    ///
+    /// let departure = replica2.depart();
+    ///
+    /// // Replica 1 hasn't received the insertion yet.
+    /// assert!(!replica1.integrate_departure(&departure));
+    ///
+    /// let _ = replica1.integrate_insertion(&insertion);
+    ///
+    /// assert!(replica1.integrate_departure(&departure));
+    ///
+    /// ids.retire(departure.replica_id());
+    ///
+    /// // The id of replica 2 is reused by a new peer.
+    /// let mut replica3 = replica1.fork(ids.allocate());
+    ///
+    /// assert_eq!(replica3.id(), 2);
+    ///
+    /// let insertion = replica3.inserted(5, 1);
+    ///
+    /// assert_eq!(replica1.try_integrate_insertion(&insertion), Ok(Some(5)));
+    /// ```
+    #[inline]
+    pub fn integrate_departure(&mut self, departure: &Departure) -> bool {
+        let replica_id = departure.replica_id();
+
+        if self.version_map.get(replica_id) < departure.character_ts()
+            || self.deletion_map.get(replica_id) < departure.deletion_ts()
+        {
+            return false;
+        }
+
+        self.backlog.remove_replica(replica_id);
+
+        if replica_id != self.id {
+            self.version_map.compact(replica_id);
+            self.deletion_map.compact(replica_id);
+            self.run_tree.run_indices_mut().compact(replica_id);
+        }
+
+        true
+    }
+
     /// Integrates a remote [`Deletion`] into this `Replica`, returning a
     /// sequence of offset [`Range`]s to be deleted from your buffer.
     ///
@@ -562,6 +1636,10 @@ impl Replica {
     /// ranges.len()` it holds that `ranges[i].end < ranges[j].start` (and of
     /// course that `ranges[i].start < ranges[i].end`).
     ///
+    /// A `Deletion` made by a replica sharing its [`ReplicaId`] with another
+    /// one is discarded if its endpoints don't point inside of the text this
+    /// `Replica` has seen from that id.
+    ///
     /// # Examples
     ///
     /// ```
@@ -614,7 +1692,7 @@ impl Replica {
         if deletion.is_no_op() || self.has_merged_deletion(deletion) {
             Vec::new()
         } else if self.can_merge_deletion(deletion) {
-            self.merge_unchecked_deletion(deletion)
+            self.merge_unchecked_deletion(deletion).unwrap_or_default()
         } else {
             self.backlog.insert_deletion(deletion.clone());
             Vec::new()
@@ -631,6 +1709,12 @@ impl Replica {
     /// [`backlogged_insertions`](Replica::backlogged_insertions) method which
     /// handles this case).
     ///
+    /// Insertions that can't be integrated, like the ones made by a replica
+    /// sharing its [`ReplicaId`] with another one, are discarded and also
+    /// return `None`, so they can't be told apart from duplicates. Use
+    /// [`try_integrate_insertion`](Replica::try_integrate_insertion) to
+    /// observe them.
+    ///
     /// # Examples
     ///
     /// ```
@@ -670,68 +1754,88 @@ impl Replica {
         &mut self,
         insertion: &Insertion,
     ) -> Option<Length> {
-        if insertion.is_no_op() || self.has_merged_insertion(insertion) {
-            None
-        } else if self.can_merge_insertion(insertion) {
-            Some(self.merge_unchecked_insertion(insertion))
-        } else {
-            self.backlog.insert_insertion(insertion.clone());
-            None
+        self.try_integrate_insertion(insertion).ok().flatten()
+    }
+
+    /// Returns a [`MemoryReport`] breaking down the memory used by this
+    /// `Replica`.
+    ///
+    /// This runs in `O(n)` in the number of edit runs in the `Replica`, so
+    /// you may want to avoid calling it after every edit.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use cola::Replica;
+    /// let mut replica = Replica::new(1, 3);
+    ///
+    /// let _ = replica.inserted(1, 2);
+    /// let _ = replica.deleted(0..1);
+    ///
+    /// let report = replica.memory_usage();
+    ///
+    /// // The initial run was split in two by the insertion, and the first
+    /// // half was then deleted.
+    /// assert_eq!(report.visible_runs(), 2);
+    /// assert_eq!(report.tombstoned_runs(), 1);
+    ///
+    /// assert!(report.total() > 0);
+    /// ```
+    #[inline]
+    pub fn memory_usage(&self) -> MemoryReport {
+        let (visible_runs, tombstoned_runs) = self.run_tree.count_runs();
+
+        MemoryReport {
+            gtree_inodes: self.run_tree.inodes_heap_size(),
+            gtree_lnodes: self.run_tree.lnodes_heap_size(),
+            run_indices: self.run_tree.run_indices().heap_size(),
+            backlog: self.backlog.heap_size(),
+            version_map: self.version_map.heap_size(),
+            deletion_map: self.deletion_map.heap_size(),
+            visible_runs,
+            tombstoned_runs,
         }
     }
 
     /// Merges the given [`Deletion`] without checking whether it can be
-    /// merged.
+    /// merged, returning `None` and leaving this `Replica` untouched if its
+    /// anchors are unknown.
     #[inline]
     pub(crate) fn merge_unchecked_deletion(
         &mut self,
         deletion: &Deletion,
-    ) -> Vec<Range<Length>> {
+    ) -> Option<Vec<Range<Length>>> {
         debug_assert!(self.can_merge_deletion(deletion));
 
-        let ranges = self.run_tree.merge_deletion(deletion);
+        let ranges = self.run_tree.merge_deletion(deletion)?;
 
         *self.deletion_map.get_mut(deletion.deleted_by()) =
             deletion.deletion_ts();
 
-        ranges
+        Some(ranges)
     }
 
     /// Merges the given [`Insertion`] without checking whether it can be
-    /// merged.
+    /// merged, returning `None` and leaving this `Replica` untouched if its
+    /// anchor is unknown.
     #[inline]
     pub(crate) fn merge_unchecked_insertion(
         &mut self,
         insertion: &Insertion,
-    ) -> Length {
+    ) -> Option<Length> {
         debug_assert!(self.can_merge_insertion(insertion));
 
-        let offset = self.run_tree.merge_insertion(insertion);
+        let offset = self.run_tree.merge_insertion(insertion)?;
 
         *self.version_map.get_mut(insertion.inserted_by()) += insertion.len();
 
         self.lamport_clock.merge(insertion.lamport_ts());
 
-        offset
+        Some(offset)
     }
 
-    /// Creates a new `Replica` with the given [`ReplicaId`] from the initial
-    /// [`Length`] of your buffer.
-    ///
-    /// Note that if you have multiple peers working on the same document you
-    /// should only use this constructor on the first peer, usually the one
-    /// that starts the collaboration session.
-    ///
-    /// The other peers should get their `Replica` from another `Replica`
-    /// already in the session by either:
-    ///
-    /// a) [`fork`](Replica::fork)ing it if the collaboration happens all in
-    /// the same process (e.g. a text editor with plugins running on separate
-    /// threads),
-    ///
-    /// b) [`encode`](Replica::encode)ing it and sending the result over the
-    /// network if the collaboration is between different processes or
-    /// machines.
+    /// Same as [`new`](Replica::new), but for a `Replica` with a custom
+    /// [arity](Replica#choosing-the-arity-of-the-run-tree).
     ///
     /// # Panics
     ///
@@ -740,27 +1844,18 @@ impl Replica {
     /// # Examples
     ///
     /// ```
-    /// # use std::thread;
     /// # use cola::Replica;
-    /// // A text editor initializes a new Replica on the main thread where the
-    /// // buffer is "foo".
-    /// let replica_main = Replica::new(1, 3);
+    /// // A short document with few edits can use a smaller arity than the
+    /// // default one.
+    /// let replica = Replica::<8>::new_with_arity(1, 3);
     ///
-    /// // It then starts a plugin on a separate thread and wants to give it a
-    /// // Replica to keep its buffer synchronized with the one on the main
-    /// // thread. It does *not* call `new()` again, but instead forks the
-    /// // existing Replica and sends it to the new thread.
-    /// let replica_plugin = replica_main.fork(2);
-    ///
-    /// thread::spawn(move || {
-    ///     // The plugin can now use its Replica to exchange edits with the
-    ///     // main thread.
-    ///     println!("{replica_plugin:?}");
-    /// });
+    /// let fork: Replica<8> = replica.fork(2);
     /// ```
     #[track_caller]
     #[inline]
-    pub fn new(id: ReplicaId, len: Length) -> Self {
+    pub fn new_with_arity(id: ReplicaId, len: Length) -> Self {
+        let () = Self::ASSERT_VALID_ARITY;
+
         if id == 0 {
             panic::replica_id_is_zero();
         }
@@ -787,13 +1882,138 @@ impl Replica {
         }
     }
 
-    #[doc(hidden)]
-    pub fn num_runs(&self) -> usize {
-        self.run_tree.count_empty_leaves().1
+    /// Returns a [`ReplicaStats`] describing the internal state of this
+    /// `Replica`.
+    ///
+    /// This runs in `O(n)` in the number of edit runs in the `Replica`, so
+    /// you may want to avoid calling it after every edit.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use cola::Replica;
+    /// let mut replica1 = Replica::new(1, 3);
+    ///
+    /// let mut replica2 = replica1.fork(2);
+    ///
+    /// let _ = replica1.inserted(3, 2);
+    /// let deletion = replica1.deleted(0..1);
+    ///
+    /// // Replica 2 can't merge the deletion before the insertion, so the
+    /// // deletion is backlogged.
+    /// let _ = replica2.integrate_deletion(&deletion);
+    ///
+    /// let stats = replica2.stats();
+    ///
+    /// assert_eq!(stats.len(), 3);
+    /// assert_eq!(stats.known_replicas(), 2);
+    /// assert_eq!(stats.backlogged_deletions(), 1);
+    /// ```
+    #[inline]
+    pub fn stats(&self) -> ReplicaStats {
+        let (visible_runs, tombstoned_runs) = self.run_tree.count_runs();
+
+        ReplicaStats {
+            len: self.run_tree.len(),
+            tree_depth: self.run_tree.height(),
+            average_inode_occupancy: self.run_tree.average_inode_occupancy(),
+            visible_runs,
+            tombstoned_runs,
+            known_replicas: self.version_map.num_replicas(),
+            backlogged_insertions: self.backlog.num_insertions(),
+            backlogged_deletions: self.backlog.num_deletions(),
+        }
+    }
+
+    /// Same as [`integrate_insertion`](Replica::integrate_insertion), but
+    /// returns an [`InsertionError`] if the `Insertion` can't be integrated.
+    ///
+    /// An [`InsertionError::ReplicaIdCollision`] is returned if the
+    /// `Insertion` conflicts with the insertions this `Replica` has already
+    /// seen from the replica that made it, and an
+    /// [`InsertionError::UnknownAnchor`] if its anchor can't be found.
+    ///
+    /// Two replicas sharing the same [`ReplicaId`] can make insertions that
+    /// claim the same temporal range, and a `Replica` integrating both would
+    /// otherwise silently drop the second one as a duplicate of the first,
+    /// diverging from the other peers. A collision is detected when the
+    /// temporal range of the `Insertion` doesn't match the runs of text
+    /// already recorded for its `ReplicaId`, when it overlaps with a
+    /// different backlogged insertion, or when it continues a run of text
+    /// from somewhere other than its end. This doesn't catch every
+    /// collision (e.g. two insertions claiming the exact same temporal range
+    /// and run look like duplicates of one another), so it's a safety net
+    /// rather than a replacement for unique ids. The `Replica` is left
+    /// untouched when an error is returned.
+    ///
+    /// Collisions can be avoided altogether by handing out ids with a
+    /// [`ReplicaIdAllocator`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use cola::{InsertionError, Replica};
+    /// let mut replica1 = Replica::new(1, 3);
+    ///
+    /// // Peers 2 and 3 are mistakenly given the same id.
+    /// let mut replica2 = replica1.fork(2);
+    /// let mut replica3 = replica1.fork(2);
+    ///
+    /// // Peer 2 inserts a character at the start and another one at the end
+    /// // of the document, while peer 3 inserts three characters.
+    /// let insertion2a = replica2.inserted(0, 1);
+    /// let insertion2b = replica2.inserted(4, 1);
+    /// let insertion3 = replica3.inserted(1, 3);
+    ///
+    /// assert_eq!(replica1.try_integrate_insertion(&insertion2a), Ok(Some(0)));
+    /// assert_eq!(replica1.try_integrate_insertion(&insertion2b), Ok(Some(4)));
+    ///
+    /// // Peer 3's insertion claims to be the first run of text inserted by
+    /// // replica 2, but that run only contains a single character.
+    /// let Err(InsertionError::ReplicaIdCollision(collision)) =
+    ///     replica1.try_integrate_insertion(&insertion3)
+    /// else {
+    ///     unreachable!();
+    /// };
+    ///
+    /// assert_eq!(collision.replica_id(), 2);
+    /// assert_eq!(collision.temporal_range(), 0..3);
+    /// ```
+    #[inline]
+    pub fn try_integrate_insertion(
+        &mut self,
+        insertion: &Insertion,
+    ) -> Result<Option<Length>, InsertionError> {
+        if insertion.is_no_op() {
+            Ok(None)
+        } else if self.has_replica_id_collision(insertion) {
+            Err(ReplicaIdCollision::new(insertion.text().clone()).into())
+        } else if self.has_merged_insertion(insertion) {
+            Ok(None)
+        } else if self.can_merge_insertion(insertion) {
+            match self.merge_unchecked_insertion(insertion) {
+                Some(offset) => Ok(Some(offset)),
+                None => Err(InsertionError::UnknownAnchor(
+                    insertion.text().clone(),
+                )),
+            }
+        } else {
+            match self.backlog.overlapping_insertion(insertion) {
+                Some(backlogged) if backlogged == insertion => {},
+                Some(_) => {
+                    return Err(ReplicaIdCollision::new(
+                        insertion.text().clone(),
+                    )
+                    .into())
+                },
+                None => self.backlog.insert_insertion(insertion.clone()),
+            }
+            Ok(None)
+        }
     }
 }
 
-impl core::fmt::Debug for Replica {
+impl<const ARITY: usize> core::fmt::Debug for Replica<ARITY> {
     fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
         struct DebugHexU64(u64);
 
@@ -823,7 +2043,6 @@ pub type LamportTs = u64;
 ///
 /// See [this](https://en.wikipedia.org/wiki/Lamport_timestamp) for more.
 #[derive(Copy, Clone)]
-#[cfg_attr(feature = "encode", derive(serde::Serialize, serde::Deserialize))]
 pub struct LamportClock(LamportTs);
 
 impl core::fmt::Debug for LamportClock {
@@ -891,96 +2110,62 @@ impl RunClock {
 
 pub type DeletionTs = u64;
 
+/// The fields of a `Replica` that are encoded, in the order they're encoded.
 #[cfg(feature = "encode")]
-mod encode {
-    use serde::{de, ser};
+pub(crate) type EncodedFields<const ARITY: usize> =
+    (RunTree<ARITY>, LamportClock, VersionMap, DeletionMap, Backlog);
 
+#[cfg(feature = "encode")]
+mod encode {
     use super::*;
-
-    type EncodedFields =
-        (RunTree, LamportClock, VersionMap, DeletionMap, Backlog);
+    use crate::encode::{Decode, Encode};
 
     #[inline]
-    pub(super) fn encode(replica: &Replica) -> Vec<u8> {
+    pub(super) fn encode<const ARITY: usize>(
+        replica: &Replica<ARITY>,
+    ) -> Vec<u8> {
         let mut encoded = Vec::new();
 
-        encode_field(&mut encoded, &replica.run_tree);
-        encode_field(&mut encoded, &replica.lamport_clock);
-        encode_field(&mut encoded, &replica.version_map);
-        encode_field(&mut encoded, &replica.deletion_map);
-        encode_field(&mut encoded, &replica.backlog);
+        replica.run_tree.encode(&mut encoded);
+        replica.lamport_clock.encode(&mut encoded);
+        replica.version_map.encode(&mut encoded);
+        replica.deletion_map.encode(&mut encoded);
+        replica.backlog.encode(&mut encoded);
 
         encoded
     }
 
     #[inline]
-    pub(super) fn decode(bytes: &[u8]) -> Option<EncodedFields> {
-        let (run_tree, bytes) = decode_field(bytes)?;
-        let (lamport_clock, bytes) = decode_field(bytes)?;
-        let (version_map, bytes) = decode_field(bytes)?;
-        let (deletion_map, bytes) = decode_field(bytes)?;
-        let (backlog, bytes) = decode_field(bytes)?;
-
-        if bytes.is_empty() {
+    pub(super) fn decode<const ARITY: usize>(
+        mut bytes: &[u8],
+    ) -> Option<EncodedFields<ARITY>> {
+        let buf = &mut bytes;
+
+        let run_tree = RunTree::decode(buf)?;
+        let lamport_clock = LamportClock::decode(buf)?;
+        let version_map = VersionMap::decode(buf)?;
+        let deletion_map = DeletionMap::decode(buf)?;
+        let backlog = Backlog::decode(buf)?;
+
+        if buf.is_empty() {
             Some((run_tree, lamport_clock, version_map, deletion_map, backlog))
         } else {
             None
         }
     }
 
-    #[inline]
-    fn encode_field<T>(buf: &mut Vec<u8>, field: &T)
-    where
-        T: ser::Serialize,
-    {
-        let field_bytes = serialize(field);
-        // We convert the usize into a u64 before turning it into a byte array
-        // to guarantee that the latter is always 8 bytes long. If we don't the
-        // length will vary depending on the architecture.
-        let len_bytes = (field_bytes.len() as u64).to_le_bytes();
-        buf.extend_from_slice(&len_bytes);
-        buf.extend_from_slice(&field_bytes);
-    }
-
-    #[inline]
-    fn decode_field<'a, T>(buf: &'a [u8]) -> Option<(T, &'a [u8])>
-    where
-        T: de::Deserialize<'a>,
-    {
-        // The first 8 bytes represent the length of the encoded field.
-        let (len_bytes, rest) = if buf.len() >= 8 {
-            buf.split_at(8)
-        } else {
-            return None;
-        };
-
-        let len_bytes: [u8; 8] = len_bytes.try_into().ok()?;
-
-        let len = u64::from_le_bytes(len_bytes) as usize;
-
-        let (encoded_field, rest) = if rest.len() >= len {
-            rest.split_at(len)
-        } else {
-            return None;
-        };
-
-        deserialize::<T>(encoded_field).map(|field| (field, rest))
-    }
-
-    #[inline]
-    fn serialize<T>(value: &T) -> Vec<u8>
-    where
-        T: ser::Serialize,
-    {
-        bincode::serialize(value).expect("failed to serialize")
+    impl Encode for LamportClock {
+        #[inline]
+        fn encode(&self, buf: &mut Vec<u8>) {
+            self.0.encode(buf);
+        }
     }
 
-    #[inline]
-    fn deserialize<'a, T>(bytes: &'a [u8]) -> Option<T>
-    where
-        T: de::Deserialize<'a>,
-    {
-        bincode::deserialize(bytes).ok()
+    impl Decode for LamportClock {
+        #[inline]
+        fn decode(buf: &mut &[u8]) -> Option<Self> {
+            LamportTs::decode(buf).map(Self)
+        }
     }
 }
 
@@ -989,11 +2174,15 @@ mod debug {
 
     use super::*;
 
-    pub struct DebugAsSelf<'a>(BaseDebug<'a, run_tree::DebugAsSelf<'a>>);
+    pub struct DebugAsSelf<'a, const ARITY: usize>(
+        BaseDebug<'a, ARITY, run_tree::DebugAsSelf<'a, ARITY>>,
+    );
 
-    impl<'a> From<&'a Replica> for DebugAsSelf<'a> {
+    impl<'a, const ARITY: usize> From<&'a Replica<ARITY>>
+        for DebugAsSelf<'a, ARITY>
+    {
         #[inline]
-        fn from(replica: &'a Replica) -> DebugAsSelf<'a> {
+        fn from(replica: &'a Replica<ARITY>) -> DebugAsSelf<'a, ARITY> {
             let base = BaseDebug {
                 replica,
                 debug_run_tree: replica.run_tree.debug_as_self(),
@@ -1003,17 +2192,21 @@ mod debug {
         }
     }
 
-    impl<'a> core::fmt::Debug for DebugAsSelf<'a> {
+    impl<const ARITY: usize> core::fmt::Debug for DebugAsSelf<'_, ARITY> {
         fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
             self.0.fmt(f)
         }
     }
 
-    pub struct DebugAsBtree<'a>(BaseDebug<'a, run_tree::DebugAsBtree<'a>>);
+    pub struct DebugAsBtree<'a, const ARITY: usize>(
+        BaseDebug<'a, ARITY, run_tree::DebugAsBtree<'a, ARITY>>,
+    );
 
-    impl<'a> From<&'a Replica> for DebugAsBtree<'a> {
+    impl<'a, const ARITY: usize> From<&'a Replica<ARITY>>
+        for DebugAsBtree<'a, ARITY>
+    {
         #[inline]
-        fn from(replica: &'a Replica) -> DebugAsBtree<'a> {
+        fn from(replica: &'a Replica<ARITY>) -> DebugAsBtree<'a, ARITY> {
             let base = BaseDebug {
                 replica,
                 debug_run_tree: replica.run_tree.debug_as_btree(),
@@ -1023,18 +2216,18 @@ mod debug {
         }
     }
 
-    impl<'a> core::fmt::Debug for DebugAsBtree<'a> {
+    impl<const ARITY: usize> core::fmt::Debug for DebugAsBtree<'_, ARITY> {
         fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
             self.0.fmt(f)
         }
     }
 
-    struct BaseDebug<'a, T: Debug> {
-        replica: &'a Replica,
+    struct BaseDebug<'a, const ARITY: usize, T: Debug> {
+        replica: &'a Replica<ARITY>,
         debug_run_tree: T,
     }
 
-    impl<'a, T: Debug> Debug for BaseDebug<'a, T> {
+    impl<const ARITY: usize, T: Debug> Debug for BaseDebug<'_, ARITY, T> {
         fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
             let replica = &self.replica;
 
@@ -0,0 +1,416 @@
+use core::future::Future;
+use core::ops::{Bound, RangeBounds};
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+use futures_channel::{mpsc, oneshot};
+use futures_core::Stream;
+
+use crate::panic_messages as panic;
+use crate::*;
+
+/// A cloneable handle to a [`Replica`] owned by a [`ReplicaActor`].
+///
+/// Sharing a `Replica` between the tasks of an async application usually
+/// means wrapping it in a mutex and hand-rolling the loops that feed it the
+/// edits received from the network. A `ReplicaHandle` instead sends every
+/// operation to a `ReplicaActor`, a future which owns the `Replica` and
+/// applies the operations one at a time in the order they were sent.
+///
+/// The actor doesn't depend on any particular runtime, and it's up to you to
+/// spawn it. Since it's just a future and doesn't need a thread of its own,
+/// a single runtime can host as many documents as needed.
+///
+/// Local edits are performed via [`inserted`](Self::inserted) and
+/// [`deleted`](Self::deleted), which return futures resolving to the
+/// [`Insertion`] or [`Deletion`] to send to the other peers. Remote edits
+/// are handed to the actor via [`integrate`](Self::integrate), and the
+/// changes they cause to the document are yielded by the [`TextChanges`]
+/// streams returned by [`changes`](Self::changes).
+///
+/// Note that the offsets of the local edits are interpreted against the
+/// state of the `Replica` at the time the actor receives them, which
+/// includes all the remote edits handed to it before. A task making local
+/// edits to a buffer should therefore apply the changes caused by a remote
+/// edit before making any new local edit, e.g. by integrating the remote
+/// edits itself rather than from a separate task.
+///
+/// # Examples
+///
+/// ```
+/// # use cola::{Replica, ReplicaHandle, TextChange};
+/// # use futures::StreamExt;
+/// # let runtime = tokio::runtime::Builder::new_current_thread()
+/// #     .build()
+/// #     .unwrap();
+/// # runtime.block_on(async {
+/// let replica1 = Replica::new(1, 0);
+/// let replica2 = replica1.fork(2);
+///
+/// let (peer1, actor1) = ReplicaHandle::new(replica1);
+/// let (peer2, actor2) = ReplicaHandle::new(replica2);
+///
+/// // Spawn the actors on your runtime of choice.
+/// tokio::spawn(actor1);
+/// tokio::spawn(actor2);
+///
+/// let mut changes = peer2.changes().await?;
+///
+/// let insertion = peer1.inserted(0, 5).await?;
+///
+/// peer2.integrate(insertion)?;
+///
+/// assert!(matches!(changes.next().await, Some(TextChange::Inserted(_, 0))));
+/// # Ok::<(), cola::EditError>(())
+/// # }).unwrap();
+/// ```
+#[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+#[derive(Clone)]
+pub struct ReplicaHandle {
+    commands: mpsc::UnboundedSender<Command>,
+}
+
+impl core::fmt::Debug for ReplicaHandle {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.debug_struct("ReplicaHandle")
+            .field("is_stopped", &self.commands.is_closed())
+            .finish()
+    }
+}
+
+impl ReplicaHandle {
+    /// Returns a [`TextChanges`] stream yielding the changes caused by the
+    /// remote edits integrated from now on.
+    ///
+    /// The returned future resolves once the actor has registered the new
+    /// stream, so no change is missed by a stream which is awaited before
+    /// integrating the next edit.
+    #[inline]
+    pub fn changes(
+        &self,
+    ) -> impl Future<Output = Result<TextChanges, ActorStopped>> {
+        let (sender, receiver) = mpsc::unbounded();
+        let registered =
+            self.request(|reply| Command::Subscribe(sender, reply));
+        async move {
+            registered?.await.map_err(|_| ActorStopped)?;
+            Ok(TextChanges { receiver })
+        }
+    }
+
+    /// Deletes the given range from the document, resolving to the
+    /// [`Deletion`] to send to the other peers.
+    ///
+    /// The deletion is sent to the actor right away, so it's applied even if
+    /// the returned future is dropped without being awaited. See
+    /// [`Replica::deleted`] for more information.
+    ///
+    /// # Errors
+    ///
+    /// Resolves to [`EditError::OutOfBounds`] if the start of the range is
+    /// greater than the end or if the end is out of bounds when the actor
+    /// receives the deletion, in which case the document is left untouched.
+    #[inline]
+    pub fn deleted<R>(
+        &self,
+        range: R,
+    ) -> impl Future<Output = Result<Deletion, EditError>>
+    where
+        R: RangeBounds<Length>,
+    {
+        let range = (range.start_bound().cloned(), range.end_bound().cloned());
+
+        let deleted = self.request(|reply| Command::Deleted(range, reply));
+
+        async move { deleted?.await.map_err(|_| EditError::ActorStopped)? }
+    }
+
+    /// Forks the `Replica` owned by the actor, resolving to a new `Replica`
+    /// with the given id.
+    ///
+    /// See [`Replica::fork`] for more information.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the [`ReplicaId`] is zero. This is checked before the
+    /// request is sent, so the actor keeps running.
+    #[track_caller]
+    #[inline]
+    pub fn fork(
+        &self,
+        new_id: ReplicaId,
+    ) -> impl Future<Output = Result<Replica, ActorStopped>> {
+        if new_id == 0 {
+            panic::replica_id_is_zero();
+        }
+
+        let forked = self.request(|reply| Command::Fork(new_id, reply));
+        async move { forked?.await.map_err(|_| ActorStopped) }
+    }
+
+    /// Inserts text of the given length at the given offset, resolving to
+    /// the [`Insertion`] to send to the other peers.
+    ///
+    /// The insertion is sent to the actor right away, so it's applied even
+    /// if the returned future is dropped without being awaited. See
+    /// [`Replica::inserted`] for more information.
+    ///
+    /// # Errors
+    ///
+    /// Resolves to [`EditError::OutOfBounds`] if the offset is greater than
+    /// the length of the document when the actor receives the insertion, in
+    /// which case the document is left untouched.
+    #[inline]
+    pub fn inserted(
+        &self,
+        at_offset: Length,
+        len: Length,
+    ) -> impl Future<Output = Result<Insertion, EditError>> {
+        let inserted =
+            self.request(|reply| Command::Inserted(at_offset, len, reply));
+
+        async move { inserted?.await.map_err(|_| EditError::ActorStopped)? }
+    }
+
+    /// Hands a remote edit to the actor, which integrates it (together with
+    /// any backlogged edit it unblocks) and yields the resulting changes to
+    /// the [`TextChanges`] streams.
+    #[inline]
+    pub fn integrate<E: Into<CrdtEdit>>(
+        &self,
+        edit: E,
+    ) -> Result<(), ActorStopped> {
+        self.send(Command::Integrate(edit.into()))
+    }
+
+    /// Creates a new `ReplicaHandle` to the given [`Replica`], together with
+    /// the [`ReplicaActor`] that owns it.
+    ///
+    /// The actor has to be spawned on an async runtime for the handle's
+    /// methods to make progress. It resolves to the `Replica` once all the
+    /// handles to it have been dropped.
+    #[inline]
+    pub fn new(replica: Replica) -> (Self, ReplicaActor) {
+        let (commands, receiver) = mpsc::unbounded();
+
+        let actor = ReplicaActor {
+            replica: Some(replica),
+            commands: receiver,
+            subscribers: Vec::new(),
+        };
+
+        (Self { commands }, actor)
+    }
+
+    #[inline]
+    fn request<T>(
+        &self,
+        command: impl FnOnce(oneshot::Sender<T>) -> Command,
+    ) -> Result<oneshot::Receiver<T>, ActorStopped> {
+        let (reply, receiver) = oneshot::channel();
+        self.send(command(reply))?;
+        Ok(receiver)
+    }
+
+    #[inline]
+    fn send(&self, command: Command) -> Result<(), ActorStopped> {
+        self.commands.unbounded_send(command).map_err(|_| ActorStopped)
+    }
+}
+
+/// The future owning the [`Replica`] a [`ReplicaHandle`] refers to.
+///
+/// This struct is created by [`ReplicaHandle::new`]. See its documentation
+/// for more information.
+#[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+#[must_use = "the actor does nothing unless it's spawned"]
+pub struct ReplicaActor {
+    replica: Option<Replica>,
+    commands: mpsc::UnboundedReceiver<Command>,
+    subscribers: Vec<mpsc::UnboundedSender<TextChange>>,
+}
+
+impl core::fmt::Debug for ReplicaActor {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.debug_struct("ReplicaActor")
+            .field("replica", &self.replica)
+            .field("subscribers", &self.subscribers.len())
+            .finish()
+    }
+}
+
+impl ReplicaActor {
+    #[inline]
+    fn handle(&mut self, command: Command) {
+        let replica = self.replica.as_mut().unwrap();
+
+        // The requests whose reply can't be delivered have been cancelled
+        // by dropping the future, so we can ignore the errors.
+        match command {
+            Command::Deleted((start, end), reply) => {
+                let start = match start {
+                    Bound::Included(n) => Some(n),
+                    Bound::Excluded(n) => n.checked_add(1),
+                    Bound::Unbounded => Some(0),
+                };
+
+                let end = match end {
+                    Bound::Included(n) => n.checked_add(1),
+                    Bound::Excluded(n) => Some(n),
+                    Bound::Unbounded => Some(replica.len()),
+                };
+
+                let deleted = match (start, end) {
+                    (Some(start), Some(end))
+                        if start <= end && end <= replica.len() =>
+                    {
+                        Ok(replica.deleted(start..end))
+                    },
+                    _ => Err(EditError::OutOfBounds(replica.len())),
+                };
+
+                let _ = reply.send(deleted);
+            },
+
+            Command::Fork(new_id, reply) => {
+                let _ = reply.send(replica.fork(new_id));
+            },
+
+            Command::Inserted(at_offset, len, reply) => {
+                let inserted = if at_offset <= replica.len() {
+                    Ok(replica.inserted(at_offset, len))
+                } else {
+                    Err(EditError::OutOfBounds(replica.len()))
+                };
+
+                let _ = reply.send(inserted);
+            },
+
+            Command::Integrate(edit) => {
+                let mut changes = Vec::new();
+
+                session::integrate(replica, &edit, &mut changes);
+
+                for change in changes {
+                    self.subscribers.retain(|subscriber| {
+                        subscriber.unbounded_send(change.clone()).is_ok()
+                    });
+                }
+            },
+
+            Command::Subscribe(subscriber, reply) => {
+                self.subscribers.push(subscriber);
+                let _ = reply.send(());
+            },
+        }
+    }
+}
+
+impl Future for ReplicaActor {
+    type Output = Replica;
+
+    #[inline]
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Replica> {
+        loop {
+            match Pin::new(&mut self.commands).poll_next(cx) {
+                Poll::Ready(Some(command)) => self.handle(command),
+
+                Poll::Ready(None) => {
+                    let replica =
+                        self.replica.take().expect("actor polled after ready");
+                    return Poll::Ready(replica);
+                },
+
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+/// A [`Stream`] of the changes caused by the remote edits integrated by a
+/// [`ReplicaActor`].
+///
+/// This struct is created by [`ReplicaHandle::changes`]. See its
+/// documentation for more information. The stream ends when the actor
+/// stops.
+#[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+#[derive(Debug)]
+pub struct TextChanges {
+    receiver: mpsc::UnboundedReceiver<TextChange>,
+}
+
+impl Stream for TextChanges {
+    type Item = TextChange;
+
+    #[inline]
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<TextChange>> {
+        Pin::new(&mut self.receiver).poll_next(cx)
+    }
+}
+
+/// The error returned by the methods of a [`ReplicaHandle`] when its
+/// [`ReplicaActor`] has been dropped.
+#[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ActorStopped;
+
+impl core::fmt::Display for ActorStopped {
+    #[inline]
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.write_str("the replica actor has stopped")
+    }
+}
+
+impl std::error::Error for ActorStopped {}
+
+/// The error returned by the [`inserted`](ReplicaHandle::inserted) and
+/// [`deleted`](ReplicaHandle::deleted) methods of a [`ReplicaHandle`].
+#[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EditError {
+    /// The [`ReplicaActor`] has been dropped.
+    ActorStopped,
+
+    /// The offset of the insertion or the range of the deletion doesn't fit
+    /// in the document, whose length at the time the actor received the
+    /// edit is given.
+    OutOfBounds(Length),
+}
+
+impl From<ActorStopped> for EditError {
+    #[inline]
+    fn from(_: ActorStopped) -> Self {
+        Self::ActorStopped
+    }
+}
+
+impl core::fmt::Display for EditError {
+    #[inline]
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            Self::ActorStopped => ActorStopped.fmt(f),
+            Self::OutOfBounds(len) => write!(
+                f,
+                "the edit is out of bounds of the document, whose length is \
+                 {len}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for EditError {}
+
+/// An operation sent from a [`ReplicaHandle`] to its [`ReplicaActor`].
+enum Command {
+    Deleted(
+        (Bound<Length>, Bound<Length>),
+        oneshot::Sender<Result<Deletion, EditError>>,
+    ),
+    Fork(ReplicaId, oneshot::Sender<Replica>),
+    Inserted(Length, Length, oneshot::Sender<Result<Insertion, EditError>>),
+    Integrate(CrdtEdit),
+    Subscribe(mpsc::UnboundedSender<TextChange>, oneshot::Sender<()>),
+}
@@ -1,5 +1,8 @@
 use core::hash::BuildHasherDefault;
-use std::collections::HashMap;
+use core::ops::Range;
+use std::collections::{BTreeSet, HashMap};
+
+use crate::{Length, Text};
 
 pub type ReplicaIdMap<T> =
     HashMap<ReplicaId, T, BuildHasherDefault<ReplicaIdHasher>>;
@@ -13,7 +16,11 @@ pub type ReplicaIdMapValuesMut<'a, T> =
 /// collaborative session have unique [`ReplicaId`]s as this type is used to
 /// distinguish between them when integrating remote edits.
 ///
-/// Guaranteeing uniqueness is up to you.
+/// Guaranteeing uniqueness is up to you. If your editing session is proxied
+/// through a server you control, the server can hand out ids to the peers
+/// joining the session via a [`ReplicaIdAllocator`]. Collisions that slip
+/// through can be detected when integrating remote insertions via
+/// [`try_integrate_insertion`](crate::Replica::try_integrate_insertion).
 ///
 /// If your editing session is not proxied through a server you control you can
 /// generate a random `u64` every time a new [`Replica`](crate::Replica) is
@@ -46,3 +53,166 @@ impl core::hash::Hasher for ReplicaIdHasher {
         self.0 = i;
     }
 }
+
+/// Hands out unique [`ReplicaId`]s to the peers of a collaborative session.
+///
+/// This is meant to be used by a coordinator, like the server relaying the
+/// edits between the peers, which hands a new id to every peer that joins the
+/// session. Ids are handed out sequentially starting from 1 (since 0 is not a
+/// valid `ReplicaId`), so they're never repeated as long as the allocator is
+/// the only source of ids in the session.
+///
+/// The ids of peers that have left the session can be handed out again by
+/// [`retire`](Self::retire)-ing them, in which case the smallest retired id
+/// is handed out before any new one.
+///
+/// A coordinator that restarts should persist the
+/// [`next_id`](Self::next_id) and resume from it via
+/// [`starting_from`](Self::starting_from). The retired ids are lost, so
+/// they'll never be handed out again.
+///
+/// # Examples
+///
+/// ```
+/// # use cola::{Replica, ReplicaIdAllocator};
+/// let mut ids = ReplicaIdAllocator::new();
+///
+/// let replica1 = Replica::new(ids.allocate(), 0);
+/// let replica2 = replica1.fork(ids.allocate());
+///
+/// assert_eq!(replica1.id(), 1);
+/// assert_eq!(replica2.id(), 2);
+///
+/// // After a restart.
+/// let mut ids = ReplicaIdAllocator::starting_from(ids.next_id());
+///
+/// assert_eq!(ids.allocate(), 3);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ReplicaIdAllocator {
+    next: ReplicaId,
+    retired: BTreeSet<ReplicaId>,
+}
+
+impl Default for ReplicaIdAllocator {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ReplicaIdAllocator {
+    /// Returns the smallest retired `ReplicaId`, or a new, never handed out
+    /// one if there aren't any.
+    ///
+    /// # Panics
+    ///
+    /// Panics if all the `u64`s have been handed out.
+    #[track_caller]
+    #[inline]
+    pub fn allocate(&mut self) -> ReplicaId {
+        if let Some(id) = self.retired.pop_first() {
+            return id;
+        }
+
+        let id = self.next;
+        self.next = id.checked_add(1).expect("ran out of replica ids");
+        id
+    }
+
+    /// Creates a new `ReplicaIdAllocator` which starts handing out ids from
+    /// 1.
+    #[inline]
+    pub fn new() -> Self {
+        Self { next: 1, retired: BTreeSet::new() }
+    }
+
+    /// Returns the smallest id that has never been handed out.
+    #[inline]
+    pub fn next_id(&self) -> ReplicaId {
+        self.next
+    }
+
+    /// Makes the given id available to be handed out again.
+    ///
+    /// This should only be called with the id of a peer that has
+    /// [`depart`](crate::Replica::depart)ed, once the `Replica` new peers
+    /// are forked from has
+    /// [integrated its departure](crate::Replica::integrate_departure).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the id has never been handed out.
+    #[track_caller]
+    #[inline]
+    pub fn retire(&mut self, id: ReplicaId) {
+        assert!(
+            id != 0 && id < self.next,
+            "the replica id {id} has never been handed out"
+        );
+        self.retired.insert(id);
+    }
+
+    /// Creates a new `ReplicaIdAllocator` which starts handing out ids from
+    /// the given one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the id is zero.
+    #[track_caller]
+    #[inline]
+    pub fn starting_from(first: ReplicaId) -> Self {
+        assert!(first != 0, "the replica id 0 is reserved");
+        Self { next: first, retired: BTreeSet::new() }
+    }
+}
+
+/// The error returned (wrapped in an
+/// [`InsertionError`](crate::InsertionError)) by
+/// [`try_integrate_insertion`](crate::Replica::try_integrate_insertion) when
+/// an [`Insertion`](crate::Insertion) conflicts with the insertions already
+/// seen from the replica that made it.
+///
+/// This means that the [`ReplicaId`] of that replica is shared with another
+/// replica, and the peer that sent the insertion should be told to rejoin
+/// the session with a new id.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplicaIdCollision {
+    text: Text,
+}
+
+impl core::fmt::Display for ReplicaIdCollision {
+    #[inline]
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "the insertion of the characters {:?} by replica {} conflicts \
+             with the ones already inserted by a replica with the same id",
+            self.text.range, self.text.inserted_by,
+        )
+    }
+}
+
+impl std::error::Error for ReplicaIdCollision {}
+
+impl ReplicaIdCollision {
+    #[inline]
+    pub(crate) fn new(text: Text) -> Self {
+        Self { text }
+    }
+
+    /// Returns the `ReplicaId` shared by more than one replica.
+    #[inline]
+    pub fn replica_id(&self) -> ReplicaId {
+        self.text.inserted_by
+    }
+
+    /// Returns the temporal range of the conflicting insertion.
+    ///
+    /// See [`Text::temporal_range`] for more information.
+    #[inline]
+    pub fn temporal_range(&self) -> Range<Length> {
+        self.text.range.clone()
+    }
+}
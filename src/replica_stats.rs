@@ -0,0 +1,90 @@
+use crate::Length;
+
+/// A snapshot of the internal state of a [`Replica`](crate::Replica).
+///
+/// This struct is created by the [`stats`](crate::Replica::stats) method on
+/// [`Replica`](crate::Replica). See its documentation for more information.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReplicaStats {
+    pub(crate) len: Length,
+    pub(crate) tree_depth: usize,
+    pub(crate) average_inode_occupancy: f32,
+    pub(crate) visible_runs: usize,
+    pub(crate) tombstoned_runs: usize,
+    pub(crate) known_replicas: usize,
+    pub(crate) backlogged_insertions: usize,
+    pub(crate) backlogged_deletions: usize,
+}
+
+impl ReplicaStats {
+    /// Returns the average number of children of the internal nodes of the
+    /// run tree.
+    ///
+    /// A value close to half the arity of the `Replica` means that most nodes
+    /// are half empty, which can be used to decide whether a `Replica` would
+    /// benefit from a [different arity][arity].
+    ///
+    /// [arity]: crate::Replica#choosing-the-arity-of-the-run-tree
+    #[inline]
+    pub fn average_inode_occupancy(&self) -> f32 {
+        self.average_inode_occupancy
+    }
+
+    /// Returns the number of remote deletions waiting to be merged.
+    #[inline]
+    pub fn backlogged_deletions(&self) -> usize {
+        self.backlogged_deletions
+    }
+
+    /// Returns the number of remote insertions waiting to be merged.
+    #[inline]
+    pub fn backlogged_insertions(&self) -> usize {
+        self.backlogged_insertions
+    }
+
+    /// Returns `true` if the document is empty.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the number of replicas this `Replica` knows about, including
+    /// itself.
+    #[inline]
+    pub fn known_replicas(&self) -> usize {
+        self.known_replicas
+    }
+
+    /// Returns the length of the document, which should always match the
+    /// length of your buffer.
+    #[inline]
+    pub fn len(&self) -> Length {
+        self.len
+    }
+
+    /// Returns the fraction of edit runs whose text has been deleted, from
+    /// `0.0` if no run has been deleted to `1.0` if all of them have.
+    #[inline]
+    pub fn tombstone_ratio(&self) -> f32 {
+        let total_runs = self.visible_runs + self.tombstoned_runs;
+        (self.tombstoned_runs as f32) / (total_runs as f32)
+    }
+
+    /// Returns the number of edit runs whose text has been deleted.
+    #[inline]
+    pub fn tombstoned_runs(&self) -> usize {
+        self.tombstoned_runs
+    }
+
+    /// Returns the number of levels of internal nodes in the run tree.
+    #[inline]
+    pub fn tree_depth(&self) -> usize {
+        self.tree_depth
+    }
+
+    /// Returns the number of edit runs whose text is still in the document.
+    #[inline]
+    pub fn visible_runs(&self) -> usize {
+        self.visible_runs
+    }
+}
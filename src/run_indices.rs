@@ -1,11 +1,12 @@
-use core::ops::{Index, IndexMut};
+use core::ops::{Index, IndexMut, Range};
+
+use fragments::{RunFragments, RunSplitLeaves};
 
 use crate::*;
 
 /// A data structure used when merging remote edits to efficiently map
 /// an [`Anchor`] to the [`LeafIdx`] of the [`EditRun`] that contains it.
 #[derive(Clone, PartialEq)]
-#[cfg_attr(feature = "encode", derive(serde::Serialize, serde::Deserialize))]
 pub(crate) struct RunIndices {
     map: ReplicaIdMap<ReplicaIndices>,
 }
@@ -24,23 +25,130 @@ pub(crate) enum AnchorBias {
 }
 
 impl RunIndices {
-    pub fn assert_invariants(&self, run_tree: &RunTree) {
+    pub fn check_invariants<const ARITY: usize>(
+        &self,
+        run_tree: &RunTree<ARITY>,
+    ) -> Result<(), IntegrityError> {
         for (&replica_id, indices) in self.map.iter() {
-            indices.assert_invariants();
+            if !indices.is_valid(replica_id, run_tree) {
+                return Err(IntegrityError::RunIndices { replica_id });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns `true` if the given insertion conflicts with the runs already
+    /// recorded for the replica that made it, which has had `merged`
+    /// characters merged so far.
+    ///
+    /// This can only happen if two different replicas share the same
+    /// `ReplicaId`, since then their insertions can claim temporal ranges
+    /// and run timestamps that contradict each other.
+    #[inline]
+    pub fn conflicts_with(
+        &self,
+        insertion: &Insertion,
+        merged: Length,
+    ) -> bool {
+        let indices = self.map.get(&insertion.inserted_by());
+
+        let num_runs = self.num_runs(insertion.inserted_by());
+
+        let run_ts = insertion.run_ts() as usize;
+
+        if insertion.start() < merged {
+            // The insertion has already been merged, so it has to be fully
+            // contained in the run it claims to be part of.
+            let Some(run_range) =
+                indices.and_then(|indices| indices.run_range(run_ts))
+            else {
+                return true;
+            };
+
+            insertion.start() < run_range.start
+                || insertion.end() > run_range.end
+        } else if insertion.start() == merged {
+            // The insertion either starts a new run, or continues the last
+            // one, in which case it was typed right after it.
+            if run_ts.saturating_add(1) == num_runs {
+                insertion.anchor()
+                    != Anchor::new(insertion.inserted_by(), merged)
+                    || insertion.anchor_ts() != insertion.run_ts()
+            } else {
+                run_ts != num_runs
+            }
+        } else {
+            // The insertion can't be part of a run that was already followed
+            // by another one.
+            run_ts.saturating_add(1) < num_runs
+        }
+    }
+
+    /// Rebuilds the run indices of a run tree from its runs, which can be
+    /// yielded in any order.
+    ///
+    /// Returns `None` if the temporal ranges of a replica's runs don't cover
+    /// its whole character clock without gaps or overlaps, or if their run
+    /// timestamps aren't consecutive.
+    #[cfg(feature = "encode")]
+    #[inline]
+    pub fn from_runs<'a, I>(runs: I) -> Option<Self>
+    where
+        I: IntoIterator<Item = (LeafIdx<EditRun>, &'a EditRun)>,
+    {
+        let mut runs_by_replica = ReplicaIdMap::<Vec<_>>::default();
+
+        for (idx, run) in runs {
+            runs_by_replica.entry(run.replica_id()).or_default().push((
+                run.run_ts(),
+                run.start(),
+                run.len(),
+                idx,
+            ));
+        }
+
+        let mut this = Self::new();
+
+        for (replica_id, mut runs) in runs_by_replica {
+            runs.sort_unstable_by_key(|&(run_ts, start, ..)| (run_ts, start));
+
+            let indices = this.get_mut(replica_id);
 
             let mut offset = 0;
 
-            for (idx, splits) in indices.splits().enumerate() {
-                for split in splits.leaves() {
-                    let run = run_tree.run(split.idx);
-                    assert_eq!(replica_id, run.replica_id());
-                    assert_eq!(split.len, run.len());
-                    assert_eq!(offset, run.start());
-                    assert_eq!(idx, run.run_ts() as usize);
-                    offset += split.len;
+            for (run_ts, start, len, idx) in runs {
+                if start != offset {
+                    return None;
                 }
+
+                if run_ts as usize == indices.len() {
+                    indices.append(len, idx);
+                } else if Some(run_ts as usize) == indices.len().checked_sub(1)
+                {
+                    indices.append_to_last(len, idx);
+                } else {
+                    return None;
+                }
+
+                offset += len;
             }
         }
+
+        Some(this)
+    }
+
+    /// Switches the indices of the given replica to a more compact
+    /// representation, which is only worth it once it has departed from the
+    /// session.
+    ///
+    /// The indices keep resolving anchors into the runs it inserted, and are
+    /// switched back if its `ReplicaId` is reused.
+    #[inline]
+    pub fn compact(&mut self, id: ReplicaId) {
+        if let Some(indices) = self.map.get_mut(&id) {
+            indices.compact();
+        }
     }
 
     #[inline]
@@ -48,16 +156,26 @@ impl RunIndices {
         self.map.entry(id).or_insert_with(ReplicaIndices::new)
     }
 
+    /// Returns an estimate of the number of bytes allocated on the heap by
+    /// the run indices.
+    #[inline]
+    pub fn heap_size(&self) -> usize {
+        hashmap_heap_size(&self.map)
+            + self.map.values().map(ReplicaIndices::heap_size).sum::<usize>()
+    }
+
     /// Returns the [`LeafIdx`] of the [`EditRun`] that contains the given
-    /// [`Anchor`].
+    /// [`Anchor`], or `None` if the anchor doesn't point inside any of the
+    /// runs inserted by its replica (which can only happen if the anchor was
+    /// created by a replica sharing its `ReplicaId` with another one).
     #[inline]
     pub fn idx_at_anchor(
         &self,
         anchor: Anchor,
         anchor_ts: RunTs,
         bias: AnchorBias,
-    ) -> LeafIdx<EditRun> {
-        self.map.get(&anchor.replica_id()).unwrap().idx_at_offset(
+    ) -> Option<LeafIdx<EditRun>> {
+        self.map.get(&anchor.replica_id())?.idx_at_offset(
             anchor_ts,
             anchor.offset(),
             bias,
@@ -68,24 +186,266 @@ impl RunIndices {
     pub fn new() -> Self {
         Self { map: ReplicaIdMap::default() }
     }
+
+    /// Checks that the version map has merged exactly as many characters
+    /// from every replica as the ones contained in the runs it inserted.
+    pub fn check_version_map(
+        &self,
+        version_map: &VersionMap,
+    ) -> Result<(), IntegrityError> {
+        let indexed = self.map.keys().copied();
+
+        let merged = version_map.iter().map(|(replica_id, _)| replica_id);
+
+        for replica_id in indexed.chain(merged) {
+            if version_map.get(replica_id) != self.num_characters(replica_id) {
+                return Err(IntegrityError::VersionMap { replica_id });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the total length of the runs inserted by the given replica.
+    #[inline]
+    pub fn num_characters(&self, id: ReplicaId) -> Length {
+        self.map.get(&id).map_or(0, |indices| {
+            indices
+                .len()
+                .checked_sub(1)
+                .and_then(|last| indices.run_range(last))
+                .map_or(0, |last| last.end)
+        })
+    }
+
+    /// Returns the number of runs inserted by the given replica, which is
+    /// also the next `RunTs` it'll use.
+    #[inline]
+    pub fn num_runs(&self, id: ReplicaId) -> usize {
+        self.map.get(&id).map_or(0, ReplicaIndices::len)
+    }
+
+    /// Returns an iterator over the indices of every replica, in no
+    /// particular order.
+    #[inline]
+    pub fn replicas(
+        &self,
+    ) -> impl Iterator<Item = (ReplicaId, &ReplicaIndices)> {
+        self.map.iter().map(|(&id, indices)| (id, indices))
+    }
 }
 
 /// Contains the [`LeafIdx`]s of all the [`EditRun`]s that have been inserted
 /// by a given `Replica`.
 #[derive(Clone, PartialEq)]
-#[cfg_attr(feature = "encode", derive(serde::Serialize, serde::Deserialize))]
-pub(crate) struct ReplicaIndices {
-    /// The [`Fragments`] are stored sequentially and in order of insertion.
+pub(crate) enum ReplicaIndices {
+    /// The indices of a replica that can still insert text.
+    Live(Runs<Fragments>),
+
+    /// The indices of a replica that has departed from the session. Its runs
+    /// can still be split by the edits of other replicas, but they only take
+    /// as much memory as the fragments they've actually been split into.
+    Compact(Runs<CompactFragments>),
+}
+
+impl core::fmt::Debug for ReplicaIndices {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            Self::Live(runs) => runs.fmt(f),
+            Self::Compact(runs) => runs.fmt(f),
+        }
+    }
+}
+
+impl ReplicaIndices {
+    #[inline]
+    pub fn append(&mut self, len: Length, idx: LeafIdx<EditRun>) {
+        self.live_mut().append(len, idx);
+    }
+
+    #[inline]
+    pub fn append_to_last(&mut self, len: Length, idx: LeafIdx<EditRun>) {
+        self.live_mut().append_to_last(len, idx);
+    }
+
+    /// Switches to the compact representation, which is only worth it for
+    /// replicas that won't insert any more text.
+    #[inline]
+    fn compact(&mut self) {
+        let Self::Live(runs) = self else { return };
+
+        let mut vec = core::mem::take(&mut runs.vec)
+            .into_iter()
+            .map(|(fragments, offset)| (fragments.into(), offset))
+            .collect::<Vec<_>>();
+
+        // The elements are smaller than the ones we collected them from, so
+        // the allocation could've been reused.
+        vec.shrink_to_fit();
+
+        *self = Self::Compact(Runs { vec });
+    }
+
+    #[inline]
+    pub fn extend_last(&mut self, extend_by: Length) {
+        self.live_mut().extend_last(extend_by);
+    }
+
+    #[inline]
+    fn heap_size(&self) -> usize {
+        match self {
+            Self::Live(runs) => runs.heap_size(),
+            Self::Compact(runs) => runs.heap_size(),
+        }
+    }
+
+    #[inline]
+    fn idx_at_offset(
+        &self,
+        run_ts: RunTs,
+        at_offset: Length,
+        bias: AnchorBias,
+    ) -> Option<LeafIdx<EditRun>> {
+        match self {
+            Self::Live(runs) => runs.idx_at_offset(run_ts, at_offset, bias),
+            Self::Compact(runs) => runs.idx_at_offset(run_ts, at_offset, bias),
+        }
+    }
+
+    /// Returns `true` if these indices are consistent with the runs
+    /// inserted by the given replica in the run tree.
+    fn is_valid<const ARITY: usize>(
+        &self,
+        replica_id: ReplicaId,
+        run_tree: &RunTree<ARITY>,
+    ) -> bool {
+        match self {
+            Self::Live(runs) => runs.is_valid(replica_id, run_tree),
+            Self::Compact(runs) => runs.is_valid(replica_id, run_tree),
+        }
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        match self {
+            Self::Live(runs) => runs.vec.len(),
+            Self::Compact(runs) => runs.vec.len(),
+        }
+    }
+
+    /// Returns the indices as a [`Runs`] that can be appended to, switching
+    /// back from the compact representation if the replica's id has been
+    /// reused.
+    #[inline]
+    fn live_mut(&mut self) -> &mut Runs<Fragments> {
+        if let Self::Compact(runs) = self {
+            let vec = core::mem::take(&mut runs.vec)
+                .into_iter()
+                .map(|(fragments, offset)| (fragments.into(), offset))
+                .collect();
+
+            *self = Self::Live(Runs { vec });
+        }
+
+        match self {
+            Self::Live(runs) => runs,
+            Self::Compact(_) => unreachable!(),
+        }
+    }
+
+    #[inline]
+    pub fn move_len_to_next_split(
+        &mut self,
+        run_ts: RunTs,
+        split_at_offset: Length,
+        len_moved: Length,
+    ) {
+        match self {
+            Self::Live(runs) => {
+                runs.move_len_to_next_split(run_ts, split_at_offset, len_moved)
+            },
+            Self::Compact(runs) => {
+                runs.move_len_to_next_split(run_ts, split_at_offset, len_moved)
+            },
+        }
+    }
+
+    #[inline]
+    pub fn move_len_to_prev_split(
+        &mut self,
+        run_ts: RunTs,
+        split_at_offset: Length,
+        len_moved: Length,
+    ) {
+        match self {
+            Self::Live(runs) => {
+                runs.move_len_to_prev_split(run_ts, split_at_offset, len_moved)
+            },
+            Self::Compact(runs) => {
+                runs.move_len_to_prev_split(run_ts, split_at_offset, len_moved)
+            },
+        }
+    }
+
+    #[inline]
+    fn new() -> Self {
+        Self::Live(Runs { vec: Vec::new() })
+    }
+
+    /// Returns the temporal range of the run with the given `RunTs`, or
+    /// `None` if the replica hasn't started that run yet.
+    #[inline]
+    fn run_range(&self, run_ts: usize) -> Option<Range<Length>> {
+        match self {
+            Self::Live(runs) => runs.run_range(run_ts),
+            Self::Compact(runs) => runs.run_range(run_ts),
+        }
+    }
+
+    /// Returns an iterator over the fragments of every run, in order of
+    /// insertion.
+    #[inline]
+    pub fn runs(&self) -> impl Iterator<Item = RunSplitLeaves<'_>> {
+        let (live, compact) = match self {
+            Self::Live(runs) => (Some(runs.splits()), None),
+            Self::Compact(runs) => (None, Some(runs.splits())),
+        };
+
+        live.into_iter()
+            .flatten()
+            .map(RunFragments::leaves)
+            .chain(compact.into_iter().flatten().map(RunFragments::leaves))
+    }
+
+    #[inline]
+    pub fn split(
+        &mut self,
+        run_ts: RunTs,
+        at_offset: Length,
+        right_idx: LeafIdx<EditRun>,
+    ) {
+        match self {
+            Self::Live(runs) => runs.split(run_ts, at_offset, right_idx),
+            Self::Compact(runs) => runs.split(run_ts, at_offset, right_idx),
+        }
+    }
+}
+
+/// The runs inserted by a given `Replica`, each stored as the fragments it
+/// has been split into.
+#[derive(Clone, PartialEq)]
+pub(crate) struct Runs<F> {
+    /// The fragments are stored sequentially and in order of insertion.
     ///
-    /// When a new [`EditRun`] is created we append a new [`Fragments`] to the
+    /// When a new [`EditRun`] is created we append new [`Fragments`] to the
     /// vector. As long as the following insertions continue that run we simply
     /// increase the length of the last [`Fragments`].
     ///
     /// Once that run ends we append new [`Fragments`], and so on.
-    vec: Vec<(Fragments, Length)>,
+    vec: Vec<(F, Length)>,
 }
 
-impl core::fmt::Debug for ReplicaIndices {
+impl<F: core::fmt::Debug> core::fmt::Debug for Runs<F> {
     fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
         f.debug_list()
             .entries(self.vec.iter().map(|(splits, _)| splits))
@@ -96,11 +456,11 @@ impl core::fmt::Debug for ReplicaIndices {
 /// We can use the `RunTs` as an index into the `vec` because it is only
 /// incremented when the current run is interrupted and a new one is started.
 ///
-/// Using the `RunTs` as an index allows us to find the `Fragments`
+/// Using the `RunTs` as an index allows us to find the fragments
 /// corresponding to a given offset in `O(1)` instead of having to do a binary
 /// search.
-impl Index<RunTs> for ReplicaIndices {
-    type Output = (Fragments, Length);
+impl<F> Index<RunTs> for Runs<F> {
+    type Output = (F, Length);
 
     #[inline]
     fn index(&self, run_ts: RunTs) -> &Self::Output {
@@ -108,16 +468,16 @@ impl Index<RunTs> for ReplicaIndices {
     }
 }
 
-impl IndexMut<RunTs> for ReplicaIndices {
+impl<F> IndexMut<RunTs> for Runs<F> {
     #[inline]
     fn index_mut(&mut self, run_ts: RunTs) -> &mut Self::Output {
         &mut self.vec[run_ts as usize]
     }
 }
 
-impl ReplicaIndices {
+impl Runs<Fragments> {
     #[inline]
-    pub fn append(&mut self, len: Length, idx: LeafIdx<EditRun>) {
+    fn append(&mut self, len: Length, idx: LeafIdx<EditRun>) {
         let fragment = Fragment::new(len, idx);
 
         let new_last = Fragments::new(fragment);
@@ -132,24 +492,23 @@ impl ReplicaIndices {
     }
 
     #[inline]
-    pub fn append_to_last(&mut self, len: Length, idx: LeafIdx<EditRun>) {
+    fn append_to_last(&mut self, len: Length, idx: LeafIdx<EditRun>) {
         let split = Fragment::new(len, idx);
         self.vec.last_mut().unwrap().0.append(split);
     }
 
-    fn assert_invariants(&self) {
-        let mut offset = 0;
-
-        for &(ref splits, splits_offset) in self.vec.iter() {
-            assert_eq!(splits_offset, offset);
-            splits.assert_invariants();
-            offset += splits.len();
-        }
+    #[inline]
+    fn extend_last(&mut self, extend_by: Length) {
+        self.vec.last_mut().unwrap().0.extend(extend_by);
     }
+}
 
+impl<F: RunFragments> Runs<F> {
     #[inline]
-    pub fn extend_last(&mut self, extend_by: Length) {
-        self.vec.last_mut().unwrap().0.extend(extend_by);
+    fn heap_size(&self) -> usize {
+        let fragments = self.splits().map(F::heap_size).sum::<usize>();
+
+        self.vec.capacity() * core::mem::size_of::<(F, Length)>() + fragments
     }
 
     #[inline]
@@ -158,18 +517,60 @@ impl ReplicaIndices {
         run_ts: RunTs,
         at_offset: Length,
         bias: AnchorBias,
-    ) -> LeafIdx<EditRun> {
-        let (splits, offset) = &self[run_ts];
-        splits.fragment_at_offset(at_offset - offset, bias).idx
+    ) -> Option<LeafIdx<EditRun>> {
+        let (splits, offset) = self.vec.get(run_ts as usize)?;
+
+        let at_offset = at_offset.checked_sub(*offset)?;
+
+        // A left anchor points after the character before it, so it can't be
+        // at the start of the run.
+        let is_inside = match bias {
+            AnchorBias::Left => at_offset > 0 && at_offset <= splits.len(),
+            AnchorBias::Right => at_offset < splits.len(),
+        };
+
+        is_inside.then(|| splits.fragment_at_offset(at_offset, bias).idx)
     }
 
-    #[inline]
-    pub fn len(&self) -> usize {
-        self.vec.len()
+    fn is_valid<const ARITY: usize>(
+        &self,
+        replica_id: ReplicaId,
+        run_tree: &RunTree<ARITY>,
+    ) -> bool {
+        let mut offset = 0;
+
+        for &(ref splits, splits_offset) in self.vec.iter() {
+            if splits_offset != offset || !splits.is_valid() {
+                return false;
+            }
+            offset += splits.len();
+        }
+
+        let mut offset = 0;
+
+        for (idx, splits) in self.splits().enumerate() {
+            for split in splits.leaves() {
+                let Some(run) = run_tree.get_run(split.idx) else {
+                    return false;
+                };
+
+                if replica_id != run.replica_id()
+                    || split.len != run.len()
+                    || offset != run.start()
+                    || idx != run.run_ts() as usize
+                {
+                    return false;
+                }
+
+                offset += split.len;
+            }
+        }
+
+        true
     }
 
     #[inline]
-    pub fn move_len_to_next_split(
+    fn move_len_to_next_split(
         &mut self,
         run_ts: RunTs,
         split_at_offset: Length,
@@ -180,7 +581,7 @@ impl ReplicaIndices {
     }
 
     #[inline]
-    pub fn move_len_to_prev_split(
+    fn move_len_to_prev_split(
         &mut self,
         run_ts: RunTs,
         split_at_offset: Length,
@@ -191,12 +592,14 @@ impl ReplicaIndices {
     }
 
     #[inline]
-    fn new() -> Self {
-        Self { vec: Vec::new() }
+    fn run_range(&self, run_ts: usize) -> Option<Range<Length>> {
+        self.vec
+            .get(run_ts)
+            .map(|(splits, offset)| *offset..*offset + splits.len())
     }
 
     #[inline]
-    pub fn split(
+    fn split(
         &mut self,
         run_ts: RunTs,
         at_offset: Length,
@@ -207,7 +610,7 @@ impl ReplicaIndices {
     }
 
     #[inline]
-    fn splits(&self) -> impl Iterator<Item = &Fragments> {
+    fn splits(&self) -> impl Iterator<Item = &F> {
         self.vec.iter().map(|(splits, _)| splits)
     }
 }
@@ -216,15 +619,45 @@ const FRAGMENTS_INLINE: usize = 8;
 
 type Fragments = fragments::Fragments<FRAGMENTS_INLINE>;
 
+type CompactFragments = fragments::CompactFragments<FRAGMENTS_INLINE>;
+
 mod fragments {
     use super::*;
 
+    /// The operations shared by the live and compact representations of the
+    /// fragments of an insertion run.
+    pub(crate) trait RunFragments {
+        fn fragment_at_offset(
+            &self,
+            at_offset: Length,
+            bias: AnchorBias,
+        ) -> &Fragment;
+
+        fn heap_size(&self) -> usize;
+
+        fn is_valid(&self) -> bool;
+
+        fn leaves(&self) -> RunSplitLeaves<'_>;
+
+        fn len(&self) -> Length;
+
+        fn move_len_to_next_fragment(
+            &mut self,
+            fragment_at_offset: Length,
+            len_move: Length,
+        );
+
+        fn move_len_to_prev_split(
+            &mut self,
+            at_offset: Length,
+            len_move: Length,
+        );
+
+        fn split(&mut self, at_offset: Length, new_idx: LeafIdx<EditRun>);
+    }
+
     /// The `Fragment`s that an insertion run has been fragmented into.
     #[derive(Clone, PartialEq)]
-    #[cfg_attr(
-        feature = "encode",
-        derive(serde::Serialize, serde::Deserialize)
-    )]
     pub(crate) enum Fragments<const INLINE: usize> {
         /// The first `INLINE` fragments are stored inline to avoid
         /// allocating a `Gtree` for runs that are not heavily fragmented.
@@ -249,13 +682,6 @@ mod fragments {
     }
 
     impl<const INLINE: usize> Fragments<INLINE> {
-        pub fn assert_invariants(&self) {
-            match self {
-                Self::Array(array) => array.assert_invariants(),
-                Self::Gtree(gtree) => gtree.assert_invariants(),
-            }
-        }
-
         #[inline]
         pub fn append(&mut self, fragment: Fragment) {
             match self {
@@ -263,7 +689,6 @@ mod fragments {
                     if array.len == INLINE {
                         let mut gtree = Gtree::from_leaves(
                             array.fragments().iter().copied(),
-                            array.total_len,
                         );
                         gtree.append(fragment);
                         *self = Self::Gtree(gtree);
@@ -293,8 +718,36 @@ mod fragments {
             }
         }
 
+        /// Creates the fragments of a run from at most `INLINE` fragments.
+        #[inline]
+        fn from_fragments(fragments: &[Fragment]) -> Self {
+            debug_assert!(fragments.len() <= INLINE);
+
+            let mut array = Array {
+                fragments: [Fragment::null(); INLINE],
+                len: 0,
+                total_len: 0,
+            };
+
+            for &fragment in fragments {
+                array.append(fragment);
+            }
+
+            Self::Array(array)
+        }
+
         #[inline]
-        pub fn fragment_at_offset(
+        pub fn new(first_split: Fragment) -> Self {
+            let mut array = [Fragment::null(); INLINE];
+            let total_len = first_split.len;
+            array[0] = first_split;
+            Self::Array(Array { fragments: array, len: 1, total_len })
+        }
+    }
+
+    impl<const INLINE: usize> RunFragments for Fragments<INLINE> {
+        #[inline]
+        fn fragment_at_offset(
             &self,
             at_offset: Length,
             bias: AnchorBias,
@@ -327,7 +780,41 @@ mod fragments {
         }
 
         #[inline]
-        pub fn len(&self) -> Length {
+        fn heap_size(&self) -> usize {
+            match self {
+                Self::Array(_) => 0,
+                Self::Gtree(gtree) => {
+                    gtree.inodes_heap_size() + gtree.lnodes_heap_size()
+                },
+            }
+        }
+
+        fn is_valid(&self) -> bool {
+            match self {
+                Self::Array(array) => array.is_valid(),
+                Self::Gtree(gtree) => gtree.check_invariants().is_ok(),
+            }
+        }
+
+        #[inline]
+        fn leaves(&self) -> RunSplitLeaves<'_> {
+            match self {
+                Self::Array(array) => {
+                    let iter = Box::new(array.fragments().iter()) as _;
+                    RunSplitLeaves { iter }
+                },
+
+                Self::Gtree(gtree) => {
+                    let iter = Box::new(
+                        gtree.leaves_from_first().map(|(_idx, leaf)| leaf),
+                    ) as _;
+                    RunSplitLeaves { iter }
+                },
+            }
+        }
+
+        #[inline]
+        fn len(&self) -> Length {
             match self {
                 Self::Array(array) => array.total_len,
                 Self::Gtree(splits) => splits.len(),
@@ -335,7 +822,7 @@ mod fragments {
         }
 
         #[inline]
-        pub fn move_len_to_next_fragment(
+        fn move_len_to_next_fragment(
             &mut self,
             fragment_at_offset: Length,
             len_move: Length,
@@ -366,7 +853,7 @@ mod fragments {
         }
 
         #[inline]
-        pub fn move_len_to_prev_split(
+        fn move_len_to_prev_split(
             &mut self,
             at_offset: Length,
             len_move: Length,
@@ -393,21 +880,12 @@ mod fragments {
         }
 
         #[inline]
-        pub fn new(first_split: Fragment) -> Self {
-            let mut array = [Fragment::null(); INLINE];
-            let total_len = first_split.len;
-            array[0] = first_split;
-            Self::Array(Array { fragments: array, len: 1, total_len })
-        }
-
-        #[inline]
-        pub fn split(&mut self, at_offset: Length, new_idx: LeafIdx<EditRun>) {
+        fn split(&mut self, at_offset: Length, new_idx: LeafIdx<EditRun>) {
             match self {
                 Fragments::Array(array) => {
                     if array.len == INLINE {
                         let gtree = Gtree::from_leaves(
                             array.fragments().iter().copied(),
-                            array.total_len,
                         );
                         *self = Fragments::Gtree(gtree);
                         self.split(at_offset, new_idx);
@@ -446,22 +924,6 @@ mod fragments {
     }
 
     impl<const N: usize> Array<N> {
-        #[inline]
-        fn assert_invariants(&self) {
-            let mut total_len = 0;
-
-            for fragment in &self.fragments[0..self.len] {
-                total_len += fragment.len;
-                assert!(!fragment.is_null());
-            }
-
-            assert_eq!(self.total_len, total_len);
-
-            for fragment in &self.fragments[self.len..] {
-                assert!(fragment.is_null());
-            }
-        }
-
         #[inline]
         fn append(&mut self, fragment: Fragment) {
             debug_assert!(self.len < N);
@@ -483,15 +945,7 @@ mod fragments {
             at_offset: Length,
             bias: AnchorBias,
         ) -> &Fragment {
-            let (idx, fragment_offset) = self.idx_at_offset(at_offset);
-            let fragment = &self.fragments[idx];
-            if fragment_offset + fragment.len == at_offset
-                && bias == AnchorBias::Right
-            {
-                &self.fragments[idx + 1]
-            } else {
-                fragment
-            }
+            fragment_at_offset(self.fragments(), at_offset, bias)
         }
 
         #[inline]
@@ -504,16 +958,16 @@ mod fragments {
             &mut self.fragments[..self.len]
         }
 
-        #[inline]
-        fn idx_at_offset(&self, at_offset: Length) -> (usize, Length) {
-            let mut offset = 0;
-            for (idx, fragment) in self.fragments().iter().enumerate() {
-                offset += fragment.len;
-                if offset >= at_offset {
-                    return (idx, offset - fragment.len);
-                }
-            }
-            unreachable!();
+        fn is_valid(&self) -> bool {
+            let total_len = self
+                .fragments()
+                .iter()
+                .map(|fragment| fragment.len)
+                .sum::<Length>();
+
+            self.total_len == total_len
+                && self.fragments().iter().all(|f| !f.is_null())
+                && self.fragments[self.len..].iter().all(Fragment::is_null)
         }
 
         #[inline]
@@ -522,12 +976,11 @@ mod fragments {
             fragment_at_offset: Length,
             len_move: Length,
         ) {
-            let (this, _) = self.idx_at_offset(fragment_at_offset);
-            let next = this + 1;
-            let (this, next) =
-                crate::get_two_mut(self.fragments_mut(), this, next);
-            this.len -= len_move;
-            next.len += len_move;
+            move_len_to_next_fragment(
+                self.fragments_mut(),
+                fragment_at_offset,
+                len_move,
+            );
         }
 
         #[inline]
@@ -536,17 +989,17 @@ mod fragments {
             fragment_at_offset: Length,
             len_move: Length,
         ) {
-            let (this, _) = self.idx_at_offset(fragment_at_offset);
-            let prev = this - 1;
-            let (prev, this) =
-                crate::get_two_mut(self.fragments_mut(), prev, this);
-            this.len -= len_move;
-            prev.len += len_move;
+            move_len_to_prev_fragment(
+                self.fragments_mut(),
+                fragment_at_offset,
+                len_move,
+            );
         }
 
         #[inline]
         fn split(&mut self, at_offset: Length, new_idx: LeafIdx<EditRun>) {
-            let (idx, fragment_offset) = self.idx_at_offset(at_offset);
+            let (idx, fragment_offset) =
+                idx_at_offset(self.fragments(), at_offset);
 
             self.len += 1;
 
@@ -561,166 +1014,254 @@ mod fragments {
         }
     }
 
-    #[cfg(feature = "encode")]
-    mod array_serde {
-        use serde::ser::SerializeMap;
-        use serde::{de, ser};
-
-        use super::*;
-
-        impl<const N: usize> ser::Serialize for Array<N> {
-            fn serialize<S: ser::Serializer>(
-                &self,
-                serializer: S,
-            ) -> Result<S::Ok, S::Error> {
-                let mut map = serializer.serialize_map(Some(3))?;
-                map.serialize_entry("fragments", self.fragments())?;
-                map.serialize_entry("len", &self.len)?;
-                map.serialize_entry("total_len", &self.total_len)?;
-                map.end()
+    /// The fragments of an insertion run of a replica that has departed from
+    /// the session.
+    #[derive(Clone, PartialEq)]
+    pub(crate) enum CompactFragments<const INLINE: usize> {
+        /// Up to `INLINE` fragments are stored in a slice that's exactly as
+        /// long as the number of fragments.
+        Slice(Box<[Fragment]>),
+
+        /// More heavily fragmented runs are stored like the ones of a live
+        /// replica, which by then are always in a `Gtree`.
+        Gtree(Box<Fragments<INLINE>>),
+    }
+
+    impl<const N: usize> core::fmt::Debug for CompactFragments<N> {
+        fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+            match self {
+                Self::Slice(slice) => slice.fmt(f),
+                Self::Gtree(fragments) => fragments.fmt(f),
             }
         }
+    }
 
-        impl<'de, const N: usize> de::Deserialize<'de> for Array<N> {
-            fn deserialize<D: de::Deserializer<'de>>(
-                deserializer: D,
-            ) -> Result<Self, D::Error> {
-                struct ArrayVisitor<const N: usize>;
+    impl<const INLINE: usize> From<Fragments<INLINE>>
+        for CompactFragments<INLINE>
+    {
+        #[inline]
+        fn from(fragments: Fragments<INLINE>) -> Self {
+            match fragments {
+                Fragments::Array(array) => {
+                    Self::Slice(array.fragments().into())
+                },
+                Fragments::Gtree(_) => Self::Gtree(Box::new(fragments)),
+            }
+        }
+    }
 
-                impl<'de, const N: usize> de::Visitor<'de> for ArrayVisitor<N> {
-                    type Value = Array<N>;
+    impl<const INLINE: usize> From<CompactFragments<INLINE>>
+        for Fragments<INLINE>
+    {
+        #[inline]
+        fn from(fragments: CompactFragments<INLINE>) -> Self {
+            match fragments {
+                CompactFragments::Slice(slice) => Self::from_fragments(&slice),
+                CompactFragments::Gtree(fragments) => *fragments,
+            }
+        }
+    }
 
-                    #[inline]
-                    fn expecting(
-                        &self,
-                        formatter: &mut core::fmt::Formatter,
-                    ) -> core::fmt::Result {
-                        formatter.write_str("a map representing an Array")
-                    }
+    impl<const INLINE: usize> RunFragments for CompactFragments<INLINE> {
+        #[inline]
+        fn fragment_at_offset(
+            &self,
+            at_offset: Length,
+            bias: AnchorBias,
+        ) -> &Fragment {
+            match self {
+                Self::Slice(slice) => {
+                    fragment_at_offset(slice, at_offset, bias)
+                },
+                Self::Gtree(fragments) => {
+                    fragments.fragment_at_offset(at_offset, bias)
+                },
+            }
+        }
 
-                    #[inline]
-                    fn visit_map<V: de::MapAccess<'de>>(
-                        self,
-                        mut map: V,
-                    ) -> Result<Self::Value, V::Error> {
-                        let mut len = None;
-                        let mut total_len = None;
-                        let mut fragments_vec = None;
-
-                        while let Some(key) = map.next_key()? {
-                            match key {
-                                "len" => {
-                                    if len.is_some() {
-                                        return Err(
-                                            de::Error::duplicate_field("len"),
-                                        );
-                                    }
-                                    len = Some(map.next_value()?);
-                                },
-
-                                "total_len" => {
-                                    if total_len.is_some() {
-                                        return Err(
-                                            de::Error::duplicate_field(
-                                                "total_len",
-                                            ),
-                                        );
-                                    }
-                                    total_len = Some(map.next_value()?);
-                                },
-
-                                "fragments" => {
-                                    if fragments_vec.is_some() {
-                                        return Err(
-                                            de::Error::duplicate_field(
-                                                "fragments",
-                                            ),
-                                        );
-                                    }
-                                    fragments_vec = Some(
-                                        map.next_value::<Vec<Fragment>>()?,
-                                    );
-                                },
-
-                                _ => {
-                                    return Err(de::Error::unknown_field(
-                                        key,
-                                        &["fragments", "len", "total_len"],
-                                    ));
-                                },
-                            }
-                        }
-
-                        let len = len
-                            .ok_or_else(|| de::Error::missing_field("len"))?;
-
-                        let total_len = total_len.ok_or_else(|| {
-                            de::Error::missing_field("total_len")
-                        })?;
-
-                        let fragments_vec =
-                            fragments_vec.ok_or_else(|| {
-                                de::Error::missing_field("fragments")
-                            })?;
-
-                        if fragments_vec.len() != len {
-                            return Err(de::Error::invalid_length(
-                                fragments_vec.len(),
-                                &len.to_string().as_str(),
-                            ));
-                        }
-
-                        if fragments_vec.len() > N {
-                            return Err(de::Error::invalid_length(
-                                fragments_vec.len(),
-                                &format!("no more than {N}").as_str(),
-                            ));
-                        }
-
-                        let mut fragments = [Fragment::null(); N];
-
-                        fragments[..len]
-                            .copy_from_slice(fragments_vec.as_slice());
-
-                        Ok(Array { fragments, len, total_len })
-                    }
-                }
+        #[inline]
+        fn heap_size(&self) -> usize {
+            match self {
+                Self::Slice(slice) => {
+                    slice.len() * core::mem::size_of::<Fragment>()
+                },
+                Self::Gtree(fragments) => {
+                    core::mem::size_of::<Fragments<INLINE>>()
+                        + fragments.heap_size()
+                },
+            }
+        }
 
-                deserializer.deserialize_map(ArrayVisitor)
+        fn is_valid(&self) -> bool {
+            match self {
+                Self::Slice(slice) => {
+                    !slice.is_empty()
+                        && slice.len() <= INLINE
+                        && slice.iter().all(|f| !f.is_null())
+                },
+                Self::Gtree(fragments) => {
+                    matches!(**fragments, Fragments::Gtree(_))
+                        && fragments.is_valid()
+                },
             }
         }
-    }
 
-    impl<const N: usize> gtree::Join for Fragments<N> {}
+        #[inline]
+        fn leaves(&self) -> RunSplitLeaves<'_> {
+            match self {
+                Self::Slice(slice) => {
+                    let iter = Box::new(slice.iter()) as _;
+                    RunSplitLeaves { iter }
+                },
+                Self::Gtree(fragments) => fragments.leaves(),
+            }
+        }
 
-    impl<const N: usize> gtree::Leaf for Fragments<N> {
-        type Length = Length;
+        #[inline]
+        fn len(&self) -> Length {
+            match self {
+                Self::Slice(slice) => {
+                    slice.iter().map(|fragment| fragment.len).sum()
+                },
+                Self::Gtree(fragments) => fragments.len(),
+            }
+        }
 
         #[inline]
-        fn len(&self) -> Self::Length {
-            self.len()
+        fn move_len_to_next_fragment(
+            &mut self,
+            fragment_at_offset: Length,
+            len_move: Length,
+        ) {
+            match self {
+                Self::Slice(slice) => move_len_to_next_fragment(
+                    slice,
+                    fragment_at_offset,
+                    len_move,
+                ),
+                Self::Gtree(fragments) => fragments
+                    .move_len_to_next_fragment(fragment_at_offset, len_move),
+            }
         }
-    }
 
-    impl<const N: usize> Fragments<N> {
         #[inline]
-        pub fn leaves(&self) -> RunSplitLeaves<'_> {
+        fn move_len_to_prev_split(
+            &mut self,
+            at_offset: Length,
+            len_move: Length,
+        ) {
             match self {
-                Self::Array(array) => {
-                    let iter = Box::new(array.fragments().iter()) as _;
-                    RunSplitLeaves { iter }
+                Self::Slice(slice) => {
+                    move_len_to_prev_fragment(slice, at_offset, len_move)
+                },
+                Self::Gtree(fragments) => {
+                    fragments.move_len_to_prev_split(at_offset, len_move)
                 },
+            }
+        }
 
-                Self::Gtree(gtree) => {
-                    let iter = Box::new(
-                        gtree.leaves_from_first().map(|(_idx, leaf)| leaf),
-                    ) as _;
-                    RunSplitLeaves { iter }
+        /// Splitting a run stored in a slice reallocates it, which is fine
+        /// since the runs of departed replicas are rarely edited.
+        #[inline]
+        fn split(&mut self, at_offset: Length, new_idx: LeafIdx<EditRun>) {
+            match self {
+                Self::Slice(slice) if slice.len() < INLINE => {
+                    let mut vec = core::mem::take(slice).into_vec();
+
+                    let (idx, fragment_offset) =
+                        idx_at_offset(&vec, at_offset);
+
+                    let new_fragment =
+                        vec[idx].split(at_offset - fragment_offset, new_idx);
+
+                    vec.insert(idx + 1, new_fragment);
+
+                    *slice = vec.into_boxed_slice();
                 },
+
+                Self::Slice(slice) => {
+                    let mut fragments = Fragments::from_fragments(slice);
+                    fragments.split(at_offset, new_idx);
+                    *self = Self::Gtree(Box::new(fragments));
+                },
+
+                Self::Gtree(fragments) => fragments.split(at_offset, new_idx),
             }
         }
     }
 
+    /// Returns the index of the fragment containing the given offset together
+    /// with the offset it starts at, or the one ending at it if the offset is
+    /// on a boundary between two fragments.
+    #[inline]
+    fn idx_at_offset(
+        fragments: &[Fragment],
+        at_offset: Length,
+    ) -> (usize, Length) {
+        let mut offset = 0;
+        for (idx, fragment) in fragments.iter().enumerate() {
+            offset += fragment.len;
+            if offset >= at_offset {
+                return (idx, offset - fragment.len);
+            }
+        }
+        unreachable!();
+    }
+
+    #[inline]
+    fn fragment_at_offset(
+        fragments: &[Fragment],
+        at_offset: Length,
+        bias: AnchorBias,
+    ) -> &Fragment {
+        let (idx, fragment_offset) = idx_at_offset(fragments, at_offset);
+        let fragment = &fragments[idx];
+        if fragment_offset + fragment.len == at_offset
+            && bias == AnchorBias::Right
+        {
+            &fragments[idx + 1]
+        } else {
+            fragment
+        }
+    }
+
+    #[inline]
+    fn move_len_to_next_fragment(
+        fragments: &mut [Fragment],
+        fragment_at_offset: Length,
+        len_move: Length,
+    ) {
+        let (this, _) = idx_at_offset(fragments, fragment_at_offset);
+        let next = this + 1;
+        let (this, next) = crate::get_two_mut(fragments, this, next);
+        this.len -= len_move;
+        next.len += len_move;
+    }
+
+    #[inline]
+    fn move_len_to_prev_fragment(
+        fragments: &mut [Fragment],
+        fragment_at_offset: Length,
+        len_move: Length,
+    ) {
+        let (this, _) = idx_at_offset(fragments, fragment_at_offset);
+        let prev = this - 1;
+        let (prev, this) = crate::get_two_mut(fragments, prev, this);
+        this.len -= len_move;
+        prev.len += len_move;
+    }
+
+    impl<const N: usize> gtree::Join for Fragments<N> {}
+
+    impl<const N: usize> gtree::Leaf for Fragments<N> {
+        type Length = Length;
+
+        #[inline]
+        fn len(&self) -> Self::Length {
+            RunFragments::len(self)
+        }
+    }
+
     pub(crate) struct RunSplitLeaves<'a> {
         iter: Box<dyn Iterator<Item = &'a Fragment> + 'a>,
     }
@@ -736,7 +1277,6 @@ mod fragments {
 
 /// The length and [`LeafIdx`] of a fragment of a single insertion run.
 #[derive(Copy, Clone, PartialEq)]
-#[cfg_attr(feature = "encode", derive(serde::Serialize, serde::Deserialize))]
 pub(crate) struct Fragment {
     len: Length,
     idx: LeafIdx<EditRun>,
@@ -749,6 +1289,16 @@ impl core::fmt::Debug for Fragment {
 }
 
 impl Fragment {
+    #[inline]
+    pub fn idx(&self) -> LeafIdx<EditRun> {
+        self.idx
+    }
+
+    #[inline]
+    pub fn len(&self) -> Length {
+        self.len
+    }
+
     #[inline]
     const fn null() -> Self {
         Self { len: 0, idx: LeafIdx::dangling() }
@@ -1,18 +1,20 @@
 use core::cmp::Ordering;
 use core::ops;
 
+#[cfg(feature = "sha256")]
+pub(crate) use encode::decode_runs;
+#[cfg(feature = "encode")]
+pub(crate) use encode::encode_runs;
+
 use crate::gtree::LeafIdx;
 use crate::*;
 
-const RUN_TREE_ARITY: usize = 32;
-
-type Gtree = crate::Gtree<RUN_TREE_ARITY, EditRun>;
+type Gtree<const ARITY: usize> = crate::Gtree<ARITY, EditRun>;
 
 #[derive(Clone, Debug, PartialEq)]
-#[cfg_attr(feature = "encode", derive(serde::Serialize, serde::Deserialize))]
-pub(crate) struct RunTree {
+pub(crate) struct RunTree<const ARITY: usize> {
     /// The tree of runs.
-    gtree: Gtree,
+    gtree: Gtree<ARITY>,
 
     /// A secondary data structure that allows to quickly find the
     /// [`LeafIdx`](crate::LeafIdx) of the run that contains a given
@@ -20,7 +22,7 @@ pub(crate) struct RunTree {
     run_indices: RunIndices,
 }
 
-impl RunTree {
+impl<const ARITY: usize> RunTree<ARITY> {
     #[inline]
     fn append_run_to_another(
         &mut self,
@@ -46,35 +48,71 @@ impl RunTree {
     }
 
     #[inline]
-    pub fn assert_invariants(&self) {
-        self.gtree.assert_invariants();
-        self.run_indices.assert_invariants(self);
+    pub fn average_inode_occupancy(&self) -> f32 {
+        self.gtree.average_inode_occupancy()
     }
 
     #[inline]
-    pub fn average_inode_occupancy(&self) -> f32 {
-        self.gtree.average_inode_occupancy()
+    pub fn check_invariants(&self) -> Result<(), IntegrityError> {
+        self.gtree.check_invariants()?;
+        self.run_indices.check_invariants(self)
     }
 
+    /// Returns a `(visible_runs, tombstoned_runs)` tuple.
     #[inline]
-    pub fn count_empty_leaves(&self) -> (usize, usize) {
-        self.gtree.count_empty_leaves()
+    pub fn count_runs(&self) -> (usize, usize) {
+        self.gtree.leaves_from_first().fold(
+            (0, 0),
+            |(visible, tombstoned), (_, run)| {
+                if run.is_deleted {
+                    (visible, tombstoned + 1)
+                } else {
+                    (visible + 1, tombstoned)
+                }
+            },
+        )
     }
 
     #[inline]
-    pub fn debug_as_self(&self) -> DebugAsSelf<'_> {
+    pub fn debug_as_self(&self) -> DebugAsSelf<'_, ARITY> {
         self.gtree.debug_as_self()
     }
 
     #[inline]
-    pub fn debug_as_btree(&self) -> DebugAsBtree<'_> {
+    pub fn debug_as_btree(&self) -> DebugAsBtree<'_, ARITY> {
         self.gtree.debug_as_btree()
     }
 
+    /// Deletes the given offset range, returning the anchors at the start
+    /// and at the end of the deleted range together with the run timestamps
+    /// of the runs containing them.
+    ///
+    /// It also returns the subset of the given version map that a `Replica`
+    /// needs to merge the deletion, i.e. the entries of the replicas that
+    /// inserted the runs between the two anchors.
     #[inline]
     pub fn delete(
         &mut self,
         range: Range<Length>,
+        version_map: &VersionMap,
+    ) -> (Anchor, RunTs, Anchor, RunTs, VersionMap) {
+        let (start, start_ts, end, end_ts) = self.delete_runs(range);
+
+        let version_map = self.deletion_version_map(
+            start,
+            start_ts,
+            end,
+            end_ts,
+            version_map,
+        );
+
+        (start, start_ts, end, end_ts, version_map)
+    }
+
+    #[inline]
+    fn delete_runs(
+        &mut self,
+        range: Range<Length>,
     ) -> (Anchor, RunTs, Anchor, RunTs) {
         let mut id_start = 0;
         let mut run_ts_start = 0;
@@ -195,9 +233,76 @@ impl RunTree {
         (anchor_start, run_ts_range, anchor_end, run_ts_range)
     }
 
+    /// Returns the entries of the version map that are needed to merge a
+    /// deletion between the given anchors.
+    ///
+    /// [`merge_deletion`](Self::merge_deletion) only looks up the version map
+    /// for the runs it visits, i.e. the ones between the start and the end
+    /// anchor (or between the start of the document and the end anchor if
+    /// the start anchor is zero). This includes the deleted runs, since the
+    /// merging replica may not have deleted them yet.
+    ///
+    /// The entries of the replicas of all the other runs can be left out,
+    /// which keeps `Deletion`s small in sessions with many peers.
     #[inline]
-    pub fn run(&self, run_idx: LeafIdx<EditRun>) -> &EditRun {
-        self.gtree.leaf(run_idx)
+    fn deletion_version_map(
+        &self,
+        start: Anchor,
+        start_ts: RunTs,
+        end: Anchor,
+        end_ts: RunTs,
+        version_map: &VersionMap,
+    ) -> VersionMap {
+        let runs = if start.is_zero() {
+            self.gtree.leaves_from_first()
+        } else {
+            let start_idx = self
+                .run_indices
+                .idx_at_anchor(start, start_ts, AnchorBias::Right)
+                .unwrap();
+            self.gtree.leaves::<true>(start_idx)
+        };
+
+        let end_idx = self
+            .run_indices
+            .idx_at_anchor(end, end_ts, AnchorBias::Left)
+            .unwrap();
+
+        let mut deletion_map = VersionMap::new(version_map.this_id(), 0);
+
+        for (run_idx, run) in runs {
+            let replica_id = run.replica_id();
+
+            *deletion_map.get_mut(replica_id) = version_map.get(replica_id);
+
+            if run_idx == end_idx {
+                break;
+            }
+        }
+
+        deletion_map
+    }
+
+    #[inline]
+    pub fn get_run(&self, run_idx: LeafIdx<EditRun>) -> Option<&EditRun> {
+        self.gtree.get_leaf(run_idx)
+    }
+
+    /// Returns `true` if both run trees contain the same runs in the same
+    /// order, regardless of how their internal nodes are laid out.
+    #[inline]
+    pub fn has_same_runs(&self, other: &Self) -> bool {
+        self.runs().eq(other.runs())
+    }
+
+    #[inline]
+    pub fn height(&self) -> usize {
+        self.gtree.height()
+    }
+
+    #[inline]
+    pub fn inodes_heap_size(&self) -> usize {
+        self.gtree.inodes_heap_size()
     }
 
     #[inline]
@@ -416,32 +521,62 @@ impl RunTree {
         }
     }
 
+    #[inline]
+    pub fn lnodes_heap_size(&self) -> usize {
+        self.gtree.lnodes_heap_size()
+    }
+
+    /// Merges a remote deletion, returning the ranges of visible text it
+    /// deleted.
+    ///
+    /// Returns `None` without modifying the run tree if the deletion's
+    /// anchors don't point inside of the runs they claim to, or if they're
+    /// not covered by its version map. This can only happen if the deletion
+    /// was made by a replica sharing its `ReplicaId` with another one.
     #[inline]
     pub fn merge_deletion(
         &mut self,
         deletion: &Deletion,
-    ) -> Vec<ops::Range<usize>> {
+    ) -> Option<Vec<ops::Range<usize>>> {
+        let version_map = deletion.version_map();
+
+        if deletion.end().is_zero()
+            || version_map.get(deletion.end().replica_id())
+                < deletion.end().offset
+            || !deletion.start().is_zero()
+                && version_map.get(deletion.start().replica_id())
+                    <= deletion.start().offset
+        {
+            return None;
+        }
+
         let start_idx = if deletion.start().is_zero() {
             // If the deletion starts at the beginning of the document we start
             // from the first run that was visible when the deletion was made.
-            self.gtree
-                .leaves_from_first()
-                .find_map(|(run_idx, run)| {
-                    (run.start()
-                        < deletion.version_map().get(run.replica_id()))
+            self.gtree.leaves_from_first().find_map(|(run_idx, run)| {
+                (run.start() < version_map.get(run.replica_id()))
                     .then_some(run_idx)
-                })
-                .unwrap()
+            })?
         } else {
             self.run_indices.idx_at_anchor(
                 deletion.start(),
                 deletion.start_ts(),
                 AnchorBias::Right,
-            )
+            )?
         };
 
+        let end_idx = self.run_indices.idx_at_anchor(
+            deletion.end(),
+            deletion.end_ts(),
+            AnchorBias::Left,
+        )?;
+
         let mut leaf_offset = self.gtree.offset_of_leaf(start_idx);
 
+        if self.gtree.offset_of_leaf(end_idx) < leaf_offset {
+            return None;
+        }
+
         let start = self.gtree.leaf(start_idx);
 
         let mut ranges = Vec::new();
@@ -450,7 +585,7 @@ impl RunTree {
             let run = start;
 
             if run.is_deleted {
-                return ranges;
+                return Some(ranges);
             } else {
                 let delete_from = if deletion.start().is_zero() {
                     0
@@ -459,19 +594,16 @@ impl RunTree {
                 };
 
                 let delete_up_to = deletion.end().offset - run.start();
+                if delete_from > delete_up_to {
+                    return None;
+                }
                 let delete_range = (delete_from..delete_up_to).into();
                 self.delete_leaf_range(start_idx, leaf_offset, delete_range);
                 ranges.push((delete_range + leaf_offset).into());
-                return ranges;
+                return Some(ranges);
             }
         }
 
-        let end_idx = self.run_indices.idx_at_anchor(
-            deletion.end(),
-            deletion.end_ts(),
-            AnchorBias::Left,
-        );
-
         /// TODO: docs
         enum DeletionState {
             Deleting(Length),
@@ -482,7 +614,7 @@ impl RunTree {
         let mut visible_offset = leaf_offset;
 
         let (start_idx, mut state) = if start.is_deleted {
-            let next_idx = self.gtree.next_leaf(start_idx).unwrap();
+            let next_idx = self.gtree.next_leaf(start_idx)?;
             (next_idx, DeletionState::Starting)
         } else {
             let delete_from = if deletion.start().is_zero() {
@@ -531,7 +663,16 @@ impl RunTree {
         let mut runs = self.gtree.leaves::<true>(start_idx);
 
         loop {
-            let (run_idx, run) = runs.next().unwrap();
+            // We can only run out of runs if the end of a deletion made by a
+            // replica sharing its id with another one comes before its start,
+            // which the check on the offsets above doesn't catch if there
+            // are only deleted runs between the two.
+            let Some((run_idx, run)) = runs.next() else {
+                if let DeletionState::Deleting(start_offset) = state {
+                    ranges.push(start_offset..visible_offset);
+                }
+                break;
+            };
 
             if run_idx == end_idx {
                 if run.is_deleted {
@@ -622,10 +763,14 @@ impl RunTree {
             // deleting up to the root.
             let gtree = unsafe {
                 #[allow(mutable_transmutes)]
-                core::mem::transmute::<_, &mut Gtree>(&self.gtree)
+                core::mem::transmute::<_, &mut Gtree<ARITY>>(&self.gtree)
             };
             gtree.with_leaf_mut(run_idx, |run| run.delete());
 
+            // The cursor may be parked on a run after this one, in which case
+            // its offset is now stale.
+            gtree.remove_cursor();
+
             if !matches!(state, DeletionState::Deleting(_)) {
                 state = DeletionState::Deleting(visible_offset);
             }
@@ -633,22 +778,30 @@ impl RunTree {
             visible_offset += run_len;
         }
 
-        ranges
+        Some(ranges)
     }
 
+    /// Merges a remote insertion, returning the offset at which its text
+    /// was inserted.
+    ///
+    /// Returns `None` without modifying the run tree if the insertion's
+    /// anchor doesn't point inside of the run it claims to.
     #[inline]
-    pub fn merge_insertion(&mut self, insertion: &Insertion) -> Length {
+    pub fn merge_insertion(
+        &mut self,
+        insertion: &Insertion,
+    ) -> Option<Length> {
         let run = EditRun::from_insertion(insertion);
 
         if insertion.anchor().is_zero() {
-            return self.insert_run_at_zero(run);
+            return Some(self.insert_run_at_zero(run));
         }
 
         let anchor_idx = self.run_indices.idx_at_anchor(
             insertion.anchor(),
             insertion.anchor_ts(),
             AnchorBias::Left,
-        );
+        )?;
 
         let anchor = self.gtree.leaf(anchor_idx);
 
@@ -657,7 +810,9 @@ impl RunTree {
         // can just split the anchor run and insert the new run after it.
         if insertion.anchor().offset < anchor.end() {
             let insert_at = insertion.anchor().offset - anchor.start();
-            return self.split_run_with_another(run, anchor_idx, insert_at);
+            return Some(
+                self.split_run_with_another(run, anchor_idx, insert_at),
+            );
         }
 
         let mut prev_idx = anchor_idx;
@@ -677,16 +832,18 @@ impl RunTree {
                     if run > *sibling {
                         prev_idx = idx;
                     } else {
-                        return self.insert_run_after_another(run, prev_idx);
+                        return Some(
+                            self.insert_run_after_another(run, prev_idx),
+                        );
                     }
                 }
             } else if anchor.can_append(&run) {
                 // Append the run to the anchor run. This is the only path that
                 // doesn't add new runs to the Gtree.
-                return self.append_run_to_another(run, anchor_idx);
+                return Some(self.append_run_to_another(run, anchor_idx));
             } else {
                 // Insert the run right after the anchor run.
-                return self.insert_run_after_another(run, anchor_idx);
+                return Some(self.insert_run_after_another(run, anchor_idx));
             }
         };
 
@@ -694,12 +851,12 @@ impl RunTree {
             if run > *leaf {
                 prev_idx = idx;
             } else {
-                return self.insert_run_after_another(run, prev_idx);
+                return Some(self.insert_run_after_another(run, prev_idx));
             }
         }
 
         // If we get here we're inserting after the last run in the Gtree.
-        self.insert_run_after_another(run, prev_idx)
+        Some(self.insert_run_after_another(run, prev_idx))
     }
 
     #[inline]
@@ -712,11 +869,27 @@ impl RunTree {
         Self { gtree, run_indices }
     }
 
+    #[inline]
+    pub fn nodes(&self) -> Nodes<'_, ARITY> {
+        self.gtree.nodes()
+    }
+
+    /// Returns an iterator over the runs of the tree in document order.
+    #[inline]
+    pub fn runs(&self) -> impl Iterator<Item = &EditRun> + '_ {
+        self.gtree.leaves_from_first().map(|(_, run)| run)
+    }
+
     #[inline]
     pub fn run_indices(&self) -> &RunIndices {
         &self.run_indices
     }
 
+    #[inline]
+    pub fn run_indices_mut(&mut self) -> &mut RunIndices {
+        &mut self.run_indices
+    }
+
     #[inline]
     fn split_run_with_another(
         &mut self,
@@ -757,7 +930,6 @@ impl RunTree {
 
 /// TODO: docs
 #[derive(Clone, PartialEq, Eq)]
-#[cfg_attr(feature = "encode", derive(serde::Serialize, serde::Deserialize))]
 pub(crate) struct EditRun {
     /// TODO: docs
     text: Text,
@@ -915,6 +1087,11 @@ impl EditRun {
         }
     }
 
+    #[inline(always)]
+    pub fn is_deleted(&self) -> bool {
+        self.is_deleted
+    }
+
     #[inline(always)]
     pub fn lamport_ts(&self) -> LamportTs {
         self.lamport_ts
@@ -965,6 +1142,12 @@ impl EditRun {
         &mut self.text.range.start
     }
 
+    #[cfg(feature = "encode")]
+    #[inline(always)]
+    pub fn text(&self) -> &Text {
+        &self.text
+    }
+
     #[inline]
     fn visible_len(&self) -> Length {
         self.len() * (!self.is_deleted as Length)
@@ -973,10 +1156,7 @@ impl EditRun {
 
 /// TODO: docs
 #[derive(Copy, Clone, PartialEq, Eq)]
-#[cfg_attr(
-    any(feature = "encode", feature = "serde"),
-    derive(serde::Serialize, serde::Deserialize)
-)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Anchor {
     /// TODO: docs
     replica_id: ReplicaId,
@@ -1098,8 +1278,254 @@ impl gtree::Leaf for EditRun {
     }
 }
 
-pub(crate) type DebugAsBtree<'a> =
-    gtree::DebugAsBtree<'a, RUN_TREE_ARITY, EditRun>;
+pub(crate) type DebugAsBtree<'a, const ARITY: usize> =
+    gtree::DebugAsBtree<'a, ARITY, EditRun>;
+
+pub(crate) type DebugAsSelf<'a, const ARITY: usize> =
+    gtree::DebugAsSelf<'a, ARITY, EditRun>;
+
+pub(crate) type Nodes<'a, const ARITY: usize> =
+    gtree::Nodes<'a, ARITY, EditRun>;
+
+#[cfg(feature = "encode")]
+mod encode {
+    use super::*;
+    use crate::encode::{
+        decode_bitmap,
+        decode_delta,
+        decode_len,
+        encode_bitmap,
+        encode_delta,
+        Decode,
+        Encode,
+    };
+
+    /// The `RunTree` is encoded as the list of its runs in document order,
+    /// split into columns so that similar values end up next to each other.
+    ///
+    /// The encoding starts with the number of runs and the sorted list of
+    /// the `ReplicaId`s that inserted them, each encoded as the difference
+    /// from the previous one. Then come the columns, one value per run:
+    ///
+    /// - the position of the run's `ReplicaId` in that list;
+    /// - the length of its temporal range;
+    /// - the start of its temporal range, as the difference from the end of
+    ///   the previous run inserted by the same replica;
+    /// - its `LamportTs`, as the difference from the previous run's;
+    /// - its `RunTs`, as the difference from the previous run inserted by the
+    ///   same replica;
+    /// - a bitmap of which runs are deleted.
+    ///
+    /// Neither the internal nodes of the `Gtree` nor the `RunIndices` are
+    /// encoded since both can be rebuilt from the runs.
+    impl<const ARITY: usize> Encode for RunTree<ARITY> {
+        #[inline]
+        fn encode(&self, buf: &mut Vec<u8>) {
+            encode_runs(self.runs(), buf);
+        }
+    }
+
+    /// Encodes the given runs, listed in document order, in the same format
+    /// as a `RunTree` containing them.
+    #[inline]
+    pub(crate) fn encode_runs<'a, I>(runs: I, buf: &mut Vec<u8>)
+    where
+        I: IntoIterator<Item = &'a EditRun>,
+    {
+        let runs = runs.into_iter().collect::<Vec<_>>();
+
+        runs.len().encode(buf);
+
+        let mut replica_ids =
+            runs.iter().map(|run| run.replica_id()).collect::<Vec<_>>();
+
+        replica_ids.sort_unstable();
+
+        replica_ids.dedup();
+
+        replica_ids.len().encode(buf);
+
+        let mut prev_id = 0;
+
+        for &id in &replica_ids {
+            (id - prev_id).encode(buf);
+            prev_id = id;
+        }
+
+        let replica_idxs = runs
+            .iter()
+            .map(|run| replica_ids.binary_search(&run.replica_id()).unwrap())
+            .collect::<Vec<_>>();
+
+        for &replica_idx in &replica_idxs {
+            replica_idx.encode(buf);
+        }
+
+        for run in &runs {
+            run.len().encode(buf);
+        }
+
+        let mut prev_ends = vec![0; replica_ids.len()];
+
+        for (run, &replica_idx) in runs.iter().zip(&replica_idxs) {
+            let prev_end = &mut prev_ends[replica_idx];
+            encode_delta(buf, *prev_end as u64, run.start() as u64);
+            *prev_end = run.end();
+        }
+
+        let mut prev_lamport_ts = 0;
+
+        for run in &runs {
+            encode_delta(buf, prev_lamport_ts, run.lamport_ts());
+            prev_lamport_ts = run.lamport_ts();
+        }
+
+        let mut prev_run_tss = vec![0; replica_ids.len()];
 
-pub(crate) type DebugAsSelf<'a> =
-    gtree::DebugAsSelf<'a, RUN_TREE_ARITY, EditRun>;
+        for (run, &replica_idx) in runs.iter().zip(&replica_idxs) {
+            let prev_run_ts = &mut prev_run_tss[replica_idx];
+            encode_delta(buf, *prev_run_ts, run.run_ts());
+            *prev_run_ts = run.run_ts();
+        }
+
+        encode_bitmap(buf, runs.iter().map(|run| run.is_deleted));
+    }
+
+    impl<const ARITY: usize> Decode for RunTree<ARITY> {
+        #[inline]
+        fn decode(buf: &mut &[u8]) -> Option<Self> {
+            Self::from_runs(decode_runs(buf)?)
+        }
+    }
+
+    impl<const ARITY: usize> RunTree<ARITY> {
+        /// Builds a `RunTree` from its runs, listed in document order.
+        ///
+        /// Returns `None` if there are no runs or if they're not consistent
+        /// with each other.
+        #[inline]
+        pub(crate) fn from_runs(runs: Vec<EditRun>) -> Option<Self> {
+            if runs.is_empty() {
+                return None;
+            }
+
+            let gtree = Gtree::from_leaves(runs);
+
+            let run_indices =
+                RunIndices::from_runs(gtree.leaves_from_first())?;
+
+            Some(Self { gtree, run_indices })
+        }
+    }
+
+    /// Decodes the runs encoded by [`encode_runs`].
+    #[inline]
+    pub(crate) fn decode_runs(buf: &mut &[u8]) -> Option<Vec<EditRun>> {
+        let num_runs = decode_len(buf)?;
+
+        let num_replicas = decode_len(buf)?;
+
+        let mut replica_ids = Vec::with_capacity(num_replicas);
+
+        let mut prev_id: ReplicaId = 0;
+
+        for idx in 0..num_replicas {
+            let delta = ReplicaId::decode(buf)?;
+
+            if idx > 0 && delta == 0 {
+                return None;
+            }
+
+            prev_id = prev_id.checked_add(delta)?;
+
+            replica_ids.push(prev_id);
+        }
+
+        let replica_idxs = (0..num_runs)
+            .map(|_| usize::decode(buf).filter(|&idx| idx < num_replicas))
+            .collect::<Option<Vec<_>>>()?;
+
+        let lens = (0..num_runs)
+            .map(|_| Length::decode(buf))
+            .collect::<Option<Vec<_>>>()?;
+
+        let mut prev_ends = vec![0; num_replicas];
+
+        let texts = replica_idxs
+            .iter()
+            .zip(lens)
+            .map(|(&replica_idx, len)| {
+                let prev_end = &mut prev_ends[replica_idx];
+                let start = decode_delta(buf, *prev_end as u64)?;
+                let start = Length::try_from(start).ok()?;
+                let end = start.checked_add(len)?;
+                *prev_end = end;
+                Some(Text::new(replica_ids[replica_idx], start..end))
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        let mut prev_lamport_ts = 0;
+
+        let lamport_tss = (0..num_runs)
+            .map(|_| {
+                prev_lamport_ts = decode_delta(buf, prev_lamport_ts)?;
+                Some(prev_lamport_ts)
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        let mut prev_run_tss = vec![0; num_replicas];
+
+        let run_tss = replica_idxs
+            .iter()
+            .map(|&replica_idx| {
+                let prev_run_ts = &mut prev_run_tss[replica_idx];
+                *prev_run_ts = decode_delta(buf, *prev_run_ts)?;
+                Some(*prev_run_ts)
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        let are_deleted = decode_bitmap(buf, num_runs)?;
+
+        let runs = texts
+            .into_iter()
+            .zip(run_tss)
+            .zip(lamport_tss)
+            .zip(are_deleted)
+            .map(|(((text, run_ts), lamport_ts), is_deleted)| {
+                EditRun::decoded(text, run_ts, lamport_ts, is_deleted)
+            })
+            .collect();
+
+        Some(runs)
+    }
+
+    impl EditRun {
+        /// Creates a new `EditRun` from its decoded fields.
+        #[inline]
+        pub(crate) fn decoded(
+            text: Text,
+            run_ts: RunTs,
+            lamport_ts: LamportTs,
+            is_deleted: bool,
+        ) -> Self {
+            Self { text, run_ts, lamport_ts, is_deleted }
+        }
+    }
+
+    impl Encode for Anchor {
+        #[inline]
+        fn encode(&self, buf: &mut Vec<u8>) {
+            self.replica_id.encode(buf);
+            self.offset.encode(buf);
+        }
+    }
+
+    impl Decode for Anchor {
+        #[inline]
+        fn decode(buf: &mut &[u8]) -> Option<Self> {
+            let replica_id = ReplicaId::decode(buf)?;
+            let offset = Length::decode(buf)?;
+            Some(Self { replica_id, offset })
+        }
+    }
+}
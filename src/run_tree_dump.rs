@@ -0,0 +1,497 @@
+use core::fmt::{Result as FmtResult, Write};
+use std::collections::HashMap;
+
+use crate::gtree::Node;
+use crate::*;
+
+/// A dump of the internal tree of edit runs of a [`Replica`], which can be
+/// rendered as a [Graphviz] DOT graph or as JSON.
+///
+/// This struct is created by the [`dump`](Replica::dump) method on
+/// [`Replica`]. See its documentation for more information.
+///
+/// Every leaf of the tree is an edit run, and shows the [`ReplicaId`] of the
+/// replica that inserted it, its temporal range in that replica, its Lamport
+/// and run timestamps and whether its text has been deleted. The dump also
+/// includes the run indices, i.e. the index used to map each replica's runs
+/// to the leaves containing them when integrating remote edits.
+///
+/// For big documents the whole tree is usually too large to be useful, in
+/// which case the subtrees below a given depth can be collapsed into a single
+/// node with [`collapse_below`](Self::collapse_below).
+///
+/// [Graphviz]: https://graphviz.org
+#[derive(Clone, Copy)]
+pub struct RunTreeDump<'a, const ARITY: usize> {
+    run_tree: &'a RunTree<ARITY>,
+    collapse_depth: Option<usize>,
+}
+
+impl<const ARITY: usize> core::fmt::Debug for RunTreeDump<'_, ARITY> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> FmtResult {
+        f.debug_struct("RunTreeDump")
+            .field("collapse_depth", &self.collapse_depth)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<'a, const ARITY: usize> RunTreeDump<'a, ARITY> {
+    /// Collapses every internal node at the given depth into a single node
+    /// which only shows its length and how many visible and tombstoned runs
+    /// it contains, hiding the rest of its subtree.
+    ///
+    /// The root of the tree is at depth 0, so `collapse_below(0)` collapses
+    /// the whole tree into a single node. Calling this with a depth greater
+    /// than or equal to the [`tree_depth`](crate::ReplicaStats::tree_depth)
+    /// of the `Replica` doesn't collapse anything.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use cola::Replica;
+    /// let mut replica = Replica::new(1, 10);
+    ///
+    /// let _ = replica.deleted(2..4);
+    ///
+    /// let json = replica.dump().collapse_below(0).to_json();
+    ///
+    /// assert!(json.contains(r#""visible_runs":2,"tombstoned_runs":1"#));
+    /// ```
+    #[inline]
+    pub fn collapse_below(mut self, depth: usize) -> Self {
+        self.collapse_depth = Some(depth);
+        self
+    }
+
+    #[inline]
+    pub(crate) fn new(run_tree: &'a RunTree<ARITY>) -> Self {
+        Self { run_tree, collapse_depth: None }
+    }
+
+    /// Renders the dump as a Graphviz DOT graph.
+    ///
+    /// The run tree and the run indices are drawn as two separate clusters,
+    /// with a dashed edge going from every run in the run indices to the
+    /// leaves (or the collapsed nodes) it's split across. Tombstoned leaves
+    /// are filled in gray.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use cola::Replica;
+    /// let replica = Replica::new(1, 42);
+    ///
+    /// let dot = replica.dump().to_dot();
+    ///
+    /// assert!(dot.starts_with("digraph cola {"));
+    /// assert!(dot.contains("replica 1|0..42|lamport 0|run 0"));
+    /// ```
+    #[inline]
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::new();
+        // Writing to a `String` can't fail.
+        self.write_dot(&mut dot).unwrap();
+        dot
+    }
+
+    /// Renders the dump as JSON.
+    ///
+    /// The returned object has a `run_tree` field containing the root of the
+    /// tree, where each internal node has an `inode` index, a `len` and its
+    /// `children`, collapsed nodes have `"collapsed": true` and the number of
+    /// `visible_runs` and `tombstoned_runs` under them, and leaves have a
+    /// `leaf` index, the `replica` that inserted them, their
+    /// `temporal_range`, `lamport_ts`, `run_ts` and a `deleted` flag.
+    ///
+    /// The `run_indices` field is an array containing the runs of every
+    /// replica sorted by [`ReplicaId`], where each run is the list of
+    /// fragments (a `len` and a `leaf` index) it's been split into.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use cola::Replica;
+    /// let replica = Replica::new(1, 42);
+    ///
+    /// let json = replica.dump().to_json();
+    ///
+    /// assert_eq!(
+    ///     json,
+    ///     concat!(
+    ///         r#"{"run_tree":{"inode":0,"len":42,"children":["#,
+    ///         r#"{"leaf":0,"replica":1,"temporal_range":[0,42],"#,
+    ///         r#""lamport_ts":0,"run_ts":0,"deleted":false}]},"#,
+    ///         r#""run_indices":[{"replica":1,"runs":["#,
+    ///         r#"[{"len":42,"leaf":0}]]}]}"#,
+    ///     )
+    /// );
+    /// ```
+    #[inline]
+    pub fn to_json(&self) -> String {
+        let mut json = String::new();
+        // Writing to a `String` can't fail.
+        self.write_json(&mut json).unwrap();
+        json
+    }
+
+    /// Returns the nodes of the run tree that should be rendered in
+    /// depth-first pre-order, together with a map from the indices of the
+    /// leaves hidden by a collapsed node to the index of that node.
+    #[inline]
+    fn nodes(&self) -> (Vec<DumpNode<'a>>, HashMap<usize, usize>) {
+        let mut nodes = Vec::new();
+
+        let mut hidden_leaves = HashMap::new();
+
+        // The position in `nodes` and the depth of the collapsed node we're
+        // currently in, if any.
+        let mut collapsed: Option<(usize, usize)> = None;
+
+        for node in self.run_tree.nodes() {
+            if let Some((pos, collapsed_depth)) = collapsed {
+                if node.depth() > collapsed_depth {
+                    if let Node::Leaf { idx, leaf, .. } = node {
+                        let DumpNode::Collapsed {
+                            idx: collapsed_idx,
+                            visible_runs,
+                            tombstoned_runs,
+                            ..
+                        } = &mut nodes[pos]
+                        else {
+                            unreachable!();
+                        };
+
+                        if leaf.is_deleted() {
+                            *tombstoned_runs += 1;
+                        } else {
+                            *visible_runs += 1;
+                        }
+
+                        hidden_leaves.insert(idx.as_usize(), *collapsed_idx);
+                    }
+
+                    continue;
+                }
+
+                collapsed = None;
+            }
+
+            let dump_node = match node {
+                Node::Internal { idx, parent, depth, len }
+                    if Some(depth) == self.collapse_depth =>
+                {
+                    collapsed = Some((nodes.len(), depth));
+
+                    DumpNode::Collapsed {
+                        idx,
+                        parent,
+                        depth,
+                        len,
+                        visible_runs: 0,
+                        tombstoned_runs: 0,
+                    }
+                },
+
+                Node::Internal { idx, parent, depth, len } => {
+                    DumpNode::Internal { idx, parent, depth, len }
+                },
+
+                Node::Leaf { idx, parent, depth, leaf } => DumpNode::Run {
+                    idx: idx.as_usize(),
+                    parent,
+                    depth,
+                    run: leaf,
+                },
+            };
+
+            nodes.push(dump_node);
+        }
+
+        (nodes, hidden_leaves)
+    }
+
+    /// Returns the runs of every replica in the run indices sorted by
+    /// [`ReplicaId`], where each run is the list of its fragments' lengths
+    /// and leaf indices.
+    #[allow(clippy::type_complexity)]
+    #[inline]
+    fn run_indices(&self) -> Vec<(ReplicaId, Vec<Vec<(Length, usize)>>)> {
+        let mut replicas = self
+            .run_tree
+            .run_indices()
+            .replicas()
+            .map(|(replica_id, indices)| {
+                let runs = indices
+                    .runs()
+                    .map(|leaves| {
+                        leaves
+                            .map(|fragment| {
+                                (fragment.len(), fragment.idx().as_usize())
+                            })
+                            .collect()
+                    })
+                    .collect();
+
+                (replica_id, runs)
+            })
+            .collect::<Vec<_>>();
+
+        replicas.sort_unstable_by_key(|&(replica_id, _)| replica_id);
+
+        replicas
+    }
+
+    #[inline]
+    fn write_dot(&self, out: &mut impl Write) -> FmtResult {
+        let (nodes, hidden_leaves) = self.nodes();
+
+        writeln!(out, "digraph cola {{")?;
+        writeln!(out, "    ordering=out;")?;
+        writeln!(out, "    node [fontname=\"monospace\"];")?;
+        writeln!(out)?;
+        writeln!(out, "    subgraph cluster_run_tree {{")?;
+        writeln!(out, "        label=\"run tree\";")?;
+
+        for node in &nodes {
+            writeln!(out)?;
+
+            match *node {
+                DumpNode::Internal { idx, len, .. } => {
+                    writeln!(
+                        out,
+                        "        i{idx} [shape=box, label=\"len {len}\"];"
+                    )?;
+                },
+
+                DumpNode::Collapsed {
+                    idx,
+                    len,
+                    visible_runs,
+                    tombstoned_runs,
+                    ..
+                } => {
+                    writeln!(
+                        out,
+                        "        i{idx} [shape=box, style=dashed, \
+                         label=\"len {len}\\n{visible_runs} visible \
+                         runs\\n{tombstoned_runs} tombstoned runs\"];"
+                    )?;
+                },
+
+                DumpNode::Run { idx, run, .. } => {
+                    let style = if run.is_deleted() {
+                        ", style=filled, fillcolor=lightgray"
+                    } else {
+                        ""
+                    };
+
+                    writeln!(
+                        out,
+                        "        l{idx} [shape=record{style}, \
+                         label=\"{{replica {}|{}..{}|lamport {}|run {}}}\"];",
+                        run.replica_id(),
+                        run.start(),
+                        run.end(),
+                        run.lamport_ts(),
+                        run.run_ts(),
+                    )?;
+                },
+            }
+
+            match *node {
+                DumpNode::Internal { idx, parent: Some(parent), .. }
+                | DumpNode::Collapsed { idx, parent: Some(parent), .. } => {
+                    writeln!(out, "        i{parent} -> i{idx};")?;
+                },
+
+                DumpNode::Run { idx, parent, .. } => {
+                    writeln!(out, "        i{parent} -> l{idx};")?;
+                },
+
+                _ => {},
+            }
+        }
+
+        writeln!(out, "    }}")?;
+        writeln!(out)?;
+        writeln!(out, "    subgraph cluster_run_indices {{")?;
+        writeln!(out, "        label=\"run indices\";")?;
+
+        let run_indices = self.run_indices();
+
+        for (replica_id, runs) in &run_indices {
+            write!(out, "        r{replica_id} [shape=record, label=\"")?;
+            write!(out, "replica {replica_id}")?;
+            for run_ts in 0..runs.len() {
+                write!(out, "|<t{run_ts}> run {run_ts}")?;
+            }
+            writeln!(out, "\"];")?;
+        }
+
+        writeln!(out, "    }}")?;
+
+        for (replica_id, runs) in &run_indices {
+            for (run_ts, fragments) in runs.iter().enumerate() {
+                let mut targets = Vec::new();
+
+                for &(_, leaf_idx) in fragments {
+                    let target = match hidden_leaves.get(&leaf_idx) {
+                        Some(inode_idx) => format!("i{inode_idx}"),
+                        None => format!("l{leaf_idx}"),
+                    };
+
+                    if !targets.contains(&target) {
+                        targets.push(target);
+                    }
+                }
+
+                for target in targets {
+                    writeln!(
+                        out,
+                        "    r{replica_id}:t{run_ts} -> {target} \
+                         [style=dashed, constraint=false];"
+                    )?;
+                }
+            }
+        }
+
+        writeln!(out, "}}")
+    }
+
+    #[inline]
+    fn write_json(&self, out: &mut impl Write) -> FmtResult {
+        let (nodes, _) = self.nodes();
+
+        write!(out, r#"{{"run_tree":"#)?;
+
+        // The depths of the internal nodes whose `children` array is still
+        // open.
+        let mut open_depths = Vec::<usize>::new();
+
+        let mut needs_comma = false;
+
+        for node in &nodes {
+            while open_depths.last().is_some_and(|&d| d >= node.depth()) {
+                open_depths.pop();
+                write!(out, "]}}")?;
+            }
+
+            if needs_comma {
+                write!(out, ",")?;
+            }
+
+            match *node {
+                DumpNode::Internal { idx, depth, len, .. } => {
+                    write!(out, r#"{{"inode":{idx},"len":{len},"#)?;
+                    write!(out, r#""children":["#)?;
+                    open_depths.push(depth);
+                    needs_comma = false;
+                    continue;
+                },
+
+                DumpNode::Collapsed {
+                    idx,
+                    len,
+                    visible_runs,
+                    tombstoned_runs,
+                    ..
+                } => {
+                    write!(out, r#"{{"inode":{idx},"len":{len},"#)?;
+                    write!(out, r#""collapsed":true,"#)?;
+                    write!(out, r#""visible_runs":{visible_runs},"#)?;
+                    write!(out, r#""tombstoned_runs":{tombstoned_runs}}}"#)?;
+                },
+
+                DumpNode::Run { idx, run, .. } => {
+                    write!(out, r#"{{"leaf":{idx},"#)?;
+                    write!(out, r#""replica":{},"#, run.replica_id())?;
+                    write!(
+                        out,
+                        r#""temporal_range":[{},{}],"#,
+                        run.start(),
+                        run.end()
+                    )?;
+                    write!(out, r#""lamport_ts":{},"#, run.lamport_ts())?;
+                    write!(out, r#""run_ts":{},"#, run.run_ts())?;
+                    write!(out, r#""deleted":{}}}"#, run.is_deleted())?;
+                },
+            }
+
+            needs_comma = true;
+        }
+
+        for _ in open_depths {
+            write!(out, "]}}")?;
+        }
+
+        write!(out, r#","run_indices":["#)?;
+
+        for (i, (replica_id, runs)) in self.run_indices().iter().enumerate() {
+            if i > 0 {
+                write!(out, ",")?;
+            }
+
+            write!(out, r#"{{"replica":{replica_id},"runs":["#)?;
+
+            for (j, fragments) in runs.iter().enumerate() {
+                if j > 0 {
+                    write!(out, ",")?;
+                }
+
+                write!(out, "[")?;
+
+                for (k, (len, leaf_idx)) in fragments.iter().enumerate() {
+                    if k > 0 {
+                        write!(out, ",")?;
+                    }
+
+                    write!(out, r#"{{"len":{len},"leaf":{leaf_idx}}}"#)?;
+                }
+
+                write!(out, "]")?;
+            }
+
+            write!(out, "]}}")?;
+        }
+
+        write!(out, "]}}")
+    }
+}
+
+/// A node of the run tree as it's rendered in a [`RunTreeDump`].
+enum DumpNode<'a> {
+    Internal {
+        idx: usize,
+        parent: Option<usize>,
+        depth: usize,
+        len: Length,
+    },
+
+    /// An internal node whose subtree has been collapsed.
+    Collapsed {
+        idx: usize,
+        parent: Option<usize>,
+        depth: usize,
+        len: Length,
+        visible_runs: usize,
+        tombstoned_runs: usize,
+    },
+
+    Run {
+        idx: usize,
+        parent: usize,
+        depth: usize,
+        run: &'a EditRun,
+    },
+}
+
+impl DumpNode<'_> {
+    #[inline]
+    fn depth(&self) -> usize {
+        match self {
+            Self::Internal { depth, .. }
+            | Self::Collapsed { depth, .. }
+            | Self::Run { depth, .. } => *depth,
+        }
+    }
+}
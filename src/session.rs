@@ -0,0 +1,589 @@
+use core::convert::Infallible;
+use core::ops::{Range, RangeBounds};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::mpsc::{self, Receiver, Sender};
+
+use crate::*;
+
+/// The sequence number a [`Session`] assigns to each of the edits it
+/// broadcasts.
+///
+/// Every peer numbers its own edits starting from 1, so an edit is uniquely
+/// identified by the [`ReplicaId`] of the peer that made it together with
+/// its sequence number.
+pub type SeqNum = u64;
+
+/// A message exchanged by [`Session`]s.
+///
+/// Messages are broadcast to all the other peers in the session, and can be
+/// dropped, duplicated and delivered in any order by the underlying
+/// [`Transport`].
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Message {
+    /// An edit made by the peer that sent the message.
+    Edit {
+        /// The [`ReplicaId`] of the peer that made the edit.
+        sender: ReplicaId,
+
+        /// The sequence number the sender assigned to the edit.
+        seq: SeqNum,
+
+        /// The edit.
+        edit: CrdtEdit,
+    },
+
+    /// An acknowledgement of the edits received by the peer that sent the
+    /// message.
+    Ack {
+        /// The [`ReplicaId`] of the peer that received the edits.
+        sender: ReplicaId,
+
+        /// For every peer the sender has received edits from, the highest
+        /// sequence number up to which it has received all of them.
+        received: Vec<(ReplicaId, SeqNum)>,
+    },
+}
+
+impl Message {
+    /// Returns the [`ReplicaId`] of the peer that sent the message.
+    #[inline]
+    pub fn sender(&self) -> ReplicaId {
+        match self {
+            Self::Edit { sender, .. } | Self::Ack { sender, .. } => *sender,
+        }
+    }
+}
+
+/// A change to the text of the document caused by a remote edit, returned by
+/// [`Session::receive`].
+///
+/// Changes have to be applied to the buffer in the order they're returned.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TextChange {
+    /// The given [`Text`] was inserted at the given offset.
+    Inserted(Text, Length),
+
+    /// The given ranges were deleted. The ranges are sorted and
+    /// non-overlapping, and are expressed in the coordinates of the buffer
+    /// before any of them were deleted, so they should be deleted starting
+    /// from the last one.
+    Deleted(Vec<Range<Length>>),
+}
+
+/// The channel through which a [`Session`] exchanges [`Message`]s with the
+/// other peers.
+///
+/// A transport doesn't have to be reliable: the `Session` deduplicates the
+/// edits it receives, and the ones that get lost are sent again by
+/// [`resend`](Session::resend) until every peer has acknowledged them.
+pub trait Transport {
+    /// The type of error returned when sending or receiving fails.
+    type Error;
+
+    /// Broadcasts the message to all the other peers.
+    fn send(&mut self, message: Message) -> Result<(), Self::Error>;
+
+    /// Returns the next message received from another peer without
+    /// blocking, or `None` if there aren't any.
+    fn try_recv(&mut self) -> Result<Option<Message>, Self::Error>;
+}
+
+/// A [`Transport`] connecting peers in the same process through the
+/// channels in [`std::sync::mpsc`].
+///
+/// Sending a message never fails: the peers whose receiver has been dropped
+/// are simply forgotten.
+///
+/// # Examples
+///
+/// ```
+/// # use cola::{ChannelTransport, Replica, Session, TextChange};
+/// let mut transports = ChannelTransport::mesh(2);
+///
+/// let replica1 = Replica::new(1, 0);
+/// let replica2 = replica1.fork(2);
+///
+/// let mut peer2 = Session::new(replica2, transports.pop().unwrap());
+/// let mut peer1 = Session::new(replica1, transports.pop().unwrap());
+///
+/// let Ok(()) = peer1.inserted(0, 5);
+///
+/// let Ok(changes) = peer2.receive();
+///
+/// assert!(matches!(changes[..], [TextChange::Inserted(_, 0)]));
+/// ```
+#[derive(Debug)]
+pub struct ChannelTransport {
+    peers: Vec<Sender<Message>>,
+    receiver: Receiver<Message>,
+}
+
+impl ChannelTransport {
+    /// Creates `num_peers` transports, each connected to all the others.
+    #[inline]
+    pub fn mesh(num_peers: usize) -> Vec<Self> {
+        let (senders, receivers): (Vec<_>, Vec<_>) =
+            (0..num_peers).map(|_| mpsc::channel()).unzip();
+
+        receivers
+            .into_iter()
+            .enumerate()
+            .map(|(idx, receiver)| {
+                let peers = senders
+                    .iter()
+                    .enumerate()
+                    .filter(|&(peer_idx, _)| peer_idx != idx)
+                    .map(|(_, sender)| sender.clone())
+                    .collect();
+
+                Self::new(peers, receiver)
+            })
+            .collect()
+    }
+
+    /// Creates a new `ChannelTransport` which sends messages to the given
+    /// peers and receives them from the given receiver.
+    #[inline]
+    pub fn new(
+        peers: Vec<Sender<Message>>,
+        receiver: Receiver<Message>,
+    ) -> Self {
+        Self { peers, receiver }
+    }
+}
+
+impl Transport for ChannelTransport {
+    type Error = Infallible;
+
+    #[inline]
+    fn send(&mut self, message: Message) -> Result<(), Infallible> {
+        self.peers.retain(|peer| peer.send(message.clone()).is_ok());
+        Ok(())
+    }
+
+    #[inline]
+    fn try_recv(&mut self) -> Result<Option<Message>, Infallible> {
+        Ok(self.receiver.try_recv().ok())
+    }
+}
+
+/// A [`Replica`] together with the [`Transport`] it uses to broadcast its
+/// edits to the other peers in the session and to receive theirs.
+///
+/// cola leaves the delivery of the edits up to you, and while a `Replica`
+/// can integrate edits received out of order by holding them in its backlog,
+/// it still expects every edit to eventually be delivered. A `Session` takes
+/// care of that on top of a transport that can drop, duplicate and reorder
+/// messages:
+///
+/// - every local edit is assigned a [`SeqNum`] and kept around until all the
+///   known peers have acknowledged it;
+/// - remote edits that have already been received are ignored, and after
+///   integrating a new one the edits it unblocked are drained from the
+///   backlog;
+/// - every batch of received edits is acknowledged, and calling
+///   [`resend`](Self::resend) periodically sends again the edits that haven't
+///   been acknowledged yet.
+///
+/// A peer becomes known when a message is received from it or when it's
+/// explicitly added via [`add_peer`](Self::add_peer).
+///
+/// Note that only the edits are exchanged, so the contents of the inserted
+/// text have to be sent along some other channel, keyed by the [`Text`] of
+/// the insertions.
+pub struct Session<T: Transport> {
+    /// The CRDT tracking the edits made to the document.
+    replica: Replica,
+
+    /// The channel used to exchange messages with the other peers.
+    transport: T,
+
+    /// The sequence number of the last local edit.
+    last_seq: SeqNum,
+
+    /// The local edits that haven't been acknowledged by all the peers yet.
+    unacked: BTreeMap<SeqNum, CrdtEdit>,
+
+    /// The highest sequence number up to which each known peer has
+    /// acknowledged all our edits.
+    acked: ReplicaIdMap<SeqNum>,
+
+    /// The edits received from each peer.
+    received: ReplicaIdMap<Received>,
+}
+
+impl<T: Transport> core::fmt::Debug for Session<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.debug_struct("Session")
+            .field("replica", &self.replica)
+            .field("last_seq", &self.last_seq)
+            .field("unacked", &self.unacked.len())
+            .finish()
+    }
+}
+
+impl<T: Transport> Session<T> {
+    #[inline]
+    fn ack(&mut self) -> Result<(), T::Error> {
+        let mut received = self
+            .received
+            .iter()
+            .map(|(&id, received)| (id, received.contiguous))
+            .collect::<Vec<_>>();
+
+        received.sort_unstable();
+
+        self.transport.send(Message::Ack { sender: self.id(), received })
+    }
+
+    /// Adds a peer to the session, so that the local edits are kept around
+    /// until it has acknowledged them.
+    ///
+    /// This should be called when a new peer joins the session, before it
+    /// has had a chance to send any message.
+    #[inline]
+    pub fn add_peer(&mut self, peer: ReplicaId) {
+        if peer != self.id() {
+            self.acked.entry(peer).or_insert(0);
+        }
+    }
+
+    #[inline]
+    fn broadcast(&mut self, edit: CrdtEdit) -> Result<(), T::Error> {
+        self.last_seq += 1;
+
+        let seq = self.last_seq;
+
+        self.unacked.insert(seq, edit.clone());
+
+        self.transport.send(Message::Edit { sender: self.id(), seq, edit })
+    }
+
+    /// Deletes the given range from the document and broadcasts the
+    /// [`Deletion`] to the other peers.
+    ///
+    /// See [`Replica::deleted`] for more information.
+    #[inline]
+    pub fn deleted<R>(&mut self, range: R) -> Result<(), T::Error>
+    where
+        R: RangeBounds<Length>,
+    {
+        let deletion = self.replica.deleted(range);
+        self.broadcast(deletion.into())
+    }
+
+    #[inline]
+    fn id(&self) -> ReplicaId {
+        self.replica.id()
+    }
+
+    /// Inserts text of the given length at the given offset and broadcasts
+    /// the [`Insertion`] to the other peers.
+    ///
+    /// See [`Replica::inserted`] for more information.
+    #[inline]
+    pub fn inserted(
+        &mut self,
+        at_offset: Length,
+        len: Length,
+    ) -> Result<(), T::Error> {
+        let insertion = self.replica.inserted(at_offset, len);
+        self.broadcast(insertion.into())
+    }
+
+    /// Returns the [`Replica`] and the [`Transport`] owned by the session.
+    #[inline]
+    pub fn into_parts(self) -> (Replica, T) {
+        (self.replica, self.transport)
+    }
+
+    /// Creates a new `Session` from a [`Replica`] and the [`Transport`] used
+    /// to exchange messages with the other peers.
+    #[inline]
+    pub fn new(replica: Replica, transport: T) -> Self {
+        Self {
+            replica,
+            transport,
+            last_seq: 0,
+            unacked: BTreeMap::new(),
+            acked: ReplicaIdMap::default(),
+            received: ReplicaIdMap::default(),
+        }
+    }
+
+    /// Receives all the messages that are currently available from the
+    /// transport, returning the changes to apply to the document.
+    ///
+    /// Edits that have already been received are ignored, and if any edit
+    /// was received an acknowledgement is broadcast to the other peers.
+    #[inline]
+    pub fn receive(&mut self) -> Result<Vec<TextChange>, T::Error> {
+        let mut changes = Vec::new();
+
+        let mut should_ack = false;
+
+        while let Some(message) = self.transport.try_recv()? {
+            // Some transports echo our own messages back to us.
+            if message.sender() == self.id() {
+                continue;
+            }
+
+            match message {
+                Message::Edit { sender, seq, edit } => {
+                    self.add_peer(sender);
+
+                    // Even if the edit is a duplicate the sender may have
+                    // missed our acknowledgement, so we send a new one.
+                    should_ack = true;
+
+                    if self.received.entry(sender).or_default().insert(seq) {
+                        integrate(&mut self.replica, &edit, &mut changes);
+                    }
+                },
+
+                Message::Ack { sender, received } => {
+                    self.add_peer(sender);
+
+                    let this_id = self.id();
+
+                    if let Some(&(_, seq)) =
+                        received.iter().find(|&&(id, _)| id == this_id)
+                    {
+                        if let Some(acked) = self.acked.get_mut(&sender) {
+                            *acked = (*acked).max(seq);
+                        }
+                    }
+                },
+            }
+        }
+
+        self.remove_acked();
+
+        if should_ack {
+            self.ack()?;
+        }
+
+        Ok(changes)
+    }
+
+    /// Removes a peer from the session, e.g. because it has left, so that
+    /// the local edits are no longer kept around until it has acknowledged
+    /// them.
+    #[inline]
+    pub fn remove_peer(&mut self, peer: ReplicaId) {
+        self.acked.remove(&peer);
+        self.remove_acked();
+    }
+
+    #[inline]
+    fn remove_acked(&mut self) {
+        // Until we know of at least one peer we can't tell who's going to
+        // receive our edits, so we keep them all.
+        let Some(&acked_by_all) = self.acked.values().min() else {
+            return;
+        };
+
+        self.unacked = self.unacked.split_off(&(acked_by_all + 1));
+    }
+
+    /// Returns the [`Replica`] owned by the session.
+    #[inline]
+    pub fn replica(&self) -> &Replica {
+        &self.replica
+    }
+
+    /// Sends again all the local edits that haven't been acknowledged by all
+    /// the known peers, together with an acknowledgement of the edits
+    /// received so far.
+    ///
+    /// This should be called periodically when the transport can lose
+    /// messages.
+    #[inline]
+    pub fn resend(&mut self) -> Result<(), T::Error> {
+        let sender = self.id();
+
+        for (&seq, edit) in &self.unacked {
+            let edit = edit.clone();
+            self.transport.send(Message::Edit { sender, seq, edit })?;
+        }
+
+        self.ack()
+    }
+
+    /// Returns a shared reference to the [`Transport`] owned by the session.
+    #[inline]
+    pub fn transport(&self) -> &T {
+        &self.transport
+    }
+
+    /// Returns an exclusive reference to the [`Transport`] owned by the
+    /// session.
+    #[inline]
+    pub fn transport_mut(&mut self) -> &mut T {
+        &mut self.transport
+    }
+
+    /// Returns the number of local edits that haven't been acknowledged by
+    /// all the known peers yet.
+    #[inline]
+    pub fn unacked(&self) -> usize {
+        self.unacked.len()
+    }
+}
+
+/// Integrates a remote edit into the `Replica`, together with all the
+/// backlogged edits it unblocked, pushing the resulting changes to the text.
+#[inline]
+pub(crate) fn integrate(
+    replica: &mut Replica,
+    edit: &CrdtEdit,
+    changes: &mut Vec<TextChange>,
+) {
+    match edit {
+        CrdtEdit::Insertion(insertion) => {
+            if let Some(offset) = replica.integrate_insertion(insertion) {
+                changes.push(TextChange::Inserted(
+                    insertion.text().clone(),
+                    offset,
+                ));
+            }
+        },
+
+        CrdtEdit::Deletion(deletion) => {
+            let ranges = replica.integrate_deletion(deletion);
+            if !ranges.is_empty() {
+                changes.push(TextChange::Deleted(ranges));
+            }
+        },
+    }
+
+    // Integrating the edit may have unblocked some backlogged edits, which
+    // may in turn unblock others.
+    loop {
+        let num_changes = changes.len();
+
+        changes.extend(
+            replica
+                .backlogged_insertions()
+                .map(|(text, offset)| TextChange::Inserted(text, offset)),
+        );
+
+        changes
+            .extend(replica.backlogged_deletions().map(TextChange::Deleted));
+
+        if changes.len() == num_changes {
+            break;
+        }
+    }
+}
+
+/// The sequence numbers of the edits received from a peer.
+#[derive(Default)]
+struct Received {
+    /// All the edits up to this sequence number have been received.
+    contiguous: SeqNum,
+
+    /// The edits received after a gap.
+    rest: BTreeSet<SeqNum>,
+}
+
+impl Received {
+    /// Records that the edit with the given sequence number was received,
+    /// returning whether it's the first time.
+    #[inline]
+    fn insert(&mut self, seq: SeqNum) -> bool {
+        if seq <= self.contiguous || !self.rest.insert(seq) {
+            return false;
+        }
+
+        while self.rest.remove(&(self.contiguous + 1)) {
+            self.contiguous += 1;
+        }
+
+        true
+    }
+}
+
+#[cfg(feature = "futures")]
+pub use sink_stream::SinkStreamTransport;
+
+#[cfg(feature = "futures")]
+mod sink_stream {
+    use core::pin::Pin;
+    use core::task::{Context, Poll, Waker};
+
+    use futures_core::Stream;
+    use futures_sink::Sink;
+
+    use super::*;
+
+    /// A [`Transport`] built on top of a [`Sink`] of [`Message`]s and a
+    /// [`Stream`] of them, like the two halves of a WebSocket connection or
+    /// of an async channel.
+    ///
+    /// The sink and the stream are polled without blocking, so a message is
+    /// dropped if the sink isn't ready to accept it. Lost edits are sent
+    /// again by [`Session::resend`].
+    #[cfg_attr(docsrs, doc(cfg(feature = "futures")))]
+    #[derive(Debug)]
+    pub struct SinkStreamTransport<Si, St> {
+        sink: Si,
+        stream: St,
+    }
+
+    impl<Si, St> SinkStreamTransport<Si, St> {
+        /// Returns the sink and the stream the transport was created from.
+        #[inline]
+        pub fn into_inner(self) -> (Si, St) {
+            (self.sink, self.stream)
+        }
+
+        /// Creates a new `SinkStreamTransport` which sends messages to the
+        /// sink and receives them from the stream.
+        #[inline]
+        pub fn new(sink: Si, stream: St) -> Self {
+            Self { sink, stream }
+        }
+    }
+
+    impl<Si, St> Transport for SinkStreamTransport<Si, St>
+    where
+        Si: Sink<Message> + Unpin,
+        St: Stream<Item = Message> + Unpin,
+    {
+        type Error = Si::Error;
+
+        #[inline]
+        fn send(&mut self, message: Message) -> Result<(), Si::Error> {
+            let mut cx = Context::from_waker(Waker::noop());
+
+            let mut sink = Pin::new(&mut self.sink);
+
+            match sink.as_mut().poll_ready(&mut cx) {
+                Poll::Ready(Ok(())) => {
+                    sink.as_mut().start_send(message)?;
+
+                    match sink.poll_flush(&mut cx) {
+                        Poll::Ready(Err(err)) => Err(err),
+                        _ => Ok(()),
+                    }
+                },
+
+                Poll::Ready(Err(err)) => Err(err),
+
+                Poll::Pending => Ok(()),
+            }
+        }
+
+        #[inline]
+        fn try_recv(&mut self) -> Result<Option<Message>, Si::Error> {
+            let mut cx = Context::from_waker(Waker::noop());
+
+            match Pin::new(&mut self.stream).poll_next(&mut cx) {
+                Poll::Ready(message) => Ok(message),
+                Poll::Pending => Ok(None),
+            }
+        }
+    }
+}
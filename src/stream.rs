@@ -0,0 +1,244 @@
+//! Streaming encoding of [`Replica`]s, used by
+//! [`encode_to_writer`](Replica::encode_to_writer) and
+//! [`decode_from_reader`](Replica::decode_from_reader).
+//!
+//! A stream contains the same fields as an [`EncodedReplica`], but it's
+//! written and read a frame at a time so that neither side ever has to hold
+//! the whole encoding in memory. A frame is its length as a varint followed
+//! by that many bytes.
+//!
+//! A stream starts with its protocol version as a varint, followed by:
+//!
+//! - the runs of the run tree in document order, split into frames of up to
+//!   [`RUNS_PER_CHUNK`] runs each, each frame encoded like a run tree
+//!   containing only those runs, and terminated by an empty frame;
+//! - one frame each for the Lamport clock, the version map, the deletion map
+//!   and the backlog;
+//! - the SHA-256 digest of all the bytes before it.
+//!
+//! The digest is computed incrementally as the bytes are written or read.
+
+use std::io::{self, Read, Write};
+
+use sha2::{Digest, Sha256};
+
+use crate::encode::{Decode, Encode};
+use crate::*;
+
+/// The maximum number of runs encoded in a single frame.
+const RUNS_PER_CHUNK: usize = 4096;
+
+/// The length of the digest at the end of a stream.
+const DIGEST_LEN: usize = 32;
+
+/// Writes the fields of a `Replica` to the writer as a stream.
+#[inline]
+pub(crate) fn encode<W: Write, const ARITY: usize>(
+    writer: W,
+    run_tree: &RunTree<ARITY>,
+    lamport_clock: &LamportClock,
+    version_map: &VersionMap,
+    deletion_map: &DeletionMap,
+    backlog: &Backlog,
+) -> io::Result<()> {
+    let mut writer = HashingWriter { inner: writer, hasher: Sha256::new() };
+
+    let mut buf = Vec::new();
+
+    PROTOCOL_VERSION.encode(&mut buf);
+
+    writer.write_all(&buf)?;
+
+    let mut runs = run_tree.runs();
+
+    loop {
+        let chunk = runs.by_ref().take(RUNS_PER_CHUNK).collect::<Vec<_>>();
+
+        if chunk.is_empty() {
+            break;
+        }
+
+        buf.clear();
+        encode_runs(chunk, &mut buf);
+        writer.write_frame(&buf)?;
+    }
+
+    writer.write_frame(&[])?;
+
+    let fields: [&dyn Encode; 4] =
+        [lamport_clock, version_map, deletion_map, backlog];
+
+    for field in fields {
+        buf.clear();
+        field.encode(&mut buf);
+        writer.write_frame(&buf)?;
+    }
+
+    let HashingWriter { mut inner, hasher } = writer;
+
+    inner.write_all(&hasher.finalize())?;
+
+    inner.flush()
+}
+
+/// Reads the fields of a `Replica` from a stream written by [`encode`].
+///
+/// The reader is never read past the end of the stream, so it can be
+/// followed by other data.
+#[inline]
+pub(crate) fn decode<R: Read, const ARITY: usize>(
+    reader: R,
+) -> io::Result<EncodedFields<ARITY>> {
+    let mut reader = HashingReader { inner: reader, hasher: Sha256::new() };
+
+    let version = reader.read_varint()?;
+
+    protocol::check_decodable_stream(version).map_err(decode_error)?;
+
+    let mut frame = Vec::new();
+
+    let mut runs = Vec::new();
+
+    loop {
+        reader.read_frame(&mut frame)?;
+
+        if frame.is_empty() {
+            break;
+        }
+
+        let buf = &mut frame.as_slice();
+
+        match decode_runs(buf) {
+            Some(chunk) if !chunk.is_empty() && buf.is_empty() => {
+                runs.extend(chunk)
+            },
+
+            _ => return Err(decode_error(DecodeError::InvalidData)),
+        }
+    }
+
+    let lamport_clock = reader.read_field(&mut frame)?;
+    let version_map = reader.read_field(&mut frame)?;
+    let deletion_map = reader.read_field(&mut frame)?;
+    let backlog = reader.read_field(&mut frame)?;
+
+    let HashingReader { mut inner, hasher } = reader;
+
+    let mut digest = [0; DIGEST_LEN];
+
+    inner.read_exact(&mut digest)?;
+
+    if digest[..] != hasher.finalize()[..] {
+        return Err(decode_error(DecodeError::ChecksumFailed));
+    }
+
+    let run_tree = RunTree::from_runs(runs)
+        .ok_or_else(|| decode_error(DecodeError::InvalidData))?;
+
+    Ok((run_tree, lamport_clock, version_map, deletion_map, backlog))
+}
+
+/// Wraps a [`DecodeError`] into an [`io::Error`] of kind
+/// [`InvalidData`](io::ErrorKind::InvalidData).
+#[inline]
+pub(crate) fn decode_error(err: DecodeError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+/// A writer that hashes all the bytes written to it.
+struct HashingWriter<W> {
+    inner: W,
+    hasher: Sha256,
+}
+
+impl<W: Write> HashingWriter<W> {
+    #[inline]
+    fn write_frame(&mut self, frame: &[u8]) -> io::Result<()> {
+        let mut len = Vec::new();
+        frame.len().encode(&mut len);
+        self.write_all(&len)?;
+        self.write_all(frame)
+    }
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    #[inline]
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+
+    #[inline]
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        Ok(written)
+    }
+}
+
+/// A reader that hashes all the bytes read from it.
+struct HashingReader<R> {
+    inner: R,
+    hasher: Sha256,
+}
+
+impl<R: Read> HashingReader<R> {
+    /// Reads a single field contained in a frame.
+    #[inline]
+    fn read_field<T: Decode>(&mut self, frame: &mut Vec<u8>) -> io::Result<T> {
+        self.read_frame(frame)?;
+
+        let buf = &mut frame.as_slice();
+
+        match T::decode(buf) {
+            Some(field) if buf.is_empty() => Ok(field),
+            _ => Err(decode_error(DecodeError::InvalidData)),
+        }
+    }
+
+    /// Reads a frame into the buffer, replacing its previous contents.
+    ///
+    /// The frame is read in chunks rather than allocated upfront, so a
+    /// corrupted length can't cause a huge allocation.
+    #[inline]
+    fn read_frame(&mut self, frame: &mut Vec<u8>) -> io::Result<()> {
+        let len = self.read_varint()?;
+
+        frame.clear();
+
+        self.by_ref().take(len).read_to_end(frame)?;
+
+        if frame.len() as u64 == len {
+            Ok(())
+        } else {
+            Err(io::ErrorKind::UnexpectedEof.into())
+        }
+    }
+
+    /// Reads a varint one byte at a time, so that nothing past its end is
+    /// consumed from the underlying reader.
+    #[inline]
+    fn read_varint(&mut self) -> io::Result<u64> {
+        // A `u64` takes at most 10 bytes.
+        let mut bytes = [0; 10];
+
+        for len in 1..=bytes.len() {
+            self.read_exact(&mut bytes[len - 1..len])?;
+
+            if bytes[len - 1] & 0x80 == 0 {
+                return u64::decode(&mut &bytes[..len])
+                    .ok_or_else(|| decode_error(DecodeError::InvalidData));
+            }
+        }
+
+        Err(decode_error(DecodeError::InvalidData))
+    }
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    #[inline]
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.hasher.update(&buf[..read]);
+        Ok(read)
+    }
+}
@@ -10,7 +10,7 @@ use crate::*;
 /// [`inserted_by`](Text::inserted_by) and
 /// [`temporal_range`](Text::temporal_range) methods respectively.
 #[derive(Clone, PartialEq, Eq, Hash)]
-#[cfg_attr(feature = "encode", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Text {
     pub(crate) inserted_by: ReplicaId,
     pub(crate) range: Range<Length>,
@@ -109,3 +109,28 @@ impl Text {
         self.range.clone()
     }
 }
+
+#[cfg(feature = "encode")]
+mod encode {
+    use super::*;
+    use crate::encode::{Decode, Encode};
+
+    impl Encode for Text {
+        #[inline]
+        fn encode(&self, buf: &mut Vec<u8>) {
+            self.inserted_by.encode(buf);
+            self.start().encode(buf);
+            self.len().encode(buf);
+        }
+    }
+
+    impl Decode for Text {
+        #[inline]
+        fn decode(buf: &mut &[u8]) -> Option<Self> {
+            let inserted_by = ReplicaId::decode(buf)?;
+            let start = Length::decode(buf)?;
+            let end = start.checked_add(Length::decode(buf)?)?;
+            Some(Self::new(inserted_by, start..end))
+        }
+    }
+}
@@ -1,11 +1,11 @@
 use core::cmp::Ord;
 use core::fmt::{Debug, Formatter, Result as FmtResult};
 use core::ops::{Add, Range as StdRange, RangeBounds, Sub};
+use std::collections::HashMap;
 
 use crate::Length;
 
 #[derive(Clone, Copy, PartialEq, Eq, Hash)]
-#[cfg_attr(feature = "encode", derive(serde::Serialize, serde::Deserialize))]
 pub(crate) struct Range<T> {
     pub start: T,
     pub end: T,
@@ -100,6 +100,14 @@ pub(crate) fn insert_in_slice<T>(slice: &mut [T], elem: T, at_offset: usize) {
     slice[at_offset] = elem;
 }
 
+/// Returns an estimate of the number of bytes allocated on the heap by the
+/// given `HashMap`, not including the heap memory owned by its values.
+#[inline]
+pub(crate) fn hashmap_heap_size<K, V, S>(map: &HashMap<K, V, S>) -> usize {
+    // Every bucket stores a key-value pair and a one byte control tag.
+    map.capacity() * (core::mem::size_of::<(K, V)>() + 1)
+}
+
 /// TODO: docs
 #[inline(always)]
 pub(crate) fn range_bounds_to_start_end<R>(
@@ -158,4 +166,28 @@ pub mod panic_messages {
              {start} but the end is {end}"
         );
     }
+
+    #[cfg(feature = "encode")]
+    #[track_caller]
+    #[cold]
+    #[inline(never)]
+    pub(crate) fn text_length_mismatch(text_len: Length, len: Length) -> ! {
+        debug_assert!(text_len != len);
+        panic!(
+            "text length mismatch: the text is {text_len} bytes long but the \
+             length is {len}"
+        );
+    }
+
+    #[cfg(feature = "yjs")]
+    #[track_caller]
+    #[cold]
+    #[inline(never)]
+    pub(crate) fn utf16_length_mismatch(text_len: Length, len: Length) -> ! {
+        debug_assert!(text_len != len);
+        panic!(
+            "text length mismatch: the text is {text_len} UTF-16 code units \
+             long but the length is {len}"
+        );
+    }
 }
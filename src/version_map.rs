@@ -1,6 +1,6 @@
 use core::cmp::{Ordering, PartialOrd};
 
-use crate::{DeletionTs, Length, ReplicaId, ReplicaIdMap};
+use crate::{hashmap_heap_size, DeletionTs, Length, ReplicaId, ReplicaIdMap};
 
 pub type DeletionMap = BaseMap<DeletionTs>;
 
@@ -9,11 +9,8 @@ pub type VersionMap = BaseMap<Length>;
 /// A struct equivalent to a `HashMap<ReplicaId, T>`, but with the `ReplicaId`
 /// of the local `Replica` (and the corresponding `T`) stored separately from
 /// the `HashMap` itself.
-#[derive(Clone, PartialEq)]
-#[cfg_attr(
-    any(feature = "encode", feature = "serde"),
-    derive(serde::Serialize, serde::Deserialize)
-)]
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct BaseMap<T> {
     /// The `ReplicaId` of the `Replica` that this map is used in.
     this_id: ReplicaId,
@@ -23,6 +20,15 @@ pub struct BaseMap<T> {
 
     /// The values of the remote `Replica`s.
     rest: ReplicaIdMap<T>,
+
+    /// The values of the remote `Replica`s that have departed from the
+    /// session, sorted by `ReplicaId` and moved out of `rest` to free the
+    /// space they took in it.
+    ///
+    /// This is always empty in the maps carried by `Deletion`s, which are the
+    /// only ones that are serialized with `serde`.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    departed: Vec<(ReplicaId, T)>,
 }
 
 impl<T: Copy> BaseMap<T> {
@@ -34,7 +40,7 @@ impl<T: Copy> BaseMap<T> {
         if replica_id == self.this_id {
             self.this_value
         } else {
-            self.rest.get(&replica_id).copied().unwrap_or_default()
+            self.remote(replica_id).unwrap_or_default()
         }
     }
 
@@ -46,7 +52,27 @@ impl<T: Copy> BaseMap<T> {
         if replica_id == self.this_id {
             &mut self.this_value
         } else {
-            self.rest.entry(replica_id).or_default()
+            let value = self.take_departed(replica_id).unwrap_or_default();
+            self.rest.entry(replica_id).or_insert(value)
+        }
+    }
+
+    /// Moves the entry of a remote replica that has departed from the session
+    /// out of the `HashMap`, shrinking it if it has become mostly empty.
+    ///
+    /// The entry is moved back the next time it's mutated, i.e. if the
+    /// departed replica's `ReplicaId` is reused.
+    #[inline]
+    pub fn compact(&mut self, replica_id: ReplicaId) {
+        let Some(value) = self.rest.remove(&replica_id) else { return };
+
+        let idx = self.departed.partition_point(|&(id, _)| id < replica_id);
+
+        self.departed.reserve_exact(1);
+        self.departed.insert(idx, (replica_id, value));
+
+        if self.rest.len() * 2 < self.rest.capacity() {
+            self.rest.shrink_to_fit();
         }
     }
 
@@ -60,24 +86,72 @@ impl<T: Copy> BaseMap<T> {
     #[inline]
     pub fn fork_in_place(&mut self, new_id: ReplicaId, restart_at: T) {
         self.insert(self.this_id, self.this_value);
+        self.rest.remove(&new_id);
+        self.take_departed(new_id);
         self.this_id = new_id;
         self.this_value = restart_at;
     }
 
+    /// Returns an estimate of the number of bytes allocated on the heap by
+    /// this map.
+    #[inline]
+    pub fn heap_size(&self) -> usize {
+        hashmap_heap_size(&self.rest)
+            + self.departed.capacity() * core::mem::size_of::<(ReplicaId, T)>()
+    }
+
     #[inline]
     pub fn insert(&mut self, replica_id: ReplicaId, value: T) {
+        self.take_departed(replica_id);
         self.rest.insert(replica_id, value);
     }
 
     #[inline]
-    fn iter(&self) -> impl Iterator<Item = (ReplicaId, T)> + '_ {
+    pub fn iter(&self) -> impl Iterator<Item = (ReplicaId, T)> + '_ {
         let this_entry = core::iter::once((self.this_id, self.this_value));
-        this_entry.chain(self.rest.iter().map(|(&id, &value)| (id, value)))
+        this_entry
+            .chain(self.rest.iter().map(|(&id, &value)| (id, value)))
+            .chain(self.departed.iter().copied())
     }
 
     #[inline]
     pub fn new(this_id: ReplicaId, this_value: T) -> Self {
-        Self { this_id, this_value, rest: ReplicaIdMap::default() }
+        Self {
+            this_id,
+            this_value,
+            rest: ReplicaIdMap::default(),
+            departed: Vec::new(),
+        }
+    }
+
+    /// Returns the number of replicas in this map, including the local one.
+    #[inline]
+    pub fn num_replicas(&self) -> usize {
+        self.rest.len() + self.departed.len() + 1
+    }
+
+    /// Returns the value of the given remote replica, or `None` if it's not
+    /// in the map.
+    #[inline]
+    fn remote(&self, replica_id: ReplicaId) -> Option<T> {
+        self.rest.get(&replica_id).copied().or_else(|| {
+            self.departed
+                .binary_search_by_key(&replica_id, |&(id, _)| id)
+                .ok()
+                .map(|idx| self.departed[idx].1)
+        })
+    }
+
+    /// Removes the entry of the given replica from the departed ones,
+    /// returning its value.
+    #[inline]
+    fn take_departed(&mut self, replica_id: ReplicaId) -> Option<T> {
+        let idx = self
+            .departed
+            .binary_search_by_key(&replica_id, |&(id, _)| id)
+            .ok()?;
+
+        Some(self.departed.remove(idx).1)
     }
 
     #[inline]
@@ -99,7 +173,25 @@ impl<T: Copy> BaseMap<T> {
 impl<T: core::fmt::Debug> core::fmt::Debug for BaseMap<T> {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         let this_entry = core::iter::once((&self.this_id, &self.this_value));
-        f.debug_map().entries(this_entry.chain(self.rest.iter())).finish()
+        let departed = self.departed.iter().map(|(id, value)| (id, value));
+        f.debug_map()
+            .entries(this_entry.chain(self.rest.iter()).chain(departed))
+            .finish()
+    }
+}
+
+/// Two maps are equal if they contain the same entries, regardless of which
+/// of them have been compacted.
+impl<T: Copy + PartialEq> PartialEq for BaseMap<T> {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        self.this_id == other.this_id
+            && self.this_value == other.this_value
+            && self.num_replicas() == other.num_replicas()
+            && self
+                .iter()
+                .skip(1)
+                .all(|(id, value)| other.remote(id) == Some(value))
     }
 }
 
@@ -155,3 +247,78 @@ impl PartialOrd for VersionMap {
         update_order(iter, order)
     }
 }
+
+#[cfg(feature = "encode")]
+mod encode {
+    use super::*;
+    use crate::encode::{Decode, Encode};
+
+    impl<T: Copy + Encode> Encode for BaseMap<T> {
+        /// The local entry is encoded first, followed by the remote entries
+        /// sorted by `ReplicaId`, where each `ReplicaId` is encoded as the
+        /// difference from the previous one.
+        #[inline]
+        fn encode(&self, buf: &mut Vec<u8>) {
+            self.this_id.encode(buf);
+            self.this_value.encode(buf);
+
+            let mut rest = self
+                .rest
+                .iter()
+                .map(|(&id, &value)| (id, value))
+                .chain(self.departed.iter().copied())
+                .collect::<Vec<_>>();
+
+            rest.sort_unstable_by_key(|&(id, _)| id);
+
+            rest.len().encode(buf);
+
+            let mut prev_id = 0;
+
+            for (id, value) in rest {
+                (id - prev_id).encode(buf);
+                value.encode(buf);
+                prev_id = id;
+            }
+        }
+    }
+
+    impl<T: Copy + Decode> Decode for BaseMap<T> {
+        #[inline]
+        fn decode(buf: &mut &[u8]) -> Option<Self> {
+            let this_id = ReplicaId::decode(buf)?;
+            let this_value = T::decode(buf)?;
+
+            let mut map = Self::new(this_id, this_value);
+
+            let len = crate::encode::decode_len(buf)?;
+
+            map.rest.reserve(len);
+
+            let mut prev_id: ReplicaId = 0;
+
+            for idx in 0..len {
+                let delta = ReplicaId::decode(buf)?;
+
+                // The IDs are strictly increasing.
+                if idx > 0 && delta == 0 {
+                    return None;
+                }
+
+                let id = prev_id.checked_add(delta)?;
+
+                // The local entry is never repeated among the remote ones,
+                // and zero is not a valid `ReplicaId`.
+                if id == this_id || id == 0 {
+                    return None;
+                }
+
+                map.rest.insert(id, T::decode(buf)?);
+
+                prev_id = id;
+            }
+
+            Some(map)
+        }
+    }
+}
@@ -0,0 +1,400 @@
+mod update;
+
+use core::fmt;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use update::*;
+
+use crate::*;
+
+/// The largest client id Yjs can decode, since it stores them as JavaScript
+/// numbers.
+#[cfg_attr(docsrs, doc(cfg(feature = "yjs")))]
+pub const MAX_YJS_CLIENT_ID: ReplicaId = (1 << 53) - 1;
+
+/// The type of error that can occur when converting between a [`Replica`]
+/// and a Yjs update.
+#[cfg_attr(docsrs, doc(cfg(feature = "yjs")))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum YjsError {
+    /// This error occurs when decoding an update that's malformed, that
+    /// references items it doesn't contain, or that contains items made by
+    /// client 0, which isn't a valid [`ReplicaId`].
+    InvalidUpdate,
+
+    /// This error occurs when decoding an update that doesn't contain a root
+    /// type with the given name.
+    MissingType,
+
+    /// This error occurs when encoding a `Replica` containing text inserted
+    /// by a [`ReplicaId`] larger than [`MAX_YJS_CLIENT_ID`].
+    ReplicaIdTooLarge(ReplicaId),
+
+    /// This error occurs when decoding an update whose text contains visible
+    /// content other than strings, like embeds or nested types, which can't
+    /// be tracked by a `Replica`.
+    UnsupportedContent,
+}
+
+impl fmt::Display for YjsError {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidUpdate => f.write_str("invalid Yjs update"),
+            Self::MissingType => {
+                f.write_str("the Yjs update doesn't contain the given type")
+            },
+            Self::ReplicaIdTooLarge(id) => {
+                write!(
+                    f,
+                    "the ReplicaId {id} is too large for a Yjs client id"
+                )
+            },
+            Self::UnsupportedContent => {
+                f.write_str("the Yjs text contains non-string content")
+            },
+        }
+    }
+}
+
+impl std::error::Error for YjsError {}
+
+/// Encodes the runs of a `Replica`, listed in document order, as a Yjs
+/// update of the root text type with the given name.
+///
+/// Every group of runs created by the same insertion becomes an item whose
+/// origins are the closest characters on its left and on its right among
+/// the groups with a lower `LamportTs`. Those are the neighbours it had when
+/// it was inserted if the groups had been inserted in `LamportTs` order, so
+/// Yjs places every item exactly where the `Replica` did.
+#[inline]
+pub(crate) fn encode_update<'a, I>(
+    runs: I,
+    text: &str,
+    type_name: &str,
+) -> Result<Vec<u8>, YjsError>
+where
+    I: IntoIterator<Item = &'a EditRun>,
+{
+    let runs =
+        runs.into_iter().filter(|run| run.len() > 0).collect::<Vec<_>>();
+
+    if let Some(run) =
+        runs.iter().find(|run| run.replica_id() > MAX_YJS_CLIENT_ID)
+    {
+        return Err(YjsError::ReplicaIdTooLarge(run.replica_id()));
+    }
+
+    let mut groups = HashMap::<(ReplicaId, RunTs), Vec<usize>>::new();
+
+    for (idx, run) in runs.iter().enumerate() {
+        groups.entry((run.replica_id(), run.run_ts())).or_default().push(idx);
+    }
+
+    let mut groups = groups.into_values().collect::<Vec<_>>();
+
+    groups.sort_unstable_by_key(|group| {
+        let run = runs[group[0]];
+        (run.lamport_ts(), run.replica_id())
+    });
+
+    let mut origins = vec![(None, None); runs.len()];
+
+    let mut processed = BTreeSet::new();
+
+    for group in groups {
+        let (first, last) = (group[0], group[group.len() - 1]);
+
+        let left = processed.range(..first).next_back().map(|&idx| {
+            let run: &EditRun = runs[idx];
+            Id::new(run.replica_id(), (run.end() - 1) as u64)
+        });
+
+        let right = processed.range(last + 1..).next().map(|&idx| {
+            let run: &EditRun = runs[idx];
+            Id::new(run.replica_id(), run.start() as u64)
+        });
+
+        for (pos, &idx) in group.iter().enumerate() {
+            let run = runs[idx];
+
+            let origin = if pos == 0 {
+                left
+            } else {
+                Some(Id::new(run.replica_id(), (run.start() - 1) as u64))
+            };
+
+            origins[idx] = (origin, right);
+        }
+
+        processed.extend(group);
+    }
+
+    let mut contents = Vec::with_capacity(runs.len());
+
+    let mut rest = text;
+
+    for run in &runs {
+        if run.is_deleted() {
+            contents.push(None);
+        } else {
+            let (content, after) = split_utf16(rest, run.len());
+            contents.push(Some(content));
+            rest = after;
+        }
+    }
+
+    let mut clients = BTreeMap::<ReplicaId, Vec<usize>>::new();
+
+    for (idx, run) in runs.iter().enumerate() {
+        clients.entry(run.replica_id()).or_default().push(idx);
+    }
+
+    let mut buf = Vec::new();
+
+    write_var_uint(&mut buf, clients.len() as u64);
+
+    // Yjs writes the clients in descending order.
+    for (&client, idxs) in clients.iter_mut().rev() {
+        idxs.sort_unstable_by_key(|&idx| runs[idx].start());
+
+        write_var_uint(&mut buf, idxs.len() as u64);
+        write_var_uint(&mut buf, client);
+        write_var_uint(&mut buf, runs[idxs[0]].start() as u64);
+
+        for &idx in idxs.iter() {
+            let (origin, right_origin) = origins[idx];
+
+            let content_ref = match contents[idx] {
+                Some(_) => STRING,
+                None => DELETED,
+            };
+
+            let info = content_ref
+                | if origin.is_some() { HAS_ORIGIN } else { 0 }
+                | if right_origin.is_some() { HAS_RIGHT_ORIGIN } else { 0 };
+
+            buf.push(info);
+
+            if let Some(origin) = origin {
+                write_id(&mut buf, origin);
+            }
+
+            if let Some(right_origin) = right_origin {
+                write_id(&mut buf, right_origin);
+            }
+
+            if origin.is_none() && right_origin.is_none() {
+                write_var_uint(&mut buf, 1);
+                write_string(&mut buf, type_name);
+            }
+
+            match contents[idx] {
+                Some(content) => write_string(&mut buf, content),
+                None => write_var_uint(&mut buf, runs[idx].len() as u64),
+            }
+        }
+    }
+
+    let mut delete_set = BTreeMap::<ReplicaId, Vec<(Length, Length)>>::new();
+
+    for run in runs.iter().filter(|run| run.is_deleted()) {
+        let ranges = delete_set.entry(run.replica_id()).or_default();
+        ranges.push((run.start(), run.len()));
+    }
+
+    write_var_uint(&mut buf, delete_set.len() as u64);
+
+    for (&client, ranges) in delete_set.iter_mut().rev() {
+        ranges.sort_unstable();
+
+        let mut merged = Vec::<(Length, Length)>::with_capacity(ranges.len());
+
+        for &(start, len) in ranges.iter() {
+            match merged.last_mut() {
+                Some((prev_start, prev_len))
+                    if *prev_start + *prev_len == start =>
+                {
+                    *prev_len += len
+                },
+                _ => merged.push((start, len)),
+            }
+        }
+
+        write_var_uint(&mut buf, client);
+        write_var_uint(&mut buf, merged.len() as u64);
+
+        for (start, len) in merged {
+            write_var_uint(&mut buf, start as u64);
+            write_var_uint(&mut buf, len as u64);
+        }
+    }
+
+    Ok(buf)
+}
+
+/// Decodes a Yjs update, returning the runs of the root text type with the
+/// given name in document order, together with its visible text.
+///
+/// The structs are integrated in causal order with the same algorithm used
+/// by Yjs. Every struct becomes a run whose `LamportTs` is its position in
+/// that order, except for the structs continuing the previous struct of the
+/// same client, which are added to its run.
+#[inline]
+pub(crate) fn decode_update(
+    update: &[u8],
+    type_name: &str,
+) -> Result<(Vec<EditRun>, String), YjsError> {
+    let mut store = Store::default();
+
+    store.decode(update).map_err(|_| YjsError::InvalidUpdate)?;
+
+    if !store.has_root(type_name) {
+        return Err(YjsError::MissingType);
+    }
+
+    let document = Document::new(store, type_name);
+
+    // The temporal offset, `RunTs` and `LamportTs` of every struct that's
+    // part of the text, skipping the formatting attributes.
+    let mut timestamps = HashMap::new();
+
+    let mut clients = BTreeMap::<ReplicaId, Vec<(usize, LamportTs)>>::new();
+
+    for (lamport_ts, &(idx, _)) in document.integrated.iter().enumerate() {
+        let r#struct = &document.store.structs[idx];
+        if !matches!(r#struct.content, Content::Format) {
+            let structs = clients.entry(r#struct.id.client).or_default();
+            structs.push((idx, lamport_ts as LamportTs));
+        }
+    }
+
+    for (client, mut structs) in clients {
+        if client == 0 {
+            return Err(YjsError::InvalidUpdate);
+        }
+
+        structs.sort_unstable_by_key(|&(idx, _)| {
+            document.store.structs[idx].id.clock
+        });
+
+        let mut offset = 0;
+
+        let mut run_ts = 0;
+
+        let mut prev: Option<&Struct> = None;
+
+        let mut lamport_ts = 0;
+
+        for (idx, integrated_at) in structs {
+            let r#struct = &document.store.structs[idx];
+
+            let continues_prev = prev.is_some_and(|prev| {
+                prev.end() == r#struct.id.clock
+                    && r#struct.origin
+                        == Some(Id::new(client, r#struct.id.clock - 1))
+                    && r#struct.right_origin == prev.right_origin
+            });
+
+            if prev.is_some() && !continues_prev {
+                run_ts += 1;
+            }
+
+            if !continues_prev {
+                lamport_ts = integrated_at;
+            }
+
+            timestamps.insert(idx, (offset, run_ts, lamport_ts));
+
+            offset += r#struct.len as Length;
+
+            prev = Some(r#struct);
+        }
+    }
+
+    let mut runs = Vec::<EditRun>::new();
+
+    let mut text = Vec::new();
+
+    for idx in document.items() {
+        let item = &document.items[idx];
+
+        let is_deleted = match &item.content {
+            Content::Format => continue,
+            Content::Text(units) => {
+                if !item.is_deleted {
+                    text.extend_from_slice(units);
+                }
+                item.is_deleted
+            },
+            Content::Deleted => true,
+            Content::Other if item.is_deleted => true,
+            Content::Other | Content::Gc => {
+                return Err(YjsError::UnsupportedContent)
+            },
+        };
+
+        let r#struct = &document.store.structs[item.r#struct];
+
+        let (offset, run_ts, lamport_ts) = timestamps[&item.r#struct];
+
+        let start = offset + (item.id.clock - r#struct.id.clock) as Length;
+
+        let run = EditRun::decoded(
+            Text::new(item.id.client, start..start + item.len as Length),
+            run_ts,
+            lamport_ts,
+            is_deleted,
+        );
+
+        match runs.last_mut() {
+            Some(last) if last.can_append(&run) => last.extend(run.len()),
+            _ => runs.push(run),
+        }
+    }
+
+    let text =
+        String::from_utf16(&text).map_err(|_| YjsError::InvalidUpdate)?;
+
+    Ok((runs, text))
+}
+
+/// Splits the string after the given number of UTF-16 code units.
+#[track_caller]
+#[inline]
+fn split_utf16(s: &str, len: Length) -> (&str, &str) {
+    let mut utf16_len = 0;
+
+    for (byte_offset, ch) in s.char_indices() {
+        if utf16_len >= len {
+            assert_eq!(utf16_len, len, "run boundary inside of a character");
+            return s.split_at(byte_offset);
+        }
+        utf16_len += ch.len_utf16();
+    }
+
+    assert_eq!(utf16_len, len, "run boundary inside of a character");
+
+    (s, "")
+}
+
+#[inline]
+fn write_id(buf: &mut Vec<u8>, id: Id) {
+    write_var_uint(buf, id.client);
+    write_var_uint(buf, id.clock);
+}
+
+#[inline]
+fn write_string(buf: &mut Vec<u8>, s: &str) {
+    write_var_uint(buf, s.len() as u64);
+    buf.extend_from_slice(s.as_bytes());
+}
+
+#[inline]
+fn write_var_uint(buf: &mut Vec<u8>, mut num: u64) {
+    while num >= 0x80 {
+        buf.push((num as u8 & 0x7f) | 0x80);
+        num >>= 7;
+    }
+    buf.push(num as u8);
+}
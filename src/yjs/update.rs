@@ -0,0 +1,837 @@
+//! Decoding of Yjs updates encoded with the v1 encoding, and integration of
+//! their structs into a text with the same algorithm used by Yjs.
+//!
+//! This module doesn't depend on the rest of cola, since it's also included
+//! by path by the `traces` crate to import Yjs update logs as editing
+//! traces.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+const GC: u8 = 0;
+pub(crate) const DELETED: u8 = 1;
+const JSON: u8 = 2;
+const BINARY: u8 = 3;
+pub(crate) const STRING: u8 = 4;
+const EMBED: u8 = 5;
+const FORMAT: u8 = 6;
+const TYPE: u8 = 7;
+const ANY: u8 = 8;
+const DOC: u8 = 9;
+const SKIP: u8 = 10;
+
+/// Set in the info byte of an item if it has an origin.
+pub(crate) const HAS_ORIGIN: u8 = 0x80;
+
+/// Set in the info byte of an item if it has a right origin.
+pub(crate) const HAS_RIGHT_ORIGIN: u8 = 0x40;
+
+/// Set in the info byte of an item if it's the entry of a map.
+const HAS_PARENT_SUB: u8 = 0x20;
+
+/// The maximum nesting depth of the `Any` values we're willing to skip.
+const MAX_ANY_DEPTH: usize = 64;
+
+/// The id of a single clock of a client.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct Id {
+    pub(crate) client: u64,
+    pub(crate) clock: u64,
+}
+
+impl Id {
+    #[inline]
+    pub(crate) fn new(client: u64, clock: u64) -> Self {
+        Self { client, clock }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(crate) enum Parent {
+    /// The parent is the one of the struct's origin, or of its right origin
+    /// if it doesn't have one.
+    Inherited,
+
+    /// A root type with the given name.
+    Root(String),
+
+    /// A nested type, an entry of a map, or nothing for garbage collected
+    /// structs.
+    Other,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) enum Content {
+    /// A garbage collected range, which isn't part of any type anymore.
+    Gc,
+
+    /// A deleted item whose content was discarded.
+    Deleted,
+
+    /// A formatting attribute, which doesn't take up any space in the text.
+    Format,
+
+    /// A string, as UTF-16 code units.
+    Text(Vec<u16>),
+
+    /// Any other content, like embeds or nested types.
+    Other,
+}
+
+/// A struct decoded from an update, i.e. a run of consecutive clocks of the
+/// same client.
+#[derive(Clone, Debug)]
+pub(crate) struct Struct {
+    pub(crate) id: Id,
+    pub(crate) len: u64,
+    pub(crate) origin: Option<Id>,
+    pub(crate) right_origin: Option<Id>,
+    pub(crate) parent: Parent,
+    pub(crate) content: Content,
+}
+
+impl Struct {
+    #[inline]
+    pub(crate) fn end(&self) -> u64 {
+        self.id.clock + self.len
+    }
+
+    /// Splits the struct at the given offset, returning the right part.
+    #[inline]
+    fn split(&mut self, offset: u64) -> Self {
+        debug_assert!(0 < offset && offset < self.len);
+
+        let content = match &mut self.content {
+            Content::Text(units) => {
+                Content::Text(units.split_off(offset as usize))
+            },
+            other => other.clone(),
+        };
+
+        let right = Self {
+            id: Id::new(self.id.client, self.id.clock + offset),
+            len: self.len - offset,
+            origin: match self.content {
+                Content::Gc => None,
+                _ => Some(Id::new(self.id.client, self.id.clock + offset - 1)),
+            },
+            right_origin: self.right_origin,
+            parent: Parent::Inherited,
+            content,
+        };
+
+        self.len = offset;
+
+        right
+    }
+}
+
+/// The structs of one or more updates, deduplicated.
+#[derive(Default)]
+pub(crate) struct Store {
+    pub(crate) structs: Vec<Struct>,
+
+    /// Maps every client to the starting clocks of its structs.
+    clients: HashMap<u64, BTreeMap<u64, usize>>,
+
+    /// The ranges of clocks deleted by the updates.
+    pub(crate) delete_set: Vec<(Id, u64)>,
+}
+
+impl Store {
+    /// Adds a struct, dropping the clocks that were already added.
+    #[inline]
+    fn add(&mut self, mut new: Struct) {
+        let client = self.clients.entry(new.id.client).or_default();
+
+        loop {
+            if let Some((_, &idx)) = client.range(..=new.id.clock).next_back()
+            {
+                let end = self.structs[idx].end();
+
+                if end >= new.end() {
+                    return;
+                }
+
+                if end > new.id.clock {
+                    new = new.split(end - new.id.clock);
+                    continue;
+                }
+            }
+
+            let next = client
+                .range(new.id.clock + 1..new.end())
+                .next()
+                .map(|(&clock, _)| clock);
+
+            let rest = next.map(|clock| new.split(clock - new.id.clock));
+
+            client.insert(new.id.clock, self.structs.len());
+            self.structs.push(new);
+
+            match rest {
+                Some(rest) => new = rest,
+                None => return,
+            }
+        }
+    }
+
+    /// Decodes an update, adding its structs and deleted ranges to the
+    /// store.
+    #[inline]
+    pub(crate) fn decode(&mut self, update: &[u8]) -> Result<(), String> {
+        let mut decoder = Decoder { bytes: update, pos: 0 };
+
+        decoder.update(self)?;
+
+        if decoder.pos < decoder.bytes.len() {
+            return Err("trailing bytes after the delete set".to_owned());
+        }
+
+        Ok(())
+    }
+
+    /// Returns the index of the struct containing the given id.
+    #[inline]
+    pub(crate) fn find(&self, id: Id) -> Option<usize> {
+        let (_, &idx) =
+            self.clients.get(&id.client)?.range(..=id.clock).next_back()?;
+        (id.clock < self.structs[idx].end()).then_some(idx)
+    }
+
+    /// Returns whether the store contains a root type with the given name.
+    #[inline]
+    pub(crate) fn has_root(&self, name: &str) -> bool {
+        self.structs.iter().any(|r#struct| {
+            matches!(&r#struct.parent, Parent::Root(root) if root == name)
+        })
+    }
+
+    /// Returns the index of the struct right before the given one in its
+    /// client's clock order.
+    #[inline]
+    fn previous(&self, idx: usize) -> Option<usize> {
+        let Id { client, clock } = self.structs[idx].id;
+        self.clients[&client].range(..clock).next_back().map(|(_, &idx)| idx)
+    }
+}
+
+struct Decoder<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Decoder<'a> {
+    #[inline]
+    fn any(&mut self, depth: usize) -> Result<(), String> {
+        if depth > MAX_ANY_DEPTH {
+            return Err("a value is nested too deeply".to_owned());
+        }
+
+        match self.u8()? {
+            // Undefined, null, false and true.
+            127 | 126 | 121 | 120 => {},
+            125 => {
+                self.var_int()?;
+            },
+            124 => {
+                self.take(4)?;
+            },
+            123 | 122 => {
+                self.take(8)?;
+            },
+            119 => {
+                self.string()?;
+            },
+            118 => {
+                for _ in 0..self.var_uint()? {
+                    self.string()?;
+                    self.any(depth + 1)?;
+                }
+            },
+            117 => {
+                for _ in 0..self.var_uint()? {
+                    self.any(depth + 1)?;
+                }
+            },
+            116 => {
+                self.buffer()?;
+            },
+            tag => return Err(format!("unknown value tag {tag}")),
+        }
+
+        Ok(())
+    }
+
+    #[inline]
+    fn buffer(&mut self) -> Result<&'a [u8], String> {
+        let len = self.var_uint()?;
+        self.take(usize::try_from(len).map_err(|err| err.to_string())?)
+    }
+
+    #[inline]
+    fn content(&mut self, content_ref: u8) -> Result<(Content, u64), String> {
+        let content = match content_ref {
+            DELETED => return Ok((Content::Deleted, self.var_uint()?)),
+
+            JSON => {
+                let len = self.var_uint()?;
+                for _ in 0..len {
+                    self.string()?;
+                }
+                return Ok((Content::Other, len));
+            },
+
+            BINARY => {
+                self.buffer()?;
+                Content::Other
+            },
+
+            STRING => {
+                let units = self.string()?.encode_utf16().collect::<Vec<_>>();
+                let len = units.len() as u64;
+                return Ok((Content::Text(units), len));
+            },
+
+            EMBED => {
+                self.string()?;
+                Content::Other
+            },
+
+            FORMAT => {
+                self.string()?;
+                self.string()?;
+                Content::Format
+            },
+
+            TYPE => {
+                // XmlElement and XmlHook types are followed by their name.
+                if matches!(self.var_uint()?, 3 | 5) {
+                    self.string()?;
+                }
+                Content::Other
+            },
+
+            ANY => {
+                let len = self.var_uint()?;
+                for _ in 0..len {
+                    self.any(0)?;
+                }
+                return Ok((Content::Other, len));
+            },
+
+            DOC => {
+                self.string()?;
+                self.any(0)?;
+                Content::Other
+            },
+
+            _ => return Err(format!("unknown content ref {content_ref}")),
+        };
+
+        Ok((content, 1))
+    }
+
+    #[inline]
+    fn id(&mut self) -> Result<Id, String> {
+        Ok(Id::new(self.var_uint()?, self.var_uint()?))
+    }
+
+    #[inline]
+    fn string(&mut self) -> Result<&'a str, String> {
+        core::str::from_utf8(self.buffer()?).map_err(|err| err.to_string())
+    }
+
+    #[inline]
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let bytes = self
+            .bytes
+            .get(self.pos..self.pos.saturating_add(len))
+            .ok_or("unexpected end of input")?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    #[inline]
+    fn u8(&mut self) -> Result<u8, String> {
+        self.take(1).map(|bytes| bytes[0])
+    }
+
+    #[inline]
+    fn update(&mut self, store: &mut Store) -> Result<(), String> {
+        for _ in 0..self.var_uint()? {
+            let num_structs = self.var_uint()?;
+            let client = self.var_uint()?;
+            let mut clock = self.var_uint()?;
+
+            for _ in 0..num_structs {
+                let info = self.u8()?;
+
+                let id = Id::new(client, clock);
+
+                let r#struct = match info & 0b1_1111 {
+                    GC => Struct {
+                        id,
+                        len: self.var_uint()?,
+                        origin: None,
+                        right_origin: None,
+                        parent: Parent::Other,
+                        content: Content::Gc,
+                    },
+
+                    SKIP => {
+                        clock = clock
+                            .checked_add(self.var_uint()?)
+                            .ok_or("clock overflow")?;
+                        continue;
+                    },
+
+                    content_ref => {
+                        let origin = if info & HAS_ORIGIN != 0 {
+                            Some(self.id()?)
+                        } else {
+                            None
+                        };
+
+                        let right_origin = if info & HAS_RIGHT_ORIGIN != 0 {
+                            Some(self.id()?)
+                        } else {
+                            None
+                        };
+
+                        let parent =
+                            if origin.is_some() || right_origin.is_some() {
+                                Parent::Inherited
+                            } else {
+                                let parent = if self.var_uint()? == 1 {
+                                    Parent::Root(self.string()?.to_owned())
+                                } else {
+                                    self.id()?;
+                                    Parent::Other
+                                };
+                                if info & HAS_PARENT_SUB != 0 {
+                                    self.string()?;
+                                    Parent::Other
+                                } else {
+                                    parent
+                                }
+                            };
+
+                        let (content, len) = self.content(content_ref)?;
+
+                        Struct {
+                            id,
+                            len,
+                            origin,
+                            right_origin,
+                            parent,
+                            content,
+                        }
+                    },
+                };
+
+                clock =
+                    clock.checked_add(r#struct.len).ok_or("clock overflow")?;
+
+                if r#struct.len > 0 {
+                    store.add(r#struct);
+                }
+            }
+        }
+
+        for _ in 0..self.var_uint()? {
+            let client = self.var_uint()?;
+
+            for _ in 0..self.var_uint()? {
+                let clock = self.var_uint()?;
+                let len = self.var_uint()?;
+                store.delete_set.push((Id::new(client, clock), len));
+            }
+        }
+
+        Ok(())
+    }
+
+    #[inline]
+    fn var_int(&mut self) -> Result<(), String> {
+        // The first byte holds the sign and 6 bits, the rest 7 bits each,
+        // and we only need to skip over them.
+        let mut byte = self.u8()?;
+        let mut num_bits = 6;
+        while byte & 0x80 != 0 {
+            byte = self.u8()?;
+            num_bits += 7;
+            if num_bits > 64 {
+                return Err("integer overflow".to_owned());
+            }
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn var_uint(&mut self) -> Result<u64, String> {
+        let mut num = 0;
+        let mut shift = 0;
+
+        loop {
+            let byte = self.u8()?;
+            num |= u64::from(byte & 0x7f) << shift;
+            if byte < 0x80 {
+                return Ok(num);
+            }
+            shift += 7;
+            if shift > 63 {
+                return Err("integer overflow".to_owned());
+            }
+        }
+    }
+}
+
+/// A struct of the text, or a part of one.
+pub(crate) struct Item {
+    pub(crate) id: Id,
+    pub(crate) len: u64,
+    origin: Option<Id>,
+    right_origin: Option<Id>,
+
+    /// The index of the struct the item is a part of.
+    pub(crate) r#struct: usize,
+
+    /// The content of the item, which for strings only contains the code
+    /// units of the item.
+    pub(crate) content: Content,
+
+    left: Option<usize>,
+    right: Option<usize>,
+    pub(crate) is_deleted: bool,
+}
+
+/// A text, with its items linked in document order.
+pub(crate) struct Document {
+    pub(crate) store: Store,
+    pub(crate) items: Vec<Item>,
+
+    /// Maps every client to the starting clocks of its items.
+    clients: HashMap<u64, BTreeMap<u64, usize>>,
+
+    /// The first item of the text.
+    start: Option<usize>,
+
+    /// The structs of the text in the order they were integrated, each with
+    /// the index of its first item.
+    pub(crate) integrated: Vec<(usize, usize)>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Visit {
+    New,
+    Pending,
+    Done,
+}
+
+impl Document {
+    /// Returns the item ending at the given id, splitting it if necessary.
+    #[inline]
+    fn clean_end(&mut self, id: Id) -> Option<usize> {
+        let item = self.find(id)?;
+        let offset = id.clock - self.items[item].id.clock + 1;
+        if offset < self.items[item].len {
+            self.split(item, offset);
+        }
+        Some(item)
+    }
+
+    /// Returns the item starting at the given id, splitting it if necessary.
+    #[inline]
+    fn clean_start(&mut self, id: Id) -> Option<usize> {
+        let item = self.find(id)?;
+        let offset = id.clock - self.items[item].id.clock;
+        Some(if offset > 0 { self.split(item, offset) } else { item })
+    }
+
+    /// Marks the items in the given range of clocks as deleted.
+    #[inline]
+    fn delete(&mut self, Id { client, clock }: Id, len: u64) {
+        let end = clock.saturating_add(len);
+
+        let mut clock = clock;
+
+        while clock < end {
+            let item = match self.clean_start(Id::new(client, clock)) {
+                Some(item) => item,
+
+                None => match self
+                    .clients
+                    .get(&client)
+                    .and_then(|items| items.range(clock..end).next())
+                {
+                    Some((_, &item)) => item,
+                    None => return,
+                },
+            };
+
+            let item_end = self.items[item].id.clock + self.items[item].len;
+
+            if item_end > end {
+                self.split(item, end - self.items[item].id.clock);
+            }
+
+            self.items[item].is_deleted = true;
+
+            clock = item_end.min(end);
+        }
+    }
+
+    /// Returns the index of the item containing the given id.
+    #[inline]
+    fn find(&self, id: Id) -> Option<usize> {
+        let (_, &item) =
+            self.clients.get(&id.client)?.range(..=id.clock).next_back()?;
+        let Item { id: start, len, .. } = self.items[item];
+        (id.clock < start.clock + len).then_some(item)
+    }
+
+    /// Inserts the item between its origins, resolving conflicts with the
+    /// items concurrently inserted between them like Yjs does.
+    #[inline]
+    fn integrate(
+        &mut self,
+        item: usize,
+        mut left: Option<usize>,
+        right: Option<usize>,
+    ) {
+        let needs_resolving = match left {
+            Some(left) => self.items[left].right != right,
+            None => right.is_none_or(|right| self.items[right].left.is_some()),
+        };
+
+        if needs_resolving {
+            let mut other = match left {
+                Some(left) => self.items[left].right,
+                None => self.start,
+            };
+
+            let mut conflicting = HashSet::new();
+
+            let mut before_origin = HashSet::new();
+
+            while let Some(o) = other.filter(|&o| Some(o) != right) {
+                before_origin.insert(o);
+                conflicting.insert(o);
+
+                let (this, that) = (&self.items[item], &self.items[o]);
+
+                if this.origin == that.origin {
+                    if that.id.client < this.id.client {
+                        left = Some(o);
+                        conflicting.clear();
+                    } else if this.right_origin == that.right_origin {
+                        break;
+                    }
+                } else if let Some(origin) = that
+                    .origin
+                    .and_then(|origin| self.find(origin))
+                    .filter(|origin| before_origin.contains(origin))
+                {
+                    if !conflicting.contains(&origin) {
+                        left = Some(o);
+                        conflicting.clear();
+                    }
+                } else {
+                    break;
+                }
+
+                other = self.items[o].right;
+            }
+        }
+
+        let right = match left {
+            Some(left) => self.items[left].right.replace(item),
+            None => self.start.replace(item),
+        };
+
+        self.items[item].left = left;
+        self.items[item].right = right;
+
+        if let Some(right) = right {
+            self.items[right].left = Some(item);
+        }
+    }
+
+    /// Returns whether the struct is part of the text, given whether the
+    /// structs it depends on are.
+    #[inline]
+    fn is_in_text(&self, idx: usize, in_text: &[bool], text: &str) -> bool {
+        let r#struct = &self.store.structs[idx];
+
+        match (&r#struct.content, &r#struct.parent) {
+            (Content::Gc, _) | (_, Parent::Other) => false,
+
+            (_, Parent::Root(name)) => name == text,
+
+            (_, Parent::Inherited) => {
+                let origins = [r#struct.origin, r#struct.right_origin]
+                    .into_iter()
+                    .flatten()
+                    .map(|id| self.store.find(id))
+                    .collect::<Option<Vec<_>>>();
+
+                // Yjs garbage collects the items whose origins are missing
+                // or were garbage collected.
+                origins.is_some_and(|origins| {
+                    origins.first().is_some_and(|&origin| in_text[origin])
+                        && origins.iter().all(|&origin| {
+                            !matches!(
+                                self.store.structs[origin].content,
+                                Content::Gc
+                            )
+                        })
+                })
+            },
+        }
+    }
+
+    /// Returns the items of the text in document order.
+    #[inline]
+    pub(crate) fn items(&self) -> impl Iterator<Item = usize> + '_ {
+        core::iter::successors(self.start, |&item| self.items[item].right)
+    }
+
+    /// Integrates the structs of the root text with the given name in causal
+    /// order, skipping the ones that aren't part of it, and then deletes the
+    /// ranges in the store's delete set.
+    #[inline]
+    pub(crate) fn new(store: Store, text: &str) -> Self {
+        let mut this = Self {
+            store,
+            items: Vec::new(),
+            clients: HashMap::new(),
+            start: None,
+            integrated: Vec::new(),
+        };
+
+        let num_structs = this.store.structs.len();
+
+        let mut in_text = vec![false; num_structs];
+
+        let mut visits = vec![Visit::New; num_structs];
+
+        for root in 0..num_structs {
+            let mut stack = vec![root];
+
+            while let Some(&idx) = stack.last() {
+                match visits[idx] {
+                    Visit::New => {
+                        visits[idx] = Visit::Pending;
+
+                        let r#struct = &this.store.structs[idx];
+
+                        let deps = [r#struct.origin, r#struct.right_origin]
+                            .into_iter()
+                            .flatten()
+                            .filter_map(|id| this.store.find(id))
+                            .chain(this.store.previous(idx))
+                            .filter(|&dep| visits[dep] == Visit::New);
+
+                        stack.extend(deps);
+                    },
+
+                    Visit::Pending => {
+                        visits[idx] = Visit::Done;
+                        stack.pop();
+                        in_text[idx] = this.is_in_text(idx, &in_text, text);
+                        if in_text[idx] {
+                            this.push(idx);
+                        }
+                    },
+
+                    Visit::Done => {
+                        stack.pop();
+                    },
+                }
+            }
+        }
+
+        for (id, len) in core::mem::take(&mut this.store.delete_set) {
+            this.delete(id, len);
+        }
+
+        this
+    }
+
+    /// Integrates the struct into the text.
+    #[inline]
+    fn push(&mut self, idx: usize) {
+        let r#struct = &self.store.structs[idx];
+
+        let item = self.items.len();
+
+        self.items.push(Item {
+            id: r#struct.id,
+            len: r#struct.len,
+            origin: r#struct.origin,
+            right_origin: r#struct.right_origin,
+            r#struct: idx,
+            content: r#struct.content.clone(),
+            left: None,
+            right: None,
+            is_deleted: matches!(r#struct.content, Content::Deleted),
+        });
+
+        self.clients
+            .entry(r#struct.id.client)
+            .or_default()
+            .insert(r#struct.id.clock, item);
+
+        let (origin, right_origin) = (r#struct.origin, r#struct.right_origin);
+
+        let left = origin.and_then(|id| self.clean_end(id));
+
+        let right = right_origin.and_then(|id| self.clean_start(id));
+
+        self.integrate(item, left, right);
+
+        self.integrated.push((idx, item));
+    }
+
+    /// Splits the item at the given offset, returning the right part.
+    #[inline]
+    fn split(&mut self, item: usize, offset: u64) -> usize {
+        let new = self.items.len();
+
+        let left = &mut self.items[item];
+
+        let content = match &mut left.content {
+            Content::Text(units) => {
+                Content::Text(units.split_off(offset as usize))
+            },
+            other => other.clone(),
+        };
+
+        let right = Item {
+            id: Id::new(left.id.client, left.id.clock + offset),
+            len: left.len - offset,
+            origin: Some(Id::new(left.id.client, left.id.clock + offset - 1)),
+            right_origin: left.right_origin,
+            r#struct: left.r#struct,
+            content,
+            left: Some(item),
+            right: left.right.replace(new),
+            is_deleted: left.is_deleted,
+        };
+
+        left.len = offset;
+
+        if let Some(next) = right.right {
+            self.items[next].left = Some(new);
+        }
+
+        self.clients
+            .entry(right.id.client)
+            .or_default()
+            .insert(right.id.clock, new);
+
+        self.items.push(right);
+
+        new
+    }
+}
@@ -0,0 +1,285 @@
+use cola::ReplicaId;
+use proptest::prelude::*;
+
+use crate::{assert_invariants, Peer};
+
+/// The parameters of the [`History`]s generated by its [`Arbitrary`] impl.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// The maximum number of peers taking part in the session, which has to
+    /// be at least 2.
+    pub max_peers: usize,
+
+    /// The maximum number of steps in a history.
+    pub max_steps: usize,
+
+    /// The maximum length of the initial text of the document.
+    pub max_initial_len: usize,
+
+    /// The maximum length of the text inserted by a single insertion.
+    pub max_insertion_len: usize,
+
+    /// The maximum length of the range deleted by a single deletion.
+    pub max_deletion_len: usize,
+}
+
+impl Default for Config {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            max_peers: 4,
+            max_steps: 64,
+            max_initial_len: 16,
+            max_insertion_len: 8,
+            max_deletion_len: 8,
+        }
+    }
+}
+
+/// A step of a [`History`].
+///
+/// The peers, offsets and messages are picked modulo the number of peers,
+/// the length of the text and the number of messages in the inbox when the
+/// step is replayed, so that every step is valid no matter which ones come
+/// before it.
+#[derive(Debug, Clone)]
+pub enum Step {
+    /// A peer inserts some text, and the resulting message is added to the
+    /// inboxes of all the other peers.
+    Insert {
+        /// The peer making the insertion.
+        peer: usize,
+
+        /// The offset of the insertion.
+        offset: usize,
+
+        /// The inserted text, which is always ASCII.
+        text: String,
+    },
+
+    /// A peer deletes a range of its text, and the resulting message is added
+    /// to the inboxes of all the other peers. This is skipped if the text is
+    /// empty.
+    Delete {
+        /// The peer making the deletion.
+        peer: usize,
+
+        /// The start of the deleted range.
+        start: usize,
+
+        /// The length of the deleted range, which is truncated at the end of
+        /// the text.
+        len: usize,
+    },
+
+    /// A peer receives one of the messages in its inbox, which is then
+    /// removed from it. Since any message can be picked, messages are
+    /// delivered in arbitrary order and after arbitrary delays.
+    Deliver {
+        /// The receiving peer.
+        peer: usize,
+
+        /// The message to deliver.
+        message: usize,
+    },
+
+    /// Same as [`Deliver`](Step::Deliver), except that the message is left in
+    /// the inbox, so it'll be delivered again.
+    Duplicate {
+        /// The receiving peer.
+        peer: usize,
+
+        /// The message to deliver.
+        message: usize,
+    },
+}
+
+/// A randomly generated edit history of a collaborative session.
+///
+/// All the peers start from the same document, then replay the [`Step`]s.
+/// Finally every peer receives the messages still waiting in its inbox, at
+/// which point all of them must have converged to the same text.
+///
+/// # Examples
+///
+/// ```
+/// use cola_testing::proptest::prelude::*;
+/// use cola_testing::{BufferPeer, History};
+///
+/// proptest!(|(history in any::<History>())| {
+///     history.check::<BufferPeer>();
+/// });
+/// ```
+#[derive(Debug, Clone)]
+pub struct History {
+    /// The initial text of the document.
+    pub initial_text: String,
+
+    /// The number of peers taking part in the session.
+    pub num_peers: usize,
+
+    /// The steps of the history.
+    pub steps: Vec<Step>,
+}
+
+impl Arbitrary for History {
+    type Parameters = Config;
+    type Strategy = BoxedStrategy<Self>;
+
+    #[inline]
+    fn arbitrary_with(config: Config) -> Self::Strategy {
+        assert!(config.max_peers >= 2, "a session needs at least 2 peers");
+
+        let initial_text = ascii(0..=config.max_initial_len);
+
+        let insertion_len = 1..=config.max_insertion_len;
+
+        let step = prop_oneof![
+            3 => (any::<usize>(), any::<usize>(), ascii(insertion_len))
+                .prop_map(|(peer, offset, text)| Step::Insert {
+                    peer,
+                    offset,
+                    text,
+                }),
+            2 => (any::<usize>(), any::<usize>(), 1..=config.max_deletion_len)
+                .prop_map(|(peer, start, len)| Step::Delete {
+                    peer,
+                    start,
+                    len,
+                }),
+            4 => any::<(usize, usize)>()
+                .prop_map(|(peer, message)| Step::Deliver { peer, message }),
+            1 => any::<(usize, usize)>()
+                .prop_map(|(peer, message)| Step::Duplicate { peer, message }),
+        ];
+
+        (
+            initial_text,
+            2..=config.max_peers,
+            prop::collection::vec(step, 0..=config.max_steps),
+        )
+            .prop_map(|(initial_text, num_peers, steps)| Self {
+                initial_text,
+                num_peers,
+                steps,
+            })
+            .boxed()
+    }
+}
+
+impl History {
+    /// Replays the history on peers of the given type, checking the
+    /// [invariants](assert_invariants) of every peer after each of its steps
+    /// and that all of them converge to the same text at the end.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any of those checks fails.
+    #[track_caller]
+    pub fn check<P: Peer>(&self) {
+        let first = P::new(1, &self.initial_text);
+
+        let mut peers = (2..=self.num_peers as ReplicaId)
+            .map(|id| first.fork(id))
+            .collect::<Vec<_>>();
+
+        peers.insert(0, first);
+
+        let mut inboxes = vec![Vec::<P::Message>::new(); self.num_peers];
+
+        for step in &self.steps {
+            let idx = match step {
+                Step::Insert { peer, offset, text } => {
+                    let idx = peer % peers.len();
+                    let peer = &mut peers[idx];
+                    let offset = offset % (peer.replica().len() + 1);
+                    let message = peer.insert(offset, text);
+                    broadcast(&mut inboxes, idx, message);
+                    idx
+                },
+
+                Step::Delete { peer, start, len } => {
+                    let idx = peer % peers.len();
+                    let peer = &mut peers[idx];
+                    let peer_len = peer.replica().len();
+                    if peer_len == 0 {
+                        continue;
+                    }
+                    let start = start % peer_len;
+                    let end = peer_len.min(start + len);
+                    let message = peer.delete(start..end);
+                    broadcast(&mut inboxes, idx, message);
+                    idx
+                },
+
+                Step::Deliver { peer, message } => {
+                    let idx = peer % peers.len();
+                    let inbox = &mut inboxes[idx];
+                    if inbox.is_empty() {
+                        continue;
+                    }
+                    let message = inbox.remove(message % inbox.len());
+                    peers[idx].receive(&message);
+                    idx
+                },
+
+                Step::Duplicate { peer, message } => {
+                    let idx = peer % peers.len();
+                    let inbox = &inboxes[idx];
+                    if inbox.is_empty() {
+                        continue;
+                    }
+                    peers[idx].receive(&inbox[message % inbox.len()]);
+                    idx
+                },
+            };
+
+            assert_invariants(&peers[idx]);
+        }
+
+        for (peer, inbox) in peers.iter_mut().zip(&mut inboxes) {
+            for message in inbox.drain(..) {
+                peer.receive(&message);
+            }
+        }
+
+        let text = peers[0].text();
+
+        for peer in &peers {
+            assert_invariants(peer);
+
+            let id = peer.replica().id();
+
+            let stats = peer.replica().stats();
+
+            assert!(
+                stats.backlogged_insertions() == 0
+                    && stats.backlogged_deletions() == 0,
+                "peer {id} still has backlogged edits after receiving all \
+                 the messages"
+            );
+
+            assert_eq!(peer.text(), text, "peer {id} didn't converge");
+        }
+    }
+}
+
+/// Adds the message to the inboxes of every peer except the sender.
+#[inline]
+fn broadcast<M: Clone>(inboxes: &mut [Vec<M>], sender: usize, message: M) {
+    for (idx, inbox) in inboxes.iter_mut().enumerate() {
+        if idx != sender {
+            inbox.push(message.clone());
+        }
+    }
+}
+
+/// Returns a strategy generating lowercase ASCII strings with a length in
+/// the given range.
+#[inline]
+fn ascii(
+    len: core::ops::RangeInclusive<usize>,
+) -> impl Strategy<Value = String> {
+    prop::collection::vec(b'a'..=b'z', len)
+        .prop_map(|bytes| bytes.into_iter().map(char::from).collect())
+}
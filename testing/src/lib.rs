@@ -0,0 +1,45 @@
+//! Property-based convergence tests for projects integrating cola.
+//!
+//! This crate generates random edit histories of a collaborative session
+//! via [`proptest`], where any number of peers edit the same document while
+//! the messages they send each other are delivered in arbitrary order, after
+//! arbitrary delays and possibly more than once. Replaying a [`History`]
+//! checks that the [`Replica`](cola::Replica) of every peer stays
+//! internally consistent and survives encode-decode round-trips, and that
+//! all the peers converge to the same text once every message has been
+//! delivered.
+//!
+//! The peers are driven through the [`Peer`] trait, so the same histories
+//! can be replayed on top of your own integration layer (e.g. the buffer of
+//! your editor and the messages your transport sends) to fuzz it. The
+//! [`BufferPeer`] is the reference implementation used to test cola itself.
+//!
+//! The [`Simulator`] covers longer multi-peer scenarios: it generates a
+//! deterministic [`Schedule`] from a seed, where the network can also be
+//! partitioned and healed and new peers can join the session from an
+//! encoded snapshot. Failing schedules are shrunk to a minimal reproduction
+//! and printed as a script that can be replayed.
+//!
+//! # Examples
+//!
+//! ```
+//! use cola_testing::proptest::prelude::*;
+//! use cola_testing::{BufferPeer, Config, History};
+//!
+//! let config = Config { max_peers: 3, max_steps: 32, ..Config::default() };
+//!
+//! proptest!(|(history in any_with::<History>(config))| {
+//!     history.check::<BufferPeer>();
+//! });
+//! ```
+
+#![deny(missing_docs)]
+
+mod history;
+mod peer;
+mod simulator;
+
+pub use history::{Config, History, Step};
+pub use peer::{assert_invariants, BufferPeer, Edit, Peer};
+pub use proptest;
+pub use simulator::{Event, Failure, ParseScheduleError, Schedule, Simulator};
@@ -0,0 +1,233 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use cola::{Deletion, Insertion, Replica, ReplicaId, Text};
+
+/// A peer of a collaborative session, made of a [`Replica`] and whatever
+/// buffer and transport layer sit on top of it.
+///
+/// This is the trait downstream projects implement for their integration
+/// layer so that it can be driven by a [`History`](crate::History). Offsets
+/// are byte offsets into the peer's text, and the inserted text is always
+/// ASCII, so they're also valid offsets in the peer's `Replica`.
+pub trait Peer: Sized {
+    /// The message a peer sends to the other peers when it edits the
+    /// document.
+    type Message: Clone;
+
+    /// Creates the first peer of a session with the given id and initial
+    /// text.
+    fn new(id: ReplicaId, text: &str) -> Self;
+
+    /// Creates a new peer with the given id and the same state as this one.
+    fn fork(&self, id: ReplicaId) -> Self;
+
+    /// Creates a new peer with the given id which joins the session from an
+    /// encoded snapshot of this peer's state, as if it had received it over
+    /// the network.
+    ///
+    /// The default implementation just [`fork`](Peer::fork)s this peer,
+    /// skipping the encoding and decoding.
+    #[inline]
+    fn join(&self, id: ReplicaId) -> Self {
+        self.fork(id)
+    }
+
+    /// Inserts the text at the given offset, returning the message to send
+    /// to the other peers.
+    fn insert(&mut self, offset: usize, text: &str) -> Self::Message;
+
+    /// Deletes the given range, returning the message to send to the other
+    /// peers.
+    fn delete(&mut self, range: Range<usize>) -> Self::Message;
+
+    /// Receives a message sent by another peer.
+    ///
+    /// Messages can be received out of order and more than once, so this
+    /// should also merge any edit that was waiting for this one.
+    fn receive(&mut self, message: &Self::Message);
+
+    /// Returns the peer's `Replica`.
+    fn replica(&self) -> &Replica;
+
+    /// Returns the current text of the peer's document.
+    fn text(&self) -> String;
+}
+
+/// The [`Peer`] used to test cola itself, which keeps its text in a
+/// `String`.
+#[derive(Debug)]
+pub struct BufferPeer {
+    buffer: String,
+    replica: Replica,
+    backlogged: HashMap<Text, String>,
+}
+
+/// The message sent by a [`BufferPeer`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Edit {
+    /// An insertion, together with the inserted text.
+    Insertion(Insertion, String),
+
+    /// A deletion.
+    Deletion(Deletion),
+}
+
+impl BufferPeer {
+    /// Merges the backlogged edits that can now be merged.
+    #[inline]
+    fn merge_backlogged(&mut self) {
+        for (text, offset) in self.replica.backlogged_insertions() {
+            let inserted = &self.backlogged[&text];
+            self.buffer.insert_str(offset, inserted);
+        }
+
+        for ranges in self.replica.backlogged_deletions() {
+            for range in ranges.into_iter().rev() {
+                self.buffer.replace_range(range, "");
+            }
+        }
+    }
+}
+
+impl Peer for BufferPeer {
+    type Message = Edit;
+
+    #[inline]
+    fn new(id: ReplicaId, text: &str) -> Self {
+        Self {
+            buffer: text.to_owned(),
+            replica: Replica::new(id, text.len()),
+            backlogged: HashMap::new(),
+        }
+    }
+
+    #[inline]
+    fn fork(&self, id: ReplicaId) -> Self {
+        Self {
+            buffer: self.buffer.clone(),
+            replica: self.replica.fork(id),
+            backlogged: self.backlogged.clone(),
+        }
+    }
+
+    #[inline]
+    fn join(&self, id: ReplicaId) -> Self {
+        let document = self.replica.encode_document(&self.buffer);
+
+        let replica = Replica::decode_document(id, &document)
+            .unwrap_or_else(|err| panic!("peer {id} couldn't join: {err}"));
+
+        Self {
+            buffer: document.text().to_owned(),
+            replica,
+            // The texts of the backlogged insertions travel with the snapshot.
+            backlogged: self.backlogged.clone(),
+        }
+    }
+
+    #[inline]
+    fn insert(&mut self, offset: usize, text: &str) -> Edit {
+        self.buffer.insert_str(offset, text);
+        let insertion = self.replica.inserted(offset, text.len());
+        Edit::Insertion(insertion, text.to_owned())
+    }
+
+    #[inline]
+    fn delete(&mut self, range: Range<usize>) -> Edit {
+        self.buffer.replace_range(range.clone(), "");
+        Edit::Deletion(self.replica.deleted(range))
+    }
+
+    #[inline]
+    fn receive(&mut self, edit: &Edit) {
+        match edit {
+            Edit::Insertion(insertion, text) => {
+                if let Some(offset) =
+                    self.replica.integrate_insertion(insertion)
+                {
+                    self.buffer.insert_str(offset, text);
+                } else {
+                    // The insertion was either backlogged or already merged.
+                    self.backlogged
+                        .insert(insertion.text().clone(), text.clone());
+                }
+            },
+
+            Edit::Deletion(deletion) => {
+                let ranges = self.replica.integrate_deletion(deletion);
+                for range in ranges.into_iter().rev() {
+                    self.buffer.replace_range(range, "");
+                }
+            },
+        }
+
+        self.merge_backlogged();
+    }
+
+    #[inline]
+    fn replica(&self) -> &Replica {
+        &self.replica
+    }
+
+    #[inline]
+    fn text(&self) -> String {
+        self.buffer.clone()
+    }
+}
+
+/// Checks that the peer's `Replica` is internally consistent, that its
+/// length matches the one of the peer's text, and that it survives an
+/// encode-decode round-trip.
+///
+/// # Panics
+///
+/// Panics if any of those checks fails.
+#[track_caller]
+pub fn assert_invariants<P: Peer>(peer: &P) {
+    let replica = peer.replica();
+
+    let id = replica.id();
+
+    if let Err(err) = replica.check_integrity() {
+        panic!("replica {id} is corrupted: {err:?}");
+    }
+
+    let text = peer.text();
+
+    assert_eq!(
+        text.len(),
+        replica.len(),
+        "the text of peer {id} doesn't match the length of its replica"
+    );
+
+    let encoded = replica.encode();
+
+    let decoded = Replica::decode(id, &encoded)
+        .unwrap_or_else(|err| panic!("couldn't decode replica {id}: {err}"));
+
+    assert!(
+        replica.eq_decoded(&decoded),
+        "replica {id} changed after being encoded and decoded"
+    );
+
+    let resumed = Replica::resume(&encoded)
+        .unwrap_or_else(|err| panic!("couldn't resume replica {id}: {err}"));
+
+    assert!(
+        resumed.id() == id && replica.eq_decoded(&resumed),
+        "replica {id} changed after being encoded and resumed"
+    );
+
+    let document = replica.encode_document(&text);
+
+    let decoded =
+        Replica::decode_document(id, &document).unwrap_or_else(|err| {
+            panic!("couldn't decode the document of replica {id}: {err}")
+        });
+
+    assert!(
+        document.text() == text && replica.eq_decoded(&decoded),
+        "the document of replica {id} changed after being encoded and decoded"
+    );
+}
@@ -0,0 +1,926 @@
+use core::fmt;
+use core::ops::Range;
+use core::str::FromStr;
+use std::cell::Cell;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Once;
+
+use cola::ReplicaId;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use crate::{assert_invariants, Peer};
+
+/// A deterministic simulator of a collaborative session over an unreliable
+/// network.
+///
+/// Every seed generates a [`Schedule`] of edits and network events: messages
+/// are delivered in arbitrary order and possibly more than once, the network
+/// can be partitioned in two and healed, and new peers can join the session
+/// from an encoded snapshot of an existing peer. Replaying the schedule
+/// checks the [invariants](assert_invariants) of every peer after each event
+/// and that all of them converge to the same text once the network is healed
+/// and every message has been delivered.
+///
+/// When a schedule fails, it's shrunk to a minimal one that still fails,
+/// which is printed as a script that can be parsed back into a `Schedule` to
+/// replay it.
+///
+/// # Examples
+///
+/// ```
+/// use cola_testing::{BufferPeer, Simulator};
+///
+/// let simulator = Simulator { initial_peers: 4, ..Simulator::default() };
+///
+/// simulator.check::<BufferPeer>(0..8);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Simulator {
+    /// The number of peers at the start of the session, which has to be at
+    /// least 1.
+    pub initial_peers: usize,
+
+    /// The maximum number of peers, after which no more peers can join.
+    pub max_peers: usize,
+
+    /// The number of events in a schedule.
+    pub num_events: usize,
+
+    /// The maximum length of the initial text of the document.
+    pub max_initial_len: usize,
+
+    /// The maximum length of the text inserted by a single insertion.
+    pub max_insertion_len: usize,
+
+    /// The maximum length of the range deleted by a single deletion.
+    pub max_deletion_len: usize,
+}
+
+impl Default for Simulator {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            initial_peers: 3,
+            max_peers: 8,
+            num_events: 256,
+            max_initial_len: 16,
+            max_insertion_len: 8,
+            max_deletion_len: 8,
+        }
+    }
+}
+
+impl Simulator {
+    /// Runs the schedules generated from every seed in the range.
+    ///
+    /// # Panics
+    ///
+    /// Panics with the [`Failure`] of the first schedule that fails.
+    #[track_caller]
+    pub fn check<P: Peer>(&self, seeds: Range<u64>) {
+        for seed in seeds {
+            if let Err(failure) = self.run::<P>(seed) {
+                panic!("{failure}");
+            }
+        }
+    }
+
+    /// Runs the schedule generated from the given seed, shrinking it if it
+    /// fails.
+    #[inline]
+    pub fn run<P: Peer>(&self, seed: u64) -> Result<(), Failure> {
+        let schedule = self.schedule(seed);
+
+        let Err(message) = schedule.try_run::<P>() else {
+            return Ok(());
+        };
+
+        let (schedule, message) = schedule.shrink::<P>(message);
+
+        Err(Failure { seed, schedule, message })
+    }
+
+    /// Generates the schedule for the given seed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if [`initial_peers`](Self::initial_peers) is zero.
+    #[track_caller]
+    pub fn schedule(&self, seed: u64) -> Schedule {
+        assert!(self.initial_peers > 0, "a session needs at least 1 peer");
+
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+
+        let initial_len = rng.gen_range(0..=self.max_initial_len);
+
+        let initial_text = letters(&mut rng, initial_len);
+
+        let mut num_peers = self.initial_peers;
+
+        let mut events = Vec::with_capacity(self.num_events);
+
+        for _ in 0..self.num_events {
+            let peer = rng.gen_range(0..num_peers);
+
+            let event = match rng.gen_range(0..100) {
+                0..=29 => {
+                    let len = rng.gen_range(1..=self.max_insertion_len);
+                    let offset = rng.gen::<u16>() as usize;
+                    Event::Insert {
+                        peer,
+                        offset,
+                        text: letters(&mut rng, len),
+                    }
+                },
+
+                30..=49 => {
+                    let start = rng.gen::<u16>() as usize;
+                    let len = rng.gen_range(1..=self.max_deletion_len);
+                    Event::Delete { peer, start, len }
+                },
+
+                50..=84 => {
+                    Event::Deliver { peer, message: rng.gen::<u8>() as usize }
+                },
+
+                85..=89 => Event::Duplicate {
+                    peer,
+                    message: rng.gen::<u8>() as usize,
+                },
+
+                90..=93 => {
+                    let peers = (0..num_peers).filter(|_| rng.gen()).collect();
+                    Event::Partition { peers }
+                },
+
+                94..=96 => Event::Heal,
+
+                _ if num_peers < self.max_peers => {
+                    num_peers += 1;
+                    Event::Join { peer }
+                },
+
+                _ => Event::Deliver { peer, message: 0 },
+            };
+
+            events.push(event);
+        }
+
+        Schedule { initial_text, num_peers: self.initial_peers, events }
+    }
+}
+
+/// A sequence of events replayed by the [`Simulator`].
+///
+/// A `Schedule` is displayed as a script with one line for the number of
+/// peers, one for the initial text and one for each [`Event`], which can be
+/// parsed back via [`FromStr`]:
+///
+/// ```text
+/// peers 2
+/// text abc
+/// insert 0 1 xy
+/// partition 1
+/// delete 1 0 2
+/// heal
+/// deliver 1 0
+/// join 1
+/// ```
+///
+/// # Examples
+///
+/// ```
+/// use cola_testing::{BufferPeer, Schedule};
+///
+/// let script = "peers 2\ntext abc\ninsert 0 1 xy\ndeliver 1 0\n";
+///
+/// let schedule = script.parse::<Schedule>().unwrap();
+///
+/// assert_eq!(schedule.to_string(), script);
+///
+/// schedule.run::<BufferPeer>();
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Schedule {
+    /// The initial text of the document.
+    pub initial_text: String,
+
+    /// The number of peers at the start of the session.
+    pub num_peers: usize,
+
+    /// The events of the schedule.
+    pub events: Vec<Event>,
+}
+
+/// An event of a [`Schedule`].
+///
+/// Like the [`Step`](crate::Step)s of a [`History`](crate::History), the
+/// peers, offsets and messages are picked modulo the number of peers, the
+/// length of the text and the number of messages that can be delivered when
+/// the event is replayed, so that every event is valid no matter which ones
+/// come before it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// A peer inserts some text, and the resulting message is sent to all
+    /// the other peers.
+    Insert {
+        /// The peer making the insertion.
+        peer: usize,
+
+        /// The offset of the insertion.
+        offset: usize,
+
+        /// The inserted text, which has to be ASCII and can't contain any
+        /// whitespace.
+        text: String,
+    },
+
+    /// A peer deletes a range of its text, and the resulting message is sent
+    /// to all the other peers. This is skipped if the text is empty.
+    Delete {
+        /// The peer making the deletion.
+        peer: usize,
+
+        /// The start of the deleted range.
+        start: usize,
+
+        /// The length of the deleted range, which is truncated at the end of
+        /// the text.
+        len: usize,
+    },
+
+    /// A peer receives one of the messages sent to it by the peers it can
+    /// currently reach, which is then removed from the network. This is
+    /// skipped if there are no such messages.
+    Deliver {
+        /// The receiving peer.
+        peer: usize,
+
+        /// The message to deliver.
+        message: usize,
+    },
+
+    /// Same as [`Deliver`](Event::Deliver), except that the message is left
+    /// in the network, so it'll be delivered again.
+    Duplicate {
+        /// The receiving peer.
+        peer: usize,
+
+        /// The message to deliver.
+        message: usize,
+    },
+
+    /// The network is split in two, with the given peers on one side and the
+    /// rest on the other, replacing the previous partition if there was one.
+    /// Messages can't be delivered across the partition until it's healed.
+    Partition {
+        /// The peers on one side of the partition.
+        peers: Vec<usize>,
+    },
+
+    /// The partition is healed.
+    Heal,
+
+    /// A new peer joins the session from a snapshot of the given peer (see
+    /// [`Peer::join`]), and also receives the messages that were sent to it
+    /// but not yet delivered.
+    Join {
+        /// The peer whose snapshot is used to join.
+        peer: usize,
+    },
+}
+
+/// A [`Schedule`] that failed, shrunk to a minimal reproduction.
+///
+/// This is returned by [`Simulator::run`], and its `Display` impl prints the
+/// seed, the error message and the script of the shrunk schedule.
+#[derive(Debug, Clone)]
+pub struct Failure {
+    seed: u64,
+    schedule: Schedule,
+    message: String,
+}
+
+/// The error returned when parsing an invalid [`Schedule`] script.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseScheduleError {
+    line: usize,
+    reason: &'static str,
+}
+
+impl Schedule {
+    /// Replays the schedule.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any peer fails its [invariants](assert_invariants) after an
+    /// event, or if the peers don't converge at the end.
+    #[track_caller]
+    pub fn run<P: Peer>(&self) {
+        let mut network = Network::<P>::new(self);
+
+        for event in &self.events {
+            network.apply(event);
+        }
+
+        network.finish();
+    }
+
+    /// Same as [`run`](Self::run), except that the panic is caught and its
+    /// message is returned as an error.
+    #[inline]
+    pub fn try_run<P: Peer>(&self) -> Result<(), String> {
+        panic::catch_unwind(AssertUnwindSafe(|| self.run::<P>())).map_err(
+            |payload| {
+                payload
+                    .downcast_ref::<String>()
+                    .cloned()
+                    .or_else(|| {
+                        payload.downcast_ref::<&str>().map(|&s| s.to_owned())
+                    })
+                    .unwrap_or_default()
+            },
+        )
+    }
+
+    /// Shrinks a failing schedule to a minimal one that still fails,
+    /// returning it together with its error message.
+    ///
+    /// The panics of the intermediate schedules aren't printed (see
+    /// [`silence_panics`]).
+    fn shrink<P: Peer>(mut self, mut message: String) -> (Self, String) {
+        let _silenced = silence_panics();
+
+        let mut fails = |candidate: &Self| match candidate.try_run::<P>() {
+            Ok(()) => false,
+            Err(err) => {
+                message = err;
+                true
+            },
+        };
+
+        // Remove chunks of events of decreasing size.
+        let mut chunk_len = (self.events.len() / 2).max(1);
+
+        loop {
+            let mut start = 0;
+
+            while start < self.events.len() {
+                let end = self.events.len().min(start + chunk_len);
+                let mut candidate = self.clone();
+                candidate.events.drain(start..end);
+                if fails(&candidate) {
+                    self = candidate;
+                } else {
+                    start = end;
+                }
+            }
+
+            if chunk_len == 1 {
+                break;
+            }
+
+            chunk_len /= 2;
+        }
+
+        // Remove as many of the initial peers and characters as possible.
+        while self.num_peers > 1 {
+            let candidate =
+                Self { num_peers: self.num_peers - 1, ..self.clone() };
+            if !fails(&candidate) {
+                break;
+            }
+            self = candidate;
+        }
+
+        while !self.initial_text.is_empty() {
+            let mut candidate = self.clone();
+            candidate.initial_text.pop();
+            if !fails(&candidate) {
+                break;
+            }
+            self = candidate;
+        }
+
+        // Simplify the remaining events until none of them can be simplified
+        // any further.
+        let mut idx = 0;
+
+        while idx < self.events.len() {
+            let simpler = self.events[idx]
+                .simplifications()
+                .into_iter()
+                .find_map(|simpler| {
+                    let mut candidate = self.clone();
+                    candidate.events[idx] = simpler;
+                    fails(&candidate).then_some(candidate)
+                });
+
+            match simpler {
+                Some(candidate) => self = candidate,
+                None => idx += 1,
+            }
+        }
+
+        (self, message)
+    }
+}
+
+thread_local! {
+    /// Whether the panics on this thread should be kept quiet.
+    static IS_SILENCED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Keeps the panics on the current thread from being printed until the
+/// returned guard is dropped.
+///
+/// Swapping the panic hook while shrinking would also hide the panics of
+/// other threads, like the ones of the tests running in parallel with the
+/// simulation. Instead, the first call wraps the current hook in one that
+/// skips it for the panics of silenced threads, and leaves it in place.
+/// Hooks set after that call replace the wrapper.
+#[inline]
+fn silence_panics() -> SilencedPanics {
+    static WRAP_HOOK: Once = Once::new();
+
+    WRAP_HOOK.call_once(|| {
+        let hook = panic::take_hook();
+
+        panic::set_hook(Box::new(move |info| {
+            if !IS_SILENCED.with(Cell::get) {
+                hook(info);
+            }
+        }));
+    });
+
+    SilencedPanics { was_silenced: IS_SILENCED.with(|s| s.replace(true)) }
+}
+
+/// The guard returned by [`silence_panics`].
+struct SilencedPanics {
+    was_silenced: bool,
+}
+
+impl Drop for SilencedPanics {
+    #[inline]
+    fn drop(&mut self) {
+        IS_SILENCED.with(|s| s.set(self.was_silenced));
+    }
+}
+
+impl fmt::Display for Schedule {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "peers {}", self.num_peers)?;
+
+        if self.initial_text.is_empty() {
+            writeln!(f, "text")?;
+        } else {
+            writeln!(f, "text {}", self.initial_text)?;
+        }
+
+        for event in &self.events {
+            writeln!(f, "{event}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for Schedule {
+    type Err = ParseScheduleError;
+
+    #[inline]
+    fn from_str(script: &str) -> Result<Self, Self::Err> {
+        let mut lines = script
+            .lines()
+            .enumerate()
+            .map(|(idx, line)| (idx + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+
+        let (line, num_peers) = lines.next().ok_or(ParseScheduleError {
+            line: 1,
+            reason: "expected the number of peers",
+        })?;
+
+        let num_peers = num_peers
+            .strip_prefix("peers ")
+            .and_then(|n| n.trim().parse().ok())
+            .filter(|&n| n > 0)
+            .ok_or(ParseScheduleError {
+                line,
+                reason: "invalid number of peers",
+            })?;
+
+        let (line, initial_text) = lines.next().ok_or(ParseScheduleError {
+            line: line + 1,
+            reason: "expected the initial text",
+        })?;
+
+        let initial_text = match initial_text.split_once(' ') {
+            Some(("text", text)) => parse_text(text.trim(), line)?,
+            None if initial_text == "text" => String::new(),
+            _ => {
+                return Err(ParseScheduleError {
+                    line,
+                    reason: "invalid initial text",
+                })
+            },
+        };
+
+        let events = lines
+            .map(|(line, event)| parse_event(event, line))
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self { initial_text, num_peers, events })
+    }
+}
+
+impl Event {
+    /// Returns simpler versions of this event.
+    #[inline]
+    fn simplifications(&self) -> Vec<Self> {
+        let mut simpler = Vec::new();
+
+        // The peer is field 0, the offset or message field 1.
+        for field in 0..2 {
+            let mut event = self.clone();
+
+            let value = match (&mut event, field) {
+                (
+                    Self::Insert { peer, .. }
+                    | Self::Delete { peer, .. }
+                    | Self::Deliver { peer, .. }
+                    | Self::Duplicate { peer, .. }
+                    | Self::Join { peer },
+                    0,
+                ) => peer,
+                (Self::Insert { offset, .. }, 1) => offset,
+                (Self::Delete { start, .. }, 1) => start,
+                (
+                    Self::Deliver { message, .. }
+                    | Self::Duplicate { message, .. },
+                    1,
+                ) => message,
+                _ => continue,
+            };
+
+            if *value > 0 {
+                *value = 0;
+                simpler.push(event);
+            }
+        }
+
+        match self {
+            Self::Insert { peer, offset, text } if text.len() > 1 => {
+                let text = text[..1].to_owned();
+                simpler.push(Self::Insert {
+                    peer: *peer,
+                    offset: *offset,
+                    text,
+                });
+            },
+
+            Self::Delete { peer, start, len } if *len > 1 => {
+                simpler.push(Self::Delete {
+                    peer: *peer,
+                    start: *start,
+                    len: 1,
+                });
+            },
+
+            Self::Duplicate { peer, message } => {
+                simpler.push(Self::Deliver { peer: *peer, message: *message });
+            },
+
+            Self::Partition { peers } => {
+                simpler.extend((0..peers.len()).map(|idx| {
+                    let mut peers = peers.clone();
+                    peers.remove(idx);
+                    Self::Partition { peers }
+                }));
+            },
+
+            _ => {},
+        }
+
+        simpler
+    }
+}
+
+impl fmt::Display for Event {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Insert { peer, offset, text } => {
+                write!(f, "insert {peer} {offset} {text}")
+            },
+            Self::Delete { peer, start, len } => {
+                write!(f, "delete {peer} {start} {len}")
+            },
+            Self::Deliver { peer, message } => {
+                write!(f, "deliver {peer} {message}")
+            },
+            Self::Duplicate { peer, message } => {
+                write!(f, "duplicate {peer} {message}")
+            },
+            Self::Partition { peers } => {
+                write!(f, "partition")?;
+                for peer in peers {
+                    write!(f, " {peer}")?;
+                }
+                Ok(())
+            },
+            Self::Heal => write!(f, "heal"),
+            Self::Join { peer } => write!(f, "join {peer}"),
+        }
+    }
+}
+
+impl Failure {
+    /// Returns the message of the panic caused by the shrunk schedule.
+    #[inline]
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// Returns the shrunk schedule.
+    #[inline]
+    pub fn schedule(&self) -> &Schedule {
+        &self.schedule
+    }
+
+    /// Returns the seed of the schedule that failed.
+    #[inline]
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+}
+
+impl fmt::Display for Failure {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "the schedule with seed {} failed: {}",
+            self.seed, self.message
+        )?;
+        writeln!(f, "minimal reproduction:")?;
+        write!(f, "{}", self.schedule)
+    }
+}
+
+impl std::error::Error for Failure {}
+
+impl ParseScheduleError {
+    /// Returns the line of the script containing the error, starting from 1.
+    #[inline]
+    pub fn line(&self) -> usize {
+        self.line
+    }
+}
+
+impl fmt::Display for ParseScheduleError {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.reason)
+    }
+}
+
+impl std::error::Error for ParseScheduleError {}
+
+/// The state of a simulated session.
+struct Network<P: Peer> {
+    peers: Vec<P>,
+
+    /// The messages sent to each peer that haven't been delivered yet,
+    /// together with the index of their sender.
+    inboxes: Vec<Vec<(usize, P::Message)>>,
+
+    /// The peers on one side of the partition, if the network is
+    /// partitioned.
+    partition: Option<Vec<usize>>,
+}
+
+impl<P: Peer> Network<P> {
+    #[track_caller]
+    fn apply(&mut self, event: &Event) {
+        let num_peers = self.peers.len();
+
+        let idx = match event {
+            Event::Insert { peer, offset, text } => {
+                let idx = peer % num_peers;
+                let peer = &mut self.peers[idx];
+                let offset = offset % (peer.replica().len() + 1);
+                let message = peer.insert(offset, text);
+                self.broadcast(idx, message);
+                idx
+            },
+
+            Event::Delete { peer, start, len } => {
+                let idx = peer % num_peers;
+                let peer = &mut self.peers[idx];
+                let peer_len = peer.replica().len();
+                if peer_len == 0 {
+                    return;
+                }
+                let start = start % peer_len;
+                let end = peer_len.min(start + len);
+                let message = peer.delete(start..end);
+                self.broadcast(idx, message);
+                idx
+            },
+
+            Event::Deliver { peer, message } => {
+                let idx = peer % num_peers;
+                let Some(pos) = self.deliverable(idx, *message) else {
+                    return;
+                };
+                let (_, message) = self.inboxes[idx].remove(pos);
+                self.peers[idx].receive(&message);
+                idx
+            },
+
+            Event::Duplicate { peer, message } => {
+                let idx = peer % num_peers;
+                let Some(pos) = self.deliverable(idx, *message) else {
+                    return;
+                };
+                let message = self.inboxes[idx][pos].1.clone();
+                self.peers[idx].receive(&message);
+                idx
+            },
+
+            Event::Partition { peers } => {
+                let side = peers.iter().map(|peer| peer % num_peers).collect();
+                self.partition = Some(side);
+                return;
+            },
+
+            Event::Heal => {
+                self.partition = None;
+                return;
+            },
+
+            Event::Join { peer } => {
+                let idx = peer % num_peers;
+                let id = (num_peers + 1) as ReplicaId;
+                let joined = self.peers[idx].join(id);
+                let inbox = self.inboxes[idx].clone();
+                self.peers.push(joined);
+                self.inboxes.push(inbox);
+                num_peers
+            },
+        };
+
+        assert_invariants(&self.peers[idx]);
+    }
+
+    /// Sends the message to every peer except the sender.
+    #[inline]
+    fn broadcast(&mut self, sender: usize, message: P::Message) {
+        for (idx, inbox) in self.inboxes.iter_mut().enumerate() {
+            if idx != sender {
+                inbox.push((sender, message.clone()));
+            }
+        }
+    }
+
+    /// Returns whether messages can currently travel between the two peers.
+    #[inline]
+    fn can_reach(&self, from: usize, to: usize) -> bool {
+        self.partition
+            .as_ref()
+            .is_none_or(|side| side.contains(&from) == side.contains(&to))
+    }
+
+    /// Returns the position in the peer's inbox of the message with the
+    /// given index among the ones that can currently be delivered to it.
+    #[inline]
+    fn deliverable(&self, peer: usize, message: usize) -> Option<usize> {
+        let deliverable = self.inboxes[peer]
+            .iter()
+            .enumerate()
+            .filter(|(_, (sender, _))| self.can_reach(*sender, peer))
+            .map(|(pos, _)| pos)
+            .collect::<Vec<_>>();
+
+        (!deliverable.is_empty())
+            .then(|| deliverable[message % deliverable.len()])
+    }
+
+    /// Heals the network, delivers every message and checks that all the
+    /// peers have converged.
+    #[track_caller]
+    fn finish(mut self) {
+        self.partition = None;
+
+        for (peer, inbox) in self.peers.iter_mut().zip(&mut self.inboxes) {
+            for (_, message) in inbox.drain(..) {
+                peer.receive(&message);
+            }
+        }
+
+        let text = self.peers[0].text();
+
+        for peer in &self.peers {
+            assert_invariants(peer);
+
+            let id = peer.replica().id();
+
+            let stats = peer.replica().stats();
+
+            assert!(
+                stats.backlogged_insertions() == 0
+                    && stats.backlogged_deletions() == 0,
+                "peer {id} still has backlogged edits after receiving all \
+                 the messages"
+            );
+
+            assert_eq!(peer.text(), text, "peer {id} didn't converge");
+        }
+    }
+
+    #[inline]
+    fn new(schedule: &Schedule) -> Self {
+        assert!(schedule.num_peers > 0, "a session needs at least 1 peer");
+
+        let first = P::new(1, &schedule.initial_text);
+
+        let mut peers = (2..=schedule.num_peers as ReplicaId)
+            .map(|id| first.fork(id))
+            .collect::<Vec<_>>();
+
+        peers.insert(0, first);
+
+        let inboxes = (0..peers.len()).map(|_| Vec::new()).collect();
+
+        Self { peers, inboxes, partition: None }
+    }
+}
+
+/// Returns a random string of lowercase ASCII letters with the given length.
+#[inline]
+fn letters(rng: &mut impl Rng, len: usize) -> String {
+    (0..len).map(|_| char::from(rng.gen_range(b'a'..=b'z'))).collect()
+}
+
+#[inline]
+fn parse_event(event: &str, line: usize) -> Result<Event, ParseScheduleError> {
+    let err = |reason| ParseScheduleError { line, reason };
+
+    let mut words = event.split_whitespace();
+
+    let kind = words.next().ok_or(err("expected an event"))?;
+
+    let mut number = || {
+        words
+            .next()
+            .and_then(|word| word.parse::<usize>().ok())
+            .ok_or(err("expected a number"))
+    };
+
+    let event = match kind {
+        "insert" => {
+            let peer = number()?;
+            let offset = number()?;
+            let text =
+                words.next().ok_or(err("expected the inserted text"))?;
+            let text = parse_text(text, line)?;
+            Event::Insert { peer, offset, text }
+        },
+        "delete" => {
+            Event::Delete { peer: number()?, start: number()?, len: number()? }
+        },
+        "deliver" => Event::Deliver { peer: number()?, message: number()? },
+        "duplicate" => {
+            Event::Duplicate { peer: number()?, message: number()? }
+        },
+        "partition" => {
+            let peers = words
+                .by_ref()
+                .map(|word| word.parse().map_err(|_| err("expected a number")))
+                .collect::<Result<_, _>>()?;
+            Event::Partition { peers }
+        },
+        "heal" => Event::Heal,
+        "join" => Event::Join { peer: number()? },
+        _ => return Err(err("unknown event")),
+    };
+
+    if words.next().is_some() {
+        return Err(err("unexpected trailing input"));
+    }
+
+    Ok(event)
+}
+
+#[inline]
+fn parse_text(text: &str, line: usize) -> Result<String, ParseScheduleError> {
+    if !text.is_empty() && text.bytes().all(|byte| byte.is_ascii_graphic()) {
+        Ok(text.to_owned())
+    } else {
+        Err(ParseScheduleError { line, reason: "the text must be ASCII" })
+    }
+}
@@ -0,0 +1,52 @@
+use std::ops::Range;
+
+use cola::{Replica, ReplicaId};
+use cola_testing::{BufferPeer, Edit, Peer};
+
+/// A peer that forgets to apply the deletions it receives to its text.
+pub struct ForgetfulPeer(BufferPeer, String);
+
+impl Peer for ForgetfulPeer {
+    type Message = Edit;
+
+    fn new(id: ReplicaId, text: &str) -> Self {
+        Self(BufferPeer::new(id, text), text.to_owned())
+    }
+
+    fn fork(&self, id: ReplicaId) -> Self {
+        Self(self.0.fork(id), self.1.clone())
+    }
+
+    fn insert(&mut self, offset: usize, text: &str) -> Edit {
+        self.1.insert_str(offset, text);
+        self.0.insert(offset, text)
+    }
+
+    fn delete(&mut self, range: Range<usize>) -> Edit {
+        self.1.replace_range(range.clone(), "");
+        self.0.delete(range)
+    }
+
+    fn receive(&mut self, edit: &Edit) {
+        let before = self.0.text();
+        self.0.receive(edit);
+        if let Edit::Insertion(..) = edit {
+            let offset = before
+                .bytes()
+                .zip(self.0.text().bytes())
+                .take_while(|(a, b)| a == b)
+                .count();
+            let text = self.0.text();
+            let inserted = text.len() - before.len();
+            self.1.insert_str(offset, &text[offset..offset + inserted]);
+        }
+    }
+
+    fn replica(&self) -> &Replica {
+        self.0.replica()
+    }
+
+    fn text(&self) -> String {
+        self.1.clone()
+    }
+}
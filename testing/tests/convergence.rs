@@ -0,0 +1,40 @@
+mod common;
+
+use cola_testing::proptest::prelude::*;
+use cola_testing::{BufferPeer, Config, History, Step};
+use common::ForgetfulPeer;
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn convergence(history in any::<History>()) {
+        history.check::<BufferPeer>();
+    }
+
+    #[test]
+    fn convergence_many_peers(
+        history in any_with::<History>(Config {
+            max_peers: 8,
+            max_steps: 128,
+            ..Config::default()
+        })
+    ) {
+        history.check::<BufferPeer>();
+    }
+}
+
+#[test]
+#[should_panic = "doesn't match the length of its replica"]
+fn convergence_detects_lost_deletions() {
+    let history = History {
+        initial_text: "abc".to_owned(),
+        num_peers: 2,
+        steps: vec![
+            Step::Delete { peer: 0, start: 0, len: 1 },
+            Step::Deliver { peer: 1, message: 0 },
+        ],
+    };
+
+    history.check::<ForgetfulPeer>();
+}
@@ -0,0 +1,93 @@
+mod common;
+
+use cola_testing::{BufferPeer, Event, Schedule, Simulator};
+use common::ForgetfulPeer;
+
+#[test]
+fn simulator_converges() {
+    Simulator::default().check::<BufferPeer>(0..32);
+}
+
+#[test]
+fn simulator_converges_many_peers() {
+    let simulator = Simulator {
+        initial_peers: 6,
+        max_peers: 16,
+        num_events: 512,
+        ..Simulator::default()
+    };
+
+    simulator.check::<BufferPeer>(0..8);
+}
+
+#[test]
+fn simulator_is_deterministic() {
+    let simulator = Simulator::default();
+    assert_eq!(simulator.schedule(7), simulator.schedule(7));
+    assert_ne!(simulator.schedule(7), simulator.schedule(8));
+}
+
+#[test]
+fn simulator_shrinks_failures() {
+    let simulator = Simulator::default();
+
+    let failure = (0..32)
+        .find_map(|seed| simulator.run::<ForgetfulPeer>(seed).err())
+        .expect("a forgetful peer should fail");
+
+    assert!(failure.message().contains("doesn't match the length"));
+
+    // Deleting a character is enough, since the deletion is delivered to
+    // the other peer at the end of the session.
+    let schedule = failure.schedule();
+    assert_eq!(schedule.num_peers, 2);
+    assert_eq!(schedule.initial_text.len(), 1);
+    assert_eq!(schedule.events, [Event::Delete { peer: 0, start: 0, len: 1 }]);
+}
+
+#[test]
+fn simulator_script_roundtrip() {
+    let simulator = Simulator::default();
+
+    for seed in 0..8 {
+        let schedule = simulator.schedule(seed);
+        let script = schedule.to_string();
+        assert_eq!(script.parse::<Schedule>().unwrap(), schedule);
+    }
+}
+
+#[test]
+fn simulator_replays_failures() {
+    let failure = Simulator::default().run::<ForgetfulPeer>(0).unwrap_err();
+
+    let replayed = failure.to_string();
+
+    let script = replayed.split_once("minimal reproduction:\n").unwrap().1;
+
+    let schedule = script.parse::<Schedule>().unwrap();
+
+    assert_eq!(&schedule, failure.schedule());
+
+    assert_eq!(
+        schedule.try_run::<ForgetfulPeer>().unwrap_err(),
+        failure.message()
+    );
+
+    schedule.run::<BufferPeer>();
+}
+
+#[test]
+fn simulator_script_errors() {
+    let err =
+        "peers 2\ntext ab\nheal\nfly 1\n".parse::<Schedule>().unwrap_err();
+    assert_eq!(err.line(), 4);
+
+    let err = "peers 0\ntext\n".parse::<Schedule>().unwrap_err();
+    assert_eq!(err.line(), 1);
+
+    let err = "peers 2\ntext a b\n".parse::<Schedule>().unwrap_err();
+    assert_eq!(err.line(), 2);
+
+    let err = "peers 2\ntext\ndeliver 1\n".parse::<Schedule>().unwrap_err();
+    assert_eq!(err.line(), 3);
+}
@@ -0,0 +1,134 @@
+#[cfg(feature = "encode")]
+mod checksum {
+    use cola::{ChecksumAlgorithm, Replica};
+
+    fn algorithms() -> Vec<ChecksumAlgorithm> {
+        vec![
+            ChecksumAlgorithm::None,
+            #[cfg(feature = "crc32c")]
+            ChecksumAlgorithm::Crc32c,
+            #[cfg(feature = "xxhash")]
+            ChecksumAlgorithm::XxHash,
+            #[cfg(feature = "sha256")]
+            ChecksumAlgorithm::Sha256,
+        ]
+    }
+
+    fn replica() -> Replica {
+        let mut replica1 = Replica::new(1, 10);
+        let mut replica2 = replica1.fork(2);
+
+        let _ = replica1.inserted(5, 3);
+        let _ = replica1.deleted(0..2);
+
+        let insertion = replica2.inserted(0, 4);
+
+        let _ = replica1.integrate_insertion(&insertion);
+
+        replica1
+    }
+
+    #[cfg(feature = "sha256")]
+    #[test]
+    fn checksum_default() {
+        assert_eq!(ChecksumAlgorithm::default(), ChecksumAlgorithm::Sha256);
+
+        let replica = replica();
+
+        assert!(
+            replica.encode()
+                == replica.encode_with_checksum(ChecksumAlgorithm::Sha256)
+        );
+    }
+
+    #[test]
+    fn checksum_roundtrip() {
+        let replica = replica();
+
+        for algorithm in algorithms() {
+            let encoded = replica.encode_with_checksum(algorithm);
+
+            let decoded = Replica::decode(3, &encoded).unwrap();
+
+            assert!(replica.eq_decoded(&decoded));
+        }
+    }
+
+    /// Tests that corrupt `EncodedReplica`s by serializing them with
+    /// `bincode`.
+    #[cfg(feature = "serde")]
+    mod corrupted {
+        use cola::{DecodeError, EncodedReplica};
+
+        use super::*;
+
+        /// The offset of the first byte of the checksum in a serialized
+        /// `EncodedReplica`, after the protocol version and the length of the
+        /// checksum.
+        const CHECKSUM_OFFSET: usize = 16;
+
+        fn corrupt(
+            encoded: &EncodedReplica,
+            f: impl FnOnce(&mut [u8]),
+        ) -> EncodedReplica {
+            let mut bytes = bincode::serialize(encoded).unwrap();
+            f(&mut bytes);
+            bincode::deserialize(&bytes).unwrap()
+        }
+
+        fn flip_last_byte(bytes: &mut [u8]) {
+            let last = bytes.len() - 1;
+            bytes[last] ^= 0xff;
+        }
+
+        #[test]
+        fn checksum_failed() {
+            let replica = replica();
+
+            for algorithm in algorithms() {
+                if algorithm == ChecksumAlgorithm::None {
+                    continue;
+                }
+
+                let encoded = corrupt(
+                    &replica.encode_with_checksum(algorithm),
+                    flip_last_byte,
+                );
+
+                assert_eq!(
+                    Replica::decode(2, &encoded).err(),
+                    Some(DecodeError::ChecksumFailed)
+                );
+            }
+        }
+
+        #[test]
+        fn checksum_none() {
+            let replica = replica();
+
+            let encoded = corrupt(
+                &replica.encode_with_checksum(ChecksumAlgorithm::None),
+                flip_last_byte,
+            );
+
+            // Without a checksum the corruption can't be told apart from
+            // invalid data.
+            assert_eq!(
+                Replica::decode(2, &encoded).err(),
+                Some(DecodeError::InvalidData)
+            );
+        }
+
+        #[test]
+        fn checksum_unsupported() {
+            let encoded = corrupt(&replica().encode(), |bytes| {
+                bytes[CHECKSUM_OFFSET] = 0xff;
+            });
+
+            assert_eq!(
+                Replica::decode(2, &encoded).err(),
+                Some(DecodeError::UnsupportedChecksum)
+            );
+        }
+    }
+}
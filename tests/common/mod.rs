@@ -46,7 +46,7 @@ pub enum Edit {
 
 impl Replica {
     pub fn assert_invariants(&self) {
-        self.crdt.assert_invariants();
+        self.crdt.check_integrity().unwrap();
         assert_eq!(self.buffer.len(), self.crdt.len());
     }
 
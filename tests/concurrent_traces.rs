@@ -1,9 +1,9 @@
 mod common;
 
 use common::Replica;
-use traces::{ConcurrentTraceInfos, Crdt, Edit};
+use traces::{ConcurrentTraceInfos, Crdt, Edit, ImportError};
 
-fn test_trace<const N: usize>(trace: ConcurrentTraceInfos<N, Replica>) {
+fn test_trace(trace: ConcurrentTraceInfos<Replica>) {
     let ConcurrentTraceInfos { trace, mut peers, final_content, .. } = trace;
 
     for edit in trace.edits() {
@@ -36,3 +36,170 @@ fn test_trace<const N: usize>(trace: ConcurrentTraceInfos<N, Replica>) {
 fn test_friends_forever() {
     test_trace(traces::friends_forever());
 }
+
+/// Client 1 inserts "abc" in the root text "text", client 2 inserts "X"
+/// between "b" and "c", and concurrently client 1 appends "Y" and deletes
+/// "a".
+const YJS_CLIENT_1: &[u8] = &[
+    1, 2, 1, 0, 1, 1, 4, 116, 101, 120, 116, 1, 132, 1, 0, 3, 98, 99, 89, 1,
+    1, 1, 0, 1,
+];
+
+const YJS_CLIENT_2: &[u8] = &[1, 1, 2, 0, 196, 1, 1, 1, 2, 1, 88, 0];
+
+/// The state of the document after merging the two updates above, in which
+/// the insertion of client 2 split the second item of client 1.
+const YJS_STATE: &[u8] = &[
+    2, 1, 2, 0, 196, 1, 1, 1, 2, 1, 88, 3, 1, 0, 1, 1, 4, 116, 101, 120, 116,
+    1, 132, 1, 0, 1, 98, 132, 1, 1, 2, 99, 89, 1, 1, 1, 0, 1,
+];
+
+#[test]
+fn import_yjs_simple() {
+    let update = [1, 1, 1, 0, 4, 1, 4, 116, 101, 120, 116, 3, 97, 98, 99, 0];
+
+    let trace = traces::import_yjs([update], "text").unwrap();
+
+    assert_eq!(trace.final_content, "abc");
+
+    test_trace(trace);
+}
+
+#[test]
+fn import_yjs_concurrent() {
+    let trace =
+        traces::import_yjs([YJS_CLIENT_1, YJS_CLIENT_2], "text").unwrap();
+
+    assert_eq!(trace.final_content, "bXcY");
+    assert_eq!(trace.peers.len(), 2);
+
+    test_trace(trace);
+}
+
+/// Tests that the clocks contained in more than one update are only
+/// imported once.
+#[test]
+fn import_yjs_overlapping_updates() {
+    for updates in [
+        [YJS_CLIENT_1, YJS_CLIENT_2, YJS_STATE],
+        [YJS_STATE, YJS_CLIENT_2, YJS_CLIENT_1],
+        [YJS_CLIENT_2, YJS_STATE, YJS_STATE],
+    ] {
+        let trace = traces::import_yjs(updates, "text").unwrap();
+        assert_eq!(trace.final_content, "bXcY");
+        test_trace(trace);
+    }
+}
+
+/// Tests that concurrent insertions at the same position are ordered by
+/// client id like Yjs does.
+#[test]
+fn import_yjs_conflict() {
+    let update = [
+        2, 1, 2, 0, 4, 1, 4, 116, 101, 120, 116, 1, 98, 1, 1, 0, 4, 1, 4, 116,
+        101, 120, 116, 1, 97, 0,
+    ];
+
+    let trace = traces::import_yjs::<Replica, _>([update], "text").unwrap();
+
+    assert_eq!(trace.final_content, "ab");
+}
+
+/// Tests that text whose content was discarded by Yjs is inserted as
+/// placeholders and then deleted.
+#[test]
+fn import_yjs_discarded_content() {
+    let update = [
+        1, 2, 1, 0, 1, 1, 4, 116, 101, 120, 116, 2, 132, 1, 1, 1, 120, 1, 1,
+        1, 0, 2,
+    ];
+
+    let trace = traces::import_yjs([update], "text").unwrap();
+
+    assert_eq!(trace.final_content, "x");
+
+    test_trace(trace);
+}
+
+#[test]
+fn import_yjs_malformed() {
+    let Err(err) =
+        traces::import_yjs::<Replica, _>([&YJS_CLIENT_1[..10]], "text")
+    else {
+        panic!("imported a truncated update");
+    };
+
+    assert!(matches!(err, ImportError::Invalid(_)));
+
+    let Err(err) = traces::import_yjs::<Replica, _>([YJS_CLIENT_1], "notes")
+    else {
+        panic!("imported a missing text");
+    };
+
+    assert!(matches!(err, ImportError::MissingText(_)));
+}
+
+/// Alice inserts "abc", Bob inserts "X" between "b" and "c", and
+/// concurrently Alice appends "Y" and deletes "a". Then Bob merges the two
+/// branches and backspaces over "Xc".
+const DT_CONCURRENT: &[u8] = &[
+    68, 77, 78, 68, 84, 89, 80, 83, 0, 1, 12, 3, 10, 5, 97, 108, 105, 99, 101,
+    3, 98, 111, 98, 10, 3, 12, 1, 0, 20, 45, 24, 12, 0, 13, 6, 4, 97, 98, 99,
+    88, 89, 25, 1, 11, 21, 8, 0, 3, 2, 1, 0, 2, 2, 2, 22, 8, 12, 1, 3, 0, 3,
+    9, 19, 6, 23, 9, 3, 3, 1, 5, 2, 9, 2, 12, 5,
+];
+
+/// Seph inserts "hello hello wonderful world" and deletes the first
+/// "hello ", with the inserted content compressed with LZ4.
+const DT_COMPRESSED: &[u8] = &[
+    68, 77, 78, 68, 84, 89, 80, 83, 0, 1, 7, 3, 5, 4, 115, 101, 112, 104, 5,
+    28, 27, 97, 104, 101, 108, 108, 111, 32, 6, 0, 240, 1, 32, 119, 111, 110,
+    100, 101, 114, 102, 117, 108, 32, 119, 111, 114, 108, 100, 10, 6, 12, 0,
+    14, 2, 4, 0, 20, 20, 24, 5, 0, 14, 2, 4, 27, 21, 2, 0, 33, 22, 3, 108, 55,
+    55, 23, 2, 33, 3,
+];
+
+#[test]
+fn import_diamond_types_concurrent() {
+    let trace = traces::import_diamond_types(DT_CONCURRENT).unwrap();
+
+    assert_eq!(trace.final_content, "bY");
+    assert_eq!(trace.peers.len(), 2);
+
+    test_trace(trace);
+}
+
+#[test]
+fn import_diamond_types_compressed() {
+    let trace = traces::import_diamond_types(DT_COMPRESSED).unwrap();
+
+    assert_eq!(trace.final_content, "hello wonderful world");
+
+    test_trace(trace);
+}
+
+/// Tests that concurrent insertions at the same position are ordered by
+/// agent name like diamond-types does, not by their order in the file.
+#[test]
+fn import_diamond_types_conflict() {
+    let bytes = [
+        68, 77, 78, 68, 84, 89, 80, 83, 0, 1, 12, 3, 10, 5, 97, 108, 105, 99,
+        101, 3, 98, 111, 98, 20, 25, 24, 6, 0, 13, 3, 4, 98, 97, 21, 4, 2, 1,
+        0, 1, 22, 3, 0, 1, 3, 23, 4, 1, 3, 1, 3,
+    ];
+
+    let trace = traces::import_diamond_types::<Replica>(&bytes).unwrap();
+
+    assert_eq!(trace.final_content, "ab");
+}
+
+#[test]
+fn import_diamond_types_malformed() {
+    for bytes in [&DT_CONCURRENT[..40], &DT_CONCURRENT[1..], YJS_CLIENT_1] {
+        let Err(err) = traces::import_diamond_types::<Replica>(bytes) else {
+            panic!("imported a malformed file");
+        };
+
+        assert!(matches!(err, ImportError::Invalid(_)));
+    }
+}
@@ -17,8 +17,11 @@ fn join_consecutive_deletions() {
     replica2.merge(&del_c);
     replica2.merge(&del_b);
 
-    assert_eq!(replica1.crdt.num_runs(), 2);
-    assert_eq!(replica2.crdt.num_runs(), 2);
+    for replica in [&replica1, &replica2] {
+        let stats = replica.crdt.stats();
+        assert_eq!(stats.visible_runs(), 1);
+        assert_eq!(stats.tombstoned_runs(), 1);
+    }
 }
 
 #[test]
@@ -41,6 +44,116 @@ fn deletion_start_continued() {
     assert_convergence!(replica1, replica2, "fkkk");
 }
 
+#[test]
+fn deletion_after_tombstones_of_other_peer() {
+    let mut replica1 = Replica::new(1, "abc");
+    let mut replica2 = replica1.fork(2);
+    let mut replica3 = replica1.fork(3);
+
+    let ins_xy = replica2.insert(0, "xy");
+    let del_xy = replica2.delete(0..2);
+
+    replica1.merge(&ins_xy);
+    replica1.merge(&del_xy);
+
+    // The deleted range starts right after replica 2's tombstones.
+    let del_a = replica1.delete(0..1);
+
+    replica3.merge(&ins_xy);
+    replica3.merge(&del_a);
+    replica3.merge(&del_xy);
+
+    replica2.merge(&del_a);
+
+    assert_convergence!(replica1, replica2, replica3, "bc");
+}
+
+#[test]
+fn deletion_over_whole_runs_with_stale_cursor() {
+    let mut replica1 = Replica::new(1, "");
+    let mut replica2 = replica1.fork(2);
+
+    let ins_aaa = replica1.insert(0, "aaa");
+    replica2.merge(&ins_aaa);
+
+    let ins_bbb = replica2.insert(3, "bbb");
+    replica1.merge(&ins_bbb);
+
+    let ins_ccc = replica1.insert(6, "ccc");
+    replica2.merge(&ins_ccc);
+
+    // The start of this deletion is concurrently deleted by replica 1.
+    let del_abbbcc = replica2.delete(2..8);
+
+    let del_a = replica1.delete(2..3);
+
+    // This extends the "ccc" run, parking the cursor of replica 1's run tree
+    // on it.
+    let ins_dd = replica1.insert(8, "dd");
+
+    replica1.merge(&del_abbbcc);
+
+    replica2.merge(&del_a);
+    replica2.merge(&ins_dd);
+
+    assert_convergence!(replica1, replica2, "aacdd");
+}
+
+#[test]
+fn deletion_backlogged_twice() {
+    let mut replica1 = Replica::new(1, "abc");
+    let mut replica2 = replica1.fork(2);
+
+    let ins_d = replica2.insert(3, "d");
+    let del_cd = replica2.delete(2..4);
+
+    // The deletion is delivered twice before the insertion it depends on.
+    replica1.merge(&del_cd);
+    replica1.merge(&del_cd);
+
+    assert_eq!(replica1.crdt.stats().backlogged_deletions(), 1);
+
+    replica1.merge(&ins_d);
+    replica1.merge_backlogged();
+
+    replica1.assert_invariants();
+
+    assert_convergence!(replica1, replica2, "ab");
+}
+
+#[test]
+fn deletion_with_many_peers() {
+    let mut replica1 = Replica::new(1, "");
+    let mut replica2 = replica1.fork(2);
+
+    let mut peers = (3..50).map(|id| replica1.fork(id)).collect::<Vec<_>>();
+
+    // Every peer inserts some text that is not going to be deleted.
+    let insertions =
+        peers.iter_mut().map(|peer| peer.insert(0, "--")).collect::<Vec<_>>();
+
+    for insertion in &insertions {
+        replica1.merge(insertion);
+    }
+
+    let ins_abc = replica1.insert(0, "abc");
+    let del_b = replica1.delete(1..2);
+
+    replica2.merge(&ins_abc);
+    replica2.merge(&del_b);
+
+    // The deletion doesn't depend on the text of the other peers, so it can
+    // be merged before receiving it.
+    assert_eq!(replica2.crdt.stats().backlogged_deletions(), 0);
+    assert_eq!(replica2, "ac");
+
+    for insertion in &insertions {
+        replica2.merge(insertion);
+    }
+
+    assert_convergence!(replica1, replica2);
+}
+
 #[test]
 fn random_deletions() {
     let seed = rand::random::<u64>();
@@ -0,0 +1,366 @@
+mod common;
+
+use cola::ReplicaIdAllocator;
+use common::Replica;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+/// Checks that a peer reusing the id of a departed one can keep editing, even
+/// with peers that haven't received all of the departed peer's edits yet.
+#[test]
+fn departure_reuse_id() {
+    let mut replica1 = Replica::new(1, "abc");
+    let mut replica2 = replica1.fork(2);
+    let mut replica4 = replica1.fork(4);
+
+    let ins_hello = replica2.insert(3, "hello");
+    let del_bc = replica2.delete(1..3);
+    let ins_world = replica2.insert(6, " world");
+
+    replica1.merge(&ins_hello);
+    replica1.merge(&del_bc);
+    replica1.merge(&ins_world);
+
+    // Replica 4 is still missing the last insertion of replica 2.
+    replica4.merge(&ins_hello);
+    replica4.merge(&del_bc);
+
+    let departure = replica2.crdt.depart();
+
+    assert!(!replica4.crdt.integrate_departure(&departure));
+    assert!(replica1.crdt.integrate_departure(&departure));
+
+    let mut replica3 = replica1.fork(departure.replica_id());
+
+    assert_eq!(replica3, "ahello world");
+
+    let ins_excl = replica3.insert(12, "!");
+    let del_a = replica3.delete(0..1);
+
+    replica1.merge(&ins_excl);
+    replica1.merge(&del_a);
+
+    assert_eq!(replica1, "hello world!");
+
+    replica4.merge(&ins_excl);
+    replica4.merge(&del_a);
+    replica4.merge(&ins_world);
+    replica4.merge_backlogged();
+
+    assert_eq!(replica4, "hello world!");
+
+    assert!(replica4.crdt.integrate_departure(&departure));
+
+    for replica in [&replica1, &replica3, &replica4] {
+        replica.assert_invariants();
+    }
+
+    // Replica 3 didn't add a new entry to the metadata.
+    assert_eq!(replica1.crdt.stats().known_replicas(), 2);
+    assert_eq!(replica4.crdt.stats().known_replicas(), 3);
+}
+
+#[test]
+fn departure_waits_for_backlogged_deletions() {
+    let mut replica1 = Replica::new(1, "abc");
+    let mut replica2 = replica1.fork(2);
+    let mut replica3 = replica1.fork(3);
+
+    let ins_d = replica3.insert(3, "d");
+    let del_cd = {
+        replica2.merge(&ins_d);
+        replica2.delete(2..4)
+    };
+
+    let departure = replica2.crdt.depart();
+
+    // The deletion is backlogged until replica 1 receives the insertion it
+    // depends on.
+    replica1.merge(&del_cd);
+
+    assert!(!replica1.crdt.integrate_departure(&departure));
+
+    replica1.merge(&ins_d);
+    replica1.merge_backlogged();
+
+    assert_eq!(replica1, "ab");
+
+    assert!(replica1.crdt.integrate_departure(&departure));
+}
+
+/// Peers keep joining and leaving the session while editing, and the ids of
+/// the ones that leave are handed out again.
+#[test]
+fn departure_churn() {
+    for seed in 0..10 {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+
+        let mut ids = ReplicaIdAllocator::new();
+
+        let mut server = Replica::new_with_len(ids.allocate(), 10, &mut rng);
+
+        let mut peers = (0..3)
+            .map(|_| server.fork(ids.allocate()))
+            .collect::<Vec<Replica>>();
+
+        for _ in 0..50 {
+            let mut edits = Vec::new();
+
+            for peer in &mut peers {
+                for _ in 0..rng.gen_range(0..3) {
+                    let edit = peer.random_edit(&mut rng, 5, 3);
+                    edits.push(peer.edit(edit));
+                }
+            }
+
+            for edit in &edits {
+                server.merge(edit);
+            }
+
+            // A peer leaves and a new one joins with its id.
+            let leaving = rng.gen_range(0..peers.len());
+
+            let departed = peers.swap_remove(leaving).crdt.depart();
+
+            assert!(server.crdt.integrate_departure(&departed));
+
+            ids.retire(departed.replica_id());
+
+            peers.push(server.fork(ids.allocate()));
+
+            // Every other peer gets the edits in a random order.
+            edits.shuffle(&mut rng);
+
+            for peer in &mut peers {
+                for edit in &edits {
+                    peer.merge(edit);
+                }
+                peer.merge_backlogged();
+            }
+        }
+
+        for peer in &peers {
+            assert_eq!(*peer, server, "seed: {seed}");
+            peer.assert_invariants();
+        }
+
+        assert_eq!(server.crdt.stats().known_replicas(), 4);
+
+        assert_eq!(ids.next_id(), 5);
+    }
+}
+
+#[test]
+fn departure_allocator_reuses_smallest_id() {
+    let mut ids = ReplicaIdAllocator::new();
+
+    for _ in 0..5 {
+        let _ = ids.allocate();
+    }
+
+    ids.retire(4);
+    ids.retire(2);
+
+    assert_eq!(ids.allocate(), 2);
+    assert_eq!(ids.allocate(), 4);
+    assert_eq!(ids.allocate(), 6);
+}
+
+#[test]
+#[should_panic]
+fn departure_allocator_retire_unknown_id() {
+    let mut ids = ReplicaIdAllocator::new();
+    let _ = ids.allocate();
+    ids.retire(2);
+}
+
+#[cfg(feature = "encode")]
+#[test]
+fn departure_encode_decode() {
+    let mut replica1 = cola::Replica::new(1, 3);
+    let mut replica2 = replica1.fork(2);
+
+    let insertion = replica2.inserted(1, 2);
+    let deletion = replica2.deleted(0..2);
+
+    let departure = replica2.depart();
+
+    let decoded = cola::Departure::decode(&departure.encode()).unwrap();
+
+    assert_eq!(decoded, departure);
+
+    let _ = replica1.integrate_insertion(&insertion);
+
+    assert!(!replica1.integrate_departure(&decoded));
+
+    let _ = replica1.integrate_deletion(&deletion);
+
+    assert!(replica1.integrate_departure(&decoded));
+
+    // A new peer decoding the document with the departed peer's id continues
+    // from where it left off.
+    let mut replica3 =
+        cola::Replica::decode(decoded.replica_id(), &replica1.encode())
+            .unwrap();
+
+    let insertion = replica3.inserted(0, 1);
+    let deletion = replica3.deleted(2..3);
+
+    assert_eq!(replica1.try_integrate_insertion(&insertion), Ok(Some(0)));
+    assert_eq!(replica1.integrate_deletion(&deletion), vec![2..3]);
+}
+
+/// Forking with an id that's never been seen starts from scratch, while
+/// forking with a known id continues from the text inserted with it.
+#[test]
+fn departure_fork_continues_from_known_id() {
+    let mut replica1 = cola::Replica::new(1, 3);
+    let mut replica2 = replica1.fork(2);
+
+    let insertion = replica2.inserted(3, 4);
+
+    let _ = replica1.integrate_insertion(&insertion);
+
+    let mut fresh = replica1.fork(3);
+    assert_eq!(fresh.inserted(0, 2).text().temporal_range(), 0..2);
+
+    let mut reused = replica1.fork(2);
+    assert_eq!(reused.inserted(0, 2).text().temporal_range(), 4..6);
+
+    // The replica being forked continues from its own edits.
+    let mut same = replica1.fork(1);
+    assert_eq!(same.inserted(0, 2).text().temporal_range(), 3..5);
+}
+
+#[cfg(feature = "encode")]
+#[test]
+fn departure_decode_continues_from_known_id() {
+    let mut replica1 = cola::Replica::new(1, 3);
+    let mut replica2 = replica1.fork(2);
+
+    let insertion = replica2.inserted(3, 4);
+
+    let _ = replica1.integrate_insertion(&insertion);
+
+    let encoded = replica1.encode();
+
+    let mut fresh = cola::Replica::decode(3, &encoded).unwrap();
+    assert_eq!(fresh.inserted(0, 2).text().temporal_range(), 0..2);
+
+    let mut reused = cola::Replica::decode(2, &encoded).unwrap();
+    assert_eq!(reused.inserted(0, 2).text().temporal_range(), 4..6);
+}
+
+/// Checks that integrating the departures of many peers shrinks the metadata
+/// kept for them.
+#[test]
+fn departure_compacts_metadata() {
+    let mut rng = ChaCha8Rng::seed_from_u64(0);
+
+    let mut server = Replica::new_with_len(1, 10, &mut rng);
+
+    let mut peers = (2..66).map(|id| server.fork(id)).collect::<Vec<_>>();
+
+    for _ in 0..5 {
+        for peer in &mut peers {
+            let (offset, text) = peer.random_insert(&mut rng, 5);
+            let insertion = peer.insert(offset, text);
+            server.merge(&insertion);
+        }
+    }
+
+    let before = server.crdt.memory_usage();
+
+    for peer in peers {
+        assert!(server.crdt.integrate_departure(&peer.crdt.depart()));
+    }
+
+    let after = server.crdt.memory_usage();
+
+    assert!(after.run_indices() < before.run_indices());
+    assert!(after.version_map() < before.version_map());
+
+    server.assert_invariants();
+
+    assert_eq!(server.crdt.stats().known_replicas(), 65);
+}
+
+/// Checks that the text inserted by a departed peer can still be edited by
+/// other peers once its metadata has been compacted, and that a peer reusing
+/// its id builds on it.
+#[test]
+fn departure_compacted_text_stays_resolvable() {
+    let mut replica1 = Replica::new(1, "");
+    let mut replica2 = replica1.fork(2);
+    let mut replica3 = replica1.fork(3);
+
+    let ins_a = replica2.insert(0, "abcdefghijklmnopqrstuvwxyz");
+    let del_c = replica2.delete(2..3);
+
+    replica1.merge(&ins_a);
+    replica1.merge(&del_c);
+    replica3.merge(&ins_a);
+    replica3.merge(&del_c);
+
+    assert!(replica1.crdt.integrate_departure(&replica2.crdt.depart()));
+
+    // Split the departed peer's text into more fragments than the ones
+    // stored inline, and delete across some of them.
+    for offset in (1..20).step_by(2) {
+        let insertion = replica3.insert(offset, "_");
+        replica1.merge(&insertion);
+    }
+
+    let deletion = replica3.delete(4..9);
+    replica1.merge(&deletion);
+
+    assert_eq!(replica1, replica3);
+    replica1.assert_invariants();
+
+    let mut replica4 = replica1.fork(2);
+
+    let insertion = replica4.insert(3, "0");
+    let deletion = replica4.delete(10..12);
+
+    replica1.merge(&insertion);
+    replica1.merge(&deletion);
+    replica3.merge(&insertion);
+    replica3.merge(&deletion);
+
+    assert_eq!(replica1, replica4);
+    assert_eq!(replica3, replica4);
+
+    for replica in [&replica1, &replica3, &replica4] {
+        replica.assert_invariants();
+    }
+
+    assert_eq!(replica1.crdt.stats().known_replicas(), 3);
+}
+
+/// Compacting the metadata of a departed peer doesn't change how the
+/// `Replica` is encoded.
+#[cfg(feature = "encode")]
+#[test]
+fn departure_compacted_encode_decode() {
+    let mut replica1 = Replica::new(1, "abc");
+    let mut replica2 = replica1.fork(2);
+
+    let insertion = replica2.insert(1, "hello");
+    let deletion = replica2.delete(0..2);
+
+    replica1.merge(&insertion);
+    replica1.merge(&deletion);
+
+    let _ = replica1.insert(4, "world");
+
+    let encoded = replica1.crdt.encode();
+
+    assert!(replica1.crdt.integrate_departure(&replica2.crdt.depart()));
+
+    assert!(replica1.crdt.encode() == encoded);
+
+    let decoded = cola::Replica::decode(3, &encoded).unwrap();
+
+    assert!(replica1.crdt.eq_decoded(&decoded));
+}
@@ -0,0 +1,164 @@
+#[cfg(feature = "encode")]
+mod common;
+
+#[cfg(feature = "encode")]
+mod document {
+    use cola::{Replica, Text};
+
+    use super::common::{self, Replica as TestReplica};
+
+    #[test]
+    fn document_roundtrip() {
+        let mut replica1 = TestReplica::new(1, "Hello world");
+        let mut replica2 = replica1.fork(2);
+
+        let edit1 = replica1.insert(5, ",");
+        let edit2 = replica2.insert(11, "!");
+        let edit3 = replica1.delete(0..1);
+
+        replica1.merge(&edit2);
+        replica2.merge(&edit1);
+        replica2.merge(&edit3);
+
+        let encoded = replica1.crdt.encode_document(&replica1.buffer);
+
+        assert_eq!(encoded.text(), "ello, world!");
+
+        assert!(encoded.deleted_text().is_none());
+
+        let mut decoded = Replica::decode_document(3, &encoded).unwrap();
+
+        assert!(replica1.crdt.eq_decoded(&decoded));
+
+        // The decoded replica can keep exchanging edits with the others.
+        let mut buffer = encoded.text().to_owned();
+
+        buffer.insert(0, 'H');
+
+        let insertion = decoded.inserted(0, 1);
+
+        replica2.merge(&common::Edit::Insertion(insertion, String::from("H")));
+
+        assert_eq!(replica2.buffer, buffer);
+    }
+
+    #[test]
+    #[should_panic]
+    fn document_text_too_long() {
+        let replica = Replica::new(1, 3);
+        let _ = replica.encode_document("abcd");
+    }
+
+    #[test]
+    fn document_deleted_text() {
+        let mut replica = Replica::new(1, 0);
+
+        let insertion = replica.inserted(0, 11);
+
+        let contents = "Hello world";
+
+        let _ = replica.deleted(0..2);
+        let _ = replica.deleted(3..5);
+
+        let deleted_text = |text: &Text| {
+            let range = text.temporal_range();
+            assert_eq!(text.inserted_by(), insertion.text().inserted_by());
+            contents[range].to_owned()
+        };
+
+        let encoded =
+            replica.encode_document_with_deleted_text("lloorld", deleted_text);
+
+        let deleted = encoded
+            .deleted_text()
+            .unwrap()
+            .iter()
+            .map(|(_, contents)| contents.as_str())
+            .collect::<Vec<_>>();
+
+        assert_eq!(deleted, ["He", " w"]);
+
+        let decoded = Replica::decode_document(2, &encoded).unwrap();
+
+        assert!(replica.eq_decoded(&decoded));
+    }
+
+    #[test]
+    #[should_panic]
+    fn document_deleted_text_wrong_length() {
+        let mut replica = Replica::new(1, 5);
+
+        let _ = replica.deleted(1..3);
+
+        let _ = replica
+            .encode_document_with_deleted_text("abc", |_| String::from("x"));
+    }
+
+    /// Tests that tamper with `EncodedDocument`s by serializing them with
+    /// `bincode`.
+    #[cfg(feature = "serde")]
+    mod tampered {
+        use cola::{DecodeError, EncodedDocument, EncodedReplica};
+
+        use super::*;
+
+        type Fields = (EncodedReplica, String, Option<Vec<(Text, String)>>);
+
+        fn tamper(
+            encoded: &EncodedDocument,
+            f: impl FnOnce(&mut Fields),
+        ) -> EncodedDocument {
+            let bytes = bincode::serialize(encoded).unwrap();
+            let mut fields: Fields = bincode::deserialize(&bytes).unwrap();
+            f(&mut fields);
+            let bytes = bincode::serialize(&fields).unwrap();
+            bincode::deserialize(&bytes).unwrap()
+        }
+
+        #[test]
+        fn document_length_mismatch() {
+            let replica = Replica::new(1, 5);
+
+            let encoded =
+                tamper(&replica.encode_document("Hello"), |fields| {
+                    fields.1.push('!');
+                });
+
+            assert_eq!(
+                Replica::decode_document(2, &encoded).err(),
+                Some(DecodeError::TextLengthMismatch {
+                    replica_len: 5,
+                    text_len: 6,
+                })
+            );
+        }
+
+        #[test]
+        fn document_deleted_text_mismatch() {
+            let mut replica = Replica::new(1, 5);
+
+            let _ = replica.deleted(1..3);
+
+            let encoded = replica
+                .encode_document_with_deleted_text("Hlo", |_| "el".to_owned());
+
+            let longer = tamper(&encoded, |fields| {
+                fields.2.as_mut().unwrap()[0].1.push('l');
+            });
+
+            assert_eq!(
+                Replica::decode_document(2, &longer).err(),
+                Some(DecodeError::InvalidData)
+            );
+
+            let missing = tamper(&encoded, |fields| {
+                fields.2.as_mut().unwrap().clear();
+            });
+
+            assert_eq!(
+                Replica::decode_document(2, &missing).err(),
+                Some(DecodeError::InvalidData)
+            );
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use cola::Replica;
+
+#[test]
+fn dump_json_tombstones() {
+    let mut replica = Replica::new(1, 10);
+
+    let _ = replica.deleted(2..4);
+
+    let json = replica.dump().to_json();
+
+    assert!(json.contains(
+        r#""replica":1,"temporal_range":[0,2],"lamport_ts":0,"run_ts":0,"deleted":false"#
+    ));
+
+    assert!(json.contains(
+        r#""replica":1,"temporal_range":[2,4],"lamport_ts":0,"run_ts":0,"deleted":true"#
+    ));
+
+    assert!(json.contains(
+        r#""replica":1,"temporal_range":[4,10],"lamport_ts":0,"run_ts":0,"deleted":false"#
+    ));
+}
+
+#[test]
+fn dump_json_run_indices() {
+    let mut replica1 = Replica::new(1, 4);
+    let mut replica2 = replica1.fork(2);
+
+    let insertion = replica2.inserted(2, 3);
+
+    let _ = replica1.integrate_insertion(&insertion);
+
+    let json = replica1.dump().to_json();
+
+    // Replica 1's only run has been split in two by replica 2's insertion.
+    assert!(json.ends_with(
+        r#""run_indices":[{"replica":1,"runs":[[{"len":2,"leaf":0},{"len":2,"leaf":2}]]},{"replica":2,"runs":[[{"len":3,"leaf":1}]]}]}"#
+    ));
+}
+
+#[test]
+fn dump_collapse() {
+    let automerge = traces::automerge().chars_to_bytes();
+
+    let mut replica = Replica::<4>::new_with_arity(1, 0);
+
+    for (start, end, text) in automerge.edits().take(1000) {
+        let _ = replica.deleted(start..end);
+        let _ = replica.inserted(start, text.len());
+    }
+
+    let stats = replica.stats();
+
+    let full = replica.dump().to_json();
+
+    assert_eq!(
+        full.matches(r#""leaf":"#).count(),
+        2 * (stats.visible_runs() + stats.tombstoned_runs())
+    );
+
+    assert!(!full.contains("collapsed"));
+
+    let collapsed = replica.dump().collapse_below(0).to_json();
+
+    // The whole tree is collapsed into the root.
+    assert!(collapsed.contains(&format!(
+        r#""len":{},"collapsed":true,"visible_runs":{},"tombstoned_runs":{}}},"run_indices":"#,
+        stats.len(),
+        stats.visible_runs(),
+        stats.tombstoned_runs(),
+    )));
+
+    let collapsed = replica.dump().collapse_below(1).to_dot();
+
+    assert!(collapsed.contains("style=dashed, label=\"len"));
+
+    // The leaves are hidden, so the run indices point to the collapsed
+    // nodes instead.
+    assert!(!collapsed.contains("[shape=record, label=\"{replica"));
+    assert!(!collapsed.contains(" -> l"));
+}
+
+#[test]
+fn dump_collapse_too_deep() {
+    let mut replica = Replica::new(1, 10);
+
+    let _ = replica.deleted(2..4);
+
+    let depth = replica.stats().tree_depth();
+
+    assert_eq!(
+        replica.dump().collapse_below(depth).to_json(),
+        replica.dump().to_json()
+    );
+
+    assert_eq!(
+        replica.dump().collapse_below(depth).to_dot(),
+        replica.dump().to_dot()
+    );
+}
+
+#[test]
+fn dump_dot_edges() {
+    let mut replica1 = Replica::new(1, 4);
+    let mut replica2 = replica1.fork(2);
+
+    let insertion = replica2.inserted(2, 3);
+
+    let _ = replica1.integrate_insertion(&insertion);
+
+    let dot = replica1.dump().to_dot();
+
+    assert!(dot.contains("i0 -> l0;"));
+    assert!(dot.contains("i0 -> l1;"));
+    assert!(dot.contains("i0 -> l2;"));
+
+    assert!(dot.contains("r1:t0 -> l0 [style=dashed, constraint=false];"));
+    assert!(dot.contains("r1:t0 -> l2 [style=dashed, constraint=false];"));
+    assert!(dot.contains("r2:t0 -> l1 [style=dashed, constraint=false];"));
+
+    assert!(dot.ends_with("}\n"));
+}
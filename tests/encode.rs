@@ -1,13 +1,24 @@
 #[cfg(feature = "encode")]
 mod encode {
-    use cola::{Length, Replica, ReplicaId};
+    #[cfg(feature = "sha256")]
+    use std::io;
+
+    use cola::{
+        DecodeError,
+        Deletion,
+        Insertion,
+        Length,
+        Replica,
+        ReplicaId,
+        PROTOCOL_VERSION,
+    };
 
     #[test]
     fn encode_empty() {
         let replica = Replica::new(1, 42);
         let encoded = replica.encode();
         let decoded = Replica::decode(2, &encoded).unwrap();
-        assert_eq!(decoded.id(), 1);
+        assert_eq!(decoded.id(), 2);
         assert!(replica.eq_decoded(&decoded));
     }
 
@@ -18,16 +29,473 @@ mod encode {
         let mut replica = Replica::new(1, automerge.start_content().len());
 
         for (start, end, text) in automerge.edits() {
-            replica.deleted(start..end);
-            replica.inserted(start, text.len());
+            let _ = replica.deleted(start..end);
+            let _ = replica.inserted(start, text.len());
         }
 
         let encoded = replica.encode();
 
         let decoded = Replica::decode(2, &encoded).unwrap();
 
-        assert_eq!(decoded.id(), 1);
+        assert_eq!(decoded.id(), 2);
+
+        assert!(replica.eq_decoded(&decoded));
+    }
+
+    #[test]
+    fn encode_after_merging_backlog() {
+        let mut replica1 = Replica::new(1, 3);
+        let mut replica2 = replica1.fork(2);
+
+        let ins1 = replica2.inserted(3, 1);
+        let ins2 = replica2.inserted(4, 1);
+
+        assert!(replica1.integrate_insertion(&ins2).is_none());
+        assert_eq!(replica1.integrate_insertion(&ins1), Some(3));
+        assert_eq!(replica1.backlogged_insertions().count(), 1);
+
+        // The backlog of replica 2 is now empty, which the decoded replica
+        // can't tell apart from not having one at all.
+        let decoded = Replica::decode(3, &replica1.encode()).unwrap();
+
+        assert!(replica1.eq_decoded(&decoded));
+    }
+
+    #[test]
+    fn encode_different_arity() {
+        let automerge = traces::automerge().chars_to_bytes();
+
+        let mut replica =
+            Replica::<64>::new_with_arity(1, automerge.start_content().len());
+
+        for (start, end, text) in automerge.edits() {
+            let _ = replica.deleted(start..end);
+            let _ = replica.inserted(start, text.len());
+        }
+
+        let encoded = replica.encode();
+
+        let mut decoded =
+            Replica::<128>::decode_with_arity(2, &encoded).unwrap();
+
+        assert_eq!(decoded.len(), replica.len());
+
+        let _ = decoded.inserted(0, 1);
+
+        decoded.check_integrity().unwrap();
+
+        // The run tree is rebuilt when decoding, so even an arity smaller
+        // than the one of the encoded replica works.
+        let decoded = Replica::<4>::decode_with_arity(2, &encoded).unwrap();
+
+        assert_eq!(decoded.len(), replica.len());
+
+        assert_eq!(
+            decoded.stats().visible_runs(),
+            replica.stats().visible_runs()
+        );
+
+        decoded.check_integrity().unwrap();
+    }
+
+    #[test]
+    fn encode_concurrent() {
+        let mut replica1 = Replica::new(1, 10);
+        let mut replica2 = replica1.fork(2);
+        let mut replica3 = replica1.fork(3);
+
+        let ins1 = replica1.inserted(3, 2);
+        let ins2 = replica2.inserted(3, 5);
+        let del3 = replica3.deleted(1..8);
+
+        let _ = replica1.integrate_insertion(&ins2);
+        let _ = replica1.integrate_deletion(&del3);
+        let _ = replica2.integrate_deletion(&del3);
+        let _ = replica2.integrate_insertion(&ins1);
+
+        let encoded = replica1.encode();
+
+        let mut decoded = Replica::decode(4, &encoded).unwrap();
+
+        assert!(replica1.eq_decoded(&decoded));
+
+        // The decoded replica can keep integrating edits from the others.
+        let ins3 = replica3.inserted(0, 1);
+
+        assert_eq!(
+            decoded.integrate_insertion(&ins3),
+            replica1.integrate_insertion(&ins3)
+        );
+
+        let _ = replica2.integrate_insertion(&ins3);
+
+        let ins4 = decoded.inserted(1, 3);
+
+        assert_eq!(
+            replica1.integrate_insertion(&ins4),
+            replica2.integrate_insertion(&ins4),
+        );
+    }
+
+    #[test]
+    fn encode_backlog() {
+        let mut replica1 = Replica::new(1, 0);
+        let mut replica2 = replica1.fork(2);
+
+        let ins1 = replica1.inserted(0, 3);
+        let ins2 = replica1.inserted(3, 3);
+        let del = replica1.deleted(1..4);
+
+        let _ = replica2.integrate_insertion(&ins2);
+        let _ = replica2.integrate_deletion(&del);
+
+        let encoded = replica2.encode();
+
+        let mut decoded = Replica::decode(3, &encoded).unwrap();
+
+        assert!(replica2.eq_decoded(&decoded));
+
+        assert_eq!(decoded.stats().backlogged_insertions(), 1);
+        assert_eq!(decoded.stats().backlogged_deletions(), 1);
+
+        let offset: Length = decoded.integrate_insertion(&ins1).unwrap();
+        assert_eq!(offset, 0);
+
+        let insertions = decoded.backlogged_insertions().collect::<Vec<_>>();
+        assert_eq!(insertions.len(), 1);
+        assert_eq!(insertions[0].0.inserted_by(), 1 as ReplicaId);
+
+        let deletions = decoded.backlogged_deletions().collect::<Vec<_>>();
+        assert_eq!(deletions, vec![vec![1..4]]);
+    }
+
+    /// Checks that a resumed `Replica` makes exactly the same edits as the
+    /// one it was encoded from.
+    #[test]
+    fn resume_restores_clocks() {
+        let mut replica1 = Replica::new(1, 3);
+        let mut replica2 = replica1.fork(2);
+
+        let _ = replica1.inserted(3, 2);
+        let _ = replica1.deleted(0..1);
+
+        // Integrating these bumps the Lamport clock of replica 1.
+        let ins1 = replica2.inserted(0, 1);
+        let ins2 = replica2.inserted(0, 2);
+
+        let _ = replica1.integrate_insertion(&ins1);
+        let _ = replica1.integrate_insertion(&ins2);
+
+        let mut resumed = Replica::resume(&replica1.encode()).unwrap();
+
+        assert_eq!(resumed.id(), replica1.id());
+
+        assert!(replica1.eq_decoded(&resumed));
+
+        assert_eq!(resumed.inserted(7, 1), replica1.inserted(7, 1));
+        assert_eq!(resumed.inserted(8, 2), replica1.inserted(8, 2));
+        assert_eq!(resumed.deleted(0..2), replica1.deleted(0..2));
+        assert_eq!(resumed.inserted(0, 3), replica1.inserted(0, 3));
+    }
+
+    #[test]
+    fn encode_empty_document() {
+        let mut replica = Replica::new(1, 0);
+
+        let encoded = replica.encode();
+
+        let mut decoded = Replica::decode(2, &encoded).unwrap();
 
         assert!(replica.eq_decoded(&decoded));
+
+        let insertion = replica.inserted(0, 5);
+
+        assert_eq!(decoded.integrate_insertion(&insertion), Some(0));
+    }
+
+    #[test]
+    fn encode_insertion() {
+        let mut replica1 = Replica::new(1, 10);
+        let mut replica2 = replica1.fork(2);
+
+        let insertion = replica1.inserted(4, 3);
+
+        let decoded = Insertion::decode(&insertion.encode()).unwrap();
+
+        assert_eq!(decoded, insertion);
+
+        assert_eq!(replica2.integrate_insertion(&decoded), Some(4));
+    }
+
+    #[test]
+    fn encode_deletion() {
+        let mut replica1 = Replica::new(1, 10);
+        let mut replica2 = replica1.fork(2);
+        let mut replica3 = replica1.fork(3);
+
+        let insertion = replica2.inserted(0, 5);
+        let _ = replica1.integrate_insertion(&insertion);
+        let _ = replica3.integrate_insertion(&insertion);
+
+        let deletion = replica1.deleted(3..12);
+
+        let decoded = Deletion::decode(&deletion.encode()).unwrap();
+
+        assert_eq!(decoded, deletion);
+
+        assert_eq!(replica3.integrate_deletion(&decoded), vec![3..12]);
+    }
+
+    #[test]
+    fn encode_deletion_many_peers() {
+        let mut replica1 = Replica::new(1, 0);
+
+        for id in 2..500 {
+            let mut peer = replica1.fork(id);
+            let insertion = peer.inserted(0, 10);
+            let _ = replica1.integrate_insertion(&insertion);
+        }
+
+        let mut replica2 = replica1.fork(1000);
+
+        let insertion = replica1.inserted(0, 10);
+
+        let deletion = replica1.deleted(3..4);
+
+        let encoded = deletion.encode();
+
+        // Only the entry of the replica whose text was deleted is sent.
+        assert!(encoded.len() < 20);
+
+        let _ = replica2.integrate_insertion(&insertion);
+
+        let deletion = Deletion::decode(&encoded).unwrap();
+
+        assert_eq!(replica2.integrate_deletion(&deletion), vec![3..4]);
+    }
+
+    #[test]
+    fn decode_edit_wrong_kind() {
+        let mut replica = Replica::new(1, 10);
+
+        let insertion = replica.inserted(0, 1).encode();
+        let deletion = replica.deleted(0..1).encode();
+
+        assert_eq!(
+            Deletion::decode(&insertion),
+            Err(DecodeError::InvalidData)
+        );
+        assert_eq!(
+            Insertion::decode(&deletion),
+            Err(DecodeError::InvalidData)
+        );
+    }
+
+    #[test]
+    fn decode_edit_invalid_data() {
+        let mut replica = Replica::new(1, 10);
+
+        let mut bytes = replica.inserted(0, 1).encode();
+
+        assert_eq!(
+            Insertion::decode(&bytes[..bytes.len() - 1]),
+            Err(DecodeError::InvalidData)
+        );
+
+        bytes.push(0);
+
+        assert_eq!(Insertion::decode(&bytes), Err(DecodeError::InvalidData));
+
+        assert_eq!(Insertion::decode(&[]), Err(DecodeError::InvalidData));
+    }
+
+    #[test]
+    fn decode_deletion_invalid_version_map() {
+        let mut replica1 = Replica::new(1, 10);
+        let mut replica2 = replica1.fork(2);
+
+        let insertion = replica2.inserted(0, 5);
+        let _ = replica1.integrate_insertion(&insertion);
+
+        let bytes = replica1.deleted(3..12).encode();
+
+        // The version map is made of the local entry `(1, 10)`, the number
+        // of remote entries and the delta-encoded `(2, 5)` entry.
+        let map_start = bytes.len() - 6;
+        assert_eq!(bytes[map_start..bytes.len() - 1], [1, 10, 1, 2, 5]);
+
+        assert!(Deletion::decode(&bytes).is_ok());
+
+        // A remote entry with the ID of the local one.
+        let mut duplicate = bytes.clone();
+        duplicate[map_start + 3] = 1;
+        assert_eq!(
+            Deletion::decode(&duplicate),
+            Err(DecodeError::InvalidData)
+        );
+
+        // A remote entry with an ID of zero.
+        let mut zero = bytes;
+        zero[map_start + 3] = 0;
+        assert_eq!(Deletion::decode(&zero), Err(DecodeError::InvalidData));
+    }
+
+    #[test]
+    fn decode_edit_different_protocol() {
+        let mut replica = Replica::new(1, 10);
+
+        let mut bytes = replica.inserted(0, 1).encode();
+
+        // The protocol version is the first byte of the header.
+        bytes[0] = PROTOCOL_VERSION as u8 + 1;
+
+        assert_eq!(
+            Insertion::decode(&bytes),
+            Err(DecodeError::DifferentProtocol {
+                encoded_on: PROTOCOL_VERSION + 1,
+                decoding_on: PROTOCOL_VERSION,
+            })
+        );
+
+        // Edits had no binary encoding before protocol version 1.
+        bytes[0] = 0;
+
+        assert!(matches!(
+            Insertion::decode(&bytes),
+            Err(DecodeError::DifferentProtocol { encoded_on: 0, .. })
+        ));
+    }
+
+    #[cfg(feature = "sha256")]
+    fn stream_decode_error(err: io::Error) -> Option<DecodeError> {
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        err.into_inner()?.downcast::<DecodeError>().ok().map(|err| *err)
+    }
+
+    #[cfg(feature = "sha256")]
+    #[test]
+    fn stream_automerge() {
+        let automerge = traces::automerge().chars_to_bytes();
+
+        let mut replica = Replica::new(1, automerge.start_content().len());
+
+        for (start, end, text) in automerge.edits() {
+            let _ = replica.deleted(start..end);
+            let _ = replica.inserted(start, text.len());
+        }
+
+        let mut stream = Vec::new();
+
+        replica.encode_to_writer(&mut stream).unwrap();
+
+        let mut decoded =
+            Replica::decode_from_reader(2, stream.as_slice()).unwrap();
+
+        assert!(replica.eq_decoded(&decoded));
+
+        decoded.check_integrity().unwrap();
+
+        let insertion = replica.inserted(0, 3);
+
+        assert_eq!(decoded.integrate_insertion(&insertion), Some(0));
+    }
+
+    #[cfg(feature = "sha256")]
+    #[test]
+    fn stream_backlog() {
+        let mut replica1 = Replica::new(1, 0);
+        let mut replica2 = replica1.fork(2);
+
+        let ins1 = replica1.inserted(0, 3);
+        let ins2 = replica1.inserted(3, 3);
+        let del = replica1.deleted(1..4);
+
+        let _ = replica2.integrate_insertion(&ins2);
+        let _ = replica2.integrate_deletion(&del);
+
+        let mut stream = Vec::new();
+
+        replica2.encode_to_writer(&mut stream).unwrap();
+
+        // Nothing past the end of the stream is read.
+        stream.extend_from_slice(b"trailing");
+
+        let mut reader = stream.as_slice();
+
+        let mut decoded = Replica::decode_from_reader(3, &mut reader).unwrap();
+
+        assert_eq!(reader, b"trailing");
+
+        assert!(replica2.eq_decoded(&decoded));
+
+        assert_eq!(decoded.integrate_insertion(&ins1), Some(0));
+
+        assert_eq!(decoded.backlogged_insertions().count(), 1);
+
+        assert_eq!(
+            decoded.backlogged_deletions().collect::<Vec<_>>(),
+            vec![vec![1..4]]
+        );
+    }
+
+    #[cfg(feature = "sha256")]
+    #[test]
+    fn stream_checksum_failed() {
+        let mut replica = Replica::new(1, 10);
+
+        let _ = replica.inserted(5, 3);
+
+        let mut stream = Vec::new();
+
+        replica.encode_to_writer(&mut stream).unwrap();
+
+        let last = stream.len() - 1;
+
+        stream[last] ^= 0xff;
+
+        let err =
+            Replica::decode_from_reader(2, stream.as_slice()).unwrap_err();
+
+        assert_eq!(
+            stream_decode_error(err),
+            Some(DecodeError::ChecksumFailed)
+        );
+    }
+
+    #[cfg(feature = "sha256")]
+    #[test]
+    fn stream_truncated() {
+        let mut stream = Vec::new();
+
+        Replica::new(1, 10).encode_to_writer(&mut stream).unwrap();
+
+        for len in 0..stream.len() {
+            let err =
+                Replica::decode_from_reader(2, &stream[..len]).unwrap_err();
+
+            assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        }
+    }
+
+    #[cfg(feature = "sha256")]
+    #[test]
+    fn stream_different_protocol() {
+        let mut stream = Vec::new();
+
+        Replica::new(1, 10).encode_to_writer(&mut stream).unwrap();
+
+        // The protocol version is the first byte of the stream.
+        stream[0] = PROTOCOL_VERSION as u8 + 1;
+
+        let err =
+            Replica::decode_from_reader(2, stream.as_slice()).unwrap_err();
+
+        assert_eq!(
+            stream_decode_error(err),
+            Some(DecodeError::DifferentProtocol {
+                encoded_on: PROTOCOL_VERSION + 1,
+                decoding_on: PROTOCOL_VERSION,
+            })
+        );
     }
 }
@@ -0,0 +1,84 @@
+// Records the Yjs fixtures used by `tests/yjs.rs`. See `README.md`.
+
+import { readdirSync, readFileSync, writeFileSync } from "node:fs";
+import { join } from "node:path";
+import * as Y from "yjs";
+
+const dir = new URL(".", import.meta.url).pathname;
+
+const newDoc = (clientID) => {
+  const doc = new Y.Doc();
+  doc.clientID = clientID;
+  return doc;
+};
+
+// Sends the structs `to` is missing from `from`.
+const sync = (from, to) => {
+  const update = Y.encodeStateAsUpdate(from, Y.encodeStateVector(to));
+  Y.applyUpdate(to, update);
+};
+
+// The edits recorded in `import`. Each scenario returns the document whose
+// state is written as the fixture.
+const scenarios = {
+  single_client() {
+    const doc = newDoc(1);
+    doc.getText("text").insert(0, "abc");
+    return doc;
+  },
+
+  // Client 1 inserts "abc", client 2 inserts "X" between "b" and "c", and
+  // concurrently client 1 appends "Y" and deletes "a".
+  concurrent() {
+    const [doc1, doc2] = [newDoc(1), newDoc(2)];
+    doc1.getText("text").insert(0, "abc");
+    sync(doc1, doc2);
+    doc2.getText("text").insert(2, "X");
+    doc1.getText("text").insert(3, "Y");
+    doc1.getText("text").delete(0, 1);
+    sync(doc2, doc1);
+    return doc1;
+  },
+
+  // Two clients concurrently insert at the start of the text.
+  conflict() {
+    const [doc1, doc2] = [newDoc(1), newDoc(2)];
+    doc1.getText("text").insert(0, "a");
+    doc2.getText("text").insert(0, "b");
+    sync(doc2, doc1);
+    return doc1;
+  },
+
+  // The content of deleted items is garbage collected, so the update only
+  // contains their length.
+  discarded_content() {
+    const doc = newDoc(1);
+    const text = doc.getText("text");
+    text.insert(0, "ab");
+    text.delete(0, 2);
+    text.insert(0, "x");
+    return doc;
+  },
+};
+
+for (const [name, scenario] of Object.entries(scenarios)) {
+  const doc = scenario();
+  writeFileSync(join(dir, "import", `${name}.bin`), Y.encodeStateAsUpdate(doc));
+  writeFileSync(join(dir, "import", `${name}.txt`), doc.getText("text").toString());
+}
+
+// The updates in `export` are encoded by cola, so only the text Yjs decodes
+// from them is recorded.
+for (const file of readdirSync(join(dir, "export"))) {
+  if (!file.endsWith(".bin")) continue;
+
+  const doc = new Y.Doc();
+  Y.applyUpdate(doc, readFileSync(join(dir, "export", file)));
+
+  if (doc.store.pendingStructs !== null || doc.store.pendingDs !== null) {
+    throw new Error(`${file} references structs it doesn't contain`);
+  }
+
+  const txt = file.replace(/\.bin$/, ".txt");
+  writeFileSync(join(dir, "export", txt), doc.getText("text").toString());
+}
@@ -88,6 +88,42 @@ fn conflicting_insertions_2() {
     assert_convergence!(peer1, peer2, "xxssdsm");
 }
 
+/// Tests that a single call to `backlogged_insertions` merges the backlogged
+/// insertions anchored to other backlogged insertions, no matter the order
+/// in which it goes through the backlogs of the different peers.
+#[test]
+fn backlogged_insertions_anchored_to_each_other() {
+    let mut peer1 = Replica::new(1, "");
+    let mut peer2 = peer1.fork(2);
+    let mut peer3 = peer1.fork(3);
+
+    let x = peer2.insert(0, 'x');
+    peer3.merge(&x);
+
+    let y = peer3.insert(1, 'y');
+    peer2.merge(&y);
+
+    let z = peer2.insert(2, 'z');
+    peer3.merge(&z);
+
+    let w = peer3.insert(3, 'w');
+
+    peer1.merge(&w);
+    peer1.merge(&z);
+    peer1.merge(&y);
+    peer1.merge(&x);
+
+    assert_eq!(peer1, "x");
+
+    peer1.merge_backlogged();
+
+    assert_eq!(peer1.crdt.stats().backlogged_insertions(), 0);
+
+    peer1.assert_invariants();
+
+    assert_convergence!(peer1, peer3, "xyzw");
+}
+
 #[test]
 fn random_insertions() {
     let seed = rand::random::<u64>();
@@ -0,0 +1,369 @@
+#[cfg(feature = "lsp")]
+mod lsp {
+    use cola::LspDocument;
+    use lsp_types::{Position, Range, TextDocumentContentChangeEvent};
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
+
+    fn change(
+        start: (u32, u32),
+        end: (u32, u32),
+        text: &str,
+    ) -> TextDocumentContentChangeEvent {
+        TextDocumentContentChangeEvent {
+            range: Some(Range::new(
+                Position::new(start.0, start.1),
+                Position::new(end.0, end.1),
+            )),
+            range_length: None,
+            text: text.to_owned(),
+        }
+    }
+
+    /// Applies an event to a buffer the way an LSP client would, i.e. by
+    /// counting characters in UTF-16 code units.
+    fn apply(buffer: &mut String, event: &TextDocumentContentChangeEvent) {
+        let Some(range) = event.range else {
+            *buffer = event.text.clone();
+            return;
+        };
+
+        let utf16_offset = |buffer: &[u16], position: Position| {
+            let mut offset = 0;
+            for _ in 0..position.line {
+                offset += buffer[offset..]
+                    .iter()
+                    .position(|&unit| unit == b'\n' as u16)
+                    .unwrap()
+                    + 1;
+            }
+            offset + position.character as usize
+        };
+
+        let mut utf16 = buffer.encode_utf16().collect::<Vec<_>>();
+        let start = utf16_offset(&utf16, range.start);
+        let end = utf16_offset(&utf16, range.end);
+        utf16.splice(start..end, event.text.encode_utf16());
+        *buffer = String::from_utf16(&utf16).unwrap();
+    }
+
+    #[test]
+    fn lsp_remote_insertion() {
+        let mut peer1 = LspDocument::new(1, "fn main() {\n    todo!()\n}\n");
+        let mut peer2 = peer1.fork(2);
+
+        let mut client2 = peer2.text().to_owned();
+
+        let change = change((1, 4), (1, 11), "println!(\"🥤 {}\", 42);");
+
+        let (deletion, insertion) = peer1.apply_change(&change);
+
+        for event in peer2.integrate_deletion(&deletion.unwrap()) {
+            apply(&mut client2, &event);
+        }
+
+        let event =
+            peer2.integrate_insertion(&insertion.unwrap(), &change.text);
+
+        apply(&mut client2, &event.unwrap());
+
+        assert_eq!(peer1.text(), peer2.text());
+        assert_eq!(peer2.text(), client2);
+    }
+
+    #[test]
+    fn lsp_split_deletion() {
+        let mut peer1 = LspDocument::new(1, "ab\ncd\nef");
+        let mut peer2 = peer1.fork(2);
+
+        let mut client2 = peer2.text().to_owned();
+
+        // Peer 1 deletes from the "b" to the "e", while peer 2 concurrently
+        // inserts two lines inside the deleted range.
+        let (deletion, _) = peer1.apply_change(&change((0, 1), (2, 1), ""));
+
+        let insert_at_c = change((1, 0), (1, 0), "😀\n");
+        let insert_at_d = change((2, 1), (2, 1), "🥤\n");
+
+        for change in [insert_at_c, insert_at_d] {
+            let _ = peer2.apply_change(&change);
+            apply(&mut client2, &change);
+        }
+
+        assert_eq!(client2, "ab\n😀\nc🥤\nd\nef");
+
+        let events = peer2.integrate_deletion(&deletion.unwrap());
+
+        assert_eq!(events.len(), 3);
+
+        for event in &events {
+            apply(&mut client2, event);
+        }
+
+        assert_eq!(peer2.text(), "a😀\n🥤\nf");
+        assert_eq!(peer2.text(), client2);
+    }
+
+    #[test]
+    fn lsp_backlogged_insertion() {
+        let mut peer1 = LspDocument::new(1, "");
+        let mut peer2 = peer1.fork(2);
+
+        let mut client2 = String::new();
+
+        let first = change((0, 0), (0, 0), "foo\n");
+        let second = change((1, 0), (1, 0), "bär");
+
+        let (_, insert_first) = peer1.apply_change(&first);
+        let (_, insert_second) = peer1.apply_change(&second);
+
+        let event =
+            peer2.integrate_insertion(&insert_second.unwrap(), &second.text);
+
+        assert!(event.is_none());
+
+        let event =
+            peer2.integrate_insertion(&insert_first.unwrap(), &first.text);
+
+        apply(&mut client2, &event.unwrap());
+
+        let events = peer2.integrate_backlogged();
+
+        assert_eq!(events.len(), 1);
+
+        apply(&mut client2, &events[0]);
+
+        assert_eq!(peer2.text(), "foo\nbär");
+        assert_eq!(peer2.text(), client2);
+    }
+
+    #[test]
+    fn lsp_insertion_with_wrong_text() {
+        let mut peer1 = LspDocument::new(1, "ab");
+        let mut peer2 = peer1.fork(2);
+
+        let insert = change((0, 1), (0, 1), "xyz");
+
+        let (_, insertion) = peer1.apply_change(&insert);
+
+        let insertion = insertion.unwrap();
+
+        assert!(peer2.integrate_insertion(&insertion, "xy").is_none());
+        assert!(peer2.integrate_insertion(&insertion, "wxyz").is_none());
+        assert_eq!(peer2.text(), "ab");
+
+        assert!(peer2.integrate_insertion(&insertion, &insert.text).is_some());
+        assert_eq!(peer2.text(), "axyzb");
+    }
+
+    /// An insertion rejected by the `Replica` because of a `ReplicaId`
+    /// collision doesn't leave its text behind.
+    #[test]
+    fn lsp_colliding_insertion() {
+        let mut peer1 = LspDocument::new(1, "abc");
+        let mut peer2 = peer1.fork(2);
+        let mut impostor = peer1.fork(2);
+
+        let first = change((0, 0), (0, 0), "x");
+        let (_, insert_first) = peer2.apply_change(&first);
+        let _ = impostor.apply_change(&change((0, 0), (0, 0), "yy"));
+
+        let second = change((0, 1), (0, 1), "zz");
+        let (_, insert_second) = peer2.apply_change(&second);
+
+        assert!(peer1
+            .integrate_insertion(&insert_second.unwrap(), &second.text)
+            .is_none());
+        assert_eq!(peer1.backlogged_texts().count(), 1);
+
+        let colliding = change((0, 2), (0, 2), "ww");
+        let (_, insert_colliding) = impostor.apply_change(&colliding);
+
+        assert!(peer1
+            .integrate_insertion(&insert_colliding.unwrap(), &colliding.text)
+            .is_none());
+        assert_eq!(peer1.backlogged_texts().count(), 1);
+
+        assert!(peer1
+            .integrate_insertion(&insert_first.unwrap(), &first.text)
+            .is_some());
+        assert_eq!(peer1.integrate_backlogged().len(), 1);
+        assert_eq!(peer1.backlogged_texts().count(), 0);
+        assert_eq!(peer1.text(), peer2.text());
+    }
+
+    #[test]
+    fn lsp_from_replica_with_backlog() {
+        let mut peer1 = LspDocument::new(1, "");
+        let mut peer2 = peer1.fork(2);
+
+        let first = change((0, 0), (0, 0), "foo\n");
+        let second = change((1, 0), (1, 0), "bär");
+
+        let (_, insert_first) = peer1.apply_change(&first);
+        let (_, insert_second) = peer1.apply_change(&second);
+
+        let insert_first = insert_first.unwrap();
+        let insert_second = insert_second.unwrap();
+
+        assert!(peer2
+            .integrate_insertion(&insert_second, &second.text)
+            .is_none());
+
+        let backlogged = peer2
+            .backlogged_texts()
+            .map(|(text, contents)| (text.clone(), contents.to_owned()))
+            .collect::<Vec<_>>();
+
+        let mut peer3 = LspDocument::from_replica(
+            peer2.replica().fork(3),
+            peer2.text(),
+            backlogged,
+        );
+
+        assert!(peer3
+            .integrate_insertion(&insert_first, &first.text)
+            .is_some());
+        assert_eq!(peer3.integrate_backlogged().len(), 1);
+        assert_eq!(peer3.text(), "foo\nbär");
+
+        // Without the contents of the backlogged insertion, the insertion is
+        // dropped and has to be integrated again.
+        let mut peer4 =
+            LspDocument::from_replica(peer2.replica().fork(4), "", []);
+
+        assert!(peer4
+            .integrate_insertion(&insert_first, &first.text)
+            .is_some());
+        assert!(peer4.integrate_backlogged().is_empty());
+        assert!(peer4
+            .integrate_insertion(&insert_second, &second.text)
+            .is_some());
+        assert_eq!(peer4.text(), "foo\nbär");
+    }
+
+    #[test]
+    fn lsp_full_document_change() {
+        let mut peer1 = LspDocument::new(1, "foo\nbar");
+        let mut peer2 = peer1.fork(2);
+
+        let change = TextDocumentContentChangeEvent {
+            range: None,
+            range_length: None,
+            text: "baz".to_owned(),
+        };
+
+        let (deletion, insertion) = peer1.apply_change(&change);
+
+        let _ = peer2.integrate_deletion(&deletion.unwrap());
+        let _ = peer2.integrate_insertion(&insertion.unwrap(), &change.text);
+
+        assert_eq!(peer1.text(), "baz");
+        assert_eq!(peer2.text(), "baz");
+    }
+
+    #[test]
+    fn lsp_out_of_bounds_positions() {
+        let mut document = LspDocument::new(1, "foo\nbar");
+
+        // The character offset is clamped to the end of the line, and the
+        // line is clamped to the end of the document.
+        let _ = document.apply_change(&change((0, 42), (0, 42), "!"));
+        let _ = document.apply_change(&change((42, 0), (42, 0), "?"));
+
+        assert_eq!(document.text(), "foo!\nbar?");
+    }
+
+    #[test]
+    fn lsp_random_edits() {
+        let seed = rand::random::<u64>();
+        println!("seed: {}", seed);
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+
+        let snippets = ["a", "bc", "\n", "ü", "🥤", "x\ny", "😀\n"];
+
+        let mut peer1 = LspDocument::new(1, "");
+        let mut peer2 = peer1.fork(2);
+
+        let mut client1 = String::new();
+        let mut client2 = String::new();
+
+        for _ in 0..200 {
+            let mut edits = Vec::new();
+
+            for (peer, client) in
+                [(&mut peer1, &mut client1), (&mut peer2, &mut client2)]
+            {
+                let mut peer_edits = Vec::new();
+
+                for _ in 0..5 {
+                    let lines = client.split('\n').collect::<Vec<_>>();
+                    let line = rng.gen_range(0..lines.len());
+                    let len = lines[line].encode_utf16().count() as u32;
+                    let start = (line as u32, rng.gen_range(0..=len));
+                    let end = (line as u32, rng.gen_range(start.1..=len));
+
+                    // Don't split surrogate pairs.
+                    let is_boundary = |character: u32| {
+                        let utf16 =
+                            lines[line].encode_utf16().collect::<Vec<_>>();
+                        let prefix = &utf16[..character as usize];
+                        String::from_utf16(prefix).is_ok()
+                    };
+
+                    if !is_boundary(start.1) || !is_boundary(end.1) {
+                        continue;
+                    }
+
+                    let text = if rng.gen::<bool>() {
+                        snippets[rng.gen_range(0..snippets.len())]
+                    } else {
+                        ""
+                    };
+
+                    let change = change(start, end, text);
+
+                    apply(client, &change);
+
+                    let (deletion, insertion) = peer.apply_change(&change);
+
+                    peer_edits.push((deletion, insertion, change.text));
+                }
+
+                edits.push(peer_edits);
+            }
+
+            let edits2 = edits.pop().unwrap();
+            let edits1 = edits.pop().unwrap();
+
+            for (peer, client, edits) in [
+                (&mut peer1, &mut client1, edits2),
+                (&mut peer2, &mut client2, edits1),
+            ] {
+                for (deletion, insertion, text) in edits {
+                    if let Some(deletion) = deletion {
+                        for event in peer.integrate_deletion(&deletion) {
+                            apply(client, &event);
+                        }
+                    }
+
+                    if let Some(insertion) = insertion {
+                        if let Some(event) =
+                            peer.integrate_insertion(&insertion, &text)
+                        {
+                            apply(client, &event);
+                        }
+                    }
+                }
+
+                for event in peer.integrate_backlogged() {
+                    apply(client, &event);
+                }
+
+                assert_eq!(peer.text(), client.as_str());
+            }
+
+            assert_eq!(peer1.text(), peer2.text());
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use cola::Replica;
+
+#[test]
+fn memory_usage_runs() {
+    let automerge = traces::automerge().chars_to_bytes();
+
+    let mut replica = Replica::new(1, automerge.start_content().len());
+
+    for (start, end, text) in automerge.edits() {
+        let _ = replica.deleted(start..end);
+        let _ = replica.inserted(start, text.len());
+    }
+
+    let report = replica.memory_usage();
+
+    let stats = replica.stats();
+
+    assert_eq!(report.visible_runs(), stats.visible_runs());
+    assert_eq!(report.tombstoned_runs(), stats.tombstoned_runs());
+
+    assert!(report.tombstoned_runs() > 0);
+
+    assert!(report.gtree_inodes() > 0);
+
+    assert!(report.gtree_lnodes() > report.gtree_inodes());
+
+    assert!(report.run_indices() > 0);
+
+    assert_eq!(
+        report.total(),
+        report.gtree_inodes()
+            + report.gtree_lnodes()
+            + report.run_indices()
+            + report.backlog()
+            + report.version_map()
+            + report.deletion_map()
+    );
+}
+
+#[test]
+fn memory_usage_backlog() {
+    let mut replica1 = Replica::new(1, 0);
+    let mut replica2 = replica1.fork(2);
+
+    assert_eq!(replica2.memory_usage().backlog(), 0);
+
+    let _ = replica1.inserted(0, 1);
+    let insertion = replica1.inserted(1, 1);
+
+    assert_eq!(replica2.integrate_insertion(&insertion), None);
+
+    assert!(replica2.memory_usage().backlog() > 0);
+}
+
+#[test]
+fn memory_usage_version_maps() {
+    let replica1 = Replica::new(1, 0);
+
+    let report = replica1.memory_usage();
+
+    assert_eq!(report.version_map(), 0);
+    assert_eq!(report.deletion_map(), 0);
+
+    // The fork has to keep track of the first replica in its maps.
+    let report = replica1.fork(2).memory_usage();
+
+    assert!(report.version_map() > 0);
+    assert!(report.deletion_map() > 0);
+}
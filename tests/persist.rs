@@ -0,0 +1,265 @@
+#[cfg(feature = "persist")]
+mod persist {
+    use std::fs::{self, OpenOptions};
+    use std::io::{ErrorKind, Write};
+    use std::path::Path;
+
+    use cola::{Replica, ReplicaStore};
+
+    fn log_len(dir: &Path) -> u64 {
+        fs::metadata(dir.join("log")).unwrap().len()
+    }
+
+    /// Creates a store for peer 1 and applies a mix of local and remote edits
+    /// to it, returning the store and peer 2's replica which has seen all
+    /// the same edits.
+    fn store_with_edits(dir: &Path) -> (ReplicaStore, Replica) {
+        let mut store =
+            ReplicaStore::create(dir, Replica::new(1, 10)).unwrap();
+
+        let mut peer = store.replica().fork(2);
+
+        let ins1 = store.inserted(3, 4).unwrap();
+        let ins2 = peer.inserted(0, 2);
+        let del1 = store.deleted(8..12).unwrap();
+        let del2 = peer.deleted(1..3);
+
+        store.integrate_insertion(&ins2).unwrap();
+        store.integrate_deletion(&del2).unwrap();
+
+        let _ = peer.integrate_insertion(&ins1);
+        let _ = peer.integrate_deletion(&del1);
+
+        (store, peer)
+    }
+
+    /// Checks that the store and the peer can keep exchanging edits.
+    fn check_in_sync(store: &mut ReplicaStore, peer: &mut Replica) {
+        assert_eq!(store.replica().len(), peer.len());
+
+        let insertion = store.inserted(1, 3).unwrap();
+        assert_eq!(peer.integrate_insertion(&insertion), Some(1));
+
+        let insertion = peer.inserted(2, 1);
+        assert_eq!(store.integrate_insertion(&insertion).unwrap(), Some(2));
+
+        let deletion = store.deleted(0..4).unwrap();
+        assert_eq!(peer.integrate_deletion(&deletion), vec![0..4]);
+
+        store.replica().check_integrity().unwrap();
+    }
+
+    #[test]
+    fn persist_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let (store, mut peer) = store_with_edits(dir.path());
+
+        let len = store.replica().len();
+
+        drop(store);
+
+        let mut store = ReplicaStore::open(dir.path()).unwrap();
+
+        assert_eq!(store.replica().id(), 1);
+
+        assert_eq!(store.replica().len(), len);
+
+        check_in_sync(&mut store, &mut peer);
+
+        // And once more, now that the log contains edits made after the
+        // store was reopened.
+        drop(store);
+
+        let mut store = ReplicaStore::open(dir.path()).unwrap();
+
+        check_in_sync(&mut store, &mut peer);
+    }
+
+    #[test]
+    fn persist_checkpoint_interval() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let mut store =
+            ReplicaStore::create(dir.path(), Replica::new(1, 0)).unwrap();
+
+        store.set_checkpoint_interval(4);
+
+        for offset in 0..10 {
+            let _ = store.inserted(offset, 1).unwrap();
+        }
+
+        // The log was cleared after the 4th and the 8th edits, so it only
+        // contains the last 2.
+        let record_len = log_len(dir.path()) / 2;
+
+        assert!(record_len > 0);
+
+        let _ = store.inserted(0, 1).unwrap();
+
+        assert_eq!(log_len(dir.path()), 3 * record_len);
+
+        let _ = store.inserted(0, 1).unwrap();
+
+        assert_eq!(log_len(dir.path()), 0);
+
+        drop(store);
+
+        let store = ReplicaStore::open(dir.path()).unwrap();
+
+        assert_eq!(store.replica().len(), 12);
+    }
+
+    #[test]
+    fn persist_create_twice() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let _ = ReplicaStore::create(dir.path(), Replica::new(1, 0)).unwrap();
+
+        let err =
+            ReplicaStore::create(dir.path(), Replica::new(1, 0)).unwrap_err();
+
+        assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+    }
+
+    /// Simulates a crash in the middle of appending each of the bytes of a
+    /// record to the log.
+    #[test]
+    fn persist_crash_mid_write() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let (mut store, mut peer) = store_with_edits(dir.path());
+
+        let len_before = log_len(dir.path());
+
+        let _ = store.inserted(0, 5).unwrap();
+
+        let log = fs::read(dir.path().join("log")).unwrap();
+
+        drop(store);
+
+        for torn_len in len_before..log.len() as u64 {
+            fs::write(dir.path().join("log"), &log[..torn_len as usize])
+                .unwrap();
+
+            let store = ReplicaStore::open(dir.path()).unwrap();
+
+            // The torn record was truncated away.
+            assert_eq!(log_len(dir.path()), len_before);
+
+            assert_eq!(store.replica().len(), peer.len());
+        }
+
+        // The insertion that was lost in the crash was never sent to the
+        // peer, so they're still in sync.
+        let mut store = ReplicaStore::open(dir.path()).unwrap();
+
+        check_in_sync(&mut store, &mut peer);
+    }
+
+    #[test]
+    fn persist_corrupted_record() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let mut store =
+            ReplicaStore::create(dir.path(), Replica::new(1, 0)).unwrap();
+
+        let _ = store.inserted(0, 1).unwrap();
+
+        let record_len = log_len(dir.path());
+
+        let _ = store.inserted(1, 1).unwrap();
+        let _ = store.inserted(2, 1).unwrap();
+
+        drop(store);
+
+        let mut log = fs::read(dir.path().join("log")).unwrap();
+
+        // Flip a byte in the contents of the second record.
+        log[record_len as usize + 5] ^= 0xff;
+
+        fs::write(dir.path().join("log"), &log).unwrap();
+
+        let store = ReplicaStore::open(dir.path()).unwrap();
+
+        // Everything from the corrupted record onwards is discarded.
+        assert_eq!(store.replica().len(), 1);
+
+        assert_eq!(log_len(dir.path()), record_len);
+    }
+
+    /// Simulates a crash while writing a new checkpoint, before it replaced
+    /// the previous one.
+    #[test]
+    fn persist_crash_mid_checkpoint() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let (store, mut peer) = store_with_edits(dir.path());
+
+        drop(store);
+
+        fs::write(dir.path().join("checkpoint.tmp"), b"half a checkpoint")
+            .unwrap();
+
+        let mut store = ReplicaStore::open(dir.path()).unwrap();
+
+        assert!(!dir.path().join("checkpoint.tmp").exists());
+
+        check_in_sync(&mut store, &mut peer);
+    }
+
+    /// Simulates a crash right after a new checkpoint was written but before
+    /// the log was cleared, in which case the edits in the log are replayed
+    /// on top of a checkpoint that already contains them.
+    #[test]
+    fn persist_crash_before_log_cleared() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let (mut store, mut peer) = store_with_edits(dir.path());
+
+        let log = fs::read(dir.path().join("log")).unwrap();
+
+        store.checkpoint().unwrap();
+
+        drop(store);
+
+        OpenOptions::new()
+            .append(true)
+            .open(dir.path().join("log"))
+            .unwrap()
+            .write_all(&log)
+            .unwrap();
+
+        let mut store = ReplicaStore::open(dir.path()).unwrap();
+
+        check_in_sync(&mut store, &mut peer);
+    }
+
+    #[test]
+    fn persist_backlogged_edits() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let mut store =
+            ReplicaStore::create(dir.path(), Replica::new(1, 3)).unwrap();
+
+        let mut peer = store.replica().fork(2);
+
+        let ins1 = peer.inserted(0, 2);
+        let ins2 = peer.inserted(1, 2);
+        let del = peer.deleted(0..3);
+
+        // Both the insertion and the deletion depend on `ins1`, so they're
+        // backlogged.
+        assert_eq!(store.integrate_insertion(&ins2).unwrap(), None);
+        assert!(store.integrate_deletion(&del).unwrap().is_empty());
+
+        drop(store);
+
+        let mut store = ReplicaStore::open(dir.path()).unwrap();
+
+        assert_eq!(store.replica().stats().backlogged_insertions(), 1);
+        assert_eq!(store.replica().stats().backlogged_deletions(), 1);
+
+        assert_eq!(store.integrate_insertion(&ins1).unwrap(), Some(0));
+    }
+}
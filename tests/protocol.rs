@@ -0,0 +1,341 @@
+#[cfg(feature = "encode")]
+mod protocol {
+    use cola::{
+        negotiate_protocol,
+        Replica,
+        MIN_DECODABLE_PROTOCOL_VERSION,
+        PROTOCOL_VERSION,
+        SUPPORTED_PROTOCOL_VERSIONS,
+    };
+
+    #[test]
+    fn negotiate_same_release() {
+        assert_eq!(
+            negotiate_protocol(SUPPORTED_PROTOCOL_VERSIONS),
+            Some(PROTOCOL_VERSION)
+        );
+    }
+
+    #[test]
+    fn negotiate_older_release() {
+        // A release predating protocol negotiation.
+        assert_eq!(negotiate_protocol(0..=0), None);
+
+        assert_eq!(
+            negotiate_protocol(0..=PROTOCOL_VERSION),
+            Some(PROTOCOL_VERSION)
+        );
+    }
+
+    #[test]
+    fn negotiate_newer_release() {
+        assert_eq!(
+            negotiate_protocol(1..=PROTOCOL_VERSION + 3),
+            Some(PROTOCOL_VERSION)
+        );
+
+        assert_eq!(
+            negotiate_protocol(PROTOCOL_VERSION + 1..=PROTOCOL_VERSION + 3),
+            None
+        );
+    }
+
+    #[test]
+    fn encode_with_protocol() {
+        let replica = Replica::new(1, 10);
+
+        let version = negotiate_protocol(SUPPORTED_PROTOCOL_VERSIONS).unwrap();
+
+        let encoded = replica.encode_with_protocol(version).unwrap();
+
+        let decoded = Replica::decode(2, &encoded).unwrap();
+
+        assert!(replica.eq_decoded(&decoded));
+
+        assert!(replica.encode_with_protocol(PROTOCOL_VERSION + 1).is_none());
+
+        assert!(replica
+            .encode_with_protocol(MIN_DECODABLE_PROTOCOL_VERSION)
+            .is_none());
+    }
+
+    /// The scenarios used to generate the fixtures in `tests/fixtures`.
+    ///
+    /// They must never change, or the replicas they produce won't match the
+    /// ones that were encoded in the fixtures.
+    #[cfg(feature = "serde")]
+    mod scenarios {
+        use cola::Replica;
+
+        pub fn empty() -> Replica {
+            Replica::new(1, 0)
+        }
+
+        pub fn single_run() -> Replica {
+            Replica::new(1, 16)
+        }
+
+        pub fn concurrent() -> Replica {
+            let mut replica1 = Replica::new(1, 10);
+            let mut replica2 = replica1.fork(2);
+            let mut replica3 = replica1.fork(3);
+
+            let _ = replica1.inserted(5, 3);
+            let _ = replica1.deleted(0..2);
+
+            let ins2 = replica2.inserted(0, 4);
+            let ins3 = replica2.inserted(4, 2);
+            let del2 = replica2.deleted(6..9);
+
+            let ins4 = replica3.inserted(10, 5);
+
+            let _ = replica1.integrate_insertion(&ins2);
+            let _ = replica1.integrate_insertion(&ins3);
+            let _ = replica1.integrate_deletion(&del2);
+            let _ = replica1.integrate_insertion(&ins4);
+
+            let _ = replica1.inserted(3, 2);
+
+            replica1
+        }
+
+        pub fn backlog() -> Replica {
+            let mut replica1 = Replica::new(1, 5);
+            let mut replica2 = replica1.fork(2);
+
+            let _ = replica2.inserted(5, 3);
+            let ins2 = replica2.inserted(1, 2);
+            let del = replica2.deleted(0..4);
+
+            assert!(replica1.integrate_insertion(&ins2).is_none());
+            assert!(replica1.integrate_deletion(&del).is_empty());
+
+            replica1
+        }
+
+        pub fn automerge() -> Replica {
+            let automerge = traces::automerge().chars_to_bytes();
+
+            let mut replica = Replica::new(1, automerge.start_content().len());
+
+            for (start, end, text) in automerge.edits().take(5000) {
+                let _ = replica.deleted(start..end);
+                let _ = replica.inserted(start, text.len());
+            }
+
+            replica
+        }
+    }
+
+    /// Tests for the frozen `EncodedReplica`s in `tests/fixtures`, which were
+    /// serialized with `bincode` by the first release of cola using each
+    /// protocol version.
+    #[cfg(feature = "serde")]
+    mod fixtures {
+        use cola::{DecodeError, EncodedReplica, Replica};
+
+        use super::*;
+
+        fn load(bytes: &[u8]) -> EncodedReplica {
+            bincode::deserialize(bytes).unwrap()
+        }
+
+        fn check_fixture(bytes: &[u8], scenario: fn() -> Replica) {
+            let encoded = load(bytes);
+
+            let mut expected = scenario();
+
+            let mut decoded = Replica::decode(42, &encoded).unwrap();
+
+            assert!(expected.eq_decoded(&decoded));
+
+            decoded.check_integrity().unwrap();
+
+            // The decoded replica can keep exchanging edits with the
+            // replica it was encoded from.
+            let insertion = expected.inserted(0, 3);
+
+            assert_eq!(decoded.integrate_insertion(&insertion), Some(0));
+
+            let insertion = decoded.inserted(1, 2);
+
+            assert_eq!(expected.integrate_insertion(&insertion), Some(1));
+        }
+
+        macro_rules! fixture_tests {
+            ($version:literal: $($name:ident),* $(,)?) => {
+                $(
+                    #[test]
+                    fn $name() {
+                        check_fixture(
+                            include_bytes!(concat!(
+                                "fixtures/",
+                                $version,
+                                "/",
+                                stringify!($name),
+                                ".bin"
+                            )),
+                            scenarios::$name,
+                        );
+                    }
+                )*
+            };
+        }
+
+        mod v0 {
+            use super::*;
+
+            fixture_tests!("v0": empty, single_run, concurrent, backlog, automerge);
+
+            #[test]
+            fn corrupted() {
+                let bytes = include_bytes!("fixtures/v0/concurrent.bin");
+
+                let mut encoded = bincode::serialize(&load(bytes)).unwrap();
+
+                // Flip a byte of the encoded replica, skipping the protocol
+                // version and the checksum.
+                let last = encoded.len() - 1;
+                encoded[last] ^= 0xff;
+
+                let encoded: EncodedReplica =
+                    bincode::deserialize(&encoded).unwrap();
+
+                assert_eq!(
+                    Replica::decode(2, &encoded).err(),
+                    Some(DecodeError::ChecksumFailed)
+                );
+            }
+        }
+
+        mod v1 {
+            use super::*;
+
+            fixture_tests!("v1": empty, single_run, concurrent, backlog, automerge);
+
+            /// Encoding the same replica with the same protocol version must
+            /// always produce the same bytes.
+            #[test]
+            fn stable_encoding() {
+                type Scenario = fn() -> Replica;
+
+                let fixtures: [(&[u8], Scenario); 3] = [
+                    (
+                        include_bytes!("fixtures/v1/single_run.bin"),
+                        scenarios::single_run,
+                    ),
+                    (
+                        include_bytes!("fixtures/v1/concurrent.bin"),
+                        scenarios::concurrent,
+                    ),
+                    (
+                        include_bytes!("fixtures/v1/backlog.bin"),
+                        scenarios::backlog,
+                    ),
+                ];
+
+                for (bytes, scenario) in fixtures {
+                    let encoded = scenario().encode_with_protocol(1).unwrap();
+                    assert!(load(bytes) == encoded);
+                }
+            }
+        }
+
+        mod v2 {
+            use super::*;
+
+            fixture_tests!("v2": empty, single_run, concurrent, backlog, automerge);
+
+            #[test]
+            fn stable_encoding() {
+                type Scenario = fn() -> Replica;
+
+                let fixtures: [(&[u8], Scenario); 3] = [
+                    (
+                        include_bytes!("fixtures/v2/single_run.bin"),
+                        scenarios::single_run,
+                    ),
+                    (
+                        include_bytes!("fixtures/v2/concurrent.bin"),
+                        scenarios::concurrent,
+                    ),
+                    (
+                        include_bytes!("fixtures/v2/backlog.bin"),
+                        scenarios::backlog,
+                    ),
+                ];
+
+                for (bytes, scenario) in fixtures {
+                    let encoded = scenario().encode_with_protocol(2).unwrap();
+                    assert!(load(bytes) == encoded);
+                }
+            }
+        }
+
+        #[test]
+        fn newer_protocol() {
+            let encoded = Replica::new(1, 10).encode();
+
+            let mut bytes = bincode::serialize(&encoded).unwrap();
+
+            // The protocol version is the first field of an
+            // `EncodedReplica`.
+            bytes[..8].copy_from_slice(&(PROTOCOL_VERSION + 1).to_le_bytes());
+
+            assert_eq!(
+                Replica::decode(2, &load(&bytes)).err(),
+                Some(DecodeError::DifferentProtocol {
+                    encoded_on: PROTOCOL_VERSION + 1,
+                    decoding_on: PROTOCOL_VERSION,
+                })
+            );
+        }
+    }
+
+    /// Tests for the frozen `Insertion`s and `Deletion`s in `tests/fixtures`,
+    /// which were encoded by the first release of cola using each protocol
+    /// version.
+    mod edit_fixtures {
+        use cola::{Deletion, Insertion, Replica};
+
+        /// The scenario used to generate the edit fixtures. It must never
+        /// change.
+        fn edits() -> (Insertion, Deletion) {
+            let mut replica1 = Replica::new(1, 10);
+            let mut replica2 = replica1.fork(2);
+
+            let insertion = replica2.inserted(3, 4);
+
+            let _ = replica1.integrate_insertion(&insertion);
+
+            let deletion = replica1.deleted(2..8);
+
+            (insertion, deletion)
+        }
+
+        #[test]
+        fn v1() {
+            let (insertion, deletion) = edits();
+
+            let insertion_bytes = include_bytes!("fixtures/v1/insertion.bin");
+            let deletion_bytes = include_bytes!("fixtures/v1/deletion.bin");
+
+            assert_eq!(Insertion::decode(insertion_bytes).unwrap(), insertion);
+            assert_eq!(Deletion::decode(deletion_bytes).unwrap(), deletion);
+        }
+
+        #[test]
+        fn v2() {
+            let (insertion, deletion) = edits();
+
+            let insertion_bytes = include_bytes!("fixtures/v2/insertion.bin");
+            let deletion_bytes = include_bytes!("fixtures/v2/deletion.bin");
+
+            assert_eq!(Insertion::decode(insertion_bytes).unwrap(), insertion);
+            assert_eq!(Deletion::decode(deletion_bytes).unwrap(), deletion);
+
+            assert_eq!(insertion.encode(), insertion_bytes);
+            assert_eq!(deletion.encode(), deletion_bytes);
+        }
+    }
+}
@@ -0,0 +1,324 @@
+#[cfg(feature = "async")]
+mod replica_handle {
+    use std::panic::{self, AssertUnwindSafe};
+
+    use cola::{
+        ActorStopped,
+        CrdtEdit,
+        EditError,
+        Replica,
+        ReplicaHandle,
+        Text,
+        TextChange,
+        TextChanges,
+    };
+    use futures::channel::mpsc;
+    use futures::{SinkExt, StreamExt};
+
+    /// The contents of the given text, which in these tests is always made
+    /// of the same character repeated, chosen based on the peer that
+    /// inserted it.
+    fn contents(text: &Text) -> String {
+        let ch = char::from(b'a' + (text.inserted_by() % 26) as u8);
+        std::iter::repeat_n(ch, text.temporal_range().len()).collect()
+    }
+
+    fn apply(buffer: &mut String, change: TextChange) {
+        match change {
+            TextChange::Inserted(text, offset) => {
+                buffer.insert_str(offset, &contents(&text));
+            },
+
+            TextChange::Deleted(ranges) => {
+                for range in ranges.into_iter().rev() {
+                    buffer.replace_range(range, "");
+                }
+            },
+        }
+    }
+
+    /// A document hosted by a `ReplicaActor`, connected to a remote peer by
+    /// one end of an in-memory duplex channel.
+    struct Peer {
+        buffer: String,
+        handle: ReplicaHandle,
+        changes: TextChanges,
+        outgoing: mpsc::UnboundedSender<CrdtEdit>,
+        incoming: mpsc::UnboundedReceiver<CrdtEdit>,
+    }
+
+    impl Peer {
+        async fn delete(&mut self, start: usize, end: usize) {
+            self.buffer.replace_range(start..end, "");
+            let deletion = self.handle.deleted(start..end).await.unwrap();
+            self.outgoing.send(deletion.into()).await.unwrap();
+        }
+
+        async fn insert(&mut self, offset: usize, len: usize) {
+            let insertion = self.handle.inserted(offset, len).await.unwrap();
+            self.buffer.insert_str(offset, &contents(insertion.text()));
+            self.outgoing.send(insertion.into()).await.unwrap();
+        }
+
+        /// Integrates the given number of remote edits, each of which is
+        /// expected to cause a single change.
+        async fn receive(&mut self, num_edits: usize) {
+            for _ in 0..num_edits {
+                let edit = self.incoming.next().await.unwrap();
+                self.handle.integrate(edit).unwrap();
+                let change = self.changes.next().await.unwrap();
+                apply(&mut self.buffer, change);
+            }
+        }
+    }
+
+    /// Hosts the replicas on the current runtime, connecting them with an
+    /// in-memory duplex channel.
+    async fn connected(replica1: Replica, replica2: Replica) -> (Peer, Peer) {
+        let (outgoing1, incoming2) = mpsc::unbounded();
+        let (outgoing2, incoming1) = mpsc::unbounded();
+
+        let peer1 = host(replica1, outgoing1, incoming1).await;
+        let peer2 = host(replica2, outgoing2, incoming2).await;
+
+        (peer1, peer2)
+    }
+
+    async fn host(
+        replica: Replica,
+        outgoing: mpsc::UnboundedSender<CrdtEdit>,
+        incoming: mpsc::UnboundedReceiver<CrdtEdit>,
+    ) -> Peer {
+        let (handle, actor) = ReplicaHandle::new(replica);
+
+        tokio::spawn(actor);
+
+        let changes = handle.changes().await.unwrap();
+
+        Peer { buffer: String::new(), handle, changes, outgoing, incoming }
+    }
+
+    #[tokio::test]
+    async fn handle_concurrent_edits() {
+        let replica1 = Replica::new(1, 0);
+        let replica2 = replica1.fork(2);
+
+        let (mut peer1, mut peer2) = connected(replica1, replica2).await;
+
+        peer1.insert(0, 3).await;
+        peer2.receive(1).await;
+
+        // Concurrent edits.
+        peer1.insert(1, 2).await;
+        peer2.delete(0, 2).await;
+        peer2.insert(1, 4).await;
+
+        peer1.receive(2).await;
+        peer2.receive(1).await;
+
+        assert_eq!(peer1.buffer, peer2.buffer);
+        assert_eq!(peer1.buffer, "bbbcccc");
+    }
+
+    #[tokio::test]
+    async fn handle_backlogged_edits() {
+        let replica1 = Replica::new(1, 0);
+        let mut replica2 = replica1.fork(2);
+
+        let (handle, actor) = ReplicaHandle::new(replica1);
+
+        tokio::spawn(actor);
+
+        let mut changes = handle.changes().await.unwrap();
+
+        let insertion1 = replica2.inserted(0, 3);
+        let insertion2 = replica2.inserted(3, 2);
+        let deletion = replica2.deleted(1..4);
+
+        // Both the second insertion and the deletion depend on the first
+        // insertion, so they're only integrated after it.
+        handle.integrate(deletion).unwrap();
+        handle.integrate(insertion2).unwrap();
+        handle.integrate(insertion1).unwrap();
+
+        let mut buffer = String::new();
+
+        for _ in 0..3 {
+            apply(&mut buffer, changes.next().await.unwrap());
+        }
+
+        assert_eq!(buffer, "cc");
+    }
+
+    /// Hosts many documents on a single-threaded runtime.
+    #[tokio::test]
+    async fn handle_many_documents() {
+        let replica = Replica::new(1, 0);
+
+        let mut peers = Vec::new();
+
+        for id in 0..100 {
+            let replica1 = replica.fork(2 * id + 2);
+            let replica2 = replica.fork(2 * id + 3);
+            peers.push(connected(replica1, replica2).await);
+        }
+
+        for (peer1, peer2) in &mut peers {
+            peer1.insert(0, 2).await;
+            peer2.insert(0, 3).await;
+        }
+
+        for (peer1, peer2) in &mut peers {
+            peer1.receive(1).await;
+            peer2.receive(1).await;
+            assert_eq!(peer1.buffer, peer2.buffer);
+            assert_eq!(peer1.buffer.len(), 5);
+        }
+    }
+
+    #[tokio::test]
+    async fn handle_actor_stopped() {
+        let (handle, actor) = ReplicaHandle::new(Replica::new(1, 0));
+
+        drop(actor);
+
+        assert_eq!(
+            handle.inserted(0, 1).await.err(),
+            Some(EditError::ActorStopped)
+        );
+        assert_eq!(
+            handle.deleted(..).await.err(),
+            Some(EditError::ActorStopped)
+        );
+        assert_eq!(
+            handle.integrate(Replica::new(2, 0).inserted(0, 1)).err(),
+            Some(ActorStopped)
+        );
+        assert!(handle.changes().await.is_err());
+    }
+
+    /// Out of bounds edits are reported to the caller instead of taking
+    /// down the actor.
+    #[tokio::test]
+    async fn handle_out_of_bounds_edits() {
+        let (handle, actor) = ReplicaHandle::new(Replica::new(1, 3));
+
+        tokio::spawn(actor);
+
+        let out_of_bounds = Some(EditError::OutOfBounds(3));
+
+        assert_eq!(handle.inserted(4, 1).await.err(), out_of_bounds);
+        assert_eq!(handle.deleted(2..4).await.err(), out_of_bounds);
+        let (start, end) = (2, 1);
+        assert_eq!(handle.deleted(start..end).await.err(), out_of_bounds);
+        assert_eq!(handle.deleted(..=usize::MAX).await.err(), out_of_bounds);
+
+        // The actor is still running.
+        let _ = handle.inserted(3, 2).await.unwrap();
+        let _ = handle.deleted(..=4).await.unwrap();
+
+        assert_eq!(handle.fork(2).await.unwrap().len(), 0);
+    }
+
+    #[tokio::test]
+    async fn handle_fork_zero_id() {
+        let (handle, actor) = ReplicaHandle::new(Replica::new(1, 3));
+
+        tokio::spawn(actor);
+
+        let fork_handle = handle.clone();
+
+        let forked = panic::catch_unwind(AssertUnwindSafe(move || {
+            drop(fork_handle.fork(0));
+        }));
+
+        assert!(forked.is_err());
+
+        // The panic happened on the caller's side, so the actor is still
+        // running.
+        assert_eq!(handle.fork(2).await.unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn handle_actor_resolves_to_replica() {
+        let (handle, actor) = ReplicaHandle::new(Replica::new(1, 0));
+
+        let actor = tokio::spawn(actor);
+
+        let mut changes = handle.changes().await.unwrap();
+
+        let _ = handle.inserted(0, 5).await.unwrap();
+        let _ = handle.deleted(1..3).await.unwrap();
+
+        let forked = handle.fork(2).await.unwrap();
+
+        assert_eq!(forked.len(), 3);
+
+        drop(handle);
+
+        let replica = actor.await.unwrap();
+
+        assert_eq!(replica.len(), 3);
+
+        assert!(replica.eq_decoded(&forked));
+
+        // The stream of changes ends together with the actor.
+        assert!(changes.next().await.is_none());
+    }
+
+    /// Sends the encoded edits over a `tokio::io::duplex` byte stream.
+    #[cfg(feature = "encode")]
+    #[tokio::test]
+    async fn handle_io_duplex() {
+        use cola::{Deletion, Insertion};
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let replica1 = Replica::new(1, 0);
+        let replica2 = replica1.fork(2);
+
+        let (handle1, actor1) = ReplicaHandle::new(replica1);
+        let (handle2, actor2) = ReplicaHandle::new(replica2);
+
+        tokio::spawn(actor1);
+        tokio::spawn(actor2);
+
+        let (mut stream1, mut stream2) = tokio::io::duplex(64);
+
+        let mut changes = handle2.changes().await.unwrap();
+
+        let integrator = handle2.clone();
+
+        let reader = tokio::spawn(async move {
+            while let Ok(len) = stream2.read_u32().await {
+                let mut bytes = vec![0; len as usize];
+                stream2.read_exact(&mut bytes).await.unwrap();
+
+                let edit = match Insertion::decode(&bytes) {
+                    Ok(insertion) => CrdtEdit::Insertion(insertion),
+                    Err(_) => Deletion::decode(&bytes).unwrap().into(),
+                };
+
+                integrator.integrate(edit).unwrap();
+            }
+        });
+
+        let insertion = handle1.inserted(0, 10).await.unwrap().encode();
+        let deletion = handle1.deleted(2..8).await.unwrap().encode();
+
+        for bytes in [insertion, deletion] {
+            stream1.write_u32(bytes.len() as u32).await.unwrap();
+            stream1.write_all(&bytes).await.unwrap();
+        }
+
+        drop(stream1);
+
+        reader.await.unwrap();
+
+        let mut buffer = String::new();
+
+        apply(&mut buffer, changes.next().await.unwrap());
+        apply(&mut buffer, changes.next().await.unwrap());
+
+        assert_eq!(buffer, "bbbb");
+    }
+}
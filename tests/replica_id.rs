@@ -0,0 +1,297 @@
+use cola::{
+    InsertionError,
+    Length,
+    Replica,
+    ReplicaIdAllocator,
+    ReplicaIdCollision,
+};
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+fn unwrap_collision(
+    result: Result<Option<Length>, InsertionError>,
+) -> ReplicaIdCollision {
+    match result {
+        Err(InsertionError::ReplicaIdCollision(collision)) => collision,
+        other => panic!("expected a ReplicaIdCollision, got {other:?}"),
+    }
+}
+
+#[test]
+fn allocator_unique_ids() {
+    let mut ids = ReplicaIdAllocator::new();
+
+    let mut allocated = (0..100).map(|_| ids.allocate()).collect::<Vec<_>>();
+
+    assert_eq!(allocated[0], 1);
+
+    assert_eq!(ids.next_id(), 101);
+
+    let mut resumed = ReplicaIdAllocator::starting_from(ids.next_id());
+
+    allocated.extend((0..100).map(|_| resumed.allocate()));
+
+    allocated.sort_unstable();
+    allocated.dedup();
+
+    assert_eq!(allocated.len(), 200);
+}
+
+#[test]
+#[should_panic]
+fn allocator_starting_from_zero() {
+    let _ = ReplicaIdAllocator::starting_from(0);
+}
+
+/// A replica decoded from an outdated snapshot of another replica with the
+/// same id reuses temporal ranges that were already taken.
+#[cfg(feature = "encode")]
+#[test]
+fn collision_decoded_from_outdated_snapshot() {
+    let mut replica1 = Replica::new(1, 3);
+
+    let mut replica2 = replica1.fork(2);
+
+    let _ = replica1.integrate_insertion(&replica2.inserted(0, 1));
+
+    let snapshot = replica2.encode();
+
+    let _ = replica1.integrate_insertion(&replica2.inserted(4, 1));
+
+    let mut decoded = Replica::decode(2, &snapshot).unwrap();
+
+    let insertion = decoded.inserted(0, 3);
+
+    let collision =
+        unwrap_collision(replica1.try_integrate_insertion(&insertion));
+
+    assert_eq!(collision.replica_id(), 2);
+    assert_eq!(collision.temporal_range(), 1..4);
+}
+
+/// A replica can't be decoded with the id of a peer whose edits are still in
+/// the backlog, since its own edits would collide with them.
+#[cfg(feature = "encode")]
+#[test]
+fn collision_decoded_with_backlogged_id() {
+    let mut replica1 = Replica::new(1, 3);
+
+    let mut replica2 = replica1.fork(2);
+
+    let _ = replica2.inserted(0, 1);
+
+    let insertion = replica2.inserted(1, 1);
+
+    assert_eq!(replica1.integrate_insertion(&insertion), None);
+
+    let encoded = replica1.encode();
+
+    assert_eq!(
+        Replica::decode(2, &encoded).unwrap_err(),
+        cola::DecodeError::InvalidData
+    );
+
+    assert!(Replica::decode(3, &encoded).is_ok());
+}
+
+/// Two replicas sharing an id make insertions that are both backlogged.
+#[test]
+fn collision_in_backlog() {
+    let mut replica1 = Replica::new(1, 3);
+
+    let mut replica2 = replica1.fork(2);
+    let mut impostor = replica1.fork(2);
+
+    let _ = replica2.inserted(0, 1);
+    let _ = impostor.inserted(0, 2);
+
+    let insertion = replica2.inserted(1, 2);
+
+    assert_eq!(replica1.try_integrate_insertion(&insertion), Ok(None));
+
+    // Backlogging the same insertion twice is fine.
+    assert_eq!(replica1.try_integrate_insertion(&insertion), Ok(None));
+
+    let insertion = impostor.inserted(2, 2);
+
+    let collision =
+        unwrap_collision(replica1.try_integrate_insertion(&insertion));
+
+    assert_eq!(collision.temporal_range(), 2..4);
+
+    assert_eq!(replica1.stats().backlogged_insertions(), 1);
+}
+
+#[test]
+fn collision_with_own_id() {
+    let replica1 = Replica::new(1, 3);
+
+    let mut replica2 = replica1.fork(2);
+
+    // A replica with the same id as replica 2.
+    let mut impostor = replica1.fork(2);
+
+    let insertion = impostor.inserted(1, 1);
+
+    let collision =
+        unwrap_collision(replica2.try_integrate_insertion(&insertion));
+
+    assert_eq!(collision.replica_id(), 2);
+
+    // Insertions made by the replica itself are just duplicates.
+    let insertion = replica2.inserted(1, 1);
+
+    assert_eq!(replica2.try_integrate_insertion(&insertion), Ok(None));
+}
+
+#[test]
+fn collision_ignored_by_integrate_insertion() {
+    let mut replica1 = Replica::new(1, 3);
+
+    let mut replica2 = replica1.fork(2);
+    let mut impostor = replica1.fork(2);
+
+    let _ = replica1.integrate_insertion(&replica2.inserted(0, 1));
+    let _ = replica1.integrate_insertion(&replica2.inserted(4, 1));
+
+    let insertion = impostor.inserted(1, 3);
+
+    assert_eq!(replica1.integrate_insertion(&insertion), None);
+
+    assert_eq!(replica1.len(), 5);
+
+    assert_eq!(replica1.stats().backlogged_insertions(), 0);
+
+    replica1.check_integrity().unwrap();
+}
+
+/// The impostor's insertion has the next temporal offset and the same run
+/// timestamp as the last run of replica 2, but it doesn't continue it.
+#[test]
+fn collision_not_continuing_last_run() {
+    let mut replica1 = Replica::new(1, 3);
+
+    let mut replica2 = replica1.fork(2);
+    let mut impostor = replica1.fork(2);
+
+    let _ = replica1.integrate_insertion(&replica2.inserted(0, 2));
+    let _ = replica1.integrate_insertion(&replica2.inserted(0, 1));
+
+    let _ = impostor.inserted(0, 3);
+
+    let insertion = impostor.inserted(0, 1);
+
+    let collision =
+        unwrap_collision(replica1.try_integrate_insertion(&insertion));
+
+    assert_eq!(collision.temporal_range(), 3..4);
+
+    replica1.check_integrity().unwrap();
+}
+
+/// The impostor deletes a run of text that replica 1 has never seen, even
+/// though it has seen the same temporal range from replica 2.
+#[test]
+fn collision_deletion_with_unknown_anchors() {
+    let mut replica1 = Replica::new(1, 3);
+
+    let mut replica2 = replica1.fork(2);
+    let mut impostor = replica1.fork(2);
+
+    let _ = replica1.integrate_insertion(&replica2.inserted(0, 2));
+
+    let _ = impostor.inserted(0, 1);
+    let _ = impostor.inserted(0, 1);
+
+    let deletion = impostor.deleted(0..1);
+
+    assert!(replica1.integrate_deletion(&deletion).is_empty());
+
+    assert_eq!(replica1.len(), 5);
+
+    replica1.check_integrity().unwrap();
+}
+
+/// Checks that duplicated and reordered insertions made by replicas with
+/// unique ids are never mistaken for collisions.
+#[test]
+fn collision_no_false_positives() {
+    for seed in 0..20 {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+
+        let mut ids = ReplicaIdAllocator::new();
+
+        let first = Replica::new(ids.allocate(), 0);
+
+        let mut peers =
+            (0..3).map(|_| first.fork(ids.allocate())).collect::<Vec<_>>();
+
+        let mut receiver = first.fork(ids.allocate());
+
+        let mut insertions = Vec::new();
+
+        for _ in 0..100 {
+            let peer = peers.choose_mut(&mut rng).unwrap();
+            let offset = rng.gen_range(0..=peer.len());
+            let insertion = peer.inserted(offset, rng.gen_range(1..4));
+
+            if rng.gen_bool(0.2) {
+                insertions.push(insertion.clone());
+            }
+
+            insertions.push(insertion);
+        }
+
+        insertions.shuffle(&mut rng);
+
+        for insertion in &insertions {
+            assert!(
+                receiver.try_integrate_insertion(insertion).is_ok(),
+                "seed: {seed}"
+            );
+
+            receiver.backlogged_insertions().for_each(drop);
+        }
+
+        // Integrating everything again only finds duplicates.
+        for insertion in &insertions {
+            assert_eq!(receiver.try_integrate_insertion(insertion), Ok(None));
+        }
+
+        let len = peers.iter().map(Replica::len).sum::<usize>();
+
+        assert_eq!(receiver.len(), len, "seed: {seed}");
+
+        assert_eq!(receiver.stats().backlogged_insertions(), 0);
+    }
+}
+
+/// An insertion whose anchor points to a run that doesn't exist is reported
+/// separately from a collision.
+#[cfg(feature = "encode")]
+#[test]
+fn unknown_anchor() {
+    let mut replica1 = Replica::new(1, 3);
+
+    let mut replica2 = replica1.fork(2);
+
+    let mut bytes = replica2.inserted(1, 1).encode();
+
+    // The anchor is made of the `ReplicaId` and offset of the character
+    // it's attached to, followed by the run timestamp of its run.
+    assert_eq!(bytes[2..5], [1, 1, 0]);
+
+    bytes[4] = 5;
+
+    let insertion = cola::Insertion::decode(&bytes).unwrap();
+
+    assert_eq!(
+        replica1.try_integrate_insertion(&insertion),
+        Err(InsertionError::UnknownAnchor(insertion.text().clone()))
+    );
+
+    assert_eq!(replica1.len(), 3);
+
+    replica1.check_integrity().unwrap();
+}
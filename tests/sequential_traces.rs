@@ -29,6 +29,27 @@ fn test_trace(trace: &SequentialTrace) {
     }
 }
 
+fn test_trace_with_arity<const ARITY: usize>(trace: &SequentialTrace) {
+    let trace = trace.chars_to_bytes();
+
+    let mut replica =
+        cola::Replica::<ARITY>::new_with_arity(1, trace.start_content().len());
+
+    for (start, end, text) in trace.edits() {
+        if end > start {
+            let _ = replica.deleted(start..end);
+        }
+
+        if !text.is_empty() {
+            let _ = replica.inserted(start, text.len());
+        }
+    }
+
+    replica.check_integrity().unwrap();
+
+    assert_eq!(replica.len(), trace.end_content().len());
+}
+
 #[test]
 fn trace_automerge() {
     test_trace(&traces::automerge());
@@ -48,3 +69,13 @@ fn trace_seph_blog() {
 fn trace_sveltecomponent() {
     test_trace(&traces::sveltecomponent());
 }
+
+#[test]
+fn trace_automerge_arity_4() {
+    test_trace_with_arity::<4>(&traces::automerge());
+}
+
+#[test]
+fn trace_seph_blog_arity_128() {
+    test_trace_with_arity::<128>(&traces::seph_blog());
+}
@@ -0,0 +1,397 @@
+use std::cell::RefCell;
+use std::convert::Infallible;
+use std::rc::Rc;
+use std::sync::mpsc;
+
+use cola::{
+    ChannelTransport,
+    Message,
+    Replica,
+    ReplicaId,
+    Session,
+    Text,
+    TextChange,
+    Transport,
+};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+/// The contents of the given text, which in these tests is always made of
+/// the same character repeated, chosen based on the peer that inserted it.
+fn contents(text: &Text) -> String {
+    let ch = char::from(b'a' + text.inserted_by() as u8);
+    std::iter::repeat_n(ch, text.temporal_range().len()).collect()
+}
+
+fn apply(buffer: &mut String, changes: Vec<TextChange>) {
+    for change in changes {
+        match change {
+            TextChange::Inserted(text, offset) => {
+                buffer.insert_str(offset, &contents(&text));
+            },
+
+            TextChange::Deleted(ranges) => {
+                for range in ranges.into_iter().rev() {
+                    buffer.replace_range(range, "");
+                }
+            },
+        }
+    }
+}
+
+/// An in-memory network connecting a number of peers which drops,
+/// duplicates and reorders the messages sent through it.
+struct Network {
+    rng: ChaCha8Rng,
+    inboxes: Vec<Vec<Message>>,
+    drop_probability: f64,
+    duplicate_probability: f64,
+}
+
+impl Network {
+    fn new(num_peers: usize, seed: u64) -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(Self {
+            rng: ChaCha8Rng::seed_from_u64(seed),
+            inboxes: vec![Vec::new(); num_peers],
+            drop_probability: 0.3,
+            duplicate_probability: 0.1,
+        }))
+    }
+
+    fn make_reliable(&mut self) {
+        self.drop_probability = 0.0;
+        self.duplicate_probability = 0.0;
+    }
+}
+
+struct LossyTransport {
+    network: Rc<RefCell<Network>>,
+    idx: usize,
+}
+
+impl Transport for LossyTransport {
+    type Error = Infallible;
+
+    fn send(&mut self, message: Message) -> Result<(), Infallible> {
+        let network = &mut *self.network.borrow_mut();
+
+        for idx in 0..network.inboxes.len() {
+            if idx == self.idx {
+                continue;
+            }
+
+            if network.rng.gen_bool(network.drop_probability) {
+                continue;
+            }
+
+            network.inboxes[idx].push(message.clone());
+
+            if network.rng.gen_bool(network.duplicate_probability) {
+                network.inboxes[idx].push(message.clone());
+            }
+        }
+
+        Ok(())
+    }
+
+    fn try_recv(&mut self) -> Result<Option<Message>, Infallible> {
+        let network = &mut *self.network.borrow_mut();
+
+        let inbox = &mut network.inboxes[self.idx];
+
+        if inbox.is_empty() {
+            return Ok(None);
+        }
+
+        let idx = network.rng.gen_range(0..inbox.len());
+
+        Ok(Some(inbox.swap_remove(idx)))
+    }
+}
+
+struct Peer<T: Transport> {
+    buffer: String,
+    session: Session<T>,
+}
+
+impl<T: Transport> Peer<T>
+where
+    T::Error: core::fmt::Debug,
+{
+    fn delete(&mut self, rng: &mut impl Rng) {
+        let start = rng.gen_range(0..self.buffer.len());
+        let end = rng.gen_range(start..=self.buffer.len().min(start + 5));
+        self.buffer.replace_range(start..end, "");
+        self.session.deleted(start..end).unwrap();
+    }
+
+    fn insert(&mut self, rng: &mut impl Rng) {
+        let offset = rng.gen_range(0..=self.buffer.len());
+        let len = rng.gen_range(1..5);
+        let id = self.session.replica().id();
+        let ch = char::from(b'a' + id as u8);
+        let text = std::iter::repeat_n(ch, len).collect::<String>();
+        self.buffer.insert_str(offset, &text);
+        self.session.inserted(offset, len).unwrap();
+    }
+
+    fn receive(&mut self) {
+        let changes = self.session.receive().unwrap();
+        apply(&mut self.buffer, changes);
+    }
+}
+
+fn lossy_peers(
+    num_peers: u64,
+    seed: u64,
+) -> (Vec<Peer<LossyTransport>>, Rc<RefCell<Network>>) {
+    let network = Network::new(num_peers as usize, seed);
+
+    // The peers are forked from a replica that never edits the document.
+    let first = Replica::new(1, 0);
+
+    let peers = (2..num_peers + 2)
+        .map(|id| {
+            let transport = LossyTransport {
+                network: Rc::clone(&network),
+                idx: (id - 2) as usize,
+            };
+
+            let mut session = Session::new(first.fork(id), transport);
+
+            for peer in 2..num_peers + 2 {
+                session.add_peer(peer as ReplicaId);
+            }
+
+            Peer { buffer: String::new(), session }
+        })
+        .collect();
+
+    (peers, network)
+}
+
+/// Drives three peers editing concurrently over a network that drops,
+/// duplicates and reorders messages, and checks that they converge once the
+/// network stops losing them.
+#[test]
+fn session_lossy_network() {
+    for seed in 0..20 {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+
+        let (mut peers, network) = lossy_peers(3, seed);
+
+        for _ in 0..200 {
+            let peer = &mut peers[rng.gen_range(0..3)];
+
+            match rng.gen_range(0..5) {
+                0 if !peer.buffer.is_empty() => peer.delete(&mut rng),
+                0 | 1 => peer.insert(&mut rng),
+                2 => peer.receive(),
+                3 => {
+                    let Ok(()) = peer.session.resend();
+                },
+                _ => {},
+            }
+        }
+
+        network.borrow_mut().make_reliable();
+
+        // Two rounds of resending are enough for every edit to be received
+        // and acknowledged.
+        for _ in 0..2 {
+            for peer in &mut peers {
+                let Ok(()) = peer.session.resend();
+            }
+
+            for peer in &mut peers {
+                peer.receive();
+            }
+        }
+
+        for peer in &mut peers {
+            peer.receive();
+        }
+
+        for peer in &peers {
+            assert_eq!(peer.buffer, peers[0].buffer, "seed: {seed}");
+            assert_eq!(peer.session.unacked(), 0, "seed: {seed}");
+            assert_eq!(
+                peer.session.replica().stats().backlogged_insertions(),
+                0
+            );
+            assert_eq!(
+                peer.session.replica().stats().backlogged_deletions(),
+                0
+            );
+        }
+    }
+}
+
+#[test]
+fn session_channels() {
+    let replica = Replica::new(1, 0);
+
+    let mut peers = ChannelTransport::mesh(3)
+        .into_iter()
+        .zip(2..)
+        .map(|(transport, id)| Peer {
+            buffer: String::new(),
+            session: Session::new(replica.fork(id), transport),
+        })
+        .collect::<Vec<_>>();
+
+    let mut rng = ChaCha8Rng::seed_from_u64(42);
+
+    for _ in 0..100 {
+        let peer = &mut peers[rng.gen_range(0..3)];
+
+        if !peer.buffer.is_empty() && rng.gen_bool(0.3) {
+            peer.delete(&mut rng);
+        } else {
+            peer.insert(&mut rng);
+        }
+
+        if rng.gen_bool(0.5) {
+            peers[rng.gen_range(0..3)].receive();
+        }
+    }
+
+    // First receive all the edits, then all the acks.
+    for _ in 0..2 {
+        for peer in &mut peers {
+            peer.receive();
+        }
+    }
+
+    for peer in &peers {
+        assert_eq!(peer.buffer, peers[0].buffer);
+        assert_eq!(peer.session.unacked(), 0);
+    }
+}
+
+#[test]
+fn session_duplicates_ignored() {
+    let (sender1, receiver1) = mpsc::channel();
+    let (sender2, receiver2) = mpsc::channel();
+
+    let mut peer1 = Session::new(
+        Replica::new(1, 0),
+        ChannelTransport::new(vec![sender2], receiver1),
+    );
+
+    let mut peer2 = Session::new(
+        peer1.replica().fork(2),
+        ChannelTransport::new(Vec::new(), receiver2),
+    );
+
+    let Ok(()) = peer1.inserted(0, 3);
+
+    let Ok(changes) = peer2.receive();
+
+    assert_eq!(changes.len(), 1);
+
+    // The ack never reaches peer 1, so it sends the insertion again.
+    let Ok(()) = peer1.resend();
+
+    let Ok(changes) = peer2.receive();
+
+    assert!(changes.is_empty());
+
+    assert_eq!(peer1.unacked(), 1);
+
+    // The insertion is only forgotten once peer 2 acknowledges it.
+    let ack = Message::Ack { sender: 2, received: vec![(1, 1)] };
+
+    sender1.send(ack.clone()).unwrap();
+    sender1.send(ack).unwrap();
+
+    let Ok(changes) = peer1.receive();
+
+    assert!(changes.is_empty());
+
+    assert_eq!(peer1.unacked(), 0);
+}
+
+#[test]
+fn session_backlog_drained() {
+    let replica = Replica::new(1, 0);
+
+    let (sender, receiver) = mpsc::channel();
+
+    let mut peer2 = Session::new(
+        replica.fork(2),
+        ChannelTransport::new(Vec::new(), receiver),
+    );
+
+    let mut replica3 = replica.fork(3);
+
+    let edits = [
+        replica3.inserted(0, 3).into(),
+        replica3.inserted(3, 2).into(),
+        replica3.deleted(1..4).into(),
+    ];
+
+    // Deliver the edits in reverse order, so that the first two are
+    // backlogged until the last one is received.
+    for (seq, edit) in edits.into_iter().enumerate().rev() {
+        let seq = seq as u64 + 1;
+        sender.send(Message::Edit { sender: 3, seq, edit }).unwrap();
+    }
+
+    let Ok(changes) = peer2.receive();
+
+    let mut buffer = String::new();
+
+    apply(&mut buffer, changes);
+
+    assert_eq!(buffer, "dd");
+    assert_eq!(peer2.replica().len(), 2);
+}
+
+#[cfg(feature = "futures")]
+mod futures {
+    use ::futures::channel::mpsc;
+    use cola::SinkStreamTransport;
+
+    use super::*;
+
+    #[test]
+    fn session_sink_stream() {
+        let (sink1, stream2) = mpsc::unbounded();
+        let (sink2, stream1) = mpsc::unbounded();
+
+        let replica = Replica::new(1, 0);
+
+        let mut peer1 = Peer {
+            buffer: String::new(),
+            session: Session::new(
+                replica.fork(2),
+                SinkStreamTransport::new(sink1, stream1),
+            ),
+        };
+
+        let mut peer2 = Peer {
+            buffer: String::new(),
+            session: Session::new(
+                replica.fork(3),
+                SinkStreamTransport::new(sink2, stream2),
+            ),
+        };
+
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+
+        for _ in 0..20 {
+            peer1.insert(&mut rng);
+            peer2.insert(&mut rng);
+            peer2.delete(&mut rng);
+        }
+
+        peer1.receive();
+        peer2.receive();
+        peer1.receive();
+
+        assert_eq!(peer1.buffer, peer2.buffer);
+        assert_eq!(peer1.session.unacked(), 0);
+        assert_eq!(peer2.session.unacked(), 0);
+    }
+}
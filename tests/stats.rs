@@ -0,0 +1,83 @@
+use cola::Replica;
+
+#[test]
+fn stats_new() {
+    let replica = Replica::new(1, 42);
+
+    let stats = replica.stats();
+
+    assert_eq!(stats.len(), 42);
+    assert_eq!(stats.tree_depth(), 1);
+    assert_eq!(stats.visible_runs(), 1);
+    assert_eq!(stats.tombstoned_runs(), 0);
+    assert_eq!(stats.tombstone_ratio(), 0.0);
+    assert_eq!(stats.known_replicas(), 1);
+    assert_eq!(stats.backlogged_insertions(), 0);
+    assert_eq!(stats.backlogged_deletions(), 0);
+}
+
+#[test]
+fn stats_tombstone_ratio() {
+    let mut replica = Replica::new(1, 10);
+
+    let _ = replica.deleted(2..4);
+    let _ = replica.deleted(4..6);
+
+    let stats = replica.stats();
+
+    assert_eq!(stats.len(), 6);
+    assert_eq!(stats.visible_runs(), 3);
+    assert_eq!(stats.tombstoned_runs(), 2);
+    assert_eq!(stats.tombstone_ratio(), 0.4);
+}
+
+#[test]
+fn stats_backlog() {
+    let mut replica1 = Replica::new(1, 0);
+    let mut replica2 = replica1.fork(2);
+
+    let insertion = replica1.inserted(0, 1);
+    let deletion = replica1.deleted(0..1);
+    let _ = replica1.inserted(0, 1);
+    let backlogged_insertion = replica1.inserted(1, 1);
+
+    let _ = replica2.integrate_deletion(&deletion);
+    let _ = replica2.integrate_insertion(&backlogged_insertion);
+
+    let stats = replica2.stats();
+    assert_eq!(stats.known_replicas(), 2);
+    assert_eq!(stats.backlogged_insertions(), 1);
+    assert_eq!(stats.backlogged_deletions(), 1);
+
+    let _ = replica2.integrate_insertion(&insertion);
+    let _ = replica2.backlogged_deletions().count();
+
+    let stats = replica2.stats();
+    assert_eq!(stats.backlogged_insertions(), 1);
+    assert_eq!(stats.backlogged_deletions(), 0);
+
+    replica2.check_integrity().unwrap();
+}
+
+#[test]
+fn stats_tree_depth() {
+    let automerge = traces::automerge().chars_to_bytes();
+
+    let mut narrow = Replica::<4>::new_with_arity(1, 0);
+    let mut wide = Replica::<64>::new_with_arity(1, 0);
+
+    for (start, end, text) in automerge.edits() {
+        let _ = narrow.deleted(start..end);
+        let _ = narrow.inserted(start, text.len());
+        let _ = wide.deleted(start..end);
+        let _ = wide.inserted(start, text.len());
+    }
+
+    let narrow = narrow.stats();
+    let wide = wide.stats();
+
+    assert_eq!(narrow.len(), wide.len());
+    assert!(narrow.tree_depth() > wide.tree_depth());
+    assert!(narrow.average_inode_occupancy() <= 4.0);
+    assert!(wide.average_inode_occupancy() > 4.0);
+}
@@ -0,0 +1,320 @@
+#[cfg(feature = "yjs")]
+mod common;
+
+#[cfg(feature = "yjs")]
+mod yjs {
+    use cola::{Replica, YjsError, MAX_YJS_CLIENT_ID};
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
+
+    use super::common;
+
+    /// Checks that the update decodes to the given text, both as a `Replica`
+    /// and as a trace imported by the `traces` crate.
+    fn check_update(update: &[u8], text: &str) {
+        let (replica, decoded) =
+            Replica::decode_yjs_update(42, update, "text").unwrap();
+
+        assert_eq!(decoded, text);
+        assert_eq!(replica.len(), text.encode_utf16().count());
+        replica.check_integrity().unwrap();
+
+        let trace = traces::import_yjs::<common::Replica, _>([update], "text")
+            .unwrap();
+
+        assert_eq!(trace.final_content, text);
+    }
+
+    /// The scenarios used to generate the fixtures in `tests/fixtures/yjs`.
+    ///
+    /// They must never change, or the updates they produce won't match the
+    /// ones that were recorded in the fixtures.
+    mod scenarios {
+        use super::common::Replica;
+
+        pub fn single_run() -> Replica {
+            let mut replica = Replica::new(1, "");
+            let _ = replica.insert(0, "Hello world");
+            replica
+        }
+
+        pub fn deleted() -> Replica {
+            let mut replica = Replica::new(1, "Hello world");
+            let _ = replica.insert(5, ",");
+            let _ = replica.delete(7..10);
+            let _ = replica.insert(7, "Wo");
+            let _ = replica.delete(0..1);
+            replica
+        }
+
+        pub fn concurrent() -> Replica {
+            let mut replica1 = Replica::new(1, "aaaaaaaaaa");
+            let mut replica2 = replica1.fork(2);
+            let mut replica3 = replica1.fork(3);
+
+            let _ = replica1.insert(5, "bbb");
+            let _ = replica1.delete(0..2);
+
+            let ins2 = replica2.insert(0, "cccc");
+            let ins3 = replica2.insert(4, "dd");
+            let del2 = replica2.delete(6..9);
+
+            let ins4 = replica3.insert(10, "eeeee");
+
+            // Concurrent insertions at the same offset.
+            let ins5 = replica3.insert(5, "ff");
+            let ins6 = replica2.insert(9, "gg");
+
+            replica1.merge(&ins2);
+            replica1.merge(&ins3);
+            replica1.merge(&del2);
+            replica1.merge(&ins4);
+            replica1.merge(&ins5);
+            replica1.merge(&ins6);
+
+            let _ = replica1.insert(3, "hh");
+
+            replica1
+        }
+    }
+
+    /// Tests for the updates in `tests/fixtures/yjs/export`, which were
+    /// encoded from the scenarios, and for the text Yjs decodes from them.
+    mod export {
+        use super::*;
+
+        fn check_fixture(
+            bytes: &[u8],
+            yjs_text: &str,
+            scenario: fn() -> common::Replica,
+        ) {
+            let replica = scenario();
+
+            assert_eq!(replica.buffer, yjs_text);
+
+            let update = replica
+                .crdt
+                .encode_yjs_update(&replica.buffer, "text")
+                .unwrap();
+
+            assert_eq!(update, bytes);
+            check_update(&update, &replica.buffer);
+        }
+
+        macro_rules! fixture_tests {
+            ($($name:ident),* $(,)?) => {
+                $(
+                    #[test]
+                    fn $name() {
+                        check_fixture(
+                            include_bytes!(concat!(
+                                "fixtures/yjs/export/",
+                                stringify!($name),
+                                ".bin"
+                            )),
+                            include_str!(concat!(
+                                "fixtures/yjs/export/",
+                                stringify!($name),
+                                ".txt"
+                            )),
+                            scenarios::$name,
+                        );
+                    }
+                )*
+            };
+        }
+
+        fixture_tests!(single_run, deleted, concurrent);
+
+        #[test]
+        fn empty() {
+            let replica = Replica::new(1, 0);
+
+            let update = replica.encode_yjs_update("", "text").unwrap();
+
+            assert_eq!(update, [0, 0]);
+        }
+
+        #[test]
+        fn utf16() {
+            let mut replica = Replica::new(1, 0);
+
+            let text = "a😀b";
+
+            let _ = replica.inserted(0, 4);
+
+            let update = replica.encode_yjs_update(text, "text").unwrap();
+
+            check_update(&update, text);
+        }
+
+        #[test]
+        #[should_panic]
+        fn utf16_length_mismatch() {
+            let replica = Replica::new(1, 6);
+            let _ = replica.encode_yjs_update("a😀b", "text");
+        }
+
+        #[test]
+        fn replica_id_too_large() {
+            let replica = Replica::new(MAX_YJS_CLIENT_ID + 1, 3);
+
+            assert_eq!(
+                replica.encode_yjs_update("abc", "text"),
+                Err(YjsError::ReplicaIdTooLarge(MAX_YJS_CLIENT_ID + 1))
+            );
+
+            let replica = Replica::new(MAX_YJS_CLIENT_ID, 3);
+
+            assert!(replica.encode_yjs_update("abc", "text").is_ok());
+        }
+
+        /// Exports the replicas of random concurrent sessions and checks that
+        /// the updates decode to their buffers.
+        #[test]
+        fn random() {
+            let mut rng = ChaCha8Rng::seed_from_u64(0x7a5);
+
+            for _ in 0..50 {
+                let mut replicas = vec![common::Replica::new(1, "abcdef")];
+
+                for id in 2..=4 {
+                    replicas.push(replicas[0].fork(id));
+                }
+
+                // The edits each replica has yet to merge, in the order
+                // they were made.
+                let mut pending = vec![Vec::new(); replicas.len()];
+
+                for _ in 0..40 {
+                    let idx = rng.gen_range(0..replicas.len());
+
+                    if rng.gen_bool(0.3) {
+                        for edit in pending[idx].drain(..) {
+                            replicas[idx].merge(&edit);
+                        }
+                    }
+
+                    let edit = replicas[idx].random_edit(&mut rng, 5, 3);
+                    let edit = replicas[idx].edit(edit);
+
+                    for (other, edits) in pending.iter_mut().enumerate() {
+                        if other != idx {
+                            edits.push(edit.clone());
+                        }
+                    }
+
+                    let update = replicas[idx]
+                        .crdt
+                        .encode_yjs_update(&replicas[idx].buffer, "text")
+                        .unwrap();
+
+                    check_update(&update, &replicas[idx].buffer);
+                }
+
+                for (replica, edits) in replicas.iter_mut().zip(pending) {
+                    for edit in edits {
+                        replica.merge(&edit);
+                    }
+                }
+
+                for replica in &replicas {
+                    assert_eq!(replica.buffer, replicas[0].buffer);
+
+                    let update = replica
+                        .crdt
+                        .encode_yjs_update(&replica.buffer, "text")
+                        .unwrap();
+
+                    check_update(&update, &replica.buffer);
+                }
+            }
+        }
+    }
+
+    /// Tests for the updates in `tests/fixtures/yjs/import`, which were
+    /// recorded with Yjs together with the text it decodes from them.
+    mod import {
+        use super::*;
+
+        fn import(bytes: &[u8], yjs_text: &str) -> Replica {
+            check_update(bytes, yjs_text);
+            Replica::decode_yjs_update(42, bytes, "text").unwrap().0
+        }
+
+        #[test]
+        fn single_client() {
+            let mut replica = import(
+                include_bytes!("fixtures/yjs/import/single_client.bin"),
+                include_str!("fixtures/yjs/import/single_client.txt"),
+            );
+
+            // The imported replica can keep editing the document, and its
+            // edits can be exported back.
+            let _ = replica.inserted(3, 3);
+            let _ = replica.deleted(0..1);
+
+            let update = replica.encode_yjs_update("bcdef", "text").unwrap();
+
+            check_update(&update, "bcdef");
+        }
+
+        #[test]
+        fn concurrent() {
+            let text = include_str!("fixtures/yjs/import/concurrent.txt");
+
+            let replica = import(
+                include_bytes!("fixtures/yjs/import/concurrent.bin"),
+                text,
+            );
+
+            // Exporting the imported replica gives back the same text.
+            let update = replica.encode_yjs_update(text, "text").unwrap();
+
+            check_update(&update, text);
+        }
+
+        #[test]
+        fn conflict() {
+            import(
+                include_bytes!("fixtures/yjs/import/conflict.bin"),
+                include_str!("fixtures/yjs/import/conflict.txt"),
+            );
+        }
+
+        #[test]
+        fn discarded_content() {
+            import(
+                include_bytes!("fixtures/yjs/import/discarded_content.bin"),
+                include_str!("fixtures/yjs/import/discarded_content.txt"),
+            );
+        }
+
+        #[test]
+        fn missing_type() {
+            let bytes =
+                include_bytes!("fixtures/yjs/import/single_client.bin");
+
+            assert_eq!(
+                Replica::decode_yjs_update(42, bytes, "notes").unwrap_err(),
+                YjsError::MissingType
+            );
+        }
+
+        #[test]
+        fn truncated() {
+            let bytes =
+                include_bytes!("fixtures/yjs/import/single_client.bin");
+
+            assert_eq!(
+                Replica::decode_yjs_update(
+                    42,
+                    &bytes[..bytes.len() - 3],
+                    "text"
+                )
+                .unwrap_err(),
+                YjsError::InvalidUpdate
+            );
+        }
+    }
+}
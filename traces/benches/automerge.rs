@@ -0,0 +1,84 @@
+//! Runs the sequential traces on Automerge, to compare it with cola's
+//! `traces` benchmarks.
+//!
+//! Run with `cargo bench -p traces --features automerge`.
+
+use automerge::transaction::Transactable;
+use automerge::{AutoCommit, Automerge, ObjType, ReadDoc, ROOT};
+use criterion::measurement::WallTime;
+use criterion::{
+    criterion_group,
+    criterion_main,
+    BenchmarkGroup,
+    BenchmarkId,
+    Criterion,
+    Throughput,
+};
+use traces::SequentialTrace;
+
+/// Runs the same benchmarks as cola's `upstream` and `downstream` ones on
+/// Automerge, whose positions are in characters instead of bytes.
+fn bench_automerge(
+    group: &mut BenchmarkGroup<WallTime>,
+    trace: &SequentialTrace,
+    trace_name: &str,
+) {
+    let apply = |doc: &mut AutoCommit, commit: bool| {
+        let text = doc.put_object(ROOT, "text", ObjType::Text).unwrap();
+        doc.splice_text(&text, 0, 0, trace.start_content()).unwrap();
+
+        for (start, end, insert) in trace.edits() {
+            let del = (end - start) as isize;
+            doc.splice_text(&text, start, del, insert).unwrap();
+            if commit {
+                doc.commit();
+            }
+        }
+
+        text
+    };
+
+    group.throughput(Throughput::Elements(trace.num_edits() as u64));
+
+    group.bench_function(BenchmarkId::new("upstream", trace_name), |b| {
+        b.iter(|| {
+            let mut doc = AutoCommit::new();
+            let text = apply(&mut doc, false);
+            assert_eq!(doc.length(&text), trace.end_content().chars().count());
+        })
+    });
+
+    let mut upstream = AutoCommit::new();
+    let text = apply(&mut upstream, true);
+    let changes =
+        upstream.get_changes(&[]).into_iter().cloned().collect::<Vec<_>>();
+
+    group.bench_function(BenchmarkId::new("downstream", trace_name), |b| {
+        b.iter(|| {
+            let mut downstream = Automerge::new();
+            downstream.apply_changes(changes.iter().cloned()).unwrap();
+            assert_eq!(
+                downstream.length(&text),
+                trace.end_content().chars().count()
+            );
+        })
+    });
+}
+
+fn automerge(c: &mut Criterion) {
+    let mut group = c.benchmark_group("automerge");
+
+    group.sample_size(10);
+
+    for (trace, trace_name) in [
+        (traces::automerge(), "automerge"),
+        (traces::rustcode(), "rustcode"),
+        (traces::seph_blog(), "seph_blog"),
+        (traces::sveltecomponent(), "sveltecomponent"),
+    ] {
+        bench_automerge(&mut group, &trace, trace_name);
+    }
+}
+
+criterion_group!(benches, automerge);
+criterion_main!(benches);
@@ -4,6 +4,8 @@ use std::io::Read;
 use flate2::bufread::GzDecoder;
 use serde::Deserialize;
 
+use crate::ImportError;
+
 type AgentIdx = usize;
 
 /// An index into the `txns` vector of a `ConcurrentDataSet`.
@@ -12,10 +14,10 @@ type TxnIdx = usize;
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub(crate) struct ConcurrentDataSet {
-    kind: String,
-    end_content: String,
-    num_agents: usize,
-    txns: Vec<Transaction>,
+    pub(crate) kind: String,
+    pub(crate) end_content: String,
+    pub(crate) num_agents: usize,
+    pub(crate) txns: Vec<Transaction>,
 }
 
 impl ConcurrentDataSet {
@@ -27,19 +29,54 @@ impl ConcurrentDataSet {
         assert_eq!(this.kind, "concurrent");
         this
     }
+
+    /// Parses a data set in the concurrent JSON format, optionally gzipped.
+    pub(crate) fn from_json(bytes: &[u8]) -> Result<Self, ImportError> {
+        let this: Self = if bytes.starts_with(&[0x1f, 0x8b]) {
+            let mut json = Vec::new();
+            GzDecoder::new(bytes).read_to_end(&mut json)?;
+            serde_json::from_slice(&json)?
+        } else {
+            serde_json::from_slice(bytes)?
+        };
+
+        if this.kind != "concurrent" {
+            return Err(ImportError::Invalid(format!(
+                "expected a concurrent trace, got a {:?} one",
+                this.kind
+            )));
+        }
+
+        for (idx, txn) in this.txns.iter().enumerate() {
+            if txn.agent >= this.num_agents {
+                return Err(ImportError::Invalid(format!(
+                    "transaction {idx} was made by agent {}, but there are \
+                     only {} agents",
+                    txn.agent, this.num_agents
+                )));
+            }
+            if txn.parents.iter().any(|&parent| parent >= idx) {
+                return Err(ImportError::Invalid(format!(
+                    "transaction {idx} doesn't come after its parents"
+                )));
+            }
+        }
+
+        Ok(this)
+    }
 }
 
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
-struct Transaction {
-    parents: Vec<TxnIdx>,
-    agent: AgentIdx,
-    patches: Vec<Patch>,
+pub(crate) struct Transaction {
+    pub(crate) parents: Vec<TxnIdx>,
+    pub(crate) agent: AgentIdx,
+    pub(crate) patches: Vec<Patch>,
 }
 
 /// (position, deleted, text)
 #[derive(Deserialize)]
-struct Patch(usize, usize, String);
+pub(crate) struct Patch(pub(crate) usize, pub(crate) usize, pub(crate) String);
 
 pub trait Crdt: core::fmt::Debug + Sized {
     type EDIT: Clone + core::fmt::Debug;
@@ -51,22 +88,21 @@ pub trait Crdt: core::fmt::Debug + Sized {
     fn remote_merge(&mut self, remote_edit: &Self::EDIT);
 }
 
-pub struct ConcurrentTraceInfos<const NUM_PEERS: usize, C: Crdt> {
-    pub trace: ConcurrentTrace<NUM_PEERS, C>,
+pub struct ConcurrentTraceInfos<C: Crdt> {
+    pub trace: ConcurrentTrace<C>,
     pub peers: Vec<C>,
     pub final_content: String,
 }
 
-impl<const NUM_PEERS: usize, C: Crdt> ConcurrentTraceInfos<NUM_PEERS, C> {
+impl<C: Crdt> ConcurrentTraceInfos<C> {
     pub(crate) fn from_data_set(data: ConcurrentDataSet) -> Self {
         let ConcurrentDataSet { end_content, num_agents, txns, .. } = data;
-        assert_eq!(num_agents, NUM_PEERS);
-        let (trace, peers) = ConcurrentTrace::from_txns(txns);
+        let (trace, peers) = ConcurrentTrace::from_txns(txns, num_agents);
         Self { trace, peers, final_content: end_content }
     }
 }
 
-pub struct ConcurrentTrace<const NUM_PEERS: usize, C: Crdt> {
+pub struct ConcurrentTrace<C: Crdt> {
     edits: Vec<Edit<C>>,
 }
 
@@ -77,17 +113,17 @@ pub enum Edit<C: Crdt> {
     Merge(AgentIdx, C::EDIT),
 }
 
-impl<const NUM_PEERS: usize, C: Crdt> ConcurrentTrace<NUM_PEERS, C> {
+impl<C: Crdt> ConcurrentTrace<C> {
     pub fn edits(&self) -> impl Iterator<Item = &Edit<C>> {
         self.edits.iter()
     }
 
-    fn from_txns(txns: Vec<Transaction>) -> (Self, Vec<C>) {
+    fn from_txns(txns: Vec<Transaction>, num_peers: usize) -> (Self, Vec<C>) {
         let mut edits_in_txns = Vec::new();
 
         let mut version_vectors = HashMap::new();
 
-        let mut agents = Self::init_peers("");
+        let mut agents = Self::init_peers("", num_peers);
 
         let mut ops = Vec::new();
 
@@ -144,22 +180,22 @@ impl<const NUM_PEERS: usize, C: Crdt> ConcurrentTrace<NUM_PEERS, C> {
             }
         }
 
-        (Self { edits: ops }, Self::init_peers(""))
+        (Self { edits: ops }, Self::init_peers("", num_peers))
     }
 
-    fn init_peers(starting_text: &str) -> Vec<C> {
-        let mut peers = Vec::with_capacity(NUM_PEERS);
+    fn init_peers(starting_text: &str, num_peers: usize) -> Vec<C> {
+        let mut peers = Vec::with_capacity(num_peers);
 
         let first_peer = C::from_str(1, starting_text);
 
         peers.push(first_peer);
 
-        for i in 1..NUM_PEERS {
+        for i in 1..num_peers {
             let first_peer = &peers[0];
             peers.push(first_peer.fork(i as u64 + 1));
         }
 
-        assert_eq!(peers.len(), NUM_PEERS);
+        assert_eq!(peers.len(), num_peers.max(1));
 
         peers
     }
@@ -206,6 +242,16 @@ fn recursive_merge<C: Crdt>(
     version_vector.push(txn_idx);
 }
 
+/// Reads a trace in the concurrent JSON format of the `concurrent`
+/// directory, optionally gzipped, like the ones exported by diamond-types'
+/// own tooling.
+pub fn concurrent_from_json<C: Crdt>(
+    bytes: &[u8],
+) -> Result<ConcurrentTraceInfos<C>, ImportError> {
+    ConcurrentDataSet::from_json(bytes)
+        .map(ConcurrentTraceInfos::from_data_set)
+}
+
 pub use traces::*;
 
 mod traces {
@@ -214,7 +260,7 @@ mod traces {
     static FRIENDS_FOREVER: &[u8] =
         include_bytes!("../concurrent/friendsforever.json.gz");
 
-    pub fn friends_forever<C: Crdt>() -> ConcurrentTraceInfos<2, C> {
+    pub fn friends_forever<C: Crdt>() -> ConcurrentTraceInfos<C> {
         let set = ConcurrentDataSet::decode_from_gzipped_json(FRIENDS_FOREVER);
         ConcurrentTraceInfos::from_data_set(set)
     }
@@ -0,0 +1,95 @@
+use std::collections::HashMap;
+
+use automerge::patches::TextRepresentation;
+use automerge::{
+    ActorId,
+    Automerge,
+    ObjType,
+    PatchAction,
+    ReadDoc,
+    TextEncoding,
+    Value,
+    ROOT,
+};
+
+use crate::concurrent::{ConcurrentDataSet, Patch, Transaction};
+use crate::{ConcurrentTraceInfos, Crdt, ImportError};
+
+/// Imports the history of the text stored under the given key at the root
+/// of a saved Automerge document.
+///
+/// Every change becomes a transaction made by the agent of its actor, whose
+/// parents are the change's dependencies and whose patches are the edits it
+/// made to the text, with positions in Unicode code points as seen by its
+/// author.
+///
+/// The patches are computed by diffing every change against its
+/// dependencies, which takes time proportional to the size of the document,
+/// so importing long histories is slow.
+pub fn import_automerge<C: Crdt>(
+    bytes: &[u8],
+    text: &str,
+) -> Result<ConcurrentTraceInfos<C>, ImportError> {
+    let doc = Automerge::load(bytes)?;
+
+    let text_obj = match doc.get(ROOT, text)? {
+        Some((Value::Object(ObjType::Text), obj)) => obj,
+        _ => return Err(ImportError::MissingText(text.to_owned())),
+    };
+
+    let repr = TextRepresentation::String(TextEncoding::UnicodeCodePoint);
+
+    let mut agents = HashMap::<ActorId, usize>::new();
+
+    let mut txn_idxs = HashMap::new();
+
+    let mut txns = Vec::new();
+
+    for change in doc.get_changes(&[]) {
+        let num_agents = agents.len();
+
+        let agent =
+            *agents.entry(change.actor_id().clone()).or_insert(num_agents);
+
+        let parents = change
+            .deps()
+            .iter()
+            .map(|dep| {
+                txn_idxs.get(dep).copied().ok_or_else(|| {
+                    ImportError::Invalid(format!(
+                        "change {} comes before its dependency {dep}",
+                        change.hash()
+                    ))
+                })
+            })
+            .collect::<Result<_, _>>()?;
+
+        let patches = doc
+            .diff(change.deps(), &[change.hash()], repr)
+            .into_iter()
+            .filter(|patch| patch.obj == text_obj)
+            .filter_map(|patch| match patch.action {
+                PatchAction::SpliceText { index, value, .. } => {
+                    Some(Patch(index, 0, value.make_string()))
+                },
+                PatchAction::DeleteSeq { index, length } => {
+                    Some(Patch(index, length, String::new()))
+                },
+                _ => None,
+            })
+            .collect();
+
+        txn_idxs.insert(change.hash(), txns.len());
+
+        txns.push(Transaction { parents, agent, patches });
+    }
+
+    let data = ConcurrentDataSet {
+        kind: "concurrent".to_owned(),
+        end_content: doc.text(&text_obj)?,
+        num_agents: agents.len(),
+        txns,
+    };
+
+    Ok(ConcurrentTraceInfos::from_data_set(data))
+}
@@ -0,0 +1,900 @@
+use crate::concurrent::{ConcurrentDataSet, Patch, Transaction};
+use crate::{ConcurrentTraceInfos, Crdt, ImportError};
+
+/// The bytes every `.dt` file starts with.
+const MAGIC_BYTES: &[u8] = b"DMNDTYPS";
+
+/// The only version of the `.dt` format.
+const PROTOCOL_VERSION: usize = 0;
+
+// The types of the chunks we read, the other ones are skipped.
+const FILE_INFO: usize = 1;
+const AGENT_NAMES: usize = 3;
+const COMPRESSED_FIELDS_LZ4: usize = 5;
+const START_BRANCH: usize = 10;
+const VERSION: usize = 12;
+const CONTENT: usize = 13;
+const CONTENT_COMPRESSED: usize = 14;
+const PATCHES: usize = 20;
+const OP_VERSIONS: usize = 21;
+const OP_TYPE_AND_POSITION: usize = 22;
+const OP_PARENTS: usize = 23;
+const PATCH_CONTENT: usize = 24;
+const CONTENT_IS_KNOWN: usize = 25;
+
+/// The data type of the content chunks storing text.
+const PLAIN_TEXT: usize = 4;
+
+/// The kind of the operations whose content is stored in a `PatchContent`
+/// chunk that we care about.
+const INSERTIONS: usize = 0;
+
+/// Imports the history stored in a diamond-types `.dt` file, like the ones
+/// saved by `OpLog::encode` or the `dt` command line tool.
+///
+/// The operations are split into transactions wherever the agent changes or
+/// an operation has parents other than the one before it, with positions in
+/// Unicode code points as seen by their author. Files saved without the
+/// inserted content have it imported as U+FFFD replacement characters, and
+/// the file has to contain the full history, not just the operations since
+/// some version.
+///
+/// The `.dt` file doesn't store the final content of the document, so it's
+/// computed by merging the transactions with the rules diamond-types uses
+/// to order concurrent insertions, which takes time proportional to the
+/// size of the document for every transaction, so importing long histories
+/// is slow.
+pub fn import_diamond_types<C: Crdt>(
+    bytes: &[u8],
+) -> Result<ConcurrentTraceInfos<C>, ImportError> {
+    let history = History::decode(bytes)?;
+    let data = history.into_data_set()?;
+    Ok(ConcurrentTraceInfos::from_data_set(data))
+}
+
+fn invalid(reason: impl Into<String>) -> ImportError {
+    ImportError::Invalid(reason.into())
+}
+
+/// Splits the lowest bit off a number.
+#[inline]
+fn strip_bit(n: usize) -> (usize, bool) {
+    (n >> 1, n & 1 == 1)
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    #[inline]
+    fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    #[inline]
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], ImportError> {
+        if len > self.bytes.len() {
+            return Err(invalid("unexpected end of file"));
+        }
+        let (bytes, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(bytes)
+    }
+
+    #[inline]
+    fn varint(&mut self) -> Result<usize, ImportError> {
+        let mut n = 0u64;
+
+        for shift in (0..64).step_by(7) {
+            let byte = self.bytes(1)?[0];
+            n |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return usize::try_from(n)
+                    .map_err(|_| invalid("varint overflow"));
+            }
+        }
+
+        Err(invalid("varint overflow"))
+    }
+
+    /// Reads a zigzag encoded signed varint.
+    #[inline]
+    fn signed(&mut self) -> Result<isize, ImportError> {
+        let (abs, is_negative) = strip_bit(self.varint()?);
+        let abs =
+            isize::try_from(abs).map_err(|_| invalid("varint overflow"))?;
+        Ok(if is_negative { -abs } else { abs })
+    }
+
+    #[inline]
+    fn str(&mut self) -> Result<&'a str, ImportError> {
+        let len = self.varint()?;
+        std::str::from_utf8(self.bytes(len)?)
+            .map_err(|_| invalid("string isn't valid UTF-8"))
+    }
+
+    #[inline]
+    fn chunk(&mut self) -> Result<(usize, Reader<'a>), ImportError> {
+        let chunk_type = self.varint()?;
+        let len = self.varint()?;
+        Ok((chunk_type, Reader { bytes: self.bytes(len)? }))
+    }
+}
+
+/// Decompresses an LZ4 block into a buffer of the given length.
+fn decompress_lz4(block: &[u8], len: usize) -> Result<Vec<u8>, ImportError> {
+    let mut block = Reader { bytes: block };
+
+    let mut out = Vec::with_capacity(len);
+
+    // Lengths of 15 are followed by bytes to add to them, until one isn't
+    // 255.
+    let length = |block: &mut Reader, nibble: u8| {
+        let mut len = nibble as usize;
+        if nibble == 15 {
+            loop {
+                let byte = block.bytes(1)?[0];
+                len += byte as usize;
+                if byte != 255 {
+                    break;
+                }
+            }
+        }
+        Ok::<_, ImportError>(len)
+    };
+
+    while !block.is_empty() {
+        let token = block.bytes(1)?[0];
+
+        let num_literals = length(&mut block, token >> 4)?;
+        out.extend_from_slice(block.bytes(num_literals)?);
+
+        // The last sequence only contains literals.
+        if block.is_empty() {
+            break;
+        }
+
+        let offset = block.bytes(2)?;
+        let offset = u16::from_le_bytes([offset[0], offset[1]]) as usize;
+
+        if offset == 0 || offset > out.len() {
+            return Err(invalid("invalid LZ4 match offset"));
+        }
+
+        let match_len = length(&mut block, token & 0xf)? + 4;
+
+        // The match can overlap the bytes it produces, so it has to be
+        // copied one byte at a time.
+        for _ in 0..match_len {
+            out.push(out[out.len() - offset]);
+        }
+    }
+
+    if out.len() != len {
+        return Err(invalid("LZ4 block has the wrong length"));
+    }
+
+    Ok(out)
+}
+
+/// Reads the text of a `Content` or `ContentCompressed` chunk, taking the
+/// latter from the decompressed fields.
+fn read_content(
+    chunk_type: usize,
+    mut chunk: Reader,
+    decompressed: &mut Reader,
+) -> Result<String, ImportError> {
+    if chunk.varint()? != PLAIN_TEXT {
+        return Err(invalid("content isn't plain text"));
+    }
+
+    let bytes = if chunk_type == CONTENT_COMPRESSED {
+        decompressed.bytes(chunk.varint()?)?
+    } else {
+        chunk.bytes
+    };
+
+    String::from_utf8(bytes.to_vec())
+        .map_err(|_| invalid("content isn't valid UTF-8"))
+}
+
+/// A run of operations of the same kind, made in the order they're
+/// applied.
+struct OpRun {
+    is_deletion: bool,
+
+    /// Whether a deletion deletes forward, like the delete key, or backward,
+    /// like backspace.
+    is_forward: bool,
+
+    /// The position of the first character inserted or deleted, or of the
+    /// end of the deleted range if the deletion is backward.
+    pos: usize,
+
+    len: usize,
+}
+
+/// The operations of a `.dt` file, indexed by their local version, i.e.
+/// their position in the file.
+#[derive(Default)]
+struct History {
+    agent_names: Vec<String>,
+
+    /// The agent and sequence number of the first operation of every run,
+    /// and its length.
+    agent_runs: Vec<(usize, usize, usize)>,
+
+    op_runs: Vec<OpRun>,
+
+    /// The first local version of every run whose first operation has
+    /// explicit parents, its length and those parents.
+    parent_runs: Vec<(usize, usize, Vec<usize>)>,
+
+    /// The characters inserted by the operations, in order.
+    inserted: Vec<char>,
+}
+
+impl History {
+    fn decode(bytes: &[u8]) -> Result<Self, ImportError> {
+        if !bytes.starts_with(MAGIC_BYTES) {
+            return Err(invalid("not a diamond-types file"));
+        }
+
+        let mut file = Reader { bytes: &bytes[MAGIC_BYTES.len()..] };
+
+        let version = file.varint()?;
+
+        if version != PROTOCOL_VERSION {
+            return Err(invalid(format!(
+                "unsupported diamond-types protocol version {version}"
+            )));
+        }
+
+        let mut chunks = Vec::new();
+
+        while !file.is_empty() {
+            chunks.push(file.chunk()?);
+        }
+
+        // The compressed content of all the `ContentCompressed` chunks is
+        // stored in a single chunk, in the order the chunks come in.
+        let decompressed = match chunks
+            .iter_mut()
+            .find(|(chunk_type, _)| *chunk_type == COMPRESSED_FIELDS_LZ4)
+        {
+            Some((_, chunk)) => {
+                let len = chunk.varint()?;
+                decompress_lz4(chunk.bytes, len)?
+            },
+            None => Vec::new(),
+        };
+
+        let mut decompressed = Reader { bytes: &decompressed };
+
+        let mut history = Self::default();
+
+        let mut has_file_info = false;
+
+        for (chunk_type, chunk) in chunks {
+            match chunk_type {
+                FILE_INFO => {
+                    history.read_file_info(chunk)?;
+                    has_file_info = true;
+                },
+                START_BRANCH => {
+                    Self::read_start_branch(chunk, &mut decompressed)?;
+                },
+                PATCHES if has_file_info => {
+                    history.read_patches(chunk, &mut decompressed)?;
+                },
+                PATCHES => {
+                    return Err(invalid("patches come before the file info"));
+                },
+                _ => {},
+            }
+        }
+
+        Ok(history)
+    }
+
+    fn read_file_info(
+        &mut self,
+        mut chunk: Reader,
+    ) -> Result<(), ImportError> {
+        while !chunk.is_empty() {
+            let (chunk_type, mut chunk) = chunk.chunk()?;
+
+            if chunk_type == AGENT_NAMES {
+                while !chunk.is_empty() {
+                    self.agent_names.push(chunk.str()?.to_owned());
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks that the file starts from an empty document.
+    fn read_start_branch(
+        mut chunk: Reader,
+        decompressed: &mut Reader,
+    ) -> Result<(), ImportError> {
+        while !chunk.is_empty() {
+            let (chunk_type, chunk) = chunk.chunk()?;
+
+            let is_empty = match chunk_type {
+                // The root version is either empty or a single zero.
+                VERSION => chunk.is_empty() || chunk.bytes == [0],
+                CONTENT | CONTENT_COMPRESSED => {
+                    read_content(chunk_type, chunk, decompressed)?.is_empty()
+                },
+                _ => true,
+            };
+
+            if !is_empty {
+                return Err(invalid(
+                    "the file doesn't contain the full history of the \
+                     document",
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn read_patches(
+        &mut self,
+        mut chunk: Reader,
+        decompressed: &mut Reader,
+    ) -> Result<(), ImportError> {
+        while !chunk.is_empty() {
+            let (chunk_type, chunk) = chunk.chunk()?;
+
+            match chunk_type {
+                PATCH_CONTENT => {
+                    self.read_patch_content(chunk, decompressed)?
+                },
+                OP_VERSIONS => self.read_op_versions(chunk)?,
+                OP_TYPE_AND_POSITION => self.read_op_types(chunk)?,
+                OP_PARENTS => self.read_op_parents(chunk)?,
+                _ => {},
+            }
+        }
+
+        let num_ops = self.num_ops();
+
+        let num_inserted = self
+            .op_runs
+            .iter()
+            .filter(|run| !run.is_deletion)
+            .map(|run| run.len)
+            .sum::<usize>();
+
+        if self.agent_runs.iter().map(|&(.., len)| len).sum::<usize>()
+            != num_ops
+            || self.parent_runs.iter().map(|&(_, len, _)| len).sum::<usize>()
+                != num_ops
+        {
+            return Err(invalid(
+                "the agents, parents and positions of the operations don't \
+                 cover the same operations",
+            ));
+        }
+
+        // Files saved without the inserted content don't have it at all,
+        // and the content of the insertions that aren't known is skipped.
+        if self.inserted.len() > num_inserted {
+            return Err(invalid("there's more content than insertions"));
+        }
+
+        self.inserted.resize(num_inserted, char::REPLACEMENT_CHARACTER);
+
+        Ok(())
+    }
+
+    fn read_patch_content(
+        &mut self,
+        mut chunk: Reader,
+        decompressed: &mut Reader,
+    ) -> Result<(), ImportError> {
+        let kind = chunk.varint()?;
+
+        let mut content = String::new();
+
+        let mut known_runs = Vec::new();
+
+        while !chunk.is_empty() {
+            let (chunk_type, mut chunk) = chunk.chunk()?;
+
+            match chunk_type {
+                CONTENT | CONTENT_COMPRESSED => {
+                    content = read_content(chunk_type, chunk, decompressed)?;
+                },
+                CONTENT_IS_KNOWN => {
+                    while !chunk.is_empty() {
+                        known_runs.push(strip_bit(chunk.varint()?));
+                    }
+                },
+                _ => {},
+            }
+        }
+
+        if kind != INSERTIONS {
+            return Ok(());
+        }
+
+        let mut chars = content.chars();
+
+        if known_runs.is_empty() {
+            self.inserted.extend(chars);
+            return Ok(());
+        }
+
+        for (len, is_known) in known_runs {
+            if is_known {
+                let len_before = self.inserted.len();
+                self.inserted.extend(chars.by_ref().take(len));
+                if self.inserted.len() - len_before != len {
+                    return Err(invalid("the inserted content is too short"));
+                }
+            } else {
+                let len = self.inserted.len() + len;
+                self.inserted.resize(len, char::REPLACEMENT_CHARACTER);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn read_op_versions(
+        &mut self,
+        mut chunk: Reader,
+    ) -> Result<(), ImportError> {
+        // The sequence number every agent is expected to continue from.
+        let mut next_seqs = vec![0usize; self.agent_names.len()];
+
+        while !chunk.is_empty() {
+            let (agent, has_jump) = strip_bit(chunk.varint()?);
+
+            let len = chunk.varint()?;
+
+            if len == 0 {
+                return Err(invalid("empty run of operations"));
+            }
+
+            let Some(next_seq) = next_seqs.get_mut(agent) else {
+                return Err(invalid(format!(
+                    "operations were made by agent {agent}, but there are \
+                     only {} agents",
+                    self.agent_names.len()
+                )));
+            };
+
+            let seq = if has_jump {
+                next_seq
+                    .checked_add_signed(chunk.signed()?)
+                    .ok_or_else(|| invalid("negative sequence number"))?
+            } else {
+                *next_seq
+            };
+
+            *next_seq = seq + len;
+
+            self.agent_runs.push((agent, seq, len));
+        }
+
+        Ok(())
+    }
+
+    fn read_op_types(&mut self, mut chunk: Reader) -> Result<(), ImportError> {
+        let mut cursor = 0usize;
+
+        while !chunk.is_empty() {
+            let (n, has_jump) = strip_bit(chunk.varint()?);
+
+            let (n, is_deletion) = strip_bit(n);
+
+            let (len, is_forward) = match (n, is_deletion) {
+                (0, _) => (1, true),
+                (n, true) => strip_bit(n),
+                (n, false) => (n, true),
+            };
+
+            if len == 0 {
+                return Err(invalid("empty run of operations"));
+            }
+
+            let pos = if has_jump {
+                cursor
+                    .checked_add_signed(chunk.signed()?)
+                    .ok_or_else(|| invalid("negative operation position"))?
+            } else {
+                cursor
+            };
+
+            cursor = match (is_deletion, is_forward) {
+                (false, _) => pos + len,
+                (true, true) => pos,
+                (true, false) => pos.checked_sub(len).ok_or_else(|| {
+                    invalid("deletion starts before the document")
+                })?,
+            };
+
+            self.op_runs.push(OpRun { is_deletion, is_forward, pos, len });
+        }
+
+        Ok(())
+    }
+
+    fn read_op_parents(
+        &mut self,
+        mut chunk: Reader,
+    ) -> Result<(), ImportError> {
+        let mut start = self.num_ops_with_parents();
+
+        while !chunk.is_empty() {
+            let len = chunk.varint()?;
+
+            if len == 0 {
+                return Err(invalid("empty run of operations"));
+            }
+
+            let mut parents = Vec::new();
+
+            loop {
+                let (n, is_last) = strip_bit(chunk.varint()?);
+
+                let (n, is_foreign) = strip_bit(n);
+
+                if !is_foreign {
+                    let parent = start.checked_sub(n).filter(|_| n > 0);
+                    parents.push(parent.ok_or_else(|| {
+                        invalid("operation comes before its parents")
+                    })?);
+                } else if n > 0 {
+                    let seq = chunk.varint()?;
+                    let parent = self.local_version(n - 1, seq)?;
+                    if parent >= start {
+                        return Err(invalid(
+                            "operation comes before its parents",
+                        ));
+                    }
+                    parents.push(parent);
+                }
+
+                if is_last {
+                    break;
+                }
+            }
+
+            self.parent_runs.push((start, len, parents));
+
+            start += len;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the local version of the operation made by the given agent
+    /// with the given sequence number.
+    fn local_version(
+        &self,
+        agent: usize,
+        seq: usize,
+    ) -> Result<usize, ImportError> {
+        let mut lv = 0;
+
+        for &(run_agent, run_seq, len) in &self.agent_runs {
+            if run_agent == agent && (run_seq..run_seq + len).contains(&seq) {
+                return Ok(lv + seq - run_seq);
+            }
+            lv += len;
+        }
+
+        Err(invalid("operation depends on operations that aren't in the file"))
+    }
+
+    fn num_ops(&self) -> usize {
+        self.op_runs.iter().map(|run| run.len).sum()
+    }
+
+    fn num_ops_with_parents(&self) -> usize {
+        self.parent_runs.iter().map(|&(_, len, _)| len).sum()
+    }
+
+    fn into_data_set(self) -> Result<ConcurrentDataSet, ImportError> {
+        let num_ops = self.num_ops();
+
+        // The local versions the transactions start at. An operation
+        // depended on by other operations has to end its transaction.
+        let mut starts = self
+            .parent_runs
+            .iter()
+            .flat_map(|(start, _, parents)| {
+                parents.iter().map(|&parent| parent + 1).chain([*start])
+            })
+            .chain(self.agent_runs.iter().scan(0, |start, &(.., len)| {
+                let agent_start = *start;
+                *start += len;
+                Some(agent_start)
+            }))
+            .filter(|&start| start < num_ops)
+            .collect::<Vec<_>>();
+
+        starts.sort_unstable();
+        starts.dedup();
+
+        let txn_of =
+            |lv: usize| starts.partition_point(|&start| start <= lv) - 1;
+
+        let mut agent_runs = self.agent_runs.iter();
+        let mut agent_run_end = 0;
+        let mut agent = 0;
+
+        let mut parent_runs = self.parent_runs.iter().peekable();
+
+        let mut op_runs = self.op_runs.iter();
+        let mut op_run = None::<&OpRun>;
+        let mut op_offset = 0;
+
+        let mut inserted = self.inserted.iter();
+
+        let mut txns = Vec::with_capacity(starts.len());
+
+        for (idx, &start) in starts.iter().enumerate() {
+            let end = starts.get(idx + 1).copied().unwrap_or(num_ops);
+
+            if start == agent_run_end {
+                let &(run_agent, _, len) = agent_runs.next().unwrap();
+                agent = run_agent;
+                agent_run_end += len;
+            }
+
+            let parents = match parent_runs.peek() {
+                Some((run_start, _, parents)) if *run_start == start => {
+                    let parents = parents.iter().map(|&lv| txn_of(lv));
+                    let parents = parents.collect();
+                    parent_runs.next();
+                    parents
+                },
+                _ => vec![idx - 1],
+            };
+
+            let mut patches = Vec::new();
+
+            let mut lv = start;
+
+            while lv < end {
+                let run = match op_run {
+                    Some(run) if op_offset < run.len => run,
+                    _ => {
+                        op_offset = 0;
+                        op_run.insert(op_runs.next().unwrap())
+                    },
+                };
+
+                let len = (run.len - op_offset).min(end - lv);
+
+                patches.push(if !run.is_deletion {
+                    let text = inserted.by_ref().take(len).collect();
+                    Patch(run.pos + op_offset, 0, text)
+                } else if run.is_forward {
+                    Patch(run.pos, len, String::new())
+                } else {
+                    Patch(run.pos - op_offset - len, len, String::new())
+                });
+
+                op_offset += len;
+                lv += len;
+            }
+
+            txns.push(Transaction { parents, agent, patches });
+        }
+
+        let end_content = merge(&txns, &self.agent_names)?;
+
+        Ok(ConcurrentDataSet {
+            kind: "concurrent".to_owned(),
+            end_content,
+            num_agents: self.agent_names.len(),
+            txns,
+        })
+    }
+}
+
+/// A character inserted during the merge.
+struct Item {
+    char: char,
+
+    /// The agent that inserted the character and its sequence number.
+    id: (usize, usize),
+
+    /// The items the character was inserted between, if any.
+    origin_left: Option<(usize, usize)>,
+    origin_right: Option<(usize, usize)>,
+
+    /// The agents and sequence numbers of the deletions of the character.
+    deleted_by: Vec<(usize, usize)>,
+}
+
+impl Item {
+    #[inline]
+    fn is_inserted(&self, version: &[usize]) -> bool {
+        self.id.1 < version[self.id.0]
+    }
+
+    #[inline]
+    fn is_visible(&self, version: &[usize]) -> bool {
+        self.is_inserted(version)
+            && !self
+                .deleted_by
+                .iter()
+                .any(|&(agent, seq)| seq < version[agent])
+    }
+}
+
+/// Merges the transactions and returns the final content of the document.
+///
+/// Concurrent insertions are ordered like diamond-types does, with the
+/// YjsMod rules and ties broken by agent name. Every transaction sees the
+/// operations of its ancestors, which are tracked with a version vector
+/// since the operations of an agent are always made one after the other.
+fn merge(
+    txns: &[Transaction],
+    agent_names: &[String],
+) -> Result<String, ImportError> {
+    let mut items = Vec::<Item>::new();
+
+    // The number of operations made by every agent, i.e. the version
+    // vector of the document once all the transactions are merged.
+    let mut num_ops = vec![0; agent_names.len()];
+
+    let mut versions = Vec::<Vec<usize>>::with_capacity(txns.len());
+
+    for (idx, txn) in txns.iter().enumerate() {
+        let mut version = vec![0; agent_names.len()];
+
+        for &parent in &txn.parents {
+            for (seq, &parent_seq) in version.iter_mut().zip(&versions[parent])
+            {
+                *seq = (*seq).max(parent_seq);
+            }
+        }
+
+        let agent = txn.agent;
+
+        if version[agent] != num_ops[agent] {
+            return Err(invalid(format!(
+                "transaction {idx} doesn't depend on the previous operations \
+                 of agent {:?}",
+                agent_names[agent]
+            )));
+        }
+
+        for Patch(pos, del, text) in &txn.patches {
+            let out_of_bounds = || {
+                invalid(format!(
+                    "transaction {idx} edits past the end of the document"
+                ))
+            };
+
+            // The index of the item after the first `pos` visible ones.
+            let mut visible = 0;
+            let mut start = 0;
+            while visible < *pos {
+                let item = items.get(start).ok_or_else(out_of_bounds)?;
+                visible += item.is_visible(&version) as usize;
+                start += 1;
+            }
+
+            if *del > 0 {
+                let mut deleted = 0;
+                for item in &mut items[start..] {
+                    if deleted == *del {
+                        break;
+                    }
+                    if item.is_visible(&version) {
+                        item.deleted_by
+                            .push((agent, version[agent] + deleted));
+                        deleted += 1;
+                    }
+                }
+                if deleted < *del {
+                    return Err(out_of_bounds());
+                }
+                version[agent] += del;
+                continue;
+            }
+
+            let mut left = start.checked_sub(1);
+
+            for char in text.chars() {
+                let right = items[left.map_or(0, |left| left + 1)..]
+                    .iter()
+                    .position(|item| item.is_inserted(&version))
+                    .map(|offset| left.map_or(0, |left| left + 1) + offset);
+
+                let item = Item {
+                    char,
+                    id: (agent, version[agent]),
+                    origin_left: left.map(|left| items[left].id),
+                    origin_right: right.map(|right| items[right].id),
+                    deleted_by: Vec::new(),
+                };
+
+                let idx = integrate(&items, &item, left, right, agent_names);
+                items.insert(idx, item);
+                left = Some(idx);
+                version[agent] += 1;
+            }
+        }
+
+        num_ops[agent] = version[agent];
+
+        versions.push(version);
+    }
+
+    Ok(items
+        .iter()
+        .filter(|item| item.deleted_by.is_empty())
+        .map(|item| item.char)
+        .collect())
+}
+
+/// Returns the index at which to insert the item between the given ones,
+/// skipping the concurrent insertions that come before it.
+fn integrate(
+    items: &[Item],
+    item: &Item,
+    left: Option<usize>,
+    right: Option<usize>,
+    agent_names: &[String],
+) -> usize {
+    // The indices are shifted by one so that the start of the document is
+    // 0, and the end of the document is `items.len()`.
+    let left = left.map_or(0, |left| left + 1);
+    let right = right.unwrap_or(items.len());
+
+    let mut dest = left;
+    let mut is_scanning = false;
+
+    for idx in left..=right {
+        if !is_scanning {
+            dest = idx;
+        }
+
+        if idx == right {
+            break;
+        }
+
+        let other = &items[idx];
+
+        // The origins of an item are always on its side of the document.
+        let other_left = other.origin_left.map_or(0, |id| {
+            items[..idx].iter().rposition(|item| item.id == id).unwrap() + 1
+        });
+
+        if other_left < left {
+            break;
+        } else if other_left > left {
+            continue;
+        }
+
+        let other_right = other.origin_right.map_or(items.len(), |id| {
+            idx + items[idx..].iter().position(|item| item.id == id).unwrap()
+        });
+
+        if other_right < right {
+            is_scanning = true;
+        } else if other_right > right
+            || agent_names[item.id.0] > agent_names[other.id.0]
+        {
+            is_scanning = false;
+        } else {
+            break;
+        }
+    }
+
+    dest
+}
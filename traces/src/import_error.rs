@@ -0,0 +1,59 @@
+use core::fmt;
+
+/// The error returned when importing a trace from another format fails.
+#[derive(Debug)]
+pub enum ImportError {
+    /// The Automerge document couldn't be loaded.
+    #[cfg(feature = "automerge")]
+    Automerge(automerge::AutomergeError),
+
+    /// The gzipped trace couldn't be decompressed.
+    Io(std::io::Error),
+
+    /// The trace is malformed.
+    Invalid(String),
+
+    /// The JSON trace couldn't be parsed.
+    Json(serde_json::Error),
+
+    /// The document doesn't contain a text with the given name.
+    MissingText(String),
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            #[cfg(feature = "automerge")]
+            Self::Automerge(err) => {
+                write!(f, "invalid Automerge document: {err}")
+            },
+            Self::Io(err) => write!(f, "couldn't decompress the trace: {err}"),
+            Self::Invalid(reason) => write!(f, "invalid trace: {reason}"),
+            Self::Json(err) => write!(f, "invalid JSON trace: {err}"),
+            Self::MissingText(name) => {
+                write!(f, "the document doesn't contain a text named {name:?}")
+            },
+        }
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+#[cfg(feature = "automerge")]
+impl From<automerge::AutomergeError> for ImportError {
+    fn from(err: automerge::AutomergeError) -> Self {
+        Self::Automerge(err)
+    }
+}
+
+impl From<std::io::Error> for ImportError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for ImportError {
+    fn from(err: serde_json::Error) -> Self {
+        Self::Json(err)
+    }
+}
@@ -0,0 +1,199 @@
+use std::collections::HashMap;
+
+use update::{Content, Document, Id, Store};
+
+use crate::concurrent::{ConcurrentDataSet, Patch, Transaction};
+use crate::{ConcurrentTraceInfos, Crdt, ImportError};
+
+/// The Yjs decoder used by cola's `yjs` feature.
+#[path = "../../src/yjs/update.rs"]
+mod update;
+
+/// Imports the history of the root-level `Y.Text` with the given name from
+/// a log of Yjs updates encoded with the v1 encoding, like the ones stored
+/// by `y-leveldb` or `y-indexeddb`.
+///
+/// Every struct of the updates becomes a transaction made by the agent of
+/// its client, whose parents are the items it was inserted between and the
+/// client's previous struct. Yjs doesn't record when or by whom text was
+/// deleted, so all the deletions are made in a last transaction that
+/// depends on every other one.
+///
+/// Updates can overlap, e.g. when the log contains both incremental updates
+/// and a full state update, and text whose content was garbage collected is
+/// imported as U+FFFD replacement characters which are then deleted.
+pub fn import_yjs<C, U>(
+    updates: U,
+    text: &str,
+) -> Result<ConcurrentTraceInfos<C>, ImportError>
+where
+    C: Crdt,
+    U: IntoIterator,
+    U::Item: AsRef<[u8]>,
+{
+    let mut store = Store::default();
+
+    for (idx, update) in updates.into_iter().enumerate() {
+        store.decode(update.as_ref()).map_err(|reason| {
+            ImportError::Invalid(format!("Yjs update {idx}: {reason}"))
+        })?;
+    }
+
+    if !store.has_root(text) {
+        return Err(ImportError::MissingText(text.to_owned()));
+    }
+
+    let data = into_data_set(&Document::new(store, text));
+
+    Ok(ConcurrentTraceInfos::from_data_set(data))
+}
+
+/// Turns the integrated text into transactions, one for every struct and a
+/// last one for all the deletions.
+fn into_data_set(document: &Document) -> ConcurrentDataSet {
+    let list = document.items().collect::<Vec<_>>();
+
+    let mut ranks = vec![0; document.items.len()];
+
+    for (rank, &item) in list.iter().enumerate() {
+        ranks[item] = rank;
+    }
+
+    let num_chars = document
+        .items
+        .iter()
+        .map(|item| match &item.content {
+            Content::Text(units) => {
+                String::from_utf16_lossy(units).chars().count()
+            },
+            Content::Deleted => item.len as usize,
+            Content::Gc | Content::Format | Content::Other => 0,
+        })
+        .collect::<Vec<_>>();
+
+    let mut agents = HashMap::new();
+
+    let mut txn_idxs = HashMap::new();
+
+    let mut last_txns = HashMap::<u64, usize>::new();
+
+    // The clock each client is at in the causal past of every
+    // transaction.
+    let mut version_vectors = Vec::<HashMap<u64, u64>>::new();
+
+    let mut txns = Vec::new();
+
+    for &(idx, first_item) in &document.integrated {
+        let r#struct = &document.store.structs[idx];
+
+        let client = r#struct.id.client;
+
+        let num_agents = agents.len();
+
+        let agent = *agents.entry(client).or_insert(num_agents);
+
+        let mut parents = [r#struct.origin, r#struct.right_origin]
+            .into_iter()
+            .flatten()
+            .filter_map(|id| document.store.find(id))
+            .filter_map(|idx| txn_idxs.get(&idx).copied())
+            .chain(last_txns.get(&client).copied())
+            .collect::<Vec<_>>();
+
+        parents.sort_unstable();
+        parents.dedup();
+
+        let mut version_vector = HashMap::<u64, u64>::new();
+
+        for &parent in &parents {
+            for (&client, &clock) in &version_vectors[parent] {
+                let entry = version_vector.entry(client).or_insert(0);
+                *entry = clock.max(*entry);
+            }
+        }
+
+        version_vector.insert(client, r#struct.end());
+
+        let text = match &r#struct.content {
+            Content::Text(units) => Some(String::from_utf16_lossy(units)),
+            Content::Deleted => Some("\u{fffd}".repeat(r#struct.len as usize)),
+            Content::Gc | Content::Format | Content::Other => None,
+        };
+
+        let patches = text
+            .map(|text| {
+                let pos = list[..ranks[first_item]]
+                    .iter()
+                    .filter(|&&item| {
+                        let Id { client, clock } = document.items[item].id;
+                        version_vector
+                            .get(&client)
+                            .is_some_and(|&end| clock < end)
+                    })
+                    .map(|&item| num_chars[item])
+                    .sum();
+
+                Patch(pos, 0, text)
+            })
+            .into_iter()
+            .collect();
+
+        txn_idxs.insert(idx, txns.len());
+        last_txns.insert(client, txns.len());
+        version_vectors.push(version_vector);
+        txns.push(Transaction { parents, agent, patches });
+    }
+
+    // The deleted ranges, in document order.
+    let mut deleted = Vec::<(usize, usize)>::new();
+
+    let mut pos = 0;
+
+    let mut end_content = String::new();
+
+    for &item in &list {
+        let len = num_chars[item];
+
+        if document.items[item].is_deleted {
+            match deleted.last_mut() {
+                Some((start, del)) if *start + *del == pos => *del += len,
+                _ => deleted.push((pos, len)),
+            }
+        } else if let Content::Text(units) = &document.items[item].content {
+            end_content.push_str(&String::from_utf16_lossy(units));
+        }
+
+        pos += len;
+    }
+
+    deleted.retain(|&(_, len)| len > 0);
+
+    if !txns.is_empty() && !deleted.is_empty() {
+        let mut is_head = vec![true; txns.len()];
+
+        for txn in &txns {
+            for &parent in &txn.parents {
+                is_head[parent] = false;
+            }
+        }
+
+        let parents = (0..txns.len()).filter(|&idx| is_head[idx]).collect();
+
+        // Deleting from the end keeps the positions of the other ranges
+        // valid.
+        let patches = deleted
+            .into_iter()
+            .rev()
+            .map(|(pos, len)| Patch(pos, len, String::new()))
+            .collect();
+
+        txns.push(Transaction { parents, agent: 0, patches });
+    }
+
+    ConcurrentDataSet {
+        kind: "concurrent".to_owned(),
+        end_content,
+        num_agents: agents.len(),
+        txns,
+    }
+}
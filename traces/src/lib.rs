@@ -1,5 +1,15 @@
 mod concurrent;
+#[cfg(feature = "automerge")]
+mod import_automerge;
+mod import_diamond_types;
+mod import_error;
+mod import_yjs;
 mod sequential;
 
 pub use concurrent::*;
+#[cfg(feature = "automerge")]
+pub use import_automerge::import_automerge;
+pub use import_diamond_types::import_diamond_types;
+pub use import_error::ImportError;
+pub use import_yjs::import_yjs;
 pub use sequential::*;
@@ -0,0 +1,114 @@
+#[path = "../../tests/common/mod.rs"]
+mod common;
+
+use automerge::transaction::Transactable;
+use automerge::{ActorId, AutoCommit, ObjType, ReadDoc, ROOT};
+use common::Replica;
+use traces::{ConcurrentTraceInfos, Crdt, Edit, ImportError};
+
+fn test_trace(trace: ConcurrentTraceInfos<Replica>) {
+    let ConcurrentTraceInfos { trace, mut peers, final_content, .. } = trace;
+
+    for edit in trace.edits() {
+        match edit {
+            Edit::Insertion(idx, offset, text) => {
+                peers[*idx].local_insert(*offset, text);
+                peers[*idx].assert_invariants();
+            },
+            Edit::Deletion(idx, start, end) => {
+                peers[*idx].local_delete(*start, *end);
+                peers[*idx].assert_invariants();
+            },
+            Edit::Merge(idx, edit) => {
+                peers[*idx].remote_merge(edit);
+                peers[*idx].assert_invariants();
+            },
+        }
+    }
+
+    for replica in &mut peers {
+        replica.merge_backlogged();
+    }
+
+    for replica in &peers {
+        assert_eq!(replica.buffer, final_content);
+    }
+}
+
+/// Tests that an Automerge document edited concurrently by two actors is
+/// imported with the right final content.
+#[test]
+fn import_automerge_concurrent() {
+    let mut alice = AutoCommit::new().with_actor(ActorId::from(b"alice"));
+    let text = alice.put_object(ROOT, "text", ObjType::Text).unwrap();
+    alice.splice_text(&text, 0, 0, "hello world").unwrap();
+    alice.commit();
+
+    let mut bob = alice.fork().with_actor(ActorId::from(b"bob"));
+
+    alice.splice_text(&text, 6, 0, "brave new ").unwrap();
+    alice.commit();
+
+    bob.splice_text(&text, 0, 6, "").unwrap();
+    bob.commit();
+    bob.splice_text(&text, 5, 0, "!").unwrap();
+    bob.commit();
+
+    alice.merge(&mut bob).unwrap();
+
+    let trace = traces::import_automerge(&alice.save(), "text").unwrap();
+
+    assert_eq!(trace.final_content, "brave new world!");
+    assert_eq!(trace.peers.len(), 2);
+
+    test_trace(trace);
+}
+
+/// Tests importing a longer Automerge history, made by replaying the start
+/// of the automerge-paper trace with the actors taking turns.
+#[test]
+fn import_automerge_turns() {
+    let automerge = traces::automerge();
+    let edits = automerge.edits().take(500).collect::<Vec<_>>();
+
+    let mut alice = AutoCommit::new().with_actor(ActorId::from(b"alice"));
+    let text = alice.put_object(ROOT, "text", ObjType::Text).unwrap();
+    alice.commit();
+
+    let mut bob = alice.fork().with_actor(ActorId::from(b"bob"));
+
+    for (turn, chunk) in edits.chunks(50).enumerate() {
+        let (doc, other) = if turn % 2 == 0 {
+            (&mut alice, &mut bob)
+        } else {
+            (&mut bob, &mut alice)
+        };
+
+        for &(start, end, insert) in chunk {
+            let del = (end - start) as isize;
+            doc.splice_text(&text, start, del, insert).unwrap();
+        }
+
+        doc.commit();
+        other.merge(doc).unwrap();
+    }
+
+    let trace = traces::import_automerge(&alice.save(), "text").unwrap();
+
+    assert_eq!(trace.final_content, alice.text(&text).unwrap());
+
+    test_trace(trace);
+}
+
+#[test]
+fn import_automerge_missing_text() {
+    let mut doc = AutoCommit::new();
+    doc.put_object(ROOT, "notes", ObjType::Text).unwrap();
+
+    let Err(err) = traces::import_automerge::<Replica>(&doc.save(), "text")
+    else {
+        panic!("imported a missing text");
+    };
+
+    assert!(matches!(err, ImportError::MissingText(name) if name == "text"));
+}